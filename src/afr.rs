@@ -0,0 +1,269 @@
+//! Wideband O2 interpretation: AFR, lambda and AFR error.
+//!
+//! Speeduino sends the O2 reading (`O2P`) as AFR × 10, calibrated for the
+//! wideband controller in TunerStudio.  [`Wideband`] turns it into real
+//! channels:
+//!
+//! | Topic | Value |
+//! |-------|-------|
+//! | `AFR` | `O2P × o2_scale + o2_offset` |
+//! | `LAMBDA` | `AFR / stoich` |
+//! | `AFR_ERROR` | `AFR − AFT` (positive = leaner than target) |
+//!
+//! The stoichiometric ratio follows `fuel_type` (or `stoich_afr`).  With
+//! `fuel_type = "flex"` it is blended linearly between petrol and ethanol by
+//! the ethanol content the ECU reports (`ETH`).
+
+use crate::config::AppConfig;
+use crate::ecu_data_parser::SpeeduinoData;
+use crate::errors::{ConfigError, Result};
+use crate::packet_layout::Field;
+
+pub const AFR_TOPIC: &str = "AFR";
+pub const LAMBDA_TOPIC: &str = "LAMBDA";
+pub const AFR_ERROR_TOPIC: &str = "AFR_ERROR";
+
+/// Stoichiometric AFR of petrol (gasoline).
+const STOICH_PETROL: f64 = 14.7;
+/// Stoichiometric AFR of pure ethanol.
+const STOICH_ETHANOL: f64 = 9.0;
+
+/// `fuel_type` setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FuelType {
+    #[default]
+    Petrol,
+    E85,
+    Ethanol,
+    Methanol,
+    Lpg,
+    Cng,
+    Diesel,
+    /// Petrol/ethanol blend measured by the flex sensor
+    Flex,
+}
+
+impl FuelType {
+    /// Parse a fuel name (case-insensitive).
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "petrol" | "gasoline" | "gas" => Some(FuelType::Petrol),
+            "e85" => Some(FuelType::E85),
+            "ethanol" | "e100" => Some(FuelType::Ethanol),
+            "methanol" => Some(FuelType::Methanol),
+            "lpg" => Some(FuelType::Lpg),
+            "cng" => Some(FuelType::Cng),
+            "diesel" => Some(FuelType::Diesel),
+            "flex" => Some(FuelType::Flex),
+            _ => None,
+        }
+    }
+
+    /// Stoichiometric AFR of the fuel; for `Flex`, that of its petrol part.
+    pub fn stoich(self) -> f64 {
+        match self {
+            FuelType::Petrol | FuelType::Flex => STOICH_PETROL,
+            FuelType::E85 => 9.765,
+            FuelType::Ethanol => STOICH_ETHANOL,
+            FuelType::Methanol => 6.4,
+            FuelType::Lpg => 15.5,
+            FuelType::Cng => 17.2,
+            FuelType::Diesel => 14.5,
+        }
+    }
+}
+
+/// Stoichiometric AFR of a petrol/ethanol blend with `ethanol_pct` % ethanol.
+pub fn blended_stoich(petrol: f64, ethanol_pct: f64) -> f64 {
+    let e = ethanol_pct.clamp(0.0, 100.0) / 100.0;
+    petrol + (STOICH_ETHANOL - petrol) * e
+}
+
+/// How the O2 reading is turned into AFR and lambda.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Wideband {
+    pub fuel: FuelType,
+    /// Stoichiometric AFR (for `Flex`, of the petrol part)
+    pub stoich: f64,
+    pub o2_scale: f64,
+    pub o2_offset: f64,
+}
+
+impl Default for Wideband {
+    /// Petrol, O2 sent as AFR × 10.
+    fn default() -> Self {
+        Self {
+            fuel: FuelType::Petrol,
+            stoich: STOICH_PETROL,
+            o2_scale: 0.1,
+            o2_offset: 0.0,
+        }
+    }
+}
+
+impl Wideband {
+    /// Settings from `fuel_type`, `stoich_afr`, `o2_scale` and `o2_offset`.
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        let Some(fuel) = FuelType::from_config(&config.fuel_type) else {
+            return Err(ConfigError::InvalidValue {
+                field: "fuel_type".to_string(),
+                message: format!(
+                    "must be petrol, e85, ethanol, methanol, lpg, cng, diesel or flex, got \"{}\"",
+                    config.fuel_type
+                ),
+            }
+            .into());
+        };
+        if let Some(stoich) = config.stoich_afr
+            && !(stoich.is_finite() && stoich > 0.0)
+        {
+            return Err(ConfigError::InvalidValue {
+                field: "stoich_afr".to_string(),
+                message: format!("must be a positive ratio, got {}", stoich),
+            }
+            .into());
+        }
+        if !(config.o2_scale.is_finite() && config.o2_scale > 0.0 && config.o2_offset.is_finite()) {
+            return Err(ConfigError::InvalidValue {
+                field: "o2_scale".to_string(),
+                message: "o2_scale must be positive and o2_offset finite".to_string(),
+            }
+            .into());
+        }
+        Ok(Self {
+            fuel,
+            stoich: config.stoich_afr.unwrap_or_else(|| fuel.stoich()),
+            o2_scale: config.o2_scale,
+            o2_offset: config.o2_offset,
+        })
+    }
+
+    /// Stoichiometric AFR for this frame, `None` for a flex car whose frame
+    /// lacks the ethanol content.
+    pub fn stoich(&self, d: &SpeeduinoData) -> Option<f64> {
+        match self.fuel {
+            FuelType::Flex => d
+                .get(Field::EthanolPct)
+                .map(|pct| blended_stoich(self.stoich, pct as f64)),
+            _ => Some(self.stoich),
+        }
+    }
+
+    /// Measured AFR, `None` when the O2 reading is missing or 0 (no sensor).
+    pub fn afr(&self, d: &SpeeduinoData) -> Option<f64> {
+        d.get(Field::O2Primary)
+            .filter(|&raw| raw > 0)
+            .map(|raw| raw as f64 * self.o2_scale + self.o2_offset)
+    }
+
+    pub fn lambda(&self, d: &SpeeduinoData) -> Option<f64> {
+        Some(self.afr(d)? / self.stoich(d)?)
+    }
+
+    /// Lambda the ECU is targeting.
+    pub fn target_lambda(&self, d: &SpeeduinoData) -> Option<f64> {
+        Some(Self::target(d)? / self.stoich(d)?)
+    }
+
+    /// Measured minus target AFR; positive is lean.
+    pub fn afr_error(&self, d: &SpeeduinoData) -> Option<f64> {
+        Some(self.afr(d)? - Self::target(d)?)
+    }
+
+    /// AFR target as a ratio (`afr_target` is sent × 10).
+    fn target(d: &SpeeduinoData) -> Option<f64> {
+        d.get(Field::AfrTarget).map(|t| t as f64 / 10.0)
+    }
+
+    /// (topic, published value) of the AFR channels this frame allows.
+    pub fn params(&self, d: &SpeeduinoData) -> Vec<(&'static str, String)> {
+        [
+            (AFR_TOPIC, self.afr(d), 1),
+            (LAMBDA_TOPIC, self.lambda(d), 2),
+            (AFR_ERROR_TOPIC, self.afr_error(d), 1),
+        ]
+        .into_iter()
+        .filter_map(|(topic, value, decimals)| Some((topic, format!("{:.*}", decimals, value?))))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(o2: u8, target: u8, ethanol: u8) -> SpeeduinoData {
+        SpeeduinoData {
+            o2_primary: o2,
+            afr_target: target,
+            ethanol_pct: ethanol,
+            ..SpeeduinoData::default()
+        }
+    }
+
+    #[test]
+    fn test_petrol_defaults() {
+        let wb = Wideband::default();
+        assert_eq!(
+            wb.params(&frame(132, 147, 0)),
+            vec![
+                ("AFR", "13.2".to_string()),
+                ("LAMBDA", "0.90".to_string()),
+                ("AFR_ERROR", "-1.5".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_fuel_types_and_stoich_override() {
+        let config = AppConfig {
+            fuel_type: "E85".to_string(),
+            ..AppConfig::default()
+        };
+        let e85 = Wideband::from_config(&config).unwrap();
+        assert_eq!(e85.stoich, 9.765);
+        assert!((e85.lambda(&frame(98, 98, 0)).unwrap() - 1.0036).abs() < 1e-3);
+
+        let config = AppConfig {
+            stoich_afr: Some(14.6),
+            ..AppConfig::default()
+        };
+        assert_eq!(Wideband::from_config(&config).unwrap().stoich, 14.6);
+        assert_eq!(FuelType::from_config("Gasoline"), Some(FuelType::Petrol));
+        assert_eq!(FuelType::from_config("kerosene"), None);
+
+        let config = AppConfig {
+            fuel_type: "kerosene".to_string(),
+            ..AppConfig::default()
+        };
+        assert!(Wideband::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_flex_blends_by_ethanol_content() {
+        let wb = Wideband {
+            fuel: FuelType::Flex,
+            ..Wideband::default()
+        };
+        assert_eq!(wb.stoich(&frame(147, 147, 0)), Some(14.7));
+        assert!((wb.stoich(&frame(0, 0, 85)).unwrap() - 9.855).abs() < 1e-9);
+        assert!((wb.stoich(&frame(0, 0, 100)).unwrap() - STOICH_ETHANOL).abs() < 1e-9);
+        let no_eth = SpeeduinoData {
+            missing: [Field::EthanolPct].into_iter().collect(),
+            ..frame(147, 147, 0)
+        };
+        assert_eq!(wb.lambda(&no_eth), None);
+        assert_eq!(wb.params(&no_eth).len(), 2);
+    }
+
+    #[test]
+    fn test_calibration_and_missing_sensor() {
+        let wb = Wideband {
+            o2_scale: 0.05,
+            o2_offset: 7.35,
+            ..Wideband::default()
+        };
+        assert!((wb.afr(&frame(147, 147, 0)).unwrap() - 14.7).abs() < 1e-9);
+        assert!(wb.params(&frame(0, 147, 0)).is_empty());
+    }
+}
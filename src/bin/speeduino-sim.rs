@@ -0,0 +1,140 @@
+//! # Speeduino ECU simulator
+//!
+//! Serves simulated realtime data (see [`speeduino_to_mqtt::simulator`]) on a
+//! pseudo-terminal or a TCP listener, so the bridge can be run and tested on a
+//! laptop without a car:
+//!
+//! ```text
+//! speeduino-sim --link /tmp/ttySPEEDUINO --scenario wot
+//! SPEEDUINO_PORT_NAME=/tmp/ttySPEEDUINO speeduino-to-mqtt
+//! ```
+
+use gumdrop::Options;
+use speeduino_to_mqtt::simulator::{Scenario, SessionEnd, Simulator};
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::time::sleep;
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Options)]
+struct SimOptions {
+    #[options(help = "print help message")]
+    help: bool,
+
+    #[options(
+        help = "listen for TCP clients instead of opening a PTY",
+        meta = "ADDR"
+    )]
+    tcp: Option<String>,
+
+    #[options(
+        help = "symlink the PTY to this path (stable across reconnects)",
+        meta = "PATH"
+    )]
+    link: Option<String>,
+
+    #[options(
+        help = "idle | wot | overheat | sync-loss | disconnect (default: idle)",
+        meta = "NAME",
+        default = "idle"
+    )]
+    scenario: String,
+
+    #[options(
+        help = "seconds each connection lasts in the disconnect scenario (default: 10)",
+        meta = "SECS",
+        default = "10"
+    )]
+    disconnect_after: u64,
+
+    #[options(
+        help = "seconds the ECU stays away after a disconnect (default: 3)",
+        meta = "SECS",
+        default = "3"
+    )]
+    downtime: u64,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let opts = SimOptions::parse_args_default_or_exit();
+    tracing_subscriber::fmt()
+        .with_env_filter(
+            EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+        )
+        .init();
+
+    let Some(scenario) = Scenario::from_name(&opts.scenario) else {
+        eprintln!("Unknown scenario '{}'", opts.scenario);
+        std::process::exit(2);
+    };
+    let sim =
+        Simulator::new(scenario).with_disconnect_after(Duration::from_secs(opts.disconnect_after));
+    let downtime = Duration::from_secs(opts.downtime);
+    info!("Simulating scenario '{}'", scenario.name());
+
+    match opts.tcp {
+        Some(addr) => serve_tcp(&sim, &addr, downtime).await,
+        None => serve_pty(&sim, opts.link.as_deref(), downtime).await,
+    }
+}
+
+/// Accept one client at a time.  While the ECU is "away" the listener is
+/// closed, so clients get connection refused like with a powered-off bridge.
+async fn serve_tcp(sim: &Simulator, addr: &str, downtime: Duration) -> anyhow::Result<()> {
+    loop {
+        let listener = TcpListener::bind(addr).await?;
+        info!("Listening on {}", listener.local_addr()?);
+        loop {
+            let (mut stream, peer) = listener.accept().await?;
+            stream.set_nodelay(true).ok();
+            info!("Client {} connected", peer);
+            match sim.serve(&mut stream).await {
+                Ok(SessionEnd::ClientClosed) => info!("Client {} disconnected", peer),
+                Ok(SessionEnd::Dropped) => break,
+                Err(e) => info!("Client {} lost: {}", peer, e),
+            }
+        }
+        drop(listener);
+        info!("Dropping the link for {}s", downtime.as_secs());
+        sleep(downtime).await;
+    }
+}
+
+/// Serve on a fresh pseudo-terminal; after a dropped link a new one is
+/// created, so use `--link` to give clients a stable path.
+#[cfg(unix)]
+async fn serve_pty(sim: &Simulator, link: Option<&str>, downtime: Duration) -> anyhow::Result<()> {
+    use tokio_serial::{SerialPort, SerialStream};
+
+    loop {
+        // The slave end stays open here so the master never sees a hang-up
+        // while no client is attached.
+        let (mut master, slave) = SerialStream::pair()?;
+        let path = slave.name().unwrap_or_default();
+        match link {
+            Some(link) => {
+                let _ = std::fs::remove_file(link);
+                std::os::unix::fs::symlink(&path, link)?;
+                info!("Serving on {} -> {}", link, path);
+            }
+            None => info!("Serving on {}", path),
+        }
+
+        // A broken session must not take the simulator down with it
+        if let Err(e) = sim.serve(&mut master).await {
+            warn!("Session on {} lost: {} – serving a new PTY", path, e);
+            continue;
+        }
+        drop(master);
+        drop(slave);
+        info!("Dropping the link for {}s", downtime.as_secs());
+        sleep(downtime).await;
+    }
+}
+
+#[cfg(not(unix))]
+async fn serve_pty(_: &Simulator, _: Option<&str>, _: Duration) -> anyhow::Result<()> {
+    anyhow::bail!("pseudo-terminals are only available on Unix; use --tcp ADDR")
+}
@@ -0,0 +1,370 @@
+//! CAN input naming, scaling and selective publishing.
+//!
+//! The sixteen `canin` words are raw u16 values (`CN01`–`CN16` in the channel
+//! registry).  A `[can_inputs.CNxx]` table turns one into a real channel:
+//!
+//! ```toml
+//! [can_inputs.CN03]
+//! name  = "EGT cylinder 1"
+//! topic = "EGT1"
+//! scale = 0.1
+//! unit  = "°C"
+//! ```
+//!
+//! The value becomes `raw × scale + offset`, published under `topic` with the
+//! given unit in the TUI and the `UNITS` metadata.  Units are labels only; CAN
+//! inputs are not converted by `unit_system`.  `enabled = false` silences an
+//! input, and `can_inputs_publish_unlisted = false` silences every input
+//! without a table.
+
+use crate::config::{AppConfig, CanInputConfig};
+use crate::derived::check_name;
+use crate::ecu_data_parser::SpeeduinoData;
+use crate::errors::{ConfigError, Result};
+use crate::packet_layout::CAN_INPUTS;
+use crate::units::with_symbol;
+
+/// Topic codes of the CAN inputs, in input order.
+const CAN_CODES: [&str; 16] = [
+    "CN01", "CN02", "CN03", "CN04", "CN05", "CN06", "CN07", "CN08", "CN09", "CN10", "CN11", "CN12",
+    "CN13", "CN14", "CN15", "CN16",
+];
+
+/// Most decimals inferred from a scale factor.
+const MAX_DECIMALS: usize = 4;
+
+/// Index (0–15) of a CAN input code `CN01`–`CN16` (case-insensitive).
+pub fn can_input_index(code: &str) -> Option<usize> {
+    CAN_CODES.iter().position(|c| c.eq_ignore_ascii_case(code))
+}
+
+/// Decimals needed to show `raw × scale + offset` without losing precision.
+fn decimals_for(scale: f64, offset: f64) -> usize {
+    (0..MAX_DECIMALS)
+        .find(|&n| {
+            let p = 10f64.powi(n as i32);
+            [scale, offset]
+                .iter()
+                .all(|v| ((v * p) - (v * p).round()).abs() < 1e-9)
+        })
+        .unwrap_or(MAX_DECIMALS)
+}
+
+/// One published CAN input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanInput {
+    /// Input index, 0 for `CN01`
+    pub index: usize,
+    pub name: String,
+    pub topic: String,
+    pub scale: f64,
+    pub offset: f64,
+    pub unit: String,
+    pub decimals: usize,
+}
+
+impl CanInput {
+    fn raw(index: usize) -> Self {
+        Self::configured(index, &CanInputConfig::default())
+    }
+
+    fn configured(index: usize, config: &CanInputConfig) -> Self {
+        Self {
+            index,
+            name: config
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("CAN input {}", index + 1)),
+            topic: config
+                .topic
+                .clone()
+                .unwrap_or_else(|| CAN_CODES[index].to_string()),
+            scale: config.scale,
+            offset: config.offset,
+            unit: config.unit.clone().unwrap_or_default(),
+            decimals: config
+                .decimals
+                .unwrap_or_else(|| decimals_for(config.scale, config.offset)),
+        }
+    }
+
+    /// Scaled value, `None` when the ECU did not send this input.
+    pub fn value(&self, d: &SpeeduinoData) -> Option<f64> {
+        d.get(CAN_INPUTS[self.index])
+            .map(|raw| raw as f64 * self.scale + self.offset)
+    }
+
+    pub fn format(&self, value: f64) -> String {
+        format!("{:.*}", self.decimals, value)
+    }
+
+    /// Value with its unit, for the TUI.
+    pub fn display(&self, value: f64) -> String {
+        with_symbol(self.format(value), &self.unit)
+    }
+}
+
+/// The CAN inputs that are published, in input order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CanInputs {
+    inputs: Vec<CanInput>,
+    /// At least one input has a `[can_inputs]` table
+    configured: bool,
+}
+
+impl Default for CanInputs {
+    /// All sixteen inputs, raw.
+    fn default() -> Self {
+        Self {
+            inputs: (0..CAN_CODES.len()).map(CanInput::raw).collect(),
+            configured: false,
+        }
+    }
+}
+
+impl CanInputs {
+    /// Inputs from the `[can_inputs]` tables.
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        let mut topics: Vec<&str> = Vec::new();
+        for (code, input) in &config.can_inputs {
+            let field = format!("can_inputs.{}", code);
+            if can_input_index(code).is_none() {
+                return Err(ConfigError::InvalidValue {
+                    field,
+                    message: "CAN inputs are CN01 to CN16".to_string(),
+                }
+                .into());
+            }
+            if !input.scale.is_finite() || !input.offset.is_finite() {
+                return Err(ConfigError::InvalidValue {
+                    field,
+                    message: "scale and offset must be finite numbers".to_string(),
+                }
+                .into());
+            }
+            if let Some(topic) = &input.topic {
+                // Keeping the input's own code is fine; anything else must be free
+                if !topic.eq_ignore_ascii_case(code)
+                    && let Err(message) = check_name(topic)
+                {
+                    return Err(ConfigError::InvalidValue { field, message }.into());
+                }
+                if topics.iter().any(|t| t.eq_ignore_ascii_case(topic))
+                    || config.derived.keys().any(|n| n.eq_ignore_ascii_case(topic))
+                {
+                    return Err(ConfigError::InvalidValue {
+                        field,
+                        message: format!("topic \"{}\" is used twice", topic),
+                    }
+                    .into());
+                }
+                topics.push(topic);
+            }
+        }
+
+        let settings = |index: usize| {
+            config
+                .can_inputs
+                .iter()
+                .find(|(code, _)| can_input_index(code) == Some(index))
+                .map(|(_, input)| input)
+        };
+        let inputs = (0..CAN_CODES.len())
+            .filter_map(|index| match settings(index) {
+                Some(input) if input.enabled => Some(CanInput::configured(index, input)),
+                Some(_) => None,
+                None if config.can_inputs_publish_unlisted => Some(CanInput::raw(index)),
+                None => None,
+            })
+            .collect();
+        Ok(Self {
+            inputs,
+            configured: !config.can_inputs.is_empty(),
+        })
+    }
+
+    /// True when the settings name, scale or silence any input.
+    pub fn is_configured(&self) -> bool {
+        self.configured
+    }
+
+    /// The published inputs, in input order.
+    pub fn inputs(&self) -> &[CanInput] {
+        &self.inputs
+    }
+
+    /// (topic, published value) of every input the ECU sent.
+    pub fn params(&self, d: &SpeeduinoData) -> Vec<(&str, String)> {
+        self.inputs
+            .iter()
+            .filter_map(|input| Some((input.topic.as_str(), input.format(input.value(d)?))))
+            .collect()
+    }
+
+    /// (topic, value with unit) of every input the ECU sent, for the TUI.
+    pub fn display(&self, d: &SpeeduinoData) -> Vec<(&str, String)> {
+        self.inputs
+            .iter()
+            .filter_map(|input| Some((input.topic.as_str(), input.display(input.value(d)?))))
+            .collect()
+    }
+
+    /// Replace the raw `CNxx` entries of `params` by the published inputs,
+    /// keeping their position.
+    pub fn apply<'a>(
+        &'a self,
+        params: Vec<(&'static str, String)>,
+        d: &SpeeduinoData,
+    ) -> Vec<(&'a str, String)> {
+        let mut out: Vec<(&'a str, String)> = Vec::with_capacity(params.len());
+        let mut inserted = false;
+        for (code, value) in params {
+            if can_input_index(code).is_none() {
+                out.push((code, value));
+            } else if !inserted {
+                out.extend(self.params(d));
+                inserted = true;
+            }
+        }
+        out
+    }
+
+    /// (topic, unit) of every input with a unit, for the `UNITS` metadata.
+    pub fn metadata(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.inputs
+            .iter()
+            .filter(|input| !input.unit.is_empty())
+            .map(|input| (input.topic.as_str(), input.unit.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn frame() -> SpeeduinoData {
+        let mut canin = [0u16; 16];
+        canin[0] = 7;
+        canin[2] = 4567;
+        canin[3] = 1;
+        SpeeduinoData {
+            canin,
+            ..SpeeduinoData::default()
+        }
+    }
+
+    fn egt_config(publish_unlisted: bool) -> AppConfig {
+        AppConfig {
+            can_inputs: HashMap::from([
+                (
+                    "cn03".to_string(),
+                    CanInputConfig {
+                        name: Some("EGT 1".to_string()),
+                        topic: Some("EGT1".to_string()),
+                        scale: 0.1,
+                        unit: Some("°C".to_string()),
+                        ..CanInputConfig::default()
+                    },
+                ),
+                (
+                    "CN04".to_string(),
+                    CanInputConfig {
+                        enabled: false,
+                        ..CanInputConfig::default()
+                    },
+                ),
+            ]),
+            can_inputs_publish_unlisted: publish_unlisted,
+            ..AppConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_input_index() {
+        assert_eq!(can_input_index("CN01"), Some(0));
+        assert_eq!(can_input_index("cn16"), Some(15));
+        assert_eq!(can_input_index("CN17"), None);
+        assert_eq!(can_input_index("CN1"), None);
+    }
+
+    #[test]
+    fn test_decimals_follow_scale() {
+        assert_eq!(decimals_for(1.0, 0.0), 0);
+        assert_eq!(decimals_for(0.1, 0.0), 1);
+        assert_eq!(decimals_for(1.0, -0.25), 2);
+        assert_eq!(decimals_for(1.0 / 3.0, 0.0), MAX_DECIMALS);
+    }
+
+    #[test]
+    fn test_default_publishes_all_raw() {
+        let can = CanInputs::default();
+        let params = can.params(&frame());
+        assert_eq!(params.len(), 16);
+        assert_eq!(params[0], ("CN01", "7".to_string()));
+        assert_eq!(params[2], ("CN03", "4567".to_string()));
+    }
+
+    #[test]
+    fn test_named_scaled_and_disabled() {
+        let can = CanInputs::from_config(&egt_config(true)).unwrap();
+        let params = can.params(&frame());
+        assert_eq!(params.len(), 15);
+        assert!(params.contains(&("EGT1", "456.7".to_string())));
+        assert!(!params.iter().any(|(t, _)| *t == "CN03" || *t == "CN04"));
+        assert_eq!(can.display(&frame())[2], ("EGT1", "456.7°C".to_string()));
+        assert_eq!(can.metadata().collect::<Vec<_>>(), vec![("EGT1", "°C")]);
+    }
+
+    #[test]
+    fn test_unlisted_inputs_can_be_silenced() {
+        let can = CanInputs::from_config(&egt_config(false)).unwrap();
+        assert_eq!(can.params(&frame()), vec![("EGT1", "456.7".to_string())]);
+    }
+
+    #[test]
+    fn test_apply_keeps_position_and_native_params() {
+        let can = CanInputs::from_config(&egt_config(false)).unwrap();
+        let params = vec![
+            ("RPM", "3000".to_string()),
+            ("CN01", "7".to_string()),
+            ("CN03", "4567".to_string()),
+            ("STA", "0".to_string()),
+        ];
+        assert_eq!(
+            can.apply(params, &frame()),
+            vec![
+                ("RPM", "3000".to_string()),
+                ("EGT1", "456.7".to_string()),
+                ("STA", "0".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_rejects_unknown_input_and_taken_topic() {
+        let mut config = egt_config(true);
+        config
+            .can_inputs
+            .insert("CN17".to_string(), CanInputConfig::default());
+        assert!(CanInputs::from_config(&config).is_err());
+
+        let mut config = egt_config(true);
+        config.derived.insert("egt1".to_string(), "RPM".to_string());
+        assert!(CanInputs::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_missing_inputs_are_not_published() {
+        let d = SpeeduinoData {
+            missing: CAN_INPUTS.into_iter().collect(),
+            ..frame()
+        };
+        assert!(
+            CanInputs::from_config(&egt_config(true))
+                .unwrap()
+                .params(&d)
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,380 @@
+//! Raw packet capture files.
+//!
+//! A capture holds the realtime responses exactly as `read_engine_data` returned
+//! them, so a replay can push them through the parser unchanged.
+//!
+//! | Part   | Layout |
+//! |--------|--------|
+//! | header | magic `SPDCAP01` (8 bytes) |
+//! | record | timestamp (u64 LE, µs since the Unix epoch) ‖ length (u16 LE) ‖ bytes |
+//!
+//! Records follow one another until the end of the file; a file that ends in
+//! the middle of a record is reported as truncated.
+//!
+//! [`CaptureWriter`] records into a directory, starting a new file whenever the
+//! current one exceeds its size or age limit and deleting the oldest files
+//! beyond the retention limit.
+
+use crate::errors::{Result, SerialError};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+/// Magic bytes at the start of every capture file.
+pub const CAPTURE_MAGIC: &[u8; 8] = b"SPDCAP01";
+/// Size of the per-record header (timestamp + length).
+pub const RECORD_HEADER_BYTES: usize = 10;
+
+/// How often buffered records are written out; a crash loses at most this much.
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// One captured ECU response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptureRecord {
+    /// Time the response was read, in microseconds since the Unix epoch
+    pub timestamp_us: u64,
+    /// Raw response bytes
+    pub data: Vec<u8>,
+}
+
+/// Sequential reader over a capture file.
+pub struct CaptureReader<R> {
+    inner: R,
+}
+
+impl CaptureReader<BufReader<File>> {
+    /// Open a capture file and check its header.
+    pub fn open(path: &str) -> Result<Self> {
+        let file = File::open(path).map_err(|e| SerialError::CaptureOpenFailed {
+            path: path.to_string(),
+            source: e,
+        })?;
+        Self::new(BufReader::new(file))
+    }
+}
+
+impl<R: Read> CaptureReader<R> {
+    /// Wrap a reader positioned at the start of a capture.
+    pub fn new(mut inner: R) -> Result<Self> {
+        let mut magic = [0u8; CAPTURE_MAGIC.len()];
+        let n = read_full(&mut inner, &mut magic).map_err(SerialError::ReadFailed)?;
+        if n < magic.len() || &magic != CAPTURE_MAGIC {
+            return Err(SerialError::InvalidCapture("missing SPDCAP01 header".to_string()).into());
+        }
+        Ok(Self { inner })
+    }
+
+    /// Next record, or `None` at the end of the capture.
+    pub fn next_record(&mut self) -> Result<Option<CaptureRecord>> {
+        let mut header = [0u8; RECORD_HEADER_BYTES];
+        match read_full(&mut self.inner, &mut header).map_err(SerialError::ReadFailed)? {
+            0 => return Ok(None),
+            RECORD_HEADER_BYTES => {}
+            n => {
+                return Err(SerialError::InvalidCapture(format!(
+                    "truncated record header ({} of {} bytes)",
+                    n, RECORD_HEADER_BYTES
+                ))
+                .into());
+            }
+        }
+
+        let (ts, len) = header.split_at(8);
+        let timestamp_us = u64::from_le_bytes(ts.try_into().unwrap());
+        let length = u16::from_le_bytes(len.try_into().unwrap()) as usize;
+
+        let mut data = vec![0u8; length];
+        let n = read_full(&mut self.inner, &mut data).map_err(SerialError::ReadFailed)?;
+        if n < length {
+            return Err(SerialError::InvalidCapture(format!(
+                "truncated record ({} of {} bytes)",
+                n, length
+            ))
+            .into());
+        }
+        Ok(Some(CaptureRecord { timestamp_us, data }))
+    }
+}
+
+/// Records raw responses into a directory of rotating capture files.
+pub struct CaptureWriter {
+    dir: PathBuf,
+    max_bytes: u64,
+    max_age: Option<Duration>,
+    /// Capture files kept in `dir`, the current one included (`None` = all)
+    max_files: Option<usize>,
+    file: Option<BufWriter<File>>,
+    /// Bytes written to the current file
+    written: u64,
+    opened: Instant,
+    flushed: Instant,
+    /// Files started so far (keeps names unique within one second)
+    files: u32,
+}
+
+impl CaptureWriter {
+    /// Record into `dir`, rotating after `max_bytes` or, if set, `max_age`,
+    /// and keeping at most `max_files` capture files there.
+    pub fn new(
+        dir: &Path,
+        max_bytes: u64,
+        max_age: Option<Duration>,
+        max_files: Option<usize>,
+    ) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            max_bytes,
+            max_age,
+            max_files,
+            file: None,
+            written: 0,
+            opened: Instant::now(),
+            flushed: Instant::now(),
+            files: 0,
+        })
+    }
+
+    /// Append one response.  Records are buffered and written out every
+    /// [`FLUSH_INTERVAL`], on rotation and when the writer is dropped.
+    pub fn write(&mut self, timestamp_us: u64, data: &[u8]) -> Result<()> {
+        let record_len = (RECORD_HEADER_BYTES + data.len()) as u64;
+        // A file always takes at least one record, however small the limit.
+        let has_records = self.written > CAPTURE_MAGIC.len() as u64;
+        let full = has_records && self.written + record_len > self.max_bytes;
+        let expired = self.max_age.is_some_and(|age| self.opened.elapsed() >= age);
+        if self.file.is_none() || full || expired {
+            self.rotate()?;
+        }
+
+        let Some(file) = self.file.as_mut() else {
+            return Ok(());
+        };
+        file.write_all(&timestamp_us.to_le_bytes())?;
+        file.write_all(&(data.len() as u16).to_le_bytes())?;
+        file.write_all(data)?;
+        if self.flushed.elapsed() >= FLUSH_INTERVAL {
+            file.flush()?;
+            self.flushed = Instant::now();
+        }
+        self.written += record_len;
+        Ok(())
+    }
+
+    /// Close the current file and start the next one.
+    fn rotate(&mut self) -> Result<()> {
+        if let Some(mut old) = self.file.take() {
+            old.flush()?;
+        }
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let path = self
+            .dir
+            .join(format!("speeduino-{}-{:04}.cap", secs, self.files));
+        let mut file = BufWriter::new(File::create(&path)?);
+        file.write_all(CAPTURE_MAGIC)?;
+        info!("Recording ECU capture to {}", path.display());
+
+        self.file = Some(file);
+        self.written = CAPTURE_MAGIC.len() as u64;
+        self.opened = Instant::now();
+        self.flushed = self.opened;
+        self.files += 1;
+        self.prune()
+    }
+
+    /// Delete the oldest capture files beyond `max_files`.  Names sort by
+    /// start time, so the oldest come first.
+    fn prune(&self) -> Result<()> {
+        let Some(max_files) = self.max_files else {
+            return Ok(());
+        };
+        let mut captures: Vec<PathBuf> = fs::read_dir(&self.dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|name| name.to_str())
+                    .is_some_and(|name| name.starts_with("speeduino-") && name.ends_with(".cap"))
+            })
+            .collect();
+        captures.sort();
+        let excess = captures.len().saturating_sub(max_files);
+        for path in &captures[..excess] {
+            fs::remove_file(path)?;
+            info!("Deleted old ECU capture {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+/// Current time in microseconds since the Unix epoch (capture timestamps).
+pub fn timestamp_us() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_micros() as u64
+}
+
+/// Fill `buf` as far as the reader allows; returns the number of bytes read
+/// (short only at end of file).
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::errors::AppError;
+
+    /// Serialise records into capture-file bytes.
+    pub(crate) fn capture_bytes(records: &[(u64, &[u8])]) -> Vec<u8> {
+        let mut out = CAPTURE_MAGIC.to_vec();
+        for (ts, data) in records {
+            out.extend_from_slice(&ts.to_le_bytes());
+            out.extend_from_slice(&(data.len() as u16).to_le_bytes());
+            out.extend_from_slice(data);
+        }
+        out
+    }
+
+    #[test]
+    fn test_reads_records_in_order() {
+        let bytes = capture_bytes(&[(1_000, &[1, 2, 3]), (21_000, &[4, 5])]);
+        let mut reader = CaptureReader::new(bytes.as_slice()).unwrap();
+
+        let first = reader.next_record().unwrap().unwrap();
+        assert_eq!(first.timestamp_us, 1_000);
+        assert_eq!(first.data, vec![1, 2, 3]);
+        let second = reader.next_record().unwrap().unwrap();
+        assert_eq!(second.timestamp_us, 21_000);
+        assert_eq!(second.data, vec![4, 5]);
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rejects_foreign_file() {
+        let err = CaptureReader::new(&b"timestamp,rpm\n"[..]).err().unwrap();
+        assert!(matches!(
+            err,
+            AppError::Serial(SerialError::InvalidCapture(_))
+        ));
+        assert!(CaptureReader::new(&b""[..]).is_err());
+    }
+
+    fn capture_files(dir: &Path) -> Vec<PathBuf> {
+        let mut files: Vec<PathBuf> = fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        files.sort();
+        files
+    }
+
+    fn read_all(path: &Path) -> Vec<CaptureRecord> {
+        let mut reader = CaptureReader::open(path.to_str().unwrap()).unwrap();
+        std::iter::from_fn(|| reader.next_record().unwrap()).collect()
+    }
+
+    #[test]
+    fn test_writer_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer = CaptureWriter::new(dir.path(), 1 << 20, None, None).unwrap();
+        writer.write(1_000, &[1, 2, 3]).unwrap();
+        writer.write(21_000, &[4, 5]).unwrap();
+        drop(writer);
+
+        let files = capture_files(dir.path());
+        assert_eq!(files.len(), 1);
+        let records = read_all(&files[0]);
+        assert_eq!(
+            records,
+            vec![
+                CaptureRecord {
+                    timestamp_us: 1_000,
+                    data: vec![1, 2, 3]
+                },
+                CaptureRecord {
+                    timestamp_us: 21_000,
+                    data: vec![4, 5]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_writer_rotates_by_size() {
+        let dir = tempfile::tempdir().unwrap();
+        // Header plus two 138-byte records fit; the third starts a new file.
+        let max = (CAPTURE_MAGIC.len() + 2 * (RECORD_HEADER_BYTES + 138)) as u64;
+        let mut writer = CaptureWriter::new(dir.path(), max, None, None).unwrap();
+        for ts in 0..5 {
+            writer.write(ts, &[0u8; 138]).unwrap();
+        }
+        drop(writer);
+
+        let files = capture_files(dir.path());
+        assert_eq!(files.len(), 3);
+        let counts: Vec<usize> = files.iter().map(|f| read_all(f).len()).collect();
+        assert_eq!(counts, vec![2, 2, 1]);
+        for file in &files {
+            assert!(fs::metadata(file).unwrap().len() <= max);
+        }
+    }
+
+    #[test]
+    fn test_writer_rotates_by_age() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut writer =
+            CaptureWriter::new(dir.path(), 1 << 20, Some(Duration::ZERO), None).unwrap();
+        writer.write(0, &[1]).unwrap();
+        writer.write(1, &[2]).unwrap();
+        assert_eq!(capture_files(dir.path()).len(), 2);
+    }
+
+    #[test]
+    fn test_writer_keeps_newest_files() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("notes.txt"), "not a capture").unwrap();
+        let mut writer =
+            CaptureWriter::new(dir.path(), 1 << 20, Some(Duration::ZERO), Some(2)).unwrap();
+        for ts in 0..5 {
+            writer.write(ts, &[ts as u8]).unwrap();
+        }
+        drop(writer);
+
+        let files = capture_files(dir.path());
+        assert_eq!(files.len(), 3, "two captures plus the unrelated file");
+        let kept: Vec<u64> = files
+            .iter()
+            .filter(|f| f.extension().is_some_and(|e| e == "cap"))
+            .map(|f| read_all(f)[0].timestamp_us)
+            .collect();
+        assert_eq!(kept, vec![3, 4]);
+    }
+
+    #[test]
+    fn test_truncated_record_is_an_error() {
+        let mut bytes = capture_bytes(&[(1_000, &[1, 2, 3])]);
+        bytes.pop();
+        let mut reader = CaptureReader::new(bytes.as_slice()).unwrap();
+        assert!(reader.next_record().is_err());
+
+        let mut bytes = capture_bytes(&[]);
+        bytes.extend_from_slice(&[0, 0, 0]);
+        let mut reader = CaptureReader::new(bytes.as_slice()).unwrap();
+        assert!(reader.next_record().is_err());
+    }
+}
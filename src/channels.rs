@@ -0,0 +1,552 @@
+//! Channel registry.
+//!
+//! Every numeric value the bridge publishes is described once in [`CHANNELS`]:
+//! its topic code, a human-readable name, the metric unit, the packet field it
+//! comes from, the scaling that turns the stored value into that unit, the
+//! number of decimals published, the plausible range and a category.
+//!
+//! The MQTT publisher, the TUI, range checks and the units metadata are all
+//! driven from this table, so a new channel only needs a new entry here (plus
+//! the packet field in [`packet_layout`](crate::packet_layout)).
+//!
+//! Values are derived from [`SpeeduinoData`] as `stored × scale + offset`,
+//! where "stored" is the struct field (already scaled by the packet layout).
+//! Quantities with a unit system (temperature, pressure, speed) are metric here
+//! and converted by [`Units`](crate::units::Units).
+//!
+//! Status flag topics (`ENG/cranking`, …) and `NER/error` are not numbers and
+//! live in [`status_flags`](crate::status_flags).
+
+use crate::ecu_data_parser::SpeeduinoData;
+use crate::packet_layout::{CAN_INPUTS, Field};
+
+/// Group a channel is documented and displayed under.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Engine,
+    Temperature,
+    Afr,
+    Fuel,
+    Ignition,
+    Correction,
+    Flex,
+    Boost,
+    Vvt,
+    Vehicle,
+    System,
+    Can,
+    /// Raw status bitfields (decoded into flags by `status_flags`)
+    Status,
+    /// Raw bytes kept for backward compatibility
+    Raw,
+}
+
+/// Categories in display order.
+pub const CATEGORIES: [Category; 14] = [
+    Category::Engine,
+    Category::Temperature,
+    Category::Afr,
+    Category::Fuel,
+    Category::Ignition,
+    Category::Correction,
+    Category::Flex,
+    Category::Boost,
+    Category::Vvt,
+    Category::Vehicle,
+    Category::System,
+    Category::Can,
+    Category::Status,
+    Category::Raw,
+];
+
+impl Category {
+    /// Parse a group name such as `"temperature"` (case-insensitive).
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "engine" => Some(Category::Engine),
+            "temperature" | "temperatures" => Some(Category::Temperature),
+            "o2" => Some(Category::Afr),
+            "fuel" => Some(Category::Fuel),
+            "ignition" => Some(Category::Ignition),
+            "correction" | "corrections" => Some(Category::Correction),
+            "flex" => Some(Category::Flex),
+            "boost" => Some(Category::Boost),
+            "vvt" => Some(Category::Vvt),
+            "vehicle" => Some(Category::Vehicle),
+            "system" => Some(Category::System),
+            "can" => Some(Category::Can),
+            "status" => Some(Category::Status),
+            "raw" => Some(Category::Raw),
+            _ => None,
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Category::Engine => "Engine",
+            Category::Temperature => "Temperatures",
+            Category::Afr => "O2 / AFR",
+            Category::Fuel => "Fuel & injection",
+            Category::Ignition => "Ignition",
+            Category::Correction => "Corrections",
+            Category::Flex => "Flex fuel",
+            Category::Boost => "Boost",
+            Category::Vvt => "VVT",
+            Category::Vehicle => "Vehicle",
+            Category::System => "System",
+            Category::Can => "CAN inputs",
+            Category::Status => "Status bytes",
+            Category::Raw => "Raw bytes",
+        }
+    }
+}
+
+/// One published channel.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Channel {
+    /// Topic code, e.g. `RPM`
+    pub code: &'static str,
+    pub name: &'static str,
+    /// Metric unit symbol, empty for dimensionless values
+    pub unit: &'static str,
+    pub field: Field,
+    /// Multiplier applied to the stored value
+    pub scale: f64,
+    /// Added after scaling (e.g. −40 for temperatures)
+    pub offset: f64,
+    /// Decimal places published
+    pub decimals: usize,
+    /// Lowest plausible value (after scaling)
+    pub min: f64,
+    /// Highest plausible value (after scaling)
+    pub max: f64,
+    pub category: Category,
+}
+
+impl Channel {
+    const fn scaled(self, scale: f64, offset: f64, decimals: usize) -> Self {
+        Self {
+            scale,
+            offset,
+            decimals,
+            ..self
+        }
+    }
+
+    const fn range(self, min: f64, max: f64) -> Self {
+        Self { min, max, ..self }
+    }
+
+    /// Range of an unsigned 16-bit field.
+    const fn wide(self) -> Self {
+        self.range(0.0, 65_535.0)
+    }
+
+    /// Milliseconds from a 16-bit field stored in 0.1 ms units.
+    const fn ms10(self) -> Self {
+        self.scaled(0.1, 0.0, 1).range(0.0, 6_553.5)
+    }
+
+    /// Value in the channel's metric unit, `None` when the ECU did not send it.
+    pub fn value(&self, d: &SpeeduinoData) -> Option<f64> {
+        // Round away binary noise such as 142 × 0.1 = 14.200000000000001
+        let precision = 10f64.powi(self.decimals as i32);
+        d.get(self.field).map(|v| {
+            let value = v as f64 * self.scale + self.offset;
+            (value * precision).round() / precision
+        })
+    }
+
+    /// True when `value` lies within the plausible range.
+    pub fn in_range(&self, value: f64) -> bool {
+        (self.min..=self.max).contains(&value)
+    }
+}
+
+use Category::*;
+
+/// Unscaled channel over a byte-wide range (0–255).
+const fn ch(
+    code: &'static str,
+    name: &'static str,
+    unit: &'static str,
+    field: Field,
+    category: Category,
+) -> Channel {
+    Channel {
+        code,
+        name,
+        unit,
+        field,
+        scale: 1.0,
+        offset: 0.0,
+        decimals: 0,
+        min: 0.0,
+        max: 255.0,
+        category,
+    }
+}
+
+/// Every numeric channel, in publishing order.
+pub const CHANNELS: &[Channel] = &[
+    // Engine
+    ch("RPM", "Engine speed", "rpm", Field::Rpm, Engine).range(0.0, 15_000.0),
+    ch("TPS", "Throttle position", "%", Field::Tps, Engine).range(0.0, 100.0),
+    ch("MAP", "Manifold pressure", "kPa", Field::Map, Engine).range(0.0, 400.0),
+    ch("BAR", "Barometric pressure", "kPa", Field::Baro, Engine),
+    ch(
+        "EMP",
+        "Exhaust manifold pressure",
+        "kPa",
+        Field::Emap,
+        Engine,
+    )
+    .wide(),
+    ch("BAT", "Battery voltage", "V", Field::Battery10, Engine)
+        .scaled(0.1, 0.0, 1)
+        .range(8.0, 18.0),
+    ch("RPD", "RPM rate of change", "rpm/s", Field::RpmDot, Engine).range(-32_768.0, 32_767.0),
+    ch("TPD", "TPS rate of change", "", Field::TpsDot, Engine).wide(),
+    ch("MPD", "MAP rate of change", "", Field::MapDot, Engine).wide(),
+    ch("TAD", "TPS ADC", "", Field::TpsAdc, Engine),
+    ch("ILL", "Idle load", "", Field::IdleLoad, Engine),
+    ch(
+        "CIT",
+        "Closed-loop idle target",
+        "",
+        Field::ClIdleTarget,
+        Engine,
+    ),
+    ch(
+        "SYN",
+        "Sync loss counter",
+        "",
+        Field::SyncLossCounter,
+        Engine,
+    ),
+    // Temperatures
+    ch(
+        "IAT",
+        "Intake air temperature",
+        "°C",
+        Field::IatRaw,
+        Temperature,
+    )
+    .scaled(1.0, -40.0, 0)
+    .range(-40.0, 200.0),
+    ch(
+        "CLT",
+        "Coolant temperature",
+        "°C",
+        Field::CoolantRaw,
+        Temperature,
+    )
+    .scaled(1.0, -40.0, 0)
+    .range(-40.0, 200.0),
+    ch(
+        "FTP",
+        "Fuel temperature",
+        "°C",
+        Field::FuelTempRaw,
+        Temperature,
+    )
+    .scaled(1.0, -40.0, 0)
+    .range(-40.0, 200.0),
+    // O2 / AFR
+    ch("O2P", "Primary O2 sensor", "", Field::O2Primary, Afr),
+    ch("O2S", "Secondary O2 sensor", "", Field::O2Secondary, Afr),
+    ch("AFT", "AFR target", "", Field::AfrTarget, Afr)
+        .scaled(0.1, 0.0, 1)
+        .range(0.0, 25.5),
+    // Fuel & injection
+    ch("VE1", "VE table 1", "%", Field::Ve1, Fuel),
+    ch("VE2", "VE table 2", "%", Field::Ve2, Fuel),
+    ch("VEC", "Current VE", "%", Field::VeCurrent, Fuel),
+    ch("PW1", "Injector 1 pulse width", "ms", Field::Pw1, Fuel).ms10(),
+    ch("PW2", "Injector 2 pulse width", "ms", Field::Pw2, Fuel).ms10(),
+    ch("PW3", "Injector 3 pulse width", "ms", Field::Pw3, Fuel).ms10(),
+    ch("PW4", "Injector 4 pulse width", "ms", Field::Pw4, Fuel).ms10(),
+    ch("PW5", "Injector 5 pulse width", "ms", Field::Pw5, Fuel).ms10(),
+    ch("PW6", "Injector 6 pulse width", "ms", Field::Pw6, Fuel).ms10(),
+    ch("PW7", "Injector 7 pulse width", "ms", Field::Pw7, Fuel).ms10(),
+    ch("PW8", "Injector 8 pulse width", "ms", Field::Pw8, Fuel).ms10(),
+    ch("FLD", "Fuel load", "", Field::FuelLoad, Fuel).wide(),
+    ch("WMI", "Water-methanol pulse width", "", Field::WmiPw, Fuel),
+    // Ignition
+    ch("ADV", "Ignition advance", "°", Field::Advance, Ignition),
+    ch("AD1", "Advance table 1", "°", Field::Advance1, Ignition),
+    ch("AD2", "Advance table 2", "°", Field::Advance2, Ignition),
+    ch("DWL", "Dwell", "ms", Field::Dwell, Ignition).ms10(),
+    ch("ADW", "Measured dwell", "ms", Field::ActualDwell, Ignition).ms10(),
+    ch("IGD", "Ignition load", "", Field::IgnLoad, Ignition).wide(),
+    ch("KNC", "Knock count", "", Field::KnockCount, Ignition),
+    ch("KNR", "Knock retard", "°", Field::KnockRetard, Ignition),
+    // Corrections
+    ch(
+        "COR",
+        "Total corrections",
+        "%",
+        Field::Corrections,
+        Correction,
+    )
+    .wide(),
+    ch(
+        "BTC",
+        "Battery correction",
+        "%",
+        Field::BatCorrection,
+        Correction,
+    ),
+    ch(
+        "EGC",
+        "EGO correction",
+        "%",
+        Field::EgoCorrection,
+        Correction,
+    ),
+    ch(
+        "ITC",
+        "IAT correction",
+        "%",
+        Field::IatCorrection,
+        Correction,
+    ),
+    ch(
+        "WEC",
+        "Warm-up enrichment",
+        "%",
+        Field::WueCorrection,
+        Correction,
+    ),
+    ch(
+        "BRC",
+        "Baro correction",
+        "%",
+        Field::BaroCorrection,
+        Correction,
+    ),
+    ch(
+        "ASE",
+        "After-start enrichment",
+        "%",
+        Field::AseValue,
+        Correction,
+    ),
+    ch(
+        "TAE",
+        "Acceleration enrichment",
+        "%",
+        Field::TaeAmount,
+        Correction,
+    )
+    .scaled(2.0, 0.0, 0)
+    .range(0.0, 510.0),
+    ch(
+        "FTC",
+        "Fuel temperature correction",
+        "%",
+        Field::FuelTempCorrection,
+        Correction,
+    ),
+    // Flex fuel
+    ch("ETH", "Ethanol content", "%", Field::EthanolPct, Flex).range(0.0, 100.0),
+    ch(
+        "FLC",
+        "Flex fuel correction",
+        "%",
+        Field::FlexCorrection,
+        Flex,
+    ),
+    ch(
+        "FIC",
+        "Flex ignition correction",
+        "°",
+        Field::FlexIgnCorrection,
+        Flex,
+    ),
+    ch(
+        "FBC",
+        "Flex boost correction",
+        "",
+        Field::FlexBoostCorrection,
+        Flex,
+    )
+    .wide(),
+    // Boost
+    ch("BST", "Boost target", "kPa", Field::BoostTarget, Boost)
+        .scaled(2.0, 0.0, 0)
+        .range(0.0, 510.0),
+    ch("BSD", "Boost duty", "%", Field::BoostDuty, Boost)
+        .scaled(100.0, 0.0, 0)
+        .range(0.0, 25_500.0),
+    // VVT
+    ch("VA1", "VVT1 cam angle", "°", Field::Vvt1Angle, Vvt).range(-32_768.0, 32_767.0),
+    ch("VT1", "VVT1 target angle", "°", Field::Vvt1TargetAngle, Vvt),
+    ch("VD1", "VVT1 duty", "%", Field::Vvt1Duty, Vvt),
+    ch("VA2", "VVT2 cam angle", "°", Field::Vvt2Angle, Vvt).range(-32_768.0, 32_767.0),
+    ch("VT2", "VVT2 target angle", "°", Field::Vvt2TargetAngle, Vvt),
+    ch("VD2", "VVT2 duty", "%", Field::Vvt2Duty, Vvt),
+    // Vehicle
+    ch("VSS", "Vehicle speed", "km/h", Field::Vss, Vehicle).range(0.0, 400.0),
+    ch("GER", "Gear", "", Field::Gear, Vehicle),
+    ch("FPR", "Fuel pressure", "kPa", Field::FuelPressure, Vehicle),
+    ch("OPR", "Oil pressure", "kPa", Field::OilPressure, Vehicle),
+    ch("FAN", "Fan duty", "%", Field::FanDuty, Vehicle).range(0.0, 100.0),
+    // System
+    ch("SCL", "Seconds counter", "s", Field::Secl, System),
+    ch("LPS", "Loops per second", "", Field::LoopsPerSecond, System).wide(),
+    ch("FRM", "Free RAM", "B", Field::FreeRam, System).wide(),
+    ch(
+        "NER",
+        "Next error (slot and code)",
+        "",
+        Field::NextError,
+        System,
+    ),
+    ch("SDS", "SD card status", "", Field::TsSdStatus, System),
+    // CAN inputs
+    ch("CN01", "CAN input 1", "", CAN_INPUTS[0], Can).wide(),
+    ch("CN02", "CAN input 2", "", CAN_INPUTS[1], Can).wide(),
+    ch("CN03", "CAN input 3", "", CAN_INPUTS[2], Can).wide(),
+    ch("CN04", "CAN input 4", "", CAN_INPUTS[3], Can).wide(),
+    ch("CN05", "CAN input 5", "", CAN_INPUTS[4], Can).wide(),
+    ch("CN06", "CAN input 6", "", CAN_INPUTS[5], Can).wide(),
+    ch("CN07", "CAN input 7", "", CAN_INPUTS[6], Can).wide(),
+    ch("CN08", "CAN input 8", "", CAN_INPUTS[7], Can).wide(),
+    ch("CN09", "CAN input 9", "", CAN_INPUTS[8], Can).wide(),
+    ch("CN10", "CAN input 10", "", CAN_INPUTS[9], Can).wide(),
+    ch("CN11", "CAN input 11", "", CAN_INPUTS[10], Can).wide(),
+    ch("CN12", "CAN input 12", "", CAN_INPUTS[11], Can).wide(),
+    ch("CN13", "CAN input 13", "", CAN_INPUTS[12], Can).wide(),
+    ch("CN14", "CAN input 14", "", CAN_INPUTS[13], Can).wide(),
+    ch("CN15", "CAN input 15", "", CAN_INPUTS[14], Can).wide(),
+    ch("CN16", "CAN input 16", "", CAN_INPUTS[15], Can).wide(),
+    // Status bytes
+    ch("STA", "Status 1", "", Field::Status1, Status),
+    ch("ENG", "Engine status", "", Field::Engine, Status),
+    ch("SPK", "Spark status", "", Field::Spark, Status),
+    ch("ST3", "Status 3", "", Field::Status3, Status),
+    ch("ST4", "Status 4", "", Field::Status4, Status),
+    ch("ST5", "Status 5", "", Field::Status5, Status),
+    ch(
+        "EPS",
+        "Engine protection status",
+        "",
+        Field::EngineProtectStatus,
+        Status,
+    ),
+    ch(
+        "ACS",
+        "Air conditioning status",
+        "",
+        Field::AirConStatus,
+        Status,
+    ),
+    ch("OUT", "Outputs status", "", Field::OutputsStatus, Status),
+    ch("TOF", "Test outputs", "", Field::TestOutputs, Status),
+    // Raw bytes
+    ch("MAT", "IAT raw byte", "", Field::IatRaw, Raw),
+    ch("CAD", "Coolant raw byte", "", Field::CoolantRaw, Raw),
+];
+
+/// Registry entry for a topic code (case-insensitive).
+pub fn channel(code: &str) -> Option<&'static Channel> {
+    CHANNELS.iter().find(|c| c.code.eq_ignore_ascii_case(code))
+}
+
+/// Channels of `category`, in registry order.
+pub fn channels_in(category: Category) -> impl Iterator<Item = &'static Channel> {
+    CHANNELS.iter().filter(move |c| c.category == category)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_codes_are_unique() {
+        let mut seen = HashSet::new();
+        for c in CHANNELS {
+            assert!(seen.insert(c.code), "duplicate code {}", c.code);
+        }
+    }
+
+    #[test]
+    fn test_every_category_is_listed() {
+        for c in CHANNELS {
+            assert!(CATEGORIES.contains(&c.category), "{}", c.code);
+        }
+    }
+
+    #[test]
+    fn test_category_names() {
+        assert_eq!(
+            Category::from_config("Temperature"),
+            Some(Category::Temperature)
+        );
+        assert_eq!(Category::from_config("o2"), Some(Category::Afr));
+        // Group names never shadow a topic code
+        assert_eq!(Category::from_config("AFR"), None);
+        for c in CHANNELS {
+            assert_eq!(Category::from_config(c.code), None, "{}", c.code);
+        }
+    }
+
+    #[test]
+    fn test_lookup() {
+        assert_eq!(channel("clt").unwrap().field, Field::CoolantRaw);
+        assert_eq!(channel("CN16").unwrap().field, Field::CanIn15);
+        assert!(channel("ENG/cranking").is_none());
+        assert_eq!(channels_in(Boost).count(), 2);
+    }
+
+    #[test]
+    fn test_scaled_values() {
+        let d = SpeeduinoData {
+            coolant_raw: 130,
+            battery_10: 142,
+            tae_amount_raw: 25,
+            rpm_dot: -300,
+            ..SpeeduinoData::default()
+        };
+        assert_eq!(channel("CLT").unwrap().value(&d), Some(90.0));
+        assert_eq!(channel("BAT").unwrap().value(&d), Some(14.2));
+        assert_eq!(channel("TAE").unwrap().value(&d), Some(50.0));
+        assert_eq!(channel("RPD").unwrap().value(&d), Some(-300.0));
+        // Optional field not sent by this packet
+        assert_eq!(channel("PW5").unwrap().value(&d), None);
+    }
+
+    #[test]
+    fn test_missing_field_has_no_value() {
+        let d = SpeeduinoData {
+            missing: [Field::Rpm].into_iter().collect(),
+            ..SpeeduinoData::default()
+        };
+        assert_eq!(channel("RPM").unwrap().value(&d), None);
+        assert_eq!(channel("MAP").unwrap().value(&d), Some(0.0));
+    }
+
+    #[test]
+    fn test_ranges() {
+        let clt = channel("CLT").unwrap();
+        assert!(clt.in_range(-40.0));
+        assert!(!clt.in_range(215.0));
+        for c in CHANNELS {
+            assert!(c.min < c.max, "{}", c.code);
+        }
+    }
+
+    #[test]
+    fn test_readme_documents_every_channel() {
+        let readme = include_str!("../README.md");
+        for c in CHANNELS {
+            assert!(
+                readme.contains(&format!("`{}`", c.code)),
+                "README lacks {}",
+                c.code
+            );
+        }
+    }
+}
@@ -3,9 +3,21 @@
 //! Handles loading, validation, and environment variable overrides for application configuration.
 //! Supports `.env` files via dotenvy, TOML config files, and `SPEEDUINO_*` env var overrides.
 
+use crate::afr::Wideband;
+use crate::can_inputs::CanInputs;
+use crate::derived::DerivedChannels;
+use crate::ecu_data_parser::{Publisher, channel_field};
+use crate::ecu_protocol::Protocol;
 use crate::errors::{ConfigError, Result};
+use crate::fuel::FuelModel;
+use crate::limits::Limits;
+use crate::mqtt_handler::{AVAILABILITY_TOPIC, PublishMode};
+use crate::packet_layout::{PRIMARY, select_layout};
+use crate::replay::ReplaySpeed;
+use crate::units::Units;
 use config::{Config, Environment, File};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::path::Path;
 use tracing::{debug, info, warn};
 
@@ -16,7 +28,8 @@ const VALID_BAUD_RATES: &[u32] = &[9600, 19200, 38400, 57600, 115200, 230400, 46
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppConfig {
     // --- Connection type ---
-    /// Connection type: "serial" (hardware UART) or "tcp" (raw TCP socket / WiFi bridge)
+    /// Connection type: "serial" (hardware UART), "tcp" (raw TCP socket / WiFi bridge)
+    /// or "replay" (play back a capture file)
     #[serde(default = "default_connection_type")]
     pub connection_type: String,
 
@@ -26,6 +39,34 @@ pub struct AppConfig {
     /// TCP port for "tcp" connection_type (e.g. 4096 for most serial-to-TCP bridges)
     pub tcp_port: Option<u16>,
 
+    // --- Replay configuration (used when connection_type = "replay") ---
+    /// Capture file to play back
+    pub replay_file: Option<String>,
+
+    /// Playback pace: "realtime", "fast" (no pacing) or a multiplier such as "4x"
+    #[serde(default = "default_replay_speed")]
+    pub replay_speed: String,
+
+    /// Start the capture over when its end is reached
+    #[serde(default)]
+    pub replay_loop: bool,
+
+    // --- Raw packet capture ---
+    /// Directory to record every raw ECU response into (unset = no recording)
+    pub capture_dir: Option<String>,
+
+    /// Start a new capture file once the current one reaches this size
+    #[serde(default = "default_capture_max_size_mb")]
+    pub capture_max_size_mb: u64,
+
+    /// Start a new capture file after this many minutes (0 = rotate by size only)
+    #[serde(default = "default_capture_rotate_minutes")]
+    pub capture_rotate_minutes: u64,
+
+    /// Capture files kept; the oldest are deleted on rotation (0 = keep all)
+    #[serde(default = "default_capture_max_files")]
+    pub capture_max_files: usize,
+
     // --- Serial port configuration (used when connection_type = "serial") ---
     /// The serial port device path (e.g., "/dev/ttyACM0", "COM3")
     #[serde(default = "default_port_name")]
@@ -44,6 +85,105 @@ pub struct AppConfig {
     #[serde(default = "default_read_timeout_ms")]
     pub read_timeout_ms: u64,
 
+    /// ECU wire protocol: "legacy" (bare 'A' command) or "crc" (msEnvelope framing
+    /// with CRC32 trailer, supported by current Speeduino firmware)
+    #[serde(default = "default_ecu_protocol")]
+    pub ecu_protocol: String,
+
+    /// Topic codes to poll (e.g. ["RPM", "MAP", "CLT", "O2P"]).  When set, only the
+    /// byte ranges backing these channels are fetched with 'r' requests and all
+    /// other channels are left unpublished.  Empty = read the whole packet.
+    #[serde(default)]
+    pub ecu_channels: Vec<String>,
+
+    /// Packet layout: "auto" (from the firmware release reported by the handshake),
+    /// a firmware release such as "202207" to pin that release's layout, or
+    /// "secondary" for the 75-byte secondary-serial packet
+    #[serde(default = "default_ecu_layout")]
+    pub ecu_layout: String,
+
+    /// Query the firmware signature ('Q') and version ('S') after connecting and
+    /// refuse devices that do not identify as Speeduino
+    #[serde(default = "default_ecu_handshake")]
+    pub ecu_handshake: bool,
+
+    // --- Units ---
+    /// Units for published and displayed values: "metric" (°C, kPa, km/h) or
+    /// "imperial" (°F, psi, mph)
+    #[serde(default = "default_unit_system")]
+    pub unit_system: String,
+
+    /// Per-channel unit overrides, topic code → unit (e.g. MAP = "bar")
+    #[serde(default)]
+    pub units: HashMap<String, String>,
+
+    // --- Sanity limits ---
+    /// What happens to values outside their channel's range when the channel
+    /// has no `[limits]` entry: "log", "flag", "drop_field" or "drop_frame"
+    #[serde(default = "default_limit_policy")]
+    pub limit_policy: String,
+
+    /// Per-channel limits and policy, topic code → min, max, policy
+    #[serde(default)]
+    pub limits: HashMap<String, LimitConfig>,
+
+    // --- Wideband O2 ---
+    /// Fuel the engine runs on, sets the stoichiometric AFR: "petrol", "e85",
+    /// "ethanol", "methanol", "lpg", "cng", "diesel", or "flex" to blend
+    /// petrol and ethanol by the measured ethanol content
+    #[serde(default = "default_fuel_type")]
+    pub fuel_type: String,
+
+    /// Stoichiometric AFR overriding the fuel type's (for "flex", that of the
+    /// petrol part)
+    #[serde(default)]
+    pub stoich_afr: Option<f64>,
+
+    /// AFR per O2 count (Speeduino sends AFR × 10)
+    #[serde(default = "default_o2_scale")]
+    pub o2_scale: f64,
+
+    /// Added to the scaled O2 reading
+    #[serde(default)]
+    pub o2_offset: f64,
+
+    // --- Fuel usage ---
+    /// Flow of one injector at fuel pressure (cc/min); enables the duty cycle,
+    /// fuel flow and consumption channels
+    #[serde(default)]
+    pub injector_flow_cc_min: Option<f64>,
+
+    /// Number of injectors on the engine (1–8, one per PW channel)
+    #[serde(default = "default_injector_count")]
+    pub injector_count: u8,
+
+    /// Injections per engine cycle (1 = sequential, 2 = semi-sequential/batch)
+    #[serde(default = "default_injector_squirts")]
+    pub injector_squirts: u8,
+
+    /// Injector opening time included in the pulse widths (ms)
+    #[serde(default)]
+    pub injector_dead_time_ms: f64,
+
+    /// File the fuel trip is kept in across restarts (unset = memory only)
+    #[serde(default)]
+    pub fuel_trip_file: Option<String>,
+
+    // --- CAN inputs ---
+    /// Per-input settings, CN01–CN16 → name, topic, scaling, unit, enabled
+    #[serde(default)]
+    pub can_inputs: HashMap<String, CanInputConfig>,
+
+    /// Publish CAN inputs without a `[can_inputs]` entry as raw CN01–CN16
+    #[serde(default = "default_can_inputs_publish_unlisted")]
+    pub can_inputs_publish_unlisted: bool,
+
+    // --- Derived channels ---
+    /// Extra channels computed from each frame, topic name → expression
+    /// (e.g. boost_psi = "(MAP - BAR) * 0.145")
+    #[serde(default)]
+    pub derived: HashMap<String, String>,
+
     // --- MQTT broker configuration ---
     /// Enable MQTT publishing (set false to run in display-only / TUI mode)
     #[serde(default = "default_mqtt_enabled")]
@@ -61,6 +201,49 @@ pub struct AppConfig {
     #[serde(default = "default_mqtt_base_topic")]
     pub mqtt_base_topic: String,
 
+    /// How frames are published: "per_topic" (one message per channel),
+    /// "json" (one JSON object per frame on FRAME) or "both"
+    #[serde(default = "default_publish_mode")]
+    pub publish_mode: String,
+
+    /// Publish a per-topic value only when it changes beyond its deadband
+    #[serde(default)]
+    pub publish_on_change: bool,
+
+    /// Default deadband for publish_on_change (absolute, in published units)
+    #[serde(default)]
+    pub publish_deadband: f64,
+
+    /// Per-topic deadbands, topic code → deadband (e.g. CLT = 1.0)
+    #[serde(default)]
+    pub deadbands: HashMap<String, f64>,
+
+    /// With publish_on_change, republish every topic at least this often
+    /// (0 = never)
+    #[serde(default = "default_publish_heartbeat_ms")]
+    pub publish_heartbeat_ms: u64,
+
+    /// Publish intervals, topic code or channel group → interval and method
+    #[serde(default)]
+    pub publish_intervals: HashMap<String, IntervalConfig>,
+
+    /// Topic code of the retained availability ("online", "ecu_offline",
+    /// "offline" as the last will)
+    #[serde(default = "default_availability_topic")]
+    pub availability_topic: String,
+
+    /// Publish Home Assistant discovery documents when the ECU connects
+    #[serde(default)]
+    pub ha_discovery: bool,
+
+    /// Topic prefix Home Assistant watches for discovery documents
+    #[serde(default = "default_ha_discovery_prefix")]
+    pub ha_discovery_prefix: String,
+
+    /// Name of the ECU's device in Home Assistant
+    #[serde(default = "default_ha_device_name")]
+    pub ha_device_name: String,
+
     /// MQTT Quality of Service level (0, 1, or 2)
     #[serde(default = "default_mqtt_qos")]
     pub mqtt_qos: i32,
@@ -122,12 +305,89 @@ pub struct AppConfig {
     pub config_path: Option<String>,
 }
 
+/// Settings of one CAN input (`[can_inputs.CN03]`).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CanInputConfig {
+    /// Human-readable name, e.g. "EGT cylinder 1"
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Topic published instead of the CNxx code, e.g. "EGT1"
+    #[serde(default)]
+    pub topic: Option<String>,
+    /// Multiplier applied to the raw u16
+    #[serde(default = "default_can_scale")]
+    pub scale: f64,
+    /// Added after scaling
+    #[serde(default)]
+    pub offset: f64,
+    /// Unit symbol for the TUI and metadata (no conversion is applied)
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Decimal places published (default: enough for `scale`)
+    #[serde(default)]
+    pub decimals: Option<usize>,
+    /// Set false to stop publishing this input
+    #[serde(default = "default_can_enabled")]
+    pub enabled: bool,
+}
+
+impl Default for CanInputConfig {
+    fn default() -> Self {
+        Self {
+            name: None,
+            topic: None,
+            scale: default_can_scale(),
+            offset: 0.0,
+            unit: None,
+            decimals: None,
+            enabled: default_can_enabled(),
+        }
+    }
+}
+
+/// Sanity limits of one channel (`[limits.RPM]`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct LimitConfig {
+    /// Lowest plausible value (default: the channel's registry range)
+    #[serde(default)]
+    pub min: Option<f64>,
+    /// Highest plausible value (default: the channel's registry range)
+    #[serde(default)]
+    pub max: Option<f64>,
+    /// "log", "flag", "drop_field" or "drop_frame" (default: `limit_policy`)
+    #[serde(default)]
+    pub policy: Option<String>,
+}
+
+/// Publish interval of a topic or channel group (`[publish_intervals.CLT]`).
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct IntervalConfig {
+    /// Publish at most once per this many milliseconds
+    #[serde(default)]
+    pub interval_ms: u64,
+    /// "last", "mean", "min" or "max" over the interval (default: "last")
+    #[serde(default)]
+    pub method: Option<String>,
+}
+
 // ---------------------------------------------------------------------------
 // Default value functions
 // ---------------------------------------------------------------------------
 fn default_connection_type() -> String {
     "serial".to_string()
 }
+fn default_replay_speed() -> String {
+    "realtime".to_string()
+}
+fn default_capture_max_size_mb() -> u64 {
+    64
+}
+fn default_capture_rotate_minutes() -> u64 {
+    60
+}
+fn default_capture_max_files() -> usize {
+    16
+}
 fn default_port_name() -> String {
     "/dev/ttyACM0".to_string()
 }
@@ -140,6 +400,42 @@ fn default_expected_data_length() -> usize {
 fn default_read_timeout_ms() -> u64 {
     2000
 }
+fn default_ecu_protocol() -> String {
+    "legacy".to_string()
+}
+fn default_ecu_layout() -> String {
+    "auto".to_string()
+}
+fn default_ecu_handshake() -> bool {
+    true
+}
+fn default_unit_system() -> String {
+    "metric".to_string()
+}
+fn default_limit_policy() -> String {
+    "log".to_string()
+}
+fn default_fuel_type() -> String {
+    "petrol".to_string()
+}
+fn default_o2_scale() -> f64 {
+    0.1
+}
+fn default_injector_count() -> u8 {
+    4
+}
+fn default_injector_squirts() -> u8 {
+    1
+}
+fn default_can_inputs_publish_unlisted() -> bool {
+    true
+}
+fn default_can_scale() -> f64 {
+    1.0
+}
+fn default_can_enabled() -> bool {
+    true
+}
 fn default_mqtt_enabled() -> bool {
     true
 }
@@ -152,6 +448,21 @@ fn default_mqtt_port() -> u16 {
 fn default_mqtt_base_topic() -> String {
     "/speeduino/ecu/".to_string()
 }
+fn default_publish_mode() -> String {
+    "per_topic".to_string()
+}
+fn default_publish_heartbeat_ms() -> u64 {
+    10_000
+}
+fn default_availability_topic() -> String {
+    AVAILABILITY_TOPIC.to_string()
+}
+fn default_ha_discovery_prefix() -> String {
+    "homeassistant".to_string()
+}
+fn default_ha_device_name() -> String {
+    "Speeduino".to_string()
+}
 fn default_mqtt_qos() -> i32 {
     0
 }
@@ -180,14 +491,51 @@ impl Default for AppConfig {
             connection_type: default_connection_type(),
             tcp_host: None,
             tcp_port: None,
+            replay_file: None,
+            replay_speed: default_replay_speed(),
+            replay_loop: false,
+            capture_dir: None,
+            capture_max_size_mb: default_capture_max_size_mb(),
+            capture_rotate_minutes: default_capture_rotate_minutes(),
+            capture_max_files: default_capture_max_files(),
             port_name: default_port_name(),
             baud_rate: default_baud_rate(),
             expected_data_length: default_expected_data_length(),
             read_timeout_ms: default_read_timeout_ms(),
+            ecu_protocol: default_ecu_protocol(),
+            ecu_channels: Vec::new(),
+            ecu_layout: default_ecu_layout(),
+            ecu_handshake: default_ecu_handshake(),
+            unit_system: default_unit_system(),
+            units: HashMap::new(),
+            can_inputs: HashMap::new(),
+            limit_policy: default_limit_policy(),
+            limits: HashMap::new(),
+            fuel_type: default_fuel_type(),
+            stoich_afr: None,
+            o2_scale: default_o2_scale(),
+            o2_offset: 0.0,
+            injector_flow_cc_min: None,
+            injector_count: default_injector_count(),
+            injector_squirts: default_injector_squirts(),
+            injector_dead_time_ms: 0.0,
+            fuel_trip_file: None,
+            can_inputs_publish_unlisted: default_can_inputs_publish_unlisted(),
+            derived: HashMap::new(),
             mqtt_enabled: default_mqtt_enabled(),
             mqtt_host: default_mqtt_host(),
             mqtt_port: default_mqtt_port(),
             mqtt_base_topic: default_mqtt_base_topic(),
+            publish_mode: default_publish_mode(),
+            publish_on_change: false,
+            publish_deadband: 0.0,
+            deadbands: HashMap::new(),
+            publish_heartbeat_ms: default_publish_heartbeat_ms(),
+            publish_intervals: HashMap::new(),
+            availability_topic: default_availability_topic(),
+            ha_discovery: false,
+            ha_discovery_prefix: default_ha_discovery_prefix(),
+            ha_device_name: default_ha_device_name(),
             mqtt_qos: default_mqtt_qos(),
             mqtt_client_id: None,
             mqtt_username: None,
@@ -245,15 +593,51 @@ impl AppConfig {
                     .into());
                 }
             }
+            "replay" => {
+                let Some(path) = self.replay_file.as_deref().filter(|p| !p.is_empty()) else {
+                    return Err(ConfigError::MissingField(
+                        "replay_file (required when connection_type = \"replay\")".to_string(),
+                    )
+                    .into());
+                };
+                if !Path::new(path).exists() {
+                    return Err(ConfigError::InvalidValue {
+                        field: "replay_file".to_string(),
+                        message: format!("file does not exist: {}", path),
+                    }
+                    .into());
+                }
+                if ReplaySpeed::from_config(&self.replay_speed).is_none() {
+                    return Err(ConfigError::InvalidValue {
+                        field: "replay_speed".to_string(),
+                        message: format!(
+                            "must be \"realtime\", \"fast\" or a multiplier such as \"4x\", got \"{}\"",
+                            self.replay_speed
+                        ),
+                    }
+                    .into());
+                }
+            }
             other => {
                 return Err(ConfigError::InvalidValue {
                     field: "connection_type".to_string(),
-                    message: format!("must be \"serial\" or \"tcp\", got \"{}\"", other),
+                    message: format!(
+                        "must be \"serial\", \"tcp\" or \"replay\", got \"{}\"",
+                        other
+                    ),
                 }
                 .into());
             }
         }
 
+        if self.capture_dir.is_some() && self.capture_max_size_mb == 0 {
+            return Err(ConfigError::InvalidValue {
+                field: "capture_max_size_mb".to_string(),
+                message: "must be greater than 0".to_string(),
+            }
+            .into());
+        }
+
         if self.expected_data_length < 119 || self.expected_data_length > 256 {
             return Err(ConfigError::InvalidValue {
                 field: "expected_data_length".to_string(),
@@ -262,6 +646,84 @@ impl AppConfig {
             .into());
         }
 
+        if Protocol::from_config(&self.ecu_protocol).is_none() {
+            return Err(ConfigError::InvalidValue {
+                field: "ecu_protocol".to_string(),
+                message: format!(
+                    "must be \"legacy\" or \"crc\", got \"{}\"",
+                    self.ecu_protocol
+                ),
+            }
+            .into());
+        }
+
+        if select_layout(&self.ecu_layout, None).is_none() {
+            return Err(ConfigError::InvalidValue {
+                field: "ecu_layout".to_string(),
+                message: format!(
+                    "must be \"auto\", \"secondary\" or a firmware release such as \"202402\", got \"{}\"",
+                    self.ecu_layout
+                ),
+            }
+            .into());
+        }
+
+        // The secondary serial port only answers the bare 'A' command.
+        if self.ecu_layout.eq_ignore_ascii_case("secondary") {
+            if Protocol::from_config(&self.ecu_protocol) != Some(Protocol::Legacy) {
+                return Err(ConfigError::InvalidValue {
+                    field: "ecu_protocol".to_string(),
+                    message: "the secondary serial layout requires \"legacy\"".to_string(),
+                }
+                .into());
+            }
+            if !self.ecu_channels.is_empty() {
+                return Err(ConfigError::InvalidValue {
+                    field: "ecu_channels".to_string(),
+                    message: "partial reads are not available on the secondary serial port"
+                        .to_string(),
+                }
+                .into());
+            }
+        }
+
+        // "auto" checks against the newest layout; the handshake may pick an
+        // older one, whose missing fields are then simply not read.
+        let layout = select_layout(&self.ecu_layout, None).unwrap_or(&PRIMARY);
+        for code in &self.ecu_channels {
+            let Some(field) = channel_field(code) else {
+                return Err(ConfigError::InvalidValue {
+                    field: "ecu_channels".to_string(),
+                    message: format!("unknown channel code \"{}\"", code),
+                }
+                .into());
+            };
+            if layout
+                .def(field)
+                .is_none_or(|def| def.bytes().end > layout.length)
+            {
+                return Err(ConfigError::InvalidValue {
+                    field: "ecu_channels".to_string(),
+                    message: format!(
+                        "channel \"{}\" is not in the {} packet layout",
+                        code, layout.name
+                    ),
+                }
+                .into());
+            }
+        }
+
+        Units::from_config(self)?;
+
+        Limits::from_config(self)?;
+        Wideband::from_config(self)?;
+
+        FuelModel::from_config(self)?;
+
+        let can_inputs = CanInputs::from_config(self)?;
+        DerivedChannels::from_config(self)?;
+        Publisher::from_config(self, &can_inputs)?;
+
         if self.read_timeout_ms == 0 || self.read_timeout_ms > 30000 {
             return Err(ConfigError::InvalidValue {
                 field: "read_timeout_ms".to_string(),
@@ -291,17 +753,47 @@ impl AppConfig {
                 }
                 .into());
             }
-            if self.mqtt_use_tls {
-                if let Some(ref ca_path) = self.mqtt_ca_cert_path {
-                    if !Path::new(ca_path).exists() {
-                        return Err(ConfigError::InvalidValue {
-                            field: "mqtt_ca_cert_path".to_string(),
-                            message: format!("file does not exist: {}", ca_path),
-                        }
-                        .into());
+            let availability = self.availability_topic.trim_matches('/');
+            if availability.is_empty() || availability.contains(['#', '+']) {
+                return Err(ConfigError::InvalidValue {
+                    field: "availability_topic".to_string(),
+                    message: format!(
+                        "must be a topic without wildcards, got \"{}\"",
+                        self.availability_topic
+                    ),
+                }
+                .into());
+            }
+            if self.ha_discovery {
+                let prefix = self.ha_discovery_prefix.trim_matches('/');
+                if prefix.is_empty() || prefix.contains(['#', '+']) {
+                    return Err(ConfigError::InvalidValue {
+                        field: "ha_discovery_prefix".to_string(),
+                        message: format!(
+                            "must be a topic without wildcards, got \"{}\"",
+                            self.ha_discovery_prefix
+                        ),
                     }
+                    .into());
+                }
+                if !PublishMode::from_config(&self.publish_mode).is_some_and(|m| m.per_topic()) {
+                    return Err(ConfigError::InvalidValue {
+                        field: "ha_discovery".to_string(),
+                        message: "needs publish_mode \"per_topic\" or \"both\"".to_string(),
+                    }
+                    .into());
                 }
             }
+            if self.mqtt_use_tls
+                && let Some(ref ca_path) = self.mqtt_ca_cert_path
+                && !Path::new(ca_path).exists()
+            {
+                return Err(ConfigError::InvalidValue {
+                    field: "mqtt_ca_cert_path".to_string(),
+                    message: format!("file does not exist: {}", ca_path),
+                }
+                .into());
+            }
             if self.message_buffer_size == 0 {
                 return Err(ConfigError::InvalidValue {
                     field: "message_buffer_size".to_string(),
@@ -356,9 +848,19 @@ impl AppConfig {
         Ok(())
     }
 
+    /// True when ECU data comes from a capture file instead of a live ECU.
+    pub fn is_replay(&self) -> bool {
+        self.connection_type.eq_ignore_ascii_case("replay")
+    }
+
     /// Returns a human-readable description of the ECU connection endpoint.
     pub fn connection_display(&self) -> String {
         match self.connection_type.to_lowercase().as_str() {
+            "replay" => format!(
+                "Replay {} ({})",
+                self.replay_file.as_deref().unwrap_or("?"),
+                self.replay_speed
+            ),
             "tcp" => format!(
                 "TCP {}:{}",
                 self.tcp_host.as_deref().unwrap_or("?"),
@@ -380,6 +882,30 @@ impl AppConfig {
         if self.mqtt_enabled {
             info!("MQTT Broker: {}:{}", self.mqtt_host, self.mqtt_port);
             info!("MQTT Base Topic: {}", self.mqtt_base_topic);
+            info!("MQTT Publish Mode: {}", self.publish_mode);
+            if self.publish_on_change {
+                info!(
+                    "MQTT On Change: deadband {}, heartbeat {}ms",
+                    self.publish_deadband, self.publish_heartbeat_ms
+                );
+            }
+            let mut intervals: Vec<_> = self.publish_intervals.iter().collect();
+            intervals.sort_by(|a, b| a.0.cmp(b.0));
+            for (name, interval) in intervals {
+                info!(
+                    "MQTT Interval {}: {}ms ({})",
+                    name,
+                    interval.interval_ms,
+                    interval.method.as_deref().unwrap_or("last")
+                );
+            }
+            info!("MQTT Availability Topic: {}", self.availability_topic);
+            if self.ha_discovery {
+                info!(
+                    "Home Assistant Discovery: {} (device \"{}\")",
+                    self.ha_discovery_prefix, self.ha_device_name
+                );
+            }
             info!("MQTT QoS: {}", self.mqtt_qos);
             if self.mqtt_use_tls {
                 info!("MQTT TLS: enabled");
@@ -390,6 +916,68 @@ impl AppConfig {
         } else {
             info!("MQTT: disabled (display-only / TUI mode)");
         }
+        info!("ECU Protocol: {}", self.ecu_protocol);
+        if !self.ecu_channels.is_empty() {
+            info!("ECU Channels: {}", self.ecu_channels.join(", "));
+        }
+        info!("ECU Layout: {}", self.ecu_layout);
+        info!("ECU Handshake: {}", self.ecu_handshake);
+        info!("Unit System: {}", self.unit_system);
+        info!("Limit Policy: {}", self.limit_policy);
+        let mut limits: Vec<_> = self.limits.iter().collect();
+        limits.sort_by(|a, b| a.0.cmp(b.0));
+        for (code, limit) in limits {
+            info!(
+                "Limit {}: {}–{} ({})",
+                code,
+                limit.min.map_or("registry".to_string(), |v| v.to_string()),
+                limit.max.map_or("registry".to_string(), |v| v.to_string()),
+                limit.policy.as_deref().unwrap_or(&self.limit_policy)
+            );
+        }
+        match self.stoich_afr {
+            Some(stoich) => info!("Fuel: {} (stoich {})", self.fuel_type, stoich),
+            None => info!("Fuel: {}", self.fuel_type),
+        }
+        if let Some(flow) = self.injector_flow_cc_min {
+            info!(
+                "Injectors: {} × {} cc/min, {} squirt(s)/cycle, dead time {} ms",
+                self.injector_count, flow, self.injector_squirts, self.injector_dead_time_ms
+            );
+            if let Some(ref file) = self.fuel_trip_file {
+                info!("Fuel Trip File: {}", file);
+            }
+        }
+        let mut can_inputs: Vec<_> = self.can_inputs.iter().collect();
+        can_inputs.sort_by(|a, b| a.0.cmp(b.0));
+        for (code, input) in can_inputs {
+            info!(
+                "CAN input {}: {} ({})",
+                code,
+                input.topic.as_deref().unwrap_or(code),
+                if input.enabled { "enabled" } else { "disabled" }
+            );
+        }
+        if !self.can_inputs_publish_unlisted {
+            info!("CAN inputs without settings: not published");
+        }
+        let mut derived: Vec<_> = self.derived.iter().collect();
+        derived.sort();
+        for (name, source) in derived {
+            info!("Derived: {} = {}", name, source);
+        }
+        if let Some(ref dir) = self.capture_dir {
+            info!(
+                "Capture: {} ({} MB / {} min per file, keeping {})",
+                dir,
+                self.capture_max_size_mb,
+                self.capture_rotate_minutes,
+                match self.capture_max_files {
+                    0 => "all files".to_string(),
+                    n => format!("the newest {}", n),
+                }
+            );
+        }
         info!("Refresh Rate: {}ms", self.refresh_rate_ms);
         info!("Max Retry Count: {}", self.max_retry_count);
         info!("Log Level: {}", self.log_level);
@@ -433,11 +1021,11 @@ pub fn load_configuration(config_path: Option<&str>) -> Result<AppConfig> {
 
         // 2. Directory containing the executable (covers `cargo install` and
         //    installed service binaries).
-        if let Ok(exe) = std::env::current_exe() {
-            if let Some(parent) = exe.parent() {
-                candidates.push(parent.join("settings.toml"));
-                candidates.push(parent.join("speeduino-to-mqtt.toml"));
-            }
+        if let Ok(exe) = std::env::current_exe()
+            && let Some(parent) = exe.parent()
+        {
+            candidates.push(parent.join("settings.toml"));
+            candidates.push(parent.join("speeduino-to-mqtt.toml"));
         }
 
         // 3. Current working directory (highest priority — developer / manual run).
@@ -464,9 +1052,12 @@ pub fn load_configuration(config_path: Option<&str>) -> Result<AppConfig> {
     // No separator: SPEEDUINO_MQTT_ENABLED → "mqtt_enabled" (flat key).
     // With separator("_") the crate converts underscores to dots producing
     // nested keys like "mqtt.enabled" which don't match the flat struct fields.
+    // List values are comma-separated: SPEEDUINO_ECU_CHANNELS=RPM,MAP,CLT.
     builder = builder.add_source(
         Environment::with_prefix("SPEEDUINO")
-            .try_parsing(true),
+            .try_parsing(true)
+            .list_separator(",")
+            .with_list_parse_key("ecu_channels"),
     );
 
     let settings = builder
@@ -491,6 +1082,7 @@ pub fn load_configuration(config_path: Option<&str>) -> Result<AppConfig> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::units::Unit;
     use std::fs;
     use tempfile::tempdir;
 
@@ -521,6 +1113,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_invalid_baud_rate() {
         let mut config = AppConfig::default();
         config.baud_rate = 12345;
@@ -528,6 +1121,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_invalid_mqtt_qos() {
         let mut config = AppConfig::default();
         config.mqtt_qos = 5;
@@ -535,6 +1129,110 @@ mod tests {
     }
 
     #[test]
+    fn test_publish_mode() {
+        let mut config = AppConfig::default();
+        for mode in ["per_topic", "json", "Both"] {
+            config.publish_mode = mode.to_string();
+            assert!(config.validate().is_ok(), "{} should be valid", mode);
+        }
+        config.publish_mode = "csv".to_string();
+        assert!(config.validate().is_err());
+        // Frames are published from PublishState whether or not MQTT is on
+        config.mqtt_enabled = false;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_publish_on_change_settings() {
+        let mut config = AppConfig {
+            publish_on_change: true,
+            publish_deadband: 0.5,
+            ..Default::default()
+        };
+        config.deadbands.insert("RPM".to_string(), 25.0);
+        assert!(config.validate().is_ok());
+
+        config.deadbands.insert("CLT".to_string(), -1.0);
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("deadbands.CLT"), "{}", err);
+
+        config.deadbands.remove("CLT");
+        config.publish_deadband = f64::NAN;
+        assert!(config.validate().is_err());
+
+        // The filter is built even when MQTT is off
+        config.publish_deadband = 0.5;
+        config.mqtt_enabled = false;
+        assert!(config.validate().is_ok());
+        config.deadbands.insert("MAP".to_string(), -2.0);
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_publish_intervals() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        fs::write(
+            &path,
+            "[publish_intervals.temperature]\ninterval_ms = 1000\nmethod = \"mean\"\n\n\
+             [publish_intervals.BAT]\ninterval_ms = 500\n",
+        )
+        .unwrap();
+        let mut config = load_configuration(Some(path.to_str().unwrap())).unwrap();
+        assert_eq!(config.publish_intervals["temperature"].interval_ms, 1000);
+        assert_eq!(config.publish_intervals["BAT"].method, None);
+        assert!(config.validate().is_ok());
+
+        config.publish_intervals.insert(
+            "RPM".to_string(),
+            IntervalConfig {
+                interval_ms: 100,
+                method: Some("median".to_string()),
+            },
+        );
+        assert!(config.validate().is_err());
+
+        config
+            .publish_intervals
+            .insert("RPM".to_string(), IntervalConfig::default());
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("publish_intervals.RPM"), "{}", err);
+        config.mqtt_enabled = false;
+        assert!(config.validate().is_err(), "checked even with MQTT off");
+    }
+
+    #[test]
+    fn test_availability_topic() {
+        let mut config = AppConfig::default();
+        assert_eq!(config.availability_topic, "AVAILABILITY");
+        config.availability_topic = "status/bridge".to_string();
+        assert!(config.validate().is_ok());
+        config.availability_topic = "/".to_string();
+        assert!(config.validate().is_err());
+        config.availability_topic = "status/#".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_ha_discovery_settings() {
+        let mut config = AppConfig {
+            ha_discovery: true,
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+        assert_eq!(config.ha_discovery_prefix, "homeassistant");
+
+        config.ha_discovery_prefix = "ha/#".to_string();
+        assert!(config.validate().is_err());
+
+        config.ha_discovery_prefix = "homeassistant".to_string();
+        config.publish_mode = "json".to_string();
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("ha_discovery"), "{}", err);
+    }
+
+    #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_invalid_refresh_rate() {
         let mut config = AppConfig::default();
         config.refresh_rate_ms = 0;
@@ -542,6 +1240,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_missing_port_name() {
         let mut config = AppConfig::default();
         config.port_name = String::new();
@@ -549,6 +1248,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_retry_delay_validation() {
         let mut config = AppConfig::default();
         config.max_retry_delay_ms = 500;
@@ -557,6 +1257,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_valid_log_levels() {
         for level in &["trace", "debug", "info", "warn", "error"] {
             let mut config = AppConfig::default();
@@ -566,6 +1267,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_invalid_log_level() {
         let mut config = AppConfig::default();
         config.log_level = "verbose".to_string();
@@ -573,6 +1275,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_tcp_connection_type_requires_host() {
         let mut config = AppConfig::default();
         config.connection_type = "tcp".to_string();
@@ -582,6 +1285,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_tcp_connection_type_requires_port() {
         let mut config = AppConfig::default();
         config.connection_type = "tcp".to_string();
@@ -591,6 +1295,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_tcp_connection_type_valid() {
         let mut config = AppConfig::default();
         config.connection_type = "tcp".to_string();
@@ -600,6 +1305,294 @@ mod tests {
     }
 
     #[test]
+    fn test_replay_connection_type() {
+        let capture = tempfile::NamedTempFile::new().unwrap();
+        let mut config = AppConfig {
+            connection_type: "replay".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err(), "replay_file is required");
+
+        config.replay_file = Some("/nonexistent/capture.bin".to_string());
+        assert!(config.validate().is_err());
+
+        config.replay_file = Some(capture.path().display().to_string());
+        for speed in ["realtime", "fast", "4x", "0.5"] {
+            config.replay_speed = speed.to_string();
+            assert!(config.validate().is_ok(), "{} should be valid", speed);
+        }
+        config.replay_speed = "warp".to_string();
+        assert!(config.validate().is_err());
+
+        assert!(config.is_replay());
+        assert!(config.connection_display().starts_with("Replay "));
+    }
+
+    #[test]
+    fn test_capture_size_validation() {
+        let mut config = AppConfig {
+            capture_max_size_mb: 0,
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok(), "unused while capture is off");
+        config.capture_dir = Some("/tmp/captures".to_string());
+        assert!(config.validate().is_err());
+        config.capture_max_size_mb = 16;
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_unit_settings() {
+        let mut config = AppConfig {
+            unit_system: "Imperial".to_string(),
+            ..Default::default()
+        };
+        config.units.insert("MAP".to_string(), "bar".to_string());
+        assert!(config.validate().is_ok());
+
+        config.unit_system = "nautical".to_string();
+        assert!(config.validate().is_err());
+
+        let mut config = AppConfig::default();
+        config.units.insert("CLT".to_string(), "psi".to_string());
+        assert!(config.validate().is_err(), "wrong quantity");
+        config.units.clear();
+        config.units.insert("RPM".to_string(), "kPa".to_string());
+        assert!(config.validate().is_err(), "channel without a unit");
+    }
+
+    #[test]
+    fn test_derived_channels() {
+        let mut config = AppConfig::default();
+        config
+            .derived
+            .insert("boost_psi".to_string(), "(MAP - BAR) * 0.145".to_string());
+        assert!(config.validate().is_ok());
+
+        config
+            .derived
+            .insert("lambda1".to_string(), "O2P / 14.7 +".to_string());
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("derived.lambda1"), "{}", err);
+        assert!(err.contains("expected a number, channel or '('"), "{}", err);
+
+        let mut config = AppConfig::default();
+        config.derived.insert("MAP".to_string(), "BAR".to_string());
+        assert!(config.validate().is_err(), "shadows a native channel");
+
+        let mut config = AppConfig::default();
+        config.derived.insert("Lambda".to_string(), "1".to_string());
+        assert!(config.validate().is_err(), "shadows the wideband lambda");
+    }
+
+    #[test]
+    fn test_limit_settings() {
+        let mut config = AppConfig {
+            limit_policy: "Flag".to_string(),
+            ..Default::default()
+        };
+        config.limits.insert(
+            "rpm".to_string(),
+            LimitConfig {
+                max: Some(9000.0),
+                policy: Some("drop_frame".to_string()),
+                ..LimitConfig::default()
+            },
+        );
+        assert!(config.validate().is_ok());
+
+        config.limit_policy = "ignore".to_string();
+        assert!(config.validate().is_err());
+
+        config.limit_policy = "log".to_string();
+        config.limits.insert(
+            "XYZ".to_string(),
+            LimitConfig {
+                max: Some(1.0),
+                ..LimitConfig::default()
+            },
+        );
+        let err = config.validate().unwrap_err().to_string();
+        assert!(err.contains("limits.XYZ"), "{}", err);
+
+        config.limits.remove("XYZ");
+        config.limits.insert(
+            "MAP".to_string(),
+            LimitConfig {
+                min: Some(300.0),
+                max: Some(20.0),
+                ..LimitConfig::default()
+            },
+        );
+        assert!(config.validate().is_err(), "min above max");
+    }
+
+    #[test]
+    fn test_limits_from_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        fs::write(
+            &path,
+            "limit_policy = \"flag\"\n[limits.RPM]\nmax = 9000\npolicy = \"drop_frame\"\n",
+        )
+        .unwrap();
+        let config = load_configuration(Some(path.to_str().unwrap())).unwrap();
+        assert_eq!(config.limit_policy, "flag");
+        assert_eq!(config.limits["RPM"].max, Some(9000.0));
+        assert_eq!(config.limits["RPM"].min, None);
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_wideband_settings() {
+        let mut config = AppConfig {
+            fuel_type: "Flex".to_string(),
+            stoich_afr: Some(14.6),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.fuel_type = "kerosene".to_string();
+        assert!(
+            config
+                .validate()
+                .unwrap_err()
+                .to_string()
+                .contains("fuel_type")
+        );
+
+        config.fuel_type = "e85".to_string();
+        config.stoich_afr = Some(0.0);
+        assert!(config.validate().is_err());
+
+        config.stoich_afr = None;
+        config.o2_scale = 0.0;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_fuel_settings() {
+        let mut config = AppConfig {
+            injector_flow_cc_min: Some(440.0),
+            injector_count: 6,
+            injector_squirts: 2,
+            injector_dead_time_ms: 0.9,
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.injector_flow_cc_min = Some(-1.0);
+        assert!(config.validate().is_err());
+
+        config.injector_flow_cc_min = Some(440.0);
+        config.injector_count = 0;
+        assert!(config.validate().is_err());
+        // Only PW1–PW8 exist
+        config.injector_count = 9;
+        assert!(config.validate().is_err());
+
+        config.injector_count = 6;
+        config.injector_squirts = 0;
+        assert!(config.validate().is_err());
+
+        config.injector_squirts = 1;
+        config.injector_dead_time_ms = -0.5;
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_can_input_settings() {
+        let mut config = AppConfig::default();
+        config.can_inputs.insert(
+            "CN03".to_string(),
+            CanInputConfig {
+                topic: Some("EGT1".to_string()),
+                scale: 0.1,
+                unit: Some("°C".to_string()),
+                ..CanInputConfig::default()
+            },
+        );
+        assert!(config.validate().is_ok());
+
+        config
+            .can_inputs
+            .insert("CN17".to_string(), CanInputConfig::default());
+        assert!(config.validate().is_err(), "no such input");
+        config.can_inputs.remove("CN17");
+
+        config.can_inputs.insert(
+            "CN04".to_string(),
+            CanInputConfig {
+                topic: Some("egt1".to_string()),
+                ..CanInputConfig::default()
+            },
+        );
+        assert!(config.validate().is_err(), "duplicate topic");
+        config.can_inputs.remove("CN04");
+
+        config.can_inputs.insert(
+            "CN05".to_string(),
+            CanInputConfig {
+                topic: Some("RPM".to_string()),
+                ..CanInputConfig::default()
+            },
+        );
+        assert!(config.validate().is_err(), "shadows a native topic");
+    }
+
+    #[test]
+    fn test_can_inputs_from_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        fs::write(
+            &path,
+            "can_inputs_publish_unlisted = false\n\
+             [can_inputs.CN03]\nname = \"EGT 1\"\ntopic = \"EGT1\"\nscale = 0.1\nunit = \"°C\"\n\
+             [can_inputs.CN04]\nenabled = false\n",
+        )
+        .unwrap();
+        let config = load_configuration(Some(path.to_str().unwrap())).unwrap();
+        assert!(!config.can_inputs_publish_unlisted);
+        let egt = &config.can_inputs["CN03"];
+        assert_eq!(egt.topic.as_deref(), Some("EGT1"));
+        assert_eq!(egt.scale, 0.1);
+        assert_eq!(egt.offset, 0.0);
+        assert!(egt.enabled);
+        assert!(!config.can_inputs["CN04"].enabled);
+    }
+
+    #[test]
+    fn test_derived_table_from_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        fs::write(
+            &path,
+            "[derived]\nboost_psi = \"(MAP - BAR) * 0.145\"\nDutyPct = \"PW1 * RPM / 1200\"\n",
+        )
+        .unwrap();
+        let config = load_configuration(Some(path.to_str().unwrap())).unwrap();
+        assert_eq!(config.derived["boost_psi"], "(MAP - BAR) * 0.145");
+        assert!(config.derived.contains_key("DutyPct"));
+    }
+
+    #[test]
+    fn test_units_table_from_file() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("settings.toml");
+        fs::write(
+            &path,
+            "unit_system = \"imperial\"\n[units]\nMAP = \"kPa\"\nvss = \"km/h\"\n",
+        )
+        .unwrap();
+        let config = load_configuration(Some(path.to_str().unwrap())).unwrap();
+        let units = Units::from_config(&config).unwrap();
+        assert_eq!(units.unit("MAP"), Some(Unit::Kpa));
+        assert_eq!(units.unit("VSS"), Some(Unit::Kmh));
+        assert_eq!(units.unit("CLT"), Some(Unit::Fahrenheit));
+    }
+
+    #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_invalid_connection_type() {
         let mut config = AppConfig::default();
         config.connection_type = "usb".to_string();
@@ -607,6 +1600,69 @@ mod tests {
     }
 
     #[test]
+    fn test_ecu_protocol_values() {
+        for protocol in &["legacy", "crc", "CRC"] {
+            let config = AppConfig {
+                ecu_protocol: protocol.to_string(),
+                ..Default::default()
+            };
+            assert!(config.validate().is_ok());
+        }
+        let config = AppConfig {
+            ecu_protocol: "msenvelope".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_ecu_layout_values() {
+        let mut config = AppConfig::default();
+        for layout in ["auto", "Auto", "202402", "201905"] {
+            config.ecu_layout = layout.to_string();
+            assert!(config.validate().is_ok(), "{} should be valid", layout);
+        }
+        config.ecu_layout = "newest".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_secondary_layout_requires_legacy_full_reads() {
+        let mut config = AppConfig {
+            ecu_layout: "secondary".to_string(),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.ecu_protocol = "crc".to_string();
+        assert!(config.validate().is_err());
+
+        config.ecu_protocol = "legacy".to_string();
+        config.ecu_channels = vec!["RPM".to_string()];
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_ecu_channels_validation() {
+        let mut config = AppConfig {
+            ecu_channels: vec!["RPM".to_string(), "MAP".to_string()],
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        config.ecu_channels = vec!["NOPE".to_string()];
+        assert!(config.validate().is_err());
+
+        // PW8 lives at bytes 136–137 of the current layout, which firmware
+        // before 202305 does not send
+        config.ecu_channels = vec!["PW8".to_string()];
+        assert!(config.validate().is_ok());
+        config.ecu_layout = "202207".to_string();
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_mqtt_disabled_skips_mqtt_validation() {
         let mut config = AppConfig::default();
         config.mqtt_enabled = false;
@@ -622,6 +1678,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_connection_display_tcp() {
         let mut config = AppConfig::default();
         config.connection_type = "tcp".to_string();
@@ -1,12 +1,14 @@
 //! ECU connection abstraction.
 //!
-//! Provides [`EcuConnection`], an enum that wraps either a hardware serial port
-//! ([`tokio_serial::SerialStream`]) or a raw TCP socket ([`tokio::net::TcpStream`]).
-//! Both variants implement [`tokio::io::AsyncRead`] and [`tokio::io::AsyncWrite`], so the
+//! Provides [`EcuConnection`], an enum that wraps a hardware serial port
+//! ([`tokio_serial::SerialStream`]), a raw TCP socket ([`tokio::net::TcpStream`])
+//! or a capture being played back ([`ReplayStream`]).
+//! All variants implement [`tokio::io::AsyncRead`] and [`tokio::io::AsyncWrite`], so the
 //! rest of the code can use them interchangeably.
 
 use crate::config::AppConfig;
 use crate::errors::{Result, SerialError};
+use crate::replay::{ReplaySpeed, ReplayStream};
 use std::io;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -16,10 +18,11 @@ use tokio::net::TcpStream;
 use tokio_serial::{SerialPort, SerialPortBuilderExt, SerialStream};
 use tracing::info;
 
-/// Unified ECU connection that is either hardware serial or raw TCP.
+/// Unified ECU connection: hardware serial, raw TCP or capture replay.
 pub enum EcuConnection {
     Serial(SerialStream),
     Tcp(TcpStream),
+    Replay(Box<ReplayStream>),
 }
 
 impl EcuConnection {
@@ -43,6 +46,13 @@ impl EcuConnection {
                 info!("TCP connection established to {}", addr);
                 Ok(EcuConnection::Tcp(stream))
             }
+            "replay" => {
+                let path = config.replay_file.as_deref().unwrap_or("");
+                let speed =
+                    ReplaySpeed::from_config(&config.replay_speed).unwrap_or(ReplaySpeed::Fast);
+                let stream = ReplayStream::open(path, speed, config.replay_loop)?;
+                Ok(EcuConnection::Replay(Box::new(stream)))
+            }
             _ => {
                 info!(
                     "Opening serial port {} at {} baud",
@@ -67,6 +77,11 @@ impl EcuConnection {
         matches!(self, EcuConnection::Tcp(_))
     }
 
+    /// True once a replayed capture has been played to the end.
+    pub fn is_exhausted(&self) -> bool {
+        matches!(self, EcuConnection::Replay(r) if r.is_finished())
+    }
+
     /// Attempt to clear input/output buffers.  No-op for TCP connections.
     #[allow(dead_code)]
     pub fn clear_buffers(&self) -> io::Result<()> {
@@ -79,7 +94,7 @@ impl EcuConnection {
 
 // ---------------------------------------------------------------------------
 // AsyncRead / AsyncWrite delegation
-// Both SerialStream and TcpStream are Unpin, so Pin::new() is safe.
+// SerialStream, TcpStream and ReplayStream are all Unpin, so Pin::new() is safe.
 // ---------------------------------------------------------------------------
 
 impl AsyncRead for EcuConnection {
@@ -91,6 +106,7 @@ impl AsyncRead for EcuConnection {
         match self.get_mut() {
             EcuConnection::Serial(s) => Pin::new(s).poll_read(cx, buf),
             EcuConnection::Tcp(s) => Pin::new(s).poll_read(cx, buf),
+            EcuConnection::Replay(s) => Pin::new(s).poll_read(cx, buf),
         }
     }
 }
@@ -104,6 +120,7 @@ impl AsyncWrite for EcuConnection {
         match self.get_mut() {
             EcuConnection::Serial(s) => Pin::new(s).poll_write(cx, buf),
             EcuConnection::Tcp(s) => Pin::new(s).poll_write(cx, buf),
+            EcuConnection::Replay(s) => Pin::new(s).poll_write(cx, buf),
         }
     }
 
@@ -111,6 +128,7 @@ impl AsyncWrite for EcuConnection {
         match self.get_mut() {
             EcuConnection::Serial(s) => Pin::new(s).poll_flush(cx),
             EcuConnection::Tcp(s) => Pin::new(s).poll_flush(cx),
+            EcuConnection::Replay(s) => Pin::new(s).poll_flush(cx),
         }
     }
 
@@ -118,6 +136,7 @@ impl AsyncWrite for EcuConnection {
         match self.get_mut() {
             EcuConnection::Serial(s) => Pin::new(s).poll_shutdown(cx),
             EcuConnection::Tcp(s) => Pin::new(s).poll_shutdown(cx),
+            EcuConnection::Replay(s) => Pin::new(s).poll_shutdown(cx),
         }
     }
 }
@@ -0,0 +1,513 @@
+//! Per-channel publish rate decimation.
+//!
+//! RPM, MAP and TPS are worth every frame; temperatures, the battery voltage
+//! and the status bytes are not.  A `[publish_intervals]` table publishes a
+//! topic, or a whole channel group, at most once per interval:
+//!
+//! ```toml
+//! [publish_intervals.temperature]
+//! interval_ms = 1000
+//! method      = "mean"
+//!
+//! [publish_intervals.BAT]
+//! interval_ms = 1000
+//! method      = "min"
+//! ```
+//!
+//! [`Decimator`] collects the values of each window and publishes one when it
+//! ends, combined by `method`: `last` (default), `mean`, `min` or `max`,
+//! rounded to the decimals the topic is published with.  Text and flag topics
+//! always use the last value.  A topic entry overrides its group's.
+//!
+//! Groups are the channel categories (`engine`, `temperature`, `o2`, `fuel`,
+//! `ignition`, `correction`, `flex`, `boost`, `vvt`, `vehicle`, `system`,
+//! `can`, `status`, `raw`); the flags of a status byte (`ENG/running`, …)
+//! belong to the byte's group.  Renamed CAN inputs stay in `can`, `AFR`,
+//! `LAMBDA` and `AFR_ERROR` are in `o2` and the fuel usage and trip topics in
+//! `fuel`.  Derived channels, `NER/error` and the `QUALITY` topics belong to
+//! no group and only follow their own topic entry.
+
+use crate::afr::{AFR_ERROR_TOPIC, AFR_TOPIC, LAMBDA_TOPIC};
+use crate::can_inputs::CanInputs;
+use crate::channels::{Category, channel};
+use crate::config::AppConfig;
+use crate::ecu_data_parser::{ERROR_NAME_TOPIC, is_frame_topic};
+use crate::errors::{ConfigError, Result};
+use crate::fuel::FUEL_TOPICS;
+use std::collections::HashMap;
+
+/// A window without values for longer than this (reconnect, partial polling)
+/// is restarted, so stale values are not published (ms).  Slow polling
+/// raises it to [`GAP_POLLS`] poll intervals.
+const MAX_GAP_MS: u64 = 2_000;
+
+/// Missed polls before a window counts as interrupted.
+const GAP_POLLS: u64 = 3;
+
+/// How the values of a window become the published one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Aggregate {
+    #[default]
+    Last,
+    Mean,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    /// Parse `"last"`, `"mean"`, `"min"` or `"max"` (case-insensitive).
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "last" => Some(Aggregate::Last),
+            "mean" | "avg" | "average" => Some(Aggregate::Mean),
+            "min" => Some(Aggregate::Min),
+            "max" => Some(Aggregate::Max),
+            _ => None,
+        }
+    }
+}
+
+/// Publish interval of a topic or group.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Rate {
+    interval_ms: u64,
+    aggregate: Aggregate,
+}
+
+/// Values of one topic since its window started.
+#[derive(Debug, Clone, PartialEq)]
+struct Window {
+    started_ms: u64,
+    /// Time of the latest value
+    last_ms: u64,
+    last: String,
+    /// Count, sum, min and max of the numeric values
+    count: u32,
+    sum: f64,
+    min: f64,
+    max: f64,
+    /// Most decimals among the values
+    decimals: usize,
+}
+
+impl Window {
+    fn new(started_ms: u64) -> Self {
+        Self {
+            started_ms,
+            last_ms: started_ms,
+            last: String::new(),
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            decimals: 0,
+        }
+    }
+
+    fn add(&mut self, value: String, now_ms: u64) {
+        self.last_ms = now_ms;
+        if let Ok(v) = value.parse::<f64>() {
+            self.count += 1;
+            self.sum += v;
+            self.min = self.min.min(v);
+            self.max = self.max.max(v);
+            let decimals = value.split_once('.').map_or(0, |(_, f)| f.len());
+            self.decimals = self.decimals.max(decimals);
+        }
+        self.last = value;
+    }
+
+    fn value(self, aggregate: Aggregate) -> String {
+        // Text values, or a window that ended on one, keep the last value
+        if self.count == 0 || self.last.parse::<f64>().is_err() {
+            return self.last;
+        }
+        let value = match aggregate {
+            Aggregate::Last => return self.last,
+            Aggregate::Mean => self.sum / self.count as f64,
+            Aggregate::Min => self.min,
+            Aggregate::Max => self.max,
+        };
+        format!("{:.*}", self.decimals, value)
+    }
+}
+
+/// Registry group of `topic`: its channel's category, or for a status flag
+/// the category of its status byte.  `NER/error` is no flag of `NER` and
+/// belongs to no group.
+fn group_of(topic: &str) -> Option<Category> {
+    if topic.eq_ignore_ascii_case(ERROR_NAME_TOPIC) {
+        return None;
+    }
+    let code = topic.split('/').next().unwrap_or(topic);
+    channel(code).map(|c| c.category)
+}
+
+/// Thins out topics with a publish interval.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Decimator {
+    /// Upper-case topic → rate
+    topics: HashMap<String, Rate>,
+    groups: Vec<(Category, Rate)>,
+    /// Upper-case topic → group, for topics outside the channel registry
+    members: HashMap<String, Category>,
+    windows: HashMap<String, Window>,
+    max_gap_ms: u64,
+}
+
+impl Default for Decimator {
+    fn default() -> Self {
+        Self {
+            topics: HashMap::new(),
+            groups: Vec::new(),
+            members: HashMap::new(),
+            windows: HashMap::new(),
+            max_gap_ms: MAX_GAP_MS,
+        }
+    }
+}
+
+impl Decimator {
+    /// Publish `topic` every `interval_ms`, combining values by `aggregate`.
+    pub fn with_topic(mut self, topic: &str, interval_ms: u64, aggregate: Aggregate) -> Self {
+        let rate = Rate {
+            interval_ms,
+            aggregate,
+        };
+        self.topics.insert(topic.to_uppercase(), rate);
+        self
+    }
+
+    /// Publish every topic of `group` every `interval_ms`.
+    pub fn with_group(mut self, group: Category, interval_ms: u64, aggregate: Aggregate) -> Self {
+        let rate = Rate {
+            interval_ms,
+            aggregate,
+        };
+        self.groups.retain(|(g, _)| *g != group);
+        self.groups.push((group, rate));
+        self
+    }
+
+    /// Count `topic`, which the channel registry does not know, in `group`.
+    pub fn with_member(mut self, topic: &str, group: Category) -> Self {
+        self.members.insert(topic.to_uppercase(), group);
+        self
+    }
+
+    /// Restart windows only after [`GAP_POLLS`] missed polls when polling
+    /// every `refresh_rate_ms`.
+    pub fn with_refresh_rate(mut self, refresh_rate_ms: u64) -> Self {
+        self.max_gap_ms = MAX_GAP_MS.max(GAP_POLLS * refresh_rate_ms);
+        self
+    }
+
+    /// Decimator from the `[publish_intervals]` tables, `None` without any.
+    /// `can_inputs` places renamed CAN topics in the `can` group.
+    pub fn from_config(config: &AppConfig, can_inputs: &CanInputs) -> Result<Option<Self>> {
+        if config.publish_intervals.is_empty() {
+            return Ok(None);
+        }
+        let mut decimator = Self::default().with_refresh_rate(config.refresh_rate_ms);
+        for (name, settings) in &config.publish_intervals {
+            let field = format!("publish_intervals.{}", name);
+            let group = Category::from_config(name);
+            if group.is_none() && !is_frame_topic(name, config, can_inputs) {
+                return Err(ConfigError::InvalidValue {
+                    field,
+                    message: format!("unknown topic or group \"{}\"", name),
+                }
+                .into());
+            }
+            if settings.interval_ms == 0 {
+                return Err(ConfigError::InvalidValue {
+                    field,
+                    message: "interval_ms must be greater than 0".to_string(),
+                }
+                .into());
+            }
+            let aggregate = match settings.method.as_deref() {
+                None => Aggregate::default(),
+                Some(method) => {
+                    Aggregate::from_config(method).ok_or_else(|| ConfigError::InvalidValue {
+                        field,
+                        message: format!(
+                            "method must be last, mean, min or max, got \"{}\"",
+                            method
+                        ),
+                    })?
+                }
+            };
+            decimator = match group {
+                Some(group) => decimator.with_group(group, settings.interval_ms, aggregate),
+                None => decimator.with_topic(name, settings.interval_ms, aggregate),
+            };
+        }
+        let members = can_inputs
+            .inputs()
+            .iter()
+            .map(|input| (input.topic.as_str(), Category::Can))
+            .chain(
+                [AFR_TOPIC, LAMBDA_TOPIC, AFR_ERROR_TOPIC]
+                    .into_iter()
+                    .map(|topic| (topic, Category::Afr)),
+            )
+            .chain(FUEL_TOPICS.into_iter().map(|topic| (topic, Category::Fuel)));
+        for (topic, group) in members {
+            decimator = decimator.with_member(topic, group);
+        }
+        Ok(Some(decimator))
+    }
+
+    fn rate(&self, topic: &str) -> Option<Rate> {
+        let key = topic.to_uppercase();
+        if let Some(rate) = self.topics.get(&key) {
+            return Some(*rate);
+        }
+        let group = self
+            .members
+            .get(&key)
+            .copied()
+            .or_else(|| group_of(topic))?;
+        self.groups
+            .iter()
+            .find(|(g, _)| *g == group)
+            .map(|(_, rate)| *rate)
+    }
+
+    /// The (topic, value) pairs of `params` to publish at `now_ms`: topics
+    /// without an interval as they are, the others once their window ends.
+    pub fn apply<'a>(
+        &mut self,
+        params: Vec<(&'a str, String)>,
+        now_ms: u64,
+    ) -> Vec<(&'a str, String)> {
+        let mut out = Vec::with_capacity(params.len());
+        for (topic, value) in params {
+            let Some(rate) = self.rate(topic) else {
+                out.push((topic, value));
+                continue;
+            };
+            let window = self
+                .windows
+                .entry(topic.to_string())
+                .or_insert_with(|| Window::new(now_ms));
+            if now_ms.saturating_sub(window.last_ms) > self.max_gap_ms {
+                *window = Window::new(now_ms);
+            }
+            window.add(value, now_ms);
+            if now_ms.saturating_sub(window.started_ms) >= rate.interval_ms
+                && let Some(window) = self.windows.remove(topic)
+            {
+                out.push((topic, window.value(rate.aggregate)));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{CanInputConfig, IntervalConfig};
+
+    fn frame(rpm: u32, clt: &str) -> Vec<(&'static str, String)> {
+        vec![
+            ("RPM", rpm.to_string()),
+            ("CLT", clt.to_string()),
+            ("ENG/running", "true".to_string()),
+        ]
+    }
+
+    fn value_of(params: &[(&str, String)], topic: &str) -> Option<String> {
+        params
+            .iter()
+            .find(|(t, _)| *t == topic)
+            .map(|(_, v)| v.clone())
+    }
+
+    #[test]
+    fn test_methods() {
+        for (aggregate, expected) in [
+            (Aggregate::Last, "90.5"),
+            (Aggregate::Mean, "88.8"),
+            (Aggregate::Min, "86.0"),
+            (Aggregate::Max, "90.5"),
+        ] {
+            let mut d = Decimator::default().with_topic("clt", 1_000, aggregate);
+            assert_eq!(value_of(&d.apply(frame(3000, "86"), 0), "CLT"), None);
+            assert_eq!(value_of(&d.apply(frame(3000, "90"), 500), "CLT"), None);
+            let out = d.apply(frame(3000, "90.5"), 1_000);
+            assert_eq!(
+                value_of(&out, "CLT").as_deref(),
+                Some(expected),
+                "{:?}",
+                aggregate
+            );
+            assert_eq!(value_of(&out, "RPM").as_deref(), Some("3000"));
+        }
+    }
+
+    #[test]
+    fn test_windows_restart() {
+        let mut d = Decimator::default().with_topic("CLT", 1_000, Aggregate::Max);
+        d.apply(frame(3000, "95"), 0);
+        assert_eq!(
+            value_of(&d.apply(frame(3000, "90"), 1_000), "CLT").as_deref(),
+            Some("95")
+        );
+        assert_eq!(value_of(&d.apply(frame(3000, "91"), 1_020), "CLT"), None);
+        assert_eq!(
+            value_of(&d.apply(frame(3000, "92"), 2_020), "CLT").as_deref(),
+            Some("92")
+        );
+        // After a pause the old values are dropped
+        d.apply(frame(3000, "99"), 2_040);
+        assert_eq!(value_of(&d.apply(frame(3000, "80"), 9_000), "CLT"), None);
+    }
+
+    #[test]
+    fn test_groups_and_flags() {
+        let mut d = Decimator::default()
+            .with_group(Category::Status, 1_000, Aggregate::Mean)
+            .with_group(Category::Temperature, 500, Aggregate::Last)
+            .with_topic("CLT", 2_000, Aggregate::Last);
+        d.apply(frame(3000, "86"), 0);
+        let out = d.apply(frame(3000, "87"), 1_000);
+        assert_eq!(value_of(&out, "ENG/running").as_deref(), Some("true"));
+        assert_eq!(value_of(&out, "CLT"), None, "the topic entry wins");
+        assert_eq!(group_of("IAT"), Some(Category::Temperature));
+        assert_eq!(group_of("QUALITY"), None);
+    }
+
+    #[test]
+    fn test_error_name_follows_only_its_own_entry() {
+        let params = || vec![("NER", "0".to_string()), ("NER/error", "none".to_string())];
+        let mut d = Decimator::default().with_group(Category::System, 1_000, Aggregate::Last);
+        assert_eq!(group_of("NER/error"), None);
+        let out = d.apply(params(), 0);
+        assert_eq!(value_of(&out, "NER"), None);
+        assert_eq!(value_of(&out, "NER/error").as_deref(), Some("none"));
+
+        let mut d = d.with_topic("NER/error", 1_000, Aggregate::Last);
+        assert_eq!(value_of(&d.apply(params(), 0), "NER/error"), None);
+    }
+
+    #[test]
+    fn test_slow_polling_keeps_windows() {
+        let mut d =
+            Decimator::default()
+                .with_refresh_rate(2_500)
+                .with_topic("CLT", 1_000, Aggregate::Last);
+        assert_eq!(value_of(&d.apply(frame(3000, "86"), 0), "CLT"), None);
+        assert_eq!(
+            value_of(&d.apply(frame(3000, "87"), 2_500), "CLT").as_deref(),
+            Some("87")
+        );
+        assert_eq!(value_of(&d.apply(frame(3000, "88"), 5_000), "CLT"), None);
+        assert_eq!(
+            value_of(&d.apply(frame(3000, "89"), 7_500), "CLT").as_deref(),
+            Some("89")
+        );
+    }
+
+    #[test]
+    fn test_from_config() {
+        let can_inputs = CanInputs::default();
+        assert!(
+            Decimator::from_config(&AppConfig::default(), &can_inputs)
+                .unwrap()
+                .is_none()
+        );
+        let mut config = AppConfig {
+            publish_intervals: HashMap::from([
+                (
+                    "Temperature".to_string(),
+                    IntervalConfig {
+                        interval_ms: 1_000,
+                        method: Some("mean".to_string()),
+                    },
+                ),
+                (
+                    "bat".to_string(),
+                    IntervalConfig {
+                        interval_ms: 500,
+                        method: None,
+                    },
+                ),
+            ]),
+            ..AppConfig::default()
+        };
+        let d = Decimator::from_config(&config, &can_inputs)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            d.rate("IAT"),
+            Some(Rate {
+                interval_ms: 1_000,
+                aggregate: Aggregate::Mean
+            })
+        );
+        assert_eq!(d.rate("BAT").unwrap().aggregate, Aggregate::Last);
+        assert_eq!(d.rate("RPM"), None);
+
+        config.publish_intervals.insert(
+            "MAP".to_string(),
+            IntervalConfig {
+                interval_ms: 500,
+                method: Some("median".to_string()),
+            },
+        );
+        assert!(Decimator::from_config(&config, &can_inputs).is_err());
+
+        config.publish_intervals.insert(
+            "rmp".to_string(),
+            IntervalConfig {
+                interval_ms: 500,
+                method: None,
+            },
+        );
+        config.publish_intervals.remove("MAP");
+        let err = Decimator::from_config(&config, &can_inputs).unwrap_err();
+        assert!(err.to_string().contains("publish_intervals.rmp"), "{}", err);
+    }
+
+    #[test]
+    fn test_groups_outside_the_registry() {
+        let config = AppConfig {
+            can_inputs: HashMap::from([(
+                "CN03".to_string(),
+                CanInputConfig {
+                    topic: Some("EGT1".to_string()),
+                    ..CanInputConfig::default()
+                },
+            )]),
+            publish_intervals: ["can", "o2", "fuel"]
+                .into_iter()
+                .map(|group| {
+                    let interval = IntervalConfig {
+                        interval_ms: 1_000,
+                        method: None,
+                    };
+                    (group.to_string(), interval)
+                })
+                .collect(),
+            ..AppConfig::default()
+        };
+        let can_inputs = CanInputs::from_config(&config).unwrap();
+        let d = Decimator::from_config(&config, &can_inputs)
+            .unwrap()
+            .unwrap();
+        for topic in [
+            "EGT1",
+            "CN01",
+            "LAMBDA",
+            "AFR_ERROR",
+            "FUEL_FLOW",
+            "TRIP_DIST",
+        ] {
+            assert!(d.rate(topic).is_some(), "{}", topic);
+        }
+        assert_eq!(d.rate("QUALITY"), None);
+    }
+}
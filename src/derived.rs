@@ -0,0 +1,562 @@
+//! User-defined derived channels.
+//!
+//! The `[derived]` table of the settings file maps a topic name to an
+//! arithmetic expression over registry channels:
+//!
+//! ```toml
+//! [derived]
+//! boost_psi = "(MAP - BAR) * 0.145"
+//! duty_pct  = "PW1 * RPM / 1200"
+//! ```
+//!
+//! Expressions are evaluated against every parsed frame and published under
+//! `<mqtt_base_topic><name>` and shown in the TUI next to the native channels.
+//!
+//! | Syntax | Meaning |
+//! |--------|---------|
+//! | `RPM`, `map`, `CN01` | Channel value in its metric registry unit (case-insensitive) |
+//! | `1`, `0.145` | Number |
+//! | `+ - * / ^` | Arithmetic, usual precedence; `^` is right-associative |
+//! | `abs(x)`, `min(a, b, …)`, `max(a, b, …)` | |
+//! | `round(x)`, `round(x, n)` | Round to `n` decimals |
+//! | `clamp(x, lo, hi)` | Limit `x` to `lo..=hi` |
+//!
+//! A frame that lacks one of the channels, or where the result is not a finite
+//! number (division by zero), simply publishes nothing for that channel.
+//! Values are published with at most three decimals.
+
+use crate::afr::{AFR_ERROR_TOPIC, AFR_TOPIC, LAMBDA_TOPIC};
+use crate::channels::{Channel, channel};
+use crate::config::AppConfig;
+use crate::ecu_data_parser::{SpeeduinoData, channel_field};
+use crate::errors::{ConfigError, ExpressionError, Result};
+use crate::events::EVENTS_TOPIC;
+use crate::fuel::FUEL_TOPICS;
+use crate::limits::QUALITY_TOPIC;
+use crate::mqtt_handler::{AVAILABILITY_TOPIC, FRAME_TOPIC};
+
+/// Topics published by the bridge itself that a derived channel may not take.
+const RESERVED_TOPICS: &[&str] = &[
+    EVENTS_TOPIC,
+    "FIRMWARE",
+    "UNITS",
+    AFR_TOPIC,
+    LAMBDA_TOPIC,
+    AFR_ERROR_TOPIC,
+    QUALITY_TOPIC,
+    FRAME_TOPIC,
+    AVAILABILITY_TOPIC,
+];
+
+/// Decimal places derived values are rounded to before publishing.
+const DERIVED_DECIMALS: i32 = 3;
+
+type ExprResult<T> = std::result::Result<T, ExpressionError>;
+
+// ---------------------------------------------------------------------------
+// Expressions
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Abs,
+    Min,
+    Max,
+    Round,
+    Clamp,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "abs" => Some(Function::Abs),
+            "min" => Some(Function::Min),
+            "max" => Some(Function::Max),
+            "round" => Some(Function::Round),
+            "clamp" => Some(Function::Clamp),
+            _ => None,
+        }
+    }
+
+    /// Accepted argument counts, and how to describe them in an error.
+    fn arity(self) -> (usize, usize, &'static str) {
+        match self {
+            Function::Abs => (1, 1, "1"),
+            Function::Min | Function::Max => (2, usize::MAX, "2 or more"),
+            Function::Round => (1, 2, "1 or 2"),
+            Function::Clamp => (3, 3, "3"),
+        }
+    }
+}
+
+/// Parsed expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Channel(&'static Channel),
+    Neg(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+impl Expr {
+    /// Parse `source`, resolving channel names against the registry.
+    pub fn parse(source: &str) -> ExprResult<Self> {
+        let tokens = tokenize(source)?;
+        if tokens.is_empty() {
+            return Err(ExpressionError::Empty);
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.expr()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some((token, position)) => Err(unexpected(token, *position)),
+        }
+    }
+
+    /// Value for frame `d`, `None` when an input is missing or the result is
+    /// not finite.
+    pub fn eval(&self, d: &SpeeduinoData) -> Option<f64> {
+        let value = match self {
+            Expr::Number(n) => *n,
+            Expr::Channel(c) => c.value(d)?,
+            Expr::Neg(e) => -e.eval(d)?,
+            Expr::Binary(op, a, b) => {
+                let (a, b) = (a.eval(d)?, b.eval(d)?);
+                match op {
+                    Op::Add => a + b,
+                    Op::Sub => a - b,
+                    Op::Mul => a * b,
+                    Op::Div => a / b,
+                    Op::Pow => a.powf(b),
+                }
+            }
+            Expr::Call(function, args) => {
+                let args = args.iter().map(|a| a.eval(d)).collect::<Option<Vec<_>>>()?;
+                match function {
+                    Function::Abs => args[0].abs(),
+                    Function::Min => args.iter().copied().fold(f64::INFINITY, f64::min),
+                    Function::Max => args.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+                    Function::Round => {
+                        let scale = 10f64.powi(args.get(1).copied().unwrap_or(0.0) as i32);
+                        (args[0] * scale).round() / scale
+                    }
+                    Function::Clamp => args[0].max(args[1]).min(args[2]),
+                }
+            }
+        };
+        value.is_finite().then_some(value)
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tokenizer and parser
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+fn unexpected(token: &Token, position: usize) -> ExpressionError {
+    let found = match token {
+        Token::Number(n) => n.to_string(),
+        Token::Ident(name) => name.clone(),
+        Token::Op(c) => c.to_string(),
+        Token::LParen => "(".to_string(),
+        Token::RParen => ")".to_string(),
+        Token::Comma => ",".to_string(),
+    };
+    ExpressionError::Unexpected { found, position }
+}
+
+/// Split `source` into tokens with their 1-based character positions.
+fn tokenize(source: &str) -> ExprResult<Vec<(Token, usize)>> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let position = i + 1;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() || c == '.' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let n = text.parse().map_err(|_| ExpressionError::Unexpected {
+                found: text.clone(),
+                position,
+            })?;
+            tokens.push((Token::Number(n), position));
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push((Token::Ident(chars[start..i].iter().collect()), position));
+            continue;
+        }
+        let token = match c {
+            '+' | '-' | '*' | '/' | '^' => Token::Op(c),
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            ',' => Token::Comma,
+            _ => {
+                return Err(ExpressionError::Unexpected {
+                    found: c.to_string(),
+                    position,
+                });
+            }
+        };
+        tokens.push((token, position));
+        i += 1;
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent parser:
+///
+/// ```text
+/// expr    = term (("+" | "-") term)*
+/// term    = unary (("*" | "/") unary)*
+/// unary   = ("-" | "+") unary | power
+/// power   = primary ("^" unary)?
+/// primary = number | name | name "(" expr ("," expr)* ")" | "(" expr ")"
+/// ```
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&(Token, usize)> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self, expected: &'static str) -> ExprResult<(Token, usize)> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or(ExpressionError::UnexpectedEnd { expected })?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    /// Consume the next token if it is one of `ops`.
+    fn eat_op(&mut self, ops: &[char]) -> Option<char> {
+        match self.peek() {
+            Some((Token::Op(c), _)) if ops.contains(c) => {
+                let c = *c;
+                self.pos += 1;
+                Some(c)
+            }
+            _ => None,
+        }
+    }
+
+    fn expect(&mut self, token: Token, expected: &'static str) -> ExprResult<()> {
+        match self.next(expected)? {
+            (t, _) if t == token => Ok(()),
+            (t, position) => Err(unexpected(&t, position)),
+        }
+    }
+
+    fn expr(&mut self) -> ExprResult<Expr> {
+        let mut lhs = self.term()?;
+        while let Some(c) = self.eat_op(&['+', '-']) {
+            let op = if c == '+' { Op::Add } else { Op::Sub };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> ExprResult<Expr> {
+        let mut lhs = self.unary()?;
+        while let Some(c) = self.eat_op(&['*', '/']) {
+            let op = if c == '*' { Op::Mul } else { Op::Div };
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> ExprResult<Expr> {
+        match self.eat_op(&['-', '+']) {
+            Some('-') => Ok(Expr::Neg(Box::new(self.unary()?))),
+            Some(_) => self.unary(),
+            None => self.power(),
+        }
+    }
+
+    fn power(&mut self) -> ExprResult<Expr> {
+        let base = self.primary()?;
+        if self.eat_op(&['^']).is_some() {
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary(Op::Pow, Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> ExprResult<Expr> {
+        match self.next("a number, channel or '('")? {
+            (Token::Number(n), _) => Ok(Expr::Number(n)),
+            (Token::LParen, _) => {
+                let inner = self.expr()?;
+                self.expect(Token::RParen, "')'")?;
+                Ok(inner)
+            }
+            (Token::Ident(name), _) if matches!(self.peek(), Some((Token::LParen, _))) => {
+                self.pos += 1;
+                self.call(&name)
+            }
+            (Token::Ident(name), _) => channel(&name)
+                .map(Expr::Channel)
+                .ok_or(ExpressionError::UnknownChannel(name)),
+            (token, position) => Err(unexpected(&token, position)),
+        }
+    }
+
+    /// Arguments of `name(`…`)`; the opening parenthesis is already consumed.
+    fn call(&mut self, name: &str) -> ExprResult<Expr> {
+        let function =
+            Function::from_name(name).ok_or(ExpressionError::UnknownFunction(name.to_string()))?;
+        let mut args = vec![self.expr()?];
+        loop {
+            match self.next("',' or ')'")? {
+                (Token::Comma, _) => args.push(self.expr()?),
+                (Token::RParen, _) => break,
+                (token, position) => return Err(unexpected(&token, position)),
+            }
+        }
+        let (min, max, expected) = function.arity();
+        if !(min..=max).contains(&args.len()) {
+            return Err(ExpressionError::WrongArgumentCount {
+                function: name.to_lowercase(),
+                expected,
+                actual: args.len(),
+            });
+        }
+        Ok(Expr::Call(function, args))
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Derived channels
+// ---------------------------------------------------------------------------
+
+/// Check a `[derived]` name: it becomes a topic, so it must be a plain word
+/// that does not shadow a native channel or bridge topic.
+pub fn check_name(name: &str) -> std::result::Result<(), String> {
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err("names may only contain letters, digits and '_'".to_string());
+    }
+    if channel_field(name).is_some()
+        || RESERVED_TOPICS
+            .iter()
+            .chain(FUEL_TOPICS.iter())
+            .any(|topic| topic.eq_ignore_ascii_case(name))
+    {
+        return Err(format!("'{}' is already a published topic", name));
+    }
+    Ok(())
+}
+
+/// One `[derived]` entry.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DerivedChannel {
+    pub name: String,
+    pub expr: Expr,
+}
+
+/// All derived channels, sorted by name.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DerivedChannels {
+    channels: Vec<DerivedChannel>,
+}
+
+impl DerivedChannels {
+    /// Compile the `[derived]` table.
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        let mut channels = Vec::with_capacity(config.derived.len());
+        for (name, source) in &config.derived {
+            let field = format!("derived.{}", name);
+            if let Err(message) = check_name(name) {
+                return Err(ConfigError::InvalidValue { field, message }.into());
+            }
+            let expr = Expr::parse(source).map_err(|e| ConfigError::InvalidValue {
+                field,
+                message: format!("{} in \"{}\"", e, source),
+            })?;
+            channels.push(DerivedChannel {
+                name: name.clone(),
+                expr,
+            });
+        }
+        channels.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Self { channels })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.channels.is_empty()
+    }
+
+    /// Names of the channels, sorted.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.channels.iter().map(|c| c.name.as_str())
+    }
+
+    /// (name, value) of every channel that evaluates for frame `d`.
+    pub fn values<'a>(&'a self, d: &SpeeduinoData) -> Vec<(&'a str, f64)> {
+        self.channels
+            .iter()
+            .filter_map(|c| Some((c.name.as_str(), c.expr.eval(d)?)))
+            .collect()
+    }
+
+    /// Published text of a derived value: at most three decimals, no
+    /// trailing zeros.
+    pub fn format(value: f64) -> String {
+        let scale = 10f64.powi(DERIVED_DECIMALS);
+        ((value * scale).round() / scale).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn frame() -> SpeeduinoData {
+        SpeeduinoData {
+            rpm: 3000,
+            map: 180,
+            baro: 100,
+            pw1: 50,
+            coolant_raw: 130,
+            ..SpeeduinoData::default()
+        }
+    }
+
+    fn eval(source: &str) -> Option<f64> {
+        Expr::parse(source).unwrap().eval(&frame())
+    }
+
+    #[test]
+    fn test_arithmetic_and_precedence() {
+        assert_eq!(eval("(MAP - BAR) * 0.5"), Some(40.0));
+        assert_eq!(eval("MAP - BAR * 0.5"), Some(130.0));
+        assert_eq!(eval("-2 ^ 2"), Some(-4.0));
+        assert_eq!(eval("2 ^ 3 ^ 2"), Some(512.0));
+        assert_eq!(eval("clt + 0"), Some(90.0));
+        // PW1 is in ms (5.0)
+        assert_eq!(eval("PW1 * RPM / 1200"), Some(12.5));
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(eval("abs(BAR - MAP)"), Some(80.0));
+        assert_eq!(eval("min(MAP, BAR, 50)"), Some(50.0));
+        assert_eq!(eval("max(MAP, BAR)"), Some(180.0));
+        assert_eq!(eval("round(10 / 3, 2)"), Some(3.33));
+        assert_eq!(eval("clamp(MAP, 0, 150)"), Some(150.0));
+    }
+
+    #[test]
+    fn test_missing_input_and_division_by_zero() {
+        assert_eq!(eval("MAP / TPS"), None);
+        assert_eq!(eval("EMP - MAP"), None);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(Expr::parse("  "), Err(ExpressionError::Empty));
+        assert_eq!(
+            Expr::parse("MAP - XYZ"),
+            Err(ExpressionError::UnknownChannel("XYZ".to_string()))
+        );
+        assert_eq!(
+            Expr::parse("MAP $ 2"),
+            Err(ExpressionError::Unexpected {
+                found: "$".to_string(),
+                position: 5
+            })
+        );
+        assert_eq!(
+            Expr::parse("(MAP - BAR"),
+            Err(ExpressionError::UnexpectedEnd { expected: "')'" })
+        );
+        assert_eq!(
+            Expr::parse("MAP BAR"),
+            Err(ExpressionError::Unexpected {
+                found: "BAR".to_string(),
+                position: 5
+            })
+        );
+        assert_eq!(
+            Expr::parse("sqrt(MAP)"),
+            Err(ExpressionError::UnknownFunction("sqrt".to_string()))
+        );
+        assert!(matches!(
+            Expr::parse("clamp(MAP, 1)"),
+            Err(ExpressionError::WrongArgumentCount { actual: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_names() {
+        assert!(check_name("boost_psi").is_ok());
+        assert!(check_name("boost psi").is_err());
+        assert!(check_name("").is_err());
+        assert!(check_name("map").is_err());
+        assert!(check_name("units").is_err());
+    }
+
+    #[test]
+    fn test_from_config_sorts_and_rejects_invalid() {
+        let mut config = AppConfig {
+            derived: HashMap::from([
+                ("z_boost".to_string(), "MAP - BAR".to_string()),
+                ("a_half".to_string(), "RPM / 2".to_string()),
+            ]),
+            ..AppConfig::default()
+        };
+        let derived = DerivedChannels::from_config(&config).unwrap();
+        assert_eq!(
+            derived.values(&frame()),
+            vec![("a_half", 1500.0), ("z_boost", 80.0)]
+        );
+
+        config
+            .derived
+            .insert("broken".to_string(), "RPM +".to_string());
+        assert!(DerivedChannels::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_format() {
+        assert_eq!(DerivedChannels::format(11.6), "11.6");
+        assert_eq!(DerivedChannels::format(2.0 / 3.0), "0.667");
+        assert_eq!(DerivedChannels::format(80.0), "80");
+    }
+}
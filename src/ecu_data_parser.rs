@@ -9,32 +9,45 @@
 //! |-------------|--------|
 //! | 138 bytes   | Real Speeduino ECU (current firmware, `LOG_ENTRY_SIZE = 138`) |
 //! | 130 bytes   | speeduino-serial-sim (identical layout, missing PW5–PW8 at bytes 130–137) |
+//! | 128 bytes   | Firmware before 202305 (single-byte `tpsDOT`/`mapDOT`) |
 //!
-//! **Note**: the secondary-serial 'A' command (75 bytes) uses an incompatible byte layout
-//! and is NOT supported here. Connect via the primary serial interface (USB or TCP/WiFi bridge).
+//! Offsets are not hard-coded here: the parser decodes from the active
+//! [`Layout`](crate::packet_layout::Layout), chosen from the firmware release
+//! reported by the connect handshake (or pinned with `ecu_layout`).  Byte numbers
+//! in the field docs below refer to the current layout.
+//!
+//! The secondary-serial 'A' command (75 bytes) uses its own, much shorter layout; select
+//! it with `ecu_layout = "secondary"` when the bridge can only reach that port.  Fields
+//! it does not carry are reported as missing (optional fields stay `None`) and are
+//! never published.
 //!
 //! All multi-byte values in the Speeduino protocol are **little-endian** (low byte first).
 //! Temperatures are stored with a +40 offset to fit in an unsigned byte; call the
 //! helper methods (e.g. [`SpeeduinoData::iat_celsius()`]) to get the real value.
-
+//!
+//! When `ecu_channels` limits polling to a subset of channels, the packet is
+//! assembled from partial `'r'` reads; fields that were not fetched are recorded in
+//! [`SpeeduinoData::missing`] and left out of the published parameters.
+
+use crate::afr::{AFR_ERROR_TOPIC, AFR_TOPIC, LAMBDA_TOPIC, Wideband};
+use crate::can_inputs::CanInputs;
+use crate::capture::timestamp_us;
+use crate::channels::{CHANNELS, channel};
 use crate::config::AppConfig;
-use crate::errors::{ParseError, Result};
-use crate::mqtt_handler::{MqttMessage, build_topic_path};
+use crate::decimation::Decimator;
+use crate::derived::DerivedChannels;
+use crate::errors::{ConfigError, ParseError, Result};
+use crate::fuel::{FUEL_TOPICS, FuelTracker};
+use crate::limits::{Limits, QUALITY_TOPIC, VIOLATIONS_TOPIC, Validation};
+use crate::mqtt_handler::{FRAME_TOPIC, MqttMessage, PublishMode, build_topic_path, frame_json};
+use crate::on_change::ChangeFilter;
+use crate::packet_layout::{Field, FieldSet, Layout, PRIMARY};
+use crate::status_flags::{STATUS_FLAGS, status_flag};
+use crate::units::Units;
 use std::sync::Arc;
 use tokio::sync::mpsc;
 use tracing::{debug, warn};
 
-// ---------------------------------------------------------------------------
-// Validation constants
-// ---------------------------------------------------------------------------
-const RPM_MAX: u16 = 15_000;
-const TEMP_MIN: i16 = -40;
-const TEMP_MAX: i16 = 200;
-const MAP_MAX: u16 = 400; // kPa
-const TPS_MAX: u8 = 100; // %
-const BATTERY_MIN: f32 = 8.0; // V
-const BATTERY_MAX: f32 = 18.0; // V
-
 // ---------------------------------------------------------------------------
 // Data structure
 // ---------------------------------------------------------------------------
@@ -232,9 +245,227 @@ pub struct SpeeduinoData {
     pub pw7: Option<u16>,
     /// Bytes 136–137 – injector pulse width 8
     pub pw8: Option<u16>,
+
+    /// Fields not fetched from the ECU for this frame (partial `'r'` polling).
+    /// Their struct values are placeholders and must not be published.
+    pub missing: FieldSet,
 }
 
 impl SpeeduinoData {
+    /// True when `field` was delivered by the ECU in this frame.
+    pub fn has(&self, field: Field) -> bool {
+        !self.missing.contains(field)
+    }
+
+    /// Store a decoded (already scaled) value into the struct field for `field`.
+    fn set(&mut self, field: Field, v: i32) {
+        match field {
+            Field::Secl => self.secl = v as u8,
+            Field::Status1 => self.status1 = v as u8,
+            Field::Engine => self.engine = v as u8,
+            Field::SyncLossCounter => self.sync_loss_counter = v as u8,
+            Field::Map => self.map = v as u16,
+            Field::IatRaw => self.iat_raw = v as u8,
+            Field::CoolantRaw => self.coolant_raw = v as u8,
+            Field::BatCorrection => self.bat_correction = v as u8,
+            Field::Battery10 => self.battery_10 = v as u8,
+            Field::O2Primary => self.o2_primary = v as u8,
+            Field::EgoCorrection => self.ego_correction = v as u8,
+            Field::IatCorrection => self.iat_correction = v as u8,
+            Field::WueCorrection => self.wue_correction = v as u8,
+            Field::Rpm => self.rpm = v as u16,
+            Field::TaeAmount => self.tae_amount_raw = v as u8,
+            Field::Corrections => self.corrections = v as u16,
+            Field::Ve1 => self.ve1 = v as u8,
+            Field::Ve2 => self.ve2 = v as u8,
+            Field::AfrTarget => self.afr_target = v as u8,
+            Field::TpsDot => self.tps_dot = v as u16,
+            Field::Advance => self.advance = v as u8,
+            Field::Tps => self.tps = v as u8,
+            Field::LoopsPerSecond => self.loops_per_second = v as u16,
+            Field::FreeRam => self.free_ram = v as u16,
+            Field::BoostTarget => self.boost_target_raw = v as u8,
+            Field::BoostDuty => self.boost_duty_raw = v as u8,
+            Field::Spark => self.spark = v as u8,
+            Field::RpmDot => self.rpm_dot = v as i16,
+            Field::EthanolPct => self.ethanol_pct = v as u8,
+            Field::FlexCorrection => self.flex_correction = v as u8,
+            Field::FlexIgnCorrection => self.flex_ign_correction = v as u8,
+            Field::IdleLoad => self.idle_load = v as u8,
+            Field::TestOutputs => self.test_outputs = v as u8,
+            Field::O2Secondary => self.o2_secondary = v as u8,
+            Field::Baro => self.baro = v as u8,
+            Field::CanIn0 => self.canin[0] = v as u16,
+            Field::CanIn1 => self.canin[1] = v as u16,
+            Field::CanIn2 => self.canin[2] = v as u16,
+            Field::CanIn3 => self.canin[3] = v as u16,
+            Field::CanIn4 => self.canin[4] = v as u16,
+            Field::CanIn5 => self.canin[5] = v as u16,
+            Field::CanIn6 => self.canin[6] = v as u16,
+            Field::CanIn7 => self.canin[7] = v as u16,
+            Field::CanIn8 => self.canin[8] = v as u16,
+            Field::CanIn9 => self.canin[9] = v as u16,
+            Field::CanIn10 => self.canin[10] = v as u16,
+            Field::CanIn11 => self.canin[11] = v as u16,
+            Field::CanIn12 => self.canin[12] = v as u16,
+            Field::CanIn13 => self.canin[13] = v as u16,
+            Field::CanIn14 => self.canin[14] = v as u16,
+            Field::CanIn15 => self.canin[15] = v as u16,
+            Field::TpsAdc => self.tps_adc = v as u8,
+            Field::NextError => self.next_error = v as u8,
+            Field::Pw1 => self.pw1 = v as u16,
+            Field::Pw2 => self.pw2 = v as u16,
+            Field::Pw3 => self.pw3 = v as u16,
+            Field::Pw4 => self.pw4 = v as u16,
+            Field::Status3 => self.status3 = v as u8,
+            Field::EngineProtectStatus => self.engine_protect_status = v as u8,
+            Field::FuelLoad => self.fuel_load = v as u16,
+            Field::IgnLoad => self.ign_load = v as u16,
+            Field::Dwell => self.dwell = v as u16,
+            Field::ClIdleTarget => self.cl_idle_target = v as u8,
+            Field::MapDot => self.map_dot = v as u16,
+            Field::Vvt1Angle => self.vvt1_angle = v as i16,
+            Field::Vvt1TargetAngle => self.vvt1_target_angle = v as u8,
+            Field::Vvt1Duty => self.vvt1_duty = v as u8,
+            Field::FlexBoostCorrection => self.flex_boost_correction = v as u16,
+            Field::BaroCorrection => self.baro_correction = v as u8,
+            Field::VeCurrent => self.ve_current = v as u8,
+            Field::AseValue => self.ase_value = v as u8,
+            Field::Vss => self.vss = v as u16,
+            Field::Gear => self.gear = v as u8,
+            Field::FuelPressure => self.fuel_pressure = v as u8,
+            Field::OilPressure => self.oil_pressure = v as u8,
+            Field::WmiPw => self.wmi_pw = v as u8,
+            Field::Status4 => self.status4 = v as u8,
+            Field::Vvt2Angle => self.vvt2_angle = v as i16,
+            Field::Vvt2TargetAngle => self.vvt2_target_angle = v as u8,
+            Field::Vvt2Duty => self.vvt2_duty = v as u8,
+            Field::OutputsStatus => self.outputs_status = v as u8,
+            Field::FuelTempRaw => self.fuel_temp_raw = v as u8,
+            Field::FuelTempCorrection => self.fuel_temp_correction = v as u8,
+            Field::Advance1 => self.advance1 = v as u8,
+            Field::Advance2 => self.advance2 = v as u8,
+            Field::TsSdStatus => self.ts_sd_status = v as u8,
+            Field::Emap => self.emap = Some(v as u16),
+            Field::FanDuty => self.fan_duty = Some(v as u8),
+            Field::AirConStatus => self.air_con_status = Some(v as u8),
+            Field::ActualDwell => self.actual_dwell = Some(v as u16),
+            Field::Status5 => self.status5 = Some(v as u8),
+            Field::KnockCount => self.knock_count = Some(v as u8),
+            Field::KnockRetard => self.knock_retard = Some(v as u8),
+            Field::Pw5 => self.pw5 = Some(v as u16),
+            Field::Pw6 => self.pw6 = Some(v as u16),
+            Field::Pw7 => self.pw7 = Some(v as u16),
+            Field::Pw8 => self.pw8 = Some(v as u16),
+        }
+    }
+
+    /// Stored value of `field`, `None` when the ECU did not send it.
+    pub fn get(&self, field: Field) -> Option<i32> {
+        if !self.has(field) {
+            return None;
+        }
+        match field {
+            Field::Secl => Some(self.secl as i32),
+            Field::Status1 => Some(self.status1 as i32),
+            Field::Engine => Some(self.engine as i32),
+            Field::SyncLossCounter => Some(self.sync_loss_counter as i32),
+            Field::Map => Some(self.map as i32),
+            Field::IatRaw => Some(self.iat_raw as i32),
+            Field::CoolantRaw => Some(self.coolant_raw as i32),
+            Field::BatCorrection => Some(self.bat_correction as i32),
+            Field::Battery10 => Some(self.battery_10 as i32),
+            Field::O2Primary => Some(self.o2_primary as i32),
+            Field::EgoCorrection => Some(self.ego_correction as i32),
+            Field::IatCorrection => Some(self.iat_correction as i32),
+            Field::WueCorrection => Some(self.wue_correction as i32),
+            Field::Rpm => Some(self.rpm as i32),
+            Field::TaeAmount => Some(self.tae_amount_raw as i32),
+            Field::Corrections => Some(self.corrections as i32),
+            Field::Ve1 => Some(self.ve1 as i32),
+            Field::Ve2 => Some(self.ve2 as i32),
+            Field::AfrTarget => Some(self.afr_target as i32),
+            Field::TpsDot => Some(self.tps_dot as i32),
+            Field::Advance => Some(self.advance as i32),
+            Field::Tps => Some(self.tps as i32),
+            Field::LoopsPerSecond => Some(self.loops_per_second as i32),
+            Field::FreeRam => Some(self.free_ram as i32),
+            Field::BoostTarget => Some(self.boost_target_raw as i32),
+            Field::BoostDuty => Some(self.boost_duty_raw as i32),
+            Field::Spark => Some(self.spark as i32),
+            Field::RpmDot => Some(self.rpm_dot as i32),
+            Field::EthanolPct => Some(self.ethanol_pct as i32),
+            Field::FlexCorrection => Some(self.flex_correction as i32),
+            Field::FlexIgnCorrection => Some(self.flex_ign_correction as i32),
+            Field::IdleLoad => Some(self.idle_load as i32),
+            Field::TestOutputs => Some(self.test_outputs as i32),
+            Field::O2Secondary => Some(self.o2_secondary as i32),
+            Field::Baro => Some(self.baro as i32),
+            Field::CanIn0 => Some(self.canin[0] as i32),
+            Field::CanIn1 => Some(self.canin[1] as i32),
+            Field::CanIn2 => Some(self.canin[2] as i32),
+            Field::CanIn3 => Some(self.canin[3] as i32),
+            Field::CanIn4 => Some(self.canin[4] as i32),
+            Field::CanIn5 => Some(self.canin[5] as i32),
+            Field::CanIn6 => Some(self.canin[6] as i32),
+            Field::CanIn7 => Some(self.canin[7] as i32),
+            Field::CanIn8 => Some(self.canin[8] as i32),
+            Field::CanIn9 => Some(self.canin[9] as i32),
+            Field::CanIn10 => Some(self.canin[10] as i32),
+            Field::CanIn11 => Some(self.canin[11] as i32),
+            Field::CanIn12 => Some(self.canin[12] as i32),
+            Field::CanIn13 => Some(self.canin[13] as i32),
+            Field::CanIn14 => Some(self.canin[14] as i32),
+            Field::CanIn15 => Some(self.canin[15] as i32),
+            Field::TpsAdc => Some(self.tps_adc as i32),
+            Field::NextError => Some(self.next_error as i32),
+            Field::Pw1 => Some(self.pw1 as i32),
+            Field::Pw2 => Some(self.pw2 as i32),
+            Field::Pw3 => Some(self.pw3 as i32),
+            Field::Pw4 => Some(self.pw4 as i32),
+            Field::Status3 => Some(self.status3 as i32),
+            Field::EngineProtectStatus => Some(self.engine_protect_status as i32),
+            Field::FuelLoad => Some(self.fuel_load as i32),
+            Field::IgnLoad => Some(self.ign_load as i32),
+            Field::Dwell => Some(self.dwell as i32),
+            Field::ClIdleTarget => Some(self.cl_idle_target as i32),
+            Field::MapDot => Some(self.map_dot as i32),
+            Field::Vvt1Angle => Some(self.vvt1_angle as i32),
+            Field::Vvt1TargetAngle => Some(self.vvt1_target_angle as i32),
+            Field::Vvt1Duty => Some(self.vvt1_duty as i32),
+            Field::FlexBoostCorrection => Some(self.flex_boost_correction as i32),
+            Field::BaroCorrection => Some(self.baro_correction as i32),
+            Field::VeCurrent => Some(self.ve_current as i32),
+            Field::AseValue => Some(self.ase_value as i32),
+            Field::Vss => Some(self.vss as i32),
+            Field::Gear => Some(self.gear as i32),
+            Field::FuelPressure => Some(self.fuel_pressure as i32),
+            Field::OilPressure => Some(self.oil_pressure as i32),
+            Field::WmiPw => Some(self.wmi_pw as i32),
+            Field::Status4 => Some(self.status4 as i32),
+            Field::Vvt2Angle => Some(self.vvt2_angle as i32),
+            Field::Vvt2TargetAngle => Some(self.vvt2_target_angle as i32),
+            Field::Vvt2Duty => Some(self.vvt2_duty as i32),
+            Field::OutputsStatus => Some(self.outputs_status as i32),
+            Field::FuelTempRaw => Some(self.fuel_temp_raw as i32),
+            Field::FuelTempCorrection => Some(self.fuel_temp_correction as i32),
+            Field::Advance1 => Some(self.advance1 as i32),
+            Field::Advance2 => Some(self.advance2 as i32),
+            Field::TsSdStatus => Some(self.ts_sd_status as i32),
+            Field::Emap => self.emap.map(i32::from),
+            Field::FanDuty => self.fan_duty.map(i32::from),
+            Field::AirConStatus => self.air_con_status.map(i32::from),
+            Field::ActualDwell => self.actual_dwell.map(i32::from),
+            Field::Status5 => self.status5.map(i32::from),
+            Field::KnockCount => self.knock_count.map(i32::from),
+            Field::KnockRetard => self.knock_retard.map(i32::from),
+            Field::Pw5 => self.pw5.map(i32::from),
+            Field::Pw6 => self.pw6.map(i32::from),
+            Field::Pw7 => self.pw7.map(i32::from),
+            Field::Pw8 => self.pw8.map(i32::from),
+        }
+    }
+
     pub fn iat_celsius(&self) -> i16 {
         self.iat_raw as i16 - 40
     }
@@ -283,52 +514,156 @@ impl SpeeduinoData {
 /// Parse raw bytes into [`SpeeduinoData`] without publishing to MQTT.
 #[allow(dead_code)]
 pub fn get_parsed_data(data: &[u8]) -> Result<SpeeduinoData> {
-    parse_realtime_data(data)
+    parse_realtime_data(data, &PRIMARY)
 }
 
-/// Parse ECU data and optionally publish all parameters to MQTT.
+/// What the settings compile to for publishing, built once at startup, and
+/// the state carried from one frame to the next.
+#[derive(Debug, Default)]
+pub struct PublishState {
+    /// Units every channel is published in
+    pub units: Units,
+    /// Names and scaling of the CAN inputs
+    pub can_inputs: CanInputs,
+    /// AFR and lambda from the O2 reading
+    pub wideband: Wideband,
+    /// `[derived]` channels
+    pub derived: DerivedChannels,
+    /// Sanity limits every frame is checked against
+    pub limits: Limits,
+    /// Fields outside `ecu_channels`, marked missing in every frame
+    pub unrequested: FieldSet,
+    /// Fuel usage and trip, when `injector_flow_cc_min` is set
+    pub fuel: Option<FuelTracker>,
+    /// How and how often the frames go out
+    pub publisher: Publisher,
+}
+
+impl PublishState {
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        let can_inputs = CanInputs::from_config(config)?;
+        let publisher = Publisher::from_config(config, &can_inputs)?;
+        Ok(Self {
+            units: Units::from_config(config)?,
+            can_inputs,
+            wideband: Wideband::from_config(config)?,
+            derived: DerivedChannels::from_config(config)?,
+            limits: Limits::from_config(config)?,
+            unrequested: if config.ecu_channels.is_empty() {
+                FieldSet::EMPTY
+            } else {
+                requested_fields(&config.ecu_channels).complement()
+            },
+            fuel: FuelTracker::from_config(config)?,
+            publisher,
+        })
+    }
+}
+
+/// Publishing settings applied to every frame, and the JSON frame counter.
+#[derive(Debug, Default)]
+pub struct Publisher {
+    pub mode: PublishMode,
+    /// Publish intervals, when `[publish_intervals]` has entries
+    pub decimator: Option<Decimator>,
+    /// Publish-on-change, when `publish_on_change` is set
+    pub changes: Option<ChangeFilter>,
+    /// Sequence number of the next JSON frame
+    pub seq: u64,
+}
+
+impl Publisher {
+    pub fn from_config(config: &AppConfig, can_inputs: &CanInputs) -> Result<Self> {
+        let Some(mode) = PublishMode::from_config(&config.publish_mode) else {
+            return Err(ConfigError::InvalidValue {
+                field: "publish_mode".to_string(),
+                message: format!(
+                    "must be \"per_topic\", \"json\" or \"both\", got \"{}\"",
+                    config.publish_mode
+                ),
+            }
+            .into());
+        };
+        Ok(Self {
+            mode,
+            decimator: Decimator::from_config(config, can_inputs)?,
+            changes: ChangeFilter::from_config(config, can_inputs)?,
+            seq: 0,
+        })
+    }
+}
+
+/// Parse ECU data with the active packet `layout` and optionally publish all
+/// parameters to MQTT.
 ///
 /// When `mqtt_sender` is `None` (MQTT disabled), only parsing happens – no
-/// network I/O takes place.  Returns the parsed struct so callers (e.g. the
-/// TUI) can display it.
+/// network I/O takes place.  `state` (units, fuel trip, publish-on-change) is
+/// advanced by every accepted frame.  Returns the parsed struct so callers
+/// (e.g. the TUI) can display it.
 pub async fn process_speeduino_realtime_data(
     data: &[u8],
     config: &Arc<AppConfig>,
+    layout: &Layout,
     mqtt_sender: Option<&mpsc::Sender<MqttMessage>>,
+    state: &mut PublishState,
 ) -> Result<SpeeduinoData> {
-    // Minimum for primary-serial packets (both sim=130 and real firmware=138).
-    // Secondary-serial 'A' (75 bytes) uses an incompatible layout — not supported.
-    const MIN_BYTES: usize = 130;
-
-    if data.len() < MIN_BYTES {
+    let data = layout.payload(data);
+    if data.len() < layout.min_length {
         warn!(
-            "Packet too short: expected ≥{} bytes, got {}. \
-             Ensure you are connected to the primary serial interface (USB/TCP bridge). \
-             The secondary-serial 'A' response (75 bytes) uses an incompatible layout.",
-            MIN_BYTES,
+            "Packet too short for the {} layout: expected ≥{} bytes, got {}. \
+             A 75-byte response comes from the secondary serial port; \
+             set ecu_layout = \"secondary\" to decode it.",
+            layout.name,
+            layout.min_length,
             data.len()
         );
         return Err(ParseError::InsufficientData {
-            expected: MIN_BYTES,
+            expected: layout.min_length,
             actual: data.len(),
         }
         .into());
     }
 
-    let fmt = if data.len() >= 138 {
-        "real-firmware/138"
-    } else {
-        "sim/130"
-    };
     debug!(
-        "Parsing {} bytes of ECU realtime data ({})",
+        "Parsing {} bytes of ECU realtime data ({} layout)",
         data.len(),
-        fmt
+        layout.name
     );
-    let ecu_data = parse_realtime_data(data)?;
+    let mut ecu_data = parse_realtime_data(data, layout)?;
+    ecu_data.missing = ecu_data.missing.union(state.unrequested);
+
+    let now_ms = timestamp_us() / 1000;
+    let validation = state.limits.check(&mut ecu_data);
+    if let Some(violation) = validation.rejection() {
+        if let Some(sender) = mqtt_sender {
+            publish_params(
+                sender,
+                config,
+                validation.params(),
+                now_ms,
+                &mut state.publisher,
+            )
+            .await?;
+        }
+        return Err(violation.to_error().into());
+    }
 
+    let fuel_params = state
+        .fuel
+        .as_mut()
+        .map(|tracker| tracker.update(&ecu_data, now_ms).params())
+        .unwrap_or_default();
     if let Some(sender) = mqtt_sender {
-        publish_speeduino_params_to_mqtt(sender, config, &ecu_data).await?;
+        publish_speeduino_params_to_mqtt(
+            sender,
+            config,
+            &ecu_data,
+            &validation,
+            fuel_params,
+            now_ms,
+            state,
+        )
+        .await?;
     }
 
     Ok(ecu_data)
@@ -338,360 +673,102 @@ pub async fn process_speeduino_realtime_data(
 // Internal parser
 // ---------------------------------------------------------------------------
 
-fn parse_realtime_data(data: &[u8]) -> Result<SpeeduinoData> {
-    // Byte layout matches `getTSLogEntry()` in Speeduino logger.cpp.
-    // Minimum: 130 bytes (speeduino-serial-sim) or 138 bytes (current firmware).
-    // The secondary-serial 'A' response (75 bytes) uses a different, incompatible layout.
-    const MIN_BYTES: usize = 130;
-    if data.len() < MIN_BYTES {
+fn parse_realtime_data(data: &[u8], layout: &Layout) -> Result<SpeeduinoData> {
+    if data.len() < layout.min_length {
         return Err(ParseError::InsufficientData {
-            expected: MIN_BYTES,
+            expected: layout.min_length,
             actual: data.len(),
         }
         .into());
     }
 
-    // Inline helpers for little-endian multi-byte reads.
-    let u16_le = |lo: usize, hi: usize| -> u16 { (data[hi] as u16) << 8 | data[lo] as u16 };
-    let i16_le = |lo: usize, hi: usize| -> i16 { u16_le(lo, hi) as i16 };
-
-    // Bytes 42–73: 16 CAN input channels (2 bytes each, little-endian u16)
-    let mut canin = [0u16; 16];
-    for i in 0..16 {
-        canin[i] = u16_le(42 + i * 2, 43 + i * 2);
+    // Fields the layout lacks, or that lie beyond a short packet, stay at their
+    // defaults (`None` for optional fields) and are recorded as missing.
+    let mut parsed = SpeeduinoData::default();
+    let mut present = FieldSet::EMPTY;
+    for def in layout.fields {
+        if def.bytes().end <= data.len() {
+            parsed.set(def.field, def.decode(data));
+            present.insert(def.field);
+        }
     }
+    parsed.missing = present.complement();
 
-    // Optional fields — all present in 130-byte packets.
-    // PW5–PW8 (bytes 130–137) require the full 138-byte current-firmware packet.
-    let emap = if data.len() >= 123 {
-        Some(u16_le(121, 122))
-    } else {
-        None
-    };
-    let fan_duty = if data.len() >= 124 {
-        Some(data[123])
-    } else {
-        None
-    };
-    let air_con_status = if data.len() >= 125 {
-        Some(data[124])
-    } else {
-        None
-    };
-    let actual_dwell = if data.len() >= 127 {
-        Some(u16_le(125, 126))
-    } else {
-        None
-    };
-    let status5 = if data.len() >= 128 {
-        Some(data[127])
-    } else {
-        None
-    };
-    let knock_count = if data.len() >= 129 {
-        Some(data[128])
-    } else {
-        None
-    };
-    let knock_retard = if data.len() >= 130 {
-        Some(data[129])
-    } else {
-        None
-    };
-    let pw5 = if data.len() >= 132 {
-        Some(u16_le(130, 131))
-    } else {
-        None
-    };
-    let pw6 = if data.len() >= 134 {
-        Some(u16_le(132, 133))
-    } else {
-        None
-    };
-    let pw7 = if data.len() >= 136 {
-        Some(u16_le(134, 135))
-    } else {
-        None
-    };
-    let pw8 = if data.len() >= 138 {
-        Some(u16_le(136, 137))
-    } else {
-        None
-    };
-
-    let parsed = SpeeduinoData {
-        // ---- Bytes 0–3 ----------------------------------------
-        secl: data[0],
-        status1: data[1],
-        engine: data[2],
-        sync_loss_counter: data[3],
-        // ---- Bytes 4–21 ---------------------------------------
-        map: u16_le(4, 5),
-        iat_raw: data[6],
-        coolant_raw: data[7],
-        bat_correction: data[8],
-        battery_10: data[9],
-        o2_primary: data[10],
-        ego_correction: data[11],
-        iat_correction: data[12],
-        wue_correction: data[13],
-        rpm: u16_le(14, 15),
-        tae_amount_raw: data[16],
-        corrections: u16_le(17, 18),
-        ve1: data[19],
-        ve2: data[20],
-        afr_target: data[21],
-        // ---- Bytes 22–41 — NOTE: tpsDOT is u16 (two bytes) ----
-        tps_dot: u16_le(22, 23),
-        advance: data[24],
-        tps: data[25],
-        loops_per_second: u16_le(26, 27),
-        free_ram: u16_le(28, 29),
-        boost_target_raw: data[30],
-        boost_duty_raw: data[31],
-        spark: data[32],
-        rpm_dot: i16_le(33, 34),
-        ethanol_pct: data[35],
-        flex_correction: data[36],
-        flex_ign_correction: data[37],
-        idle_load: data[38],
-        test_outputs: data[39],
-        o2_secondary: data[40],
-        baro: data[41],
-        // ---- Bytes 42–73 (CAN inputs) -------------------------
-        canin,
-        // ---- Bytes 74–91 --------------------------------------
-        tps_adc: data[74],
-        next_error: data[75],
-        pw1: u16_le(76, 77),
-        pw2: u16_le(78, 79),
-        pw3: u16_le(80, 81),
-        pw4: u16_le(82, 83),
-        status3: data[84],
-        engine_protect_status: data[85],
-        fuel_load: u16_le(86, 87),
-        ign_load: u16_le(88, 89),
-        dwell: u16_le(90, 91),
-        // ---- Bytes 92–120 — NOTE: mapDOT is u16 (two bytes) ---
-        cl_idle_target: data[92],
-        map_dot: u16_le(93, 94),
-        vvt1_angle: i16_le(95, 96),
-        vvt1_target_angle: data[97],
-        vvt1_duty: data[98],
-        flex_boost_correction: u16_le(99, 100),
-        baro_correction: data[101],
-        ve_current: data[102],
-        ase_value: data[103],
-        vss: u16_le(104, 105),
-        gear: data[106],
-        fuel_pressure: data[107],
-        oil_pressure: data[108],
-        wmi_pw: data[109],
-        status4: data[110],
-        vvt2_angle: i16_le(111, 112),
-        vvt2_target_angle: data[113],
-        vvt2_duty: data[114],
-        outputs_status: data[115],
-        fuel_temp_raw: data[116],
-        fuel_temp_correction: data[117],
-        advance1: data[118],
-        advance2: data[119],
-        ts_sd_status: data[120],
-        // ---- Optional / extended fields -----------------------
-        emap,
-        fan_duty,
-        air_con_status,
-        actual_dwell,
-        status5,
-        knock_count,
-        knock_retard,
-        pw5,
-        pw6,
-        pw7,
-        pw8,
-    };
-
-    validate_data(&parsed);
     Ok(parsed)
 }
 
-/// Log warnings for out-of-range values.  Never fails parsing.
-fn validate_data(d: &SpeeduinoData) {
-    if d.rpm > RPM_MAX {
-        warn!("RPM out of range: {} (max {})", d.rpm, RPM_MAX);
-    }
-    let coolant_c = d.coolant_celsius();
-    if coolant_c < TEMP_MIN || coolant_c > TEMP_MAX {
-        warn!("Coolant temp out of range: {}°C", coolant_c);
-    }
-    let iat_c = d.iat_celsius();
-    if iat_c < TEMP_MIN || iat_c > TEMP_MAX {
-        warn!("IAT out of range: {}°C", iat_c);
-    }
-    if d.map > MAP_MAX {
-        warn!("MAP out of range: {} kPa (max {})", d.map, MAP_MAX);
-    }
-    if d.tps > TPS_MAX {
-        warn!("TPS out of range: {}% (max {})", d.tps, TPS_MAX);
-    }
-    let batt = d.battery_voltage();
-    if batt > 0.0 && (batt < BATTERY_MIN || batt > BATTERY_MAX) {
-        warn!("Battery voltage out of range: {:.1} V", batt);
+// ---------------------------------------------------------------------------
+// Channel → field mapping
+// ---------------------------------------------------------------------------
+
+/// Topic published with the name of the ECU error code.
+pub const ERROR_NAME_TOPIC: &str = "NER/error";
+
+/// Packet field a topic code is derived from (`None` for unknown codes).
+/// Status flag topics such as `ENG/cranking` map to their status byte.
+pub fn channel_field(code: &str) -> Option<Field> {
+    if code.eq_ignore_ascii_case(ERROR_NAME_TOPIC) {
+        return Some(Field::NextError);
     }
+    channel(code)
+        .map(|c| c.field)
+        .or_else(|| status_flag(code).map(|flag| flag.field))
+}
+
+/// Whether a frame can publish `topic` (case-insensitive): a registry
+/// channel, status flag or `NER/error`, a CAN input topic, the wideband, fuel
+/// and `QUALITY` topics, or a `[derived]` channel.
+pub fn is_frame_topic(topic: &str, config: &AppConfig, can_inputs: &CanInputs) -> bool {
+    channel_field(topic).is_some()
+        || can_inputs
+            .inputs()
+            .iter()
+            .any(|input| input.topic.eq_ignore_ascii_case(topic))
+        || [
+            AFR_TOPIC,
+            LAMBDA_TOPIC,
+            AFR_ERROR_TOPIC,
+            QUALITY_TOPIC,
+            VIOLATIONS_TOPIC,
+        ]
+        .iter()
+        .chain(FUEL_TOPICS.iter())
+        .any(|t| t.eq_ignore_ascii_case(topic))
+        || config
+            .derived
+            .keys()
+            .any(|name| name.eq_ignore_ascii_case(topic))
+}
+
+/// Fields needed to publish the given topic codes.  Unknown codes are ignored
+/// (they are rejected earlier by [`AppConfig::validate`]).
+pub fn requested_fields(codes: &[String]) -> FieldSet {
+    codes.iter().filter_map(|c| channel_field(c)).collect()
 }
 
 // ---------------------------------------------------------------------------
 // MQTT publishing
 // ---------------------------------------------------------------------------
 
-/// Build the full list of (topic-code, value) pairs for publishing.
-/// Every Speeduino 'A' command parameter is present.
-pub fn get_params_to_publish(d: &SpeeduinoData) -> Vec<(&'static str, String)> {
-    let mut params: Vec<(&'static str, String)> = vec![
-        // Engine basics
-        ("RPM", d.rpm.to_string()),
-        ("TPS", d.tps.to_string()),
-        ("MAP", d.map.to_string()),
-        ("BAR", d.baro.to_string()),
-        ("BAT", format!("{:.1}", d.battery_voltage())),
-        ("SCL", d.secl.to_string()),
-        ("SYN", d.sync_loss_counter.to_string()),
-        // Temperatures – raw bytes (backward-compatible names)
-        ("MAT", d.iat_raw.to_string()),
-        ("CAD", d.coolant_raw.to_string()),
-        // Temperatures – converted to °C
-        ("IAT", d.iat_celsius().to_string()),
-        ("CLT", d.coolant_celsius().to_string()),
-        // O2 sensors
-        ("O2P", d.o2_primary.to_string()),
-        ("O2S", d.o2_secondary.to_string()),
-        // Fuel
-        ("AFT", format!("{:.1}", d.afr_target_real())),
-        ("VE1", d.ve1.to_string()),
-        ("VE2", d.ve2.to_string()),
-        ("VEC", d.ve_current.to_string()),
-        ("PW1", format!("{:.1}", d.pw1_ms())),
-        ("PW2", format!("{:.1}", d.pw2_ms())),
-        ("PW3", format!("{:.1}", d.pw3_ms())),
-        ("PW4", format!("{:.1}", d.pw4_ms())),
-        // Ignition
-        ("ADV", d.advance.to_string()),
-        ("AD1", d.advance1.to_string()),
-        ("AD2", d.advance2.to_string()),
-        ("DWL", format!("{:.1}", d.dwell_ms())),
-        ("SPK", d.spark.to_string()),
-        // Corrections
-        ("BTC", d.bat_correction.to_string()),
-        ("EGC", d.ego_correction.to_string()),
-        ("ITC", d.iat_correction.to_string()),
-        ("WEC", d.wue_correction.to_string()),
-        ("COR", d.corrections.to_string()),
-        ("BRC", d.baro_correction.to_string()),
-        ("ASE", d.ase_value.to_string()),
-        ("TAE", d.tae_amount_pct().to_string()),
-        // Boost
-        ("BST", d.boost_target_kpa().to_string()),
-        ("BSD", d.boost_duty_pct().to_string()),
-        // Flex / ethanol
-        ("ETH", d.ethanol_pct.to_string()),
-        ("FLC", d.flex_correction.to_string()),
-        ("FIC", d.flex_ign_correction.to_string()),
-        ("FBC", d.flex_boost_correction.to_string()),
-        // Fuel temperature
-        ("FTP", d.fuel_temp_celsius().to_string()),
-        ("FTC", d.fuel_temp_correction.to_string()),
-        // Performance
-        ("LPS", d.loops_per_second.to_string()),
-        ("FRM", d.free_ram.to_string()),
-        ("RPD", d.rpm_dot.to_string()),
-        // Throttle detail
-        ("TPD", d.tps_dot.to_string()),
-        ("TAD", d.tps_adc.to_string()),
-        // Load
-        ("FLD", d.fuel_load.to_string()),
-        ("IGD", d.ign_load.to_string()),
-        // Idle
-        ("ILL", d.idle_load.to_string()),
-        ("MPD", d.map_dot.to_string()),
-        ("CIT", d.cl_idle_target.to_string()),
-        // VVT
-        ("VA1", d.vvt1_angle.to_string()),
-        ("VT1", d.vvt1_target_angle.to_string()),
-        ("VD1", d.vvt1_duty.to_string()),
-        ("VA2", d.vvt2_angle.to_string()),
-        ("VT2", d.vvt2_target_angle.to_string()),
-        ("VD2", d.vvt2_duty.to_string()),
-        // Vehicle
-        ("VSS", d.vss.to_string()),
-        ("GER", d.gear.to_string()),
-        // Pressures
-        ("FPR", d.fuel_pressure.to_string()),
-        ("OPR", d.oil_pressure.to_string()),
-        // Misc
-        ("WMI", d.wmi_pw.to_string()),
-        ("TOF", d.test_outputs.to_string()),
-        ("NER", d.next_error.to_string()),
-        // Status bitfields
-        ("STA", d.status1.to_string()),
-        ("ENG", d.engine.to_string()),
-        ("ST3", d.status3.to_string()),
-        ("ST4", d.status4.to_string()),
-        ("EPS", d.engine_protect_status.to_string()),
-        ("OUT", d.outputs_status.to_string()),
-        ("SDS", d.ts_sd_status.to_string()),
-        // CAN inputs (CN01–CN16)
-        ("CN01", d.canin[0].to_string()),
-        ("CN02", d.canin[1].to_string()),
-        ("CN03", d.canin[2].to_string()),
-        ("CN04", d.canin[3].to_string()),
-        ("CN05", d.canin[4].to_string()),
-        ("CN06", d.canin[5].to_string()),
-        ("CN07", d.canin[6].to_string()),
-        ("CN08", d.canin[7].to_string()),
-        ("CN09", d.canin[8].to_string()),
-        ("CN10", d.canin[9].to_string()),
-        ("CN11", d.canin[10].to_string()),
-        ("CN12", d.canin[11].to_string()),
-        ("CN13", d.canin[12].to_string()),
-        ("CN14", d.canin[13].to_string()),
-        ("CN15", d.canin[14].to_string()),
-        ("CN16", d.canin[15].to_string()),
-    ];
-
-    // Optional EMAP and extended fields
-    if let Some(v) = d.emap {
-        params.push(("EMP", v.to_string()));
-    }
-    if let Some(v) = d.fan_duty {
-        params.push(("FAN", v.to_string()));
-    }
-    if let Some(v) = d.air_con_status {
-        params.push(("ACS", v.to_string()));
-    }
-    if let Some(v) = d.actual_dwell {
-        params.push(("ADW", format!("{:.1}", v as f32 / 10.0)));
-    }
-    if let Some(v) = d.status5 {
-        params.push(("ST5", v.to_string()));
-    }
-    if let Some(v) = d.knock_count {
-        params.push(("KNC", v.to_string()));
-    }
-    if let Some(v) = d.knock_retard {
-        params.push(("KNR", v.to_string()));
-    }
-    // PW5–PW8 — current firmware only (138-byte packets)
-    if let Some(v) = d.pw5 {
-        params.push(("PW5", format!("{:.1}", v as f32 / 10.0)));
-    }
-    if let Some(v) = d.pw6 {
-        params.push(("PW6", format!("{:.1}", v as f32 / 10.0)));
-    }
-    if let Some(v) = d.pw7 {
-        params.push(("PW7", format!("{:.1}", v as f32 / 10.0)));
-    }
-    if let Some(v) = d.pw8 {
-        params.push(("PW8", format!("{:.1}", v as f32 / 10.0)));
+/// Build the full list of (topic-code, value) pairs for publishing: every
+/// [`CHANNELS`] entry the ECU sent, formatted in `units`, followed by the
+/// error name and the status flags.
+pub fn get_params_to_publish(d: &SpeeduinoData, units: &Units) -> Vec<(&'static str, String)> {
+    let mut params: Vec<(&'static str, String)> = CHANNELS
+        .iter()
+        .filter_map(|c| Some((c.code, units.format(c.code, c.value(d)?))))
+        .collect();
+
+    if d.has(Field::NextError) {
+        let name = d.ecu_error().map_or("none", |e| e.name());
+        params.push((ERROR_NAME_TOPIC, name.to_string()));
+    }
+
+    // One boolean topic per named status bit (`ENG/cranking`, …)
+    for flag in STATUS_FLAGS {
+        if let Some(on) = flag.get(d) {
+            params.push((flag.topic, on.to_string()));
+        }
     }
 
     params
@@ -701,10 +778,61 @@ async fn publish_speeduino_params_to_mqtt(
     mqtt_sender: &mpsc::Sender<MqttMessage>,
     config: &Arc<AppConfig>,
     d: &SpeeduinoData,
+    validation: &Validation,
+    fuel_params: Vec<(&'static str, String)>,
+    now_ms: u64,
+    state: &mut PublishState,
 ) -> Result<()> {
-    for (code, value) in get_params_to_publish(d) {
-        let topic = build_topic_path(&config.mqtt_base_topic, code);
-        let msg = MqttMessage::new(topic, value, config.mqtt_qos);
+    let derived_params = state
+        .derived
+        .values(d)
+        .into_iter()
+        .map(|(name, value)| (name, DerivedChannels::format(value)));
+    let params: Vec<(&str, String)> = state
+        .can_inputs
+        .apply(get_params_to_publish(d, &state.units), d)
+        .into_iter()
+        .chain(state.wideband.params(d))
+        .chain(fuel_params)
+        .chain(derived_params)
+        .chain(validation.params())
+        .collect();
+    publish_params(mqtt_sender, config, params, now_ms, &mut state.publisher).await
+}
+
+/// Queue one frame's (topic code, value) pairs, taken at `now_ms`, in the
+/// publisher's mode: a message per code, one JSON object, or both.  The
+/// publish intervals apply to both; publish-on-change thins out the per-code
+/// messages only.
+async fn publish_params(
+    mqtt_sender: &mpsc::Sender<MqttMessage>,
+    config: &AppConfig,
+    params: Vec<(&str, String)>,
+    now_ms: u64,
+    publisher: &mut Publisher,
+) -> Result<()> {
+    let params = match publisher.decimator.as_mut() {
+        Some(decimator) => decimator.apply(params, now_ms),
+        None => params,
+    };
+    let mut messages = Vec::with_capacity(params.len() + 1);
+    if publisher.mode.json() {
+        let payload = frame_json(now_ms, publisher.seq, &params);
+        publisher.seq += 1;
+        let topic = build_topic_path(&config.mqtt_base_topic, FRAME_TOPIC);
+        messages.push(MqttMessage::new(topic, payload, config.mqtt_qos));
+    }
+    if publisher.mode.per_topic() {
+        let params = match publisher.changes.as_mut() {
+            Some(filter) => filter.retain(params, now_ms),
+            None => params,
+        };
+        messages.extend(params.into_iter().map(|(code, value)| {
+            let topic = build_topic_path(&config.mqtt_base_topic, code);
+            MqttMessage::new(topic, value, config.mqtt_qos)
+        }));
+    }
+    for msg in messages {
         mqtt_sender
             .send(msg)
             .await
@@ -723,6 +851,8 @@ async fn publish_speeduino_params_to_mqtt(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::LimitConfig;
+    use crate::errors::AppError;
 
     fn zero_packet() -> [u8; 130] {
         [0u8; 130]
@@ -732,47 +862,47 @@ mod tests {
 
     #[test]
     fn test_parse_too_short() {
-        assert!(parse_realtime_data(&[0u8; 50]).is_err());
+        assert!(parse_realtime_data(&[0u8; 50], &PRIMARY).is_err());
     }
 
     #[test]
     fn test_parse_empty() {
-        assert!(parse_realtime_data(&[]).is_err());
+        assert!(parse_realtime_data(&[], &PRIMARY).is_err());
     }
 
     #[test]
     fn test_parse_129_bytes_too_short() {
-        assert!(parse_realtime_data(&[0u8; 129]).is_err());
+        assert!(parse_realtime_data(&[0u8; 129], &PRIMARY).is_err());
     }
 
     #[test]
     fn test_parse_minimum_130_bytes() {
-        assert!(parse_realtime_data(&[0u8; 130]).is_ok());
+        assert!(parse_realtime_data(&[0u8; 130], &PRIMARY).is_ok());
     }
 
     #[test]
     fn test_parse_130_bytes_has_emap() {
         // All 130-byte packets include EMAP (130 >= 123)
-        let d = parse_realtime_data(&[0u8; 130]).unwrap();
+        let d = parse_realtime_data(&[0u8; 130], &PRIMARY).unwrap();
         assert_eq!(d.emap, Some(0));
     }
 
     #[test]
     fn test_parse_130_bytes_has_extended_fields() {
-        let d = parse_realtime_data(&[0u8; 130]).unwrap();
+        let d = parse_realtime_data(&[0u8; 130], &PRIMARY).unwrap();
         assert!(d.knock_retard.is_some()); // byte 129 present
     }
 
     #[test]
     fn test_parse_137_bytes_has_pw5_no_pw8() {
-        let d = parse_realtime_data(&[0u8; 137]).unwrap();
+        let d = parse_realtime_data(&[0u8; 137], &PRIMARY).unwrap();
         assert!(d.pw5.is_some()); // 137 >= 132
         assert!(d.pw8.is_none()); // 137 < 138
     }
 
     #[test]
     fn test_parse_138_bytes_all_pw_fields() {
-        let d = parse_realtime_data(&[0u8; 138]).unwrap();
+        let d = parse_realtime_data(&[0u8; 138], &PRIMARY).unwrap();
         assert!(d.pw5.is_some());
         assert!(d.pw8.is_some());
     }
@@ -783,14 +913,17 @@ mod tests {
     fn test_secl_byte_0() {
         let mut p = zero_packet();
         p[0] = 42;
-        assert_eq!(parse_realtime_data(&p).unwrap().secl, 42);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().secl, 42);
     }
 
     #[test]
     fn test_sync_loss_counter_byte_3() {
         let mut p = zero_packet();
         p[3] = 7;
-        assert_eq!(parse_realtime_data(&p).unwrap().sync_loss_counter, 7);
+        assert_eq!(
+            parse_realtime_data(&p, &PRIMARY).unwrap().sync_loss_counter,
+            7
+        );
     }
 
     #[test]
@@ -798,7 +931,7 @@ mod tests {
         let mut p = zero_packet();
         p[4] = 0x2C;
         p[5] = 0x01; // 300 kPa
-        assert_eq!(parse_realtime_data(&p).unwrap().map, 300);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().map, 300);
     }
 
     #[test]
@@ -806,7 +939,7 @@ mod tests {
         let mut p = zero_packet();
         p[14] = 0xB8;
         p[15] = 0x0B; // 3000 RPM
-        assert_eq!(parse_realtime_data(&p).unwrap().rpm, 3000);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().rpm, 3000);
     }
 
     #[test]
@@ -814,7 +947,7 @@ mod tests {
         let mut p = zero_packet();
         p[17] = 0xE8;
         p[18] = 0x03; // 1000
-        assert_eq!(parse_realtime_data(&p).unwrap().corrections, 1000);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().corrections, 1000);
     }
 
     #[test]
@@ -822,7 +955,7 @@ mod tests {
         let mut p = zero_packet();
         p[19] = 85;
         p[20] = 90;
-        let d = parse_realtime_data(&p).unwrap();
+        let d = parse_realtime_data(&p, &PRIMARY).unwrap();
         assert_eq!(d.ve1, 85);
         assert_eq!(d.ve2, 90);
     }
@@ -831,7 +964,7 @@ mod tests {
     fn test_afr_target_byte_21() {
         let mut p = zero_packet();
         p[21] = 147;
-        let d = parse_realtime_data(&p).unwrap();
+        let d = parse_realtime_data(&p, &PRIMARY).unwrap();
         assert!((d.afr_target_real() - 14.7).abs() < 0.01);
     }
 
@@ -840,21 +973,21 @@ mod tests {
         let mut p = zero_packet();
         p[22] = 0xC8;
         p[23] = 0x00; // 200
-        assert_eq!(parse_realtime_data(&p).unwrap().tps_dot, 200);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().tps_dot, 200);
     }
 
     #[test]
     fn test_advance_byte_24() {
         let mut p = zero_packet();
         p[24] = 30;
-        assert_eq!(parse_realtime_data(&p).unwrap().advance, 30);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().advance, 30);
     }
 
     #[test]
     fn test_tps_byte_25() {
         let mut p = zero_packet();
         p[25] = 75;
-        assert_eq!(parse_realtime_data(&p).unwrap().tps, 75);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().tps, 75);
     }
 
     #[test]
@@ -862,7 +995,7 @@ mod tests {
         let mut p = zero_packet();
         p[76] = 35;
         p[77] = 0; // 3.5 ms
-        let d = parse_realtime_data(&p).unwrap();
+        let d = parse_realtime_data(&p, &PRIMARY).unwrap();
         assert_eq!(d.pw1, 35);
         assert!((d.pw1_ms() - 3.5).abs() < 0.01);
     }
@@ -872,7 +1005,7 @@ mod tests {
         let mut p = zero_packet();
         p[90] = 45;
         p[91] = 0; // 4.5 ms
-        let d = parse_realtime_data(&p).unwrap();
+        let d = parse_realtime_data(&p, &PRIMARY).unwrap();
         assert_eq!(d.dwell, 45);
         assert!((d.dwell_ms() - 4.5).abs() < 0.01);
     }
@@ -882,21 +1015,21 @@ mod tests {
         let mut p = zero_packet();
         p[93] = 0x64;
         p[94] = 0x00; // 100
-        assert_eq!(parse_realtime_data(&p).unwrap().map_dot, 100);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().map_dot, 100);
     }
 
     #[test]
     fn test_ve_current_byte_102() {
         let mut p = zero_packet();
         p[102] = 88;
-        assert_eq!(parse_realtime_data(&p).unwrap().ve_current, 88);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().ve_current, 88);
     }
 
     #[test]
     fn test_ts_sd_status_byte_120() {
         let mut p = zero_packet();
         p[120] = 3;
-        assert_eq!(parse_realtime_data(&p).unwrap().ts_sd_status, 3);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().ts_sd_status, 3);
     }
 
     // --- CAN inputs ---
@@ -906,7 +1039,7 @@ mod tests {
         let mut p = zero_packet();
         p[42] = 0x00;
         p[43] = 0x02; // 512
-        assert_eq!(parse_realtime_data(&p).unwrap().canin[0], 512);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().canin[0], 512);
     }
 
     #[test]
@@ -914,7 +1047,7 @@ mod tests {
         let mut p = zero_packet();
         p[72] = 0xFF;
         p[73] = 0x00; // 255
-        assert_eq!(parse_realtime_data(&p).unwrap().canin[15], 255);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().canin[15], 255);
     }
 
     // --- Temperature offset ---
@@ -923,20 +1056,23 @@ mod tests {
     fn test_iat_celsius() {
         let mut p = zero_packet();
         p[6] = 80; // 80 - 40 = 40°C
-        assert_eq!(parse_realtime_data(&p).unwrap().iat_celsius(), 40);
+        assert_eq!(parse_realtime_data(&p, &PRIMARY).unwrap().iat_celsius(), 40);
     }
 
     #[test]
     fn test_coolant_celsius() {
         let mut p = zero_packet();
         p[7] = 125; // 85°C
-        assert_eq!(parse_realtime_data(&p).unwrap().coolant_celsius(), 85);
+        assert_eq!(
+            parse_realtime_data(&p, &PRIMARY).unwrap().coolant_celsius(),
+            85
+        );
     }
 
     #[test]
     fn test_zero_raw_temp_is_minus_40c() {
         let p = zero_packet();
-        let d = parse_realtime_data(&p).unwrap();
+        let d = parse_realtime_data(&p, &PRIMARY).unwrap();
         assert_eq!(d.iat_celsius(), -40);
         assert_eq!(d.coolant_celsius(), -40);
     }
@@ -947,21 +1083,29 @@ mod tests {
     fn test_boost_target_times_2() {
         let mut p = zero_packet();
         p[30] = 100;
-        assert_eq!(parse_realtime_data(&p).unwrap().boost_target_kpa(), 200);
+        assert_eq!(
+            parse_realtime_data(&p, &PRIMARY)
+                .unwrap()
+                .boost_target_kpa(),
+            200
+        );
     }
 
     #[test]
     fn test_tae_amount_times_2() {
         let mut p = zero_packet();
         p[16] = 25;
-        assert_eq!(parse_realtime_data(&p).unwrap().tae_amount_pct(), 50);
+        assert_eq!(
+            parse_realtime_data(&p, &PRIMARY).unwrap().tae_amount_pct(),
+            50
+        );
     }
 
     #[test]
     fn test_battery_voltage_div_10() {
         let mut p = zero_packet();
         p[9] = 142; // 14.2 V
-        assert!((parse_realtime_data(&p).unwrap().battery_voltage() - 14.2).abs() < 0.01);
+        assert!((parse_realtime_data(&p, &PRIMARY).unwrap().battery_voltage() - 14.2).abs() < 0.01);
     }
 
     // --- Validation (warnings only, should not fail) ---
@@ -971,7 +1115,7 @@ mod tests {
         let mut p = zero_packet();
         p[14] = 0xFF;
         p[15] = 0xFF; // RPM = 65535
-        assert!(parse_realtime_data(&p).is_ok());
+        assert!(parse_realtime_data(&p, &PRIMARY).is_ok());
     }
 
     // --- params list ---
@@ -979,7 +1123,7 @@ mod tests {
     #[test]
     fn test_params_min_count() {
         let d = SpeeduinoData::default();
-        let params = get_params_to_publish(&d);
+        let params = get_params_to_publish(&d, &Units::default());
         assert!(
             params.len() >= 80,
             "expected ≥80 params, got {}",
@@ -988,28 +1132,31 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_params_rpm() {
         let mut d = SpeeduinoData::default();
         d.rpm = 3000;
-        let params = get_params_to_publish(&d);
+        let params = get_params_to_publish(&d, &Units::default());
         let found = params.iter().find(|(k, _)| *k == "RPM").unwrap();
         assert_eq!(found.1, "3000");
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_params_battery_format() {
         let mut d = SpeeduinoData::default();
         d.battery_10 = 142;
-        let params = get_params_to_publish(&d);
+        let params = get_params_to_publish(&d, &Units::default());
         let found = params.iter().find(|(k, _)| *k == "BAT").unwrap();
         assert_eq!(found.1, "14.2");
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_params_emap_present_when_some() {
         let mut d = SpeeduinoData::default();
         d.emap = Some(101);
-        let params = get_params_to_publish(&d);
+        let params = get_params_to_publish(&d, &Units::default());
         let found = params.iter().find(|(k, _)| *k == "EMP");
         assert!(found.is_some());
         assert_eq!(found.unwrap().1, "101");
@@ -1018,7 +1165,7 @@ mod tests {
     #[test]
     fn test_params_emap_absent_when_none() {
         let d = SpeeduinoData::default();
-        let params = get_params_to_publish(&d);
+        let params = get_params_to_publish(&d, &Units::default());
         assert!(!params.iter().any(|(k, _)| *k == "EMP"));
     }
 
@@ -1028,7 +1175,7 @@ mod tests {
         for i in 0..16 {
             d.canin[i] = i as u16 * 100;
         }
-        let params = get_params_to_publish(&d);
+        let params = get_params_to_publish(&d, &Units::default());
         for i in 1..=16usize {
             let code = format!("CN{:02}", i);
             let found = params.iter().any(|(k, _)| *k == code.as_str());
@@ -1036,6 +1183,236 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_params_imperial_units() {
+        let d = SpeeduinoData {
+            coolant_raw: 130, // 90 °C
+            map: 100,
+            vss: 100,
+            iat_raw: 40, // 0 °C
+            ..Default::default()
+        };
+        let units = Units::new(crate::units::UnitSystem::Imperial);
+        let params = get_params_to_publish(&d, &units);
+        let value = |code: &str| params.iter().find(|(k, _)| *k == code).unwrap().1.clone();
+        assert_eq!(value("CLT"), "194");
+        assert_eq!(value("IAT"), "32");
+        assert_eq!(value("MAP"), "14.5");
+        assert_eq!(value("VSS"), "62");
+        // Raw bytes are never converted
+        assert_eq!(value("CAD"), "130");
+    }
+
+    #[test]
+    fn test_params_status_flags() {
+        let d = SpeeduinoData {
+            engine: crate::status_flags::ENGINE_CRANK,
+            ..Default::default()
+        };
+        let params = get_params_to_publish(&d, &Units::default());
+        let value = |code: &str| {
+            params
+                .iter()
+                .find(|(k, _)| *k == code)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(value("ENG/cranking"), Some("true"));
+        assert_eq!(value("ENG/running"), Some("false"));
+        assert_eq!(value("ENG"), Some("2"));
+        // status5 is not in this frame
+        assert_eq!(value("ST5/knock_active"), None);
+    }
+
+    // --- Partial frames ---
+
+    #[test]
+    fn test_channel_field_lookup() {
+        assert_eq!(channel_field("RPM"), Some(Field::Rpm));
+        assert_eq!(channel_field("clt"), Some(Field::CoolantRaw));
+        assert_eq!(channel_field("CN16"), Some(Field::CanIn15));
+        assert_eq!(channel_field("XYZ"), None);
+        assert_eq!(channel_field("ENG/cranking"), Some(Field::Engine));
+    }
+
+    #[test]
+    fn test_every_param_code_has_a_field() {
+        let d = SpeeduinoData {
+            emap: Some(0),
+            pw8: Some(0),
+            ..Default::default()
+        };
+        for (code, _) in get_params_to_publish(&d, &Units::default()) {
+            assert!(channel_field(code).is_some(), "no field for {}", code);
+        }
+    }
+
+    #[test]
+    fn test_params_partial_frame_only_requested() {
+        let codes: Vec<String> = ["RPM", "MAP", "CLT", "O2P"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let d = SpeeduinoData {
+            missing: requested_fields(&codes).complement(),
+            ..Default::default()
+        };
+        let params = get_params_to_publish(&d, &Units::default());
+        let published: Vec<&str> = params.iter().map(|(k, _)| *k).collect();
+        // CAD shares the coolant byte with CLT
+        assert_eq!(published, vec!["RPM", "MAP", "CLT", "O2P", "CAD"]);
+    }
+
+    #[tokio::test]
+    async fn test_process_marks_unrequested_fields_missing() {
+        let config = AppConfig {
+            ecu_channels: vec!["RPM".to_string()],
+            ..Default::default()
+        };
+        let config = Arc::new(config);
+        let d = process_speeduino_realtime_data(
+            &[0u8; 130],
+            &config,
+            &PRIMARY,
+            None,
+            &mut PublishState::from_config(&config).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert!(d.has(Field::Rpm));
+        assert!(!d.has(Field::Map));
+    }
+
+    #[tokio::test]
+    async fn test_process_publishes_derived_channels() {
+        let mut config = AppConfig::default();
+        config
+            .derived
+            .insert("boost".to_string(), "MAP - BAR".to_string());
+        let config = Arc::new(config);
+        let mut packet = [0u8; 130];
+        packet[4] = 180; // MAP
+        packet[41] = 100; // baro
+        let (tx, mut rx) = mpsc::channel(1000);
+        process_speeduino_realtime_data(
+            &packet,
+            &config,
+            &PRIMARY,
+            Some(&tx),
+            &mut PublishState::from_config(&config).unwrap(),
+        )
+        .await
+        .unwrap();
+        drop(tx);
+        let mut boost = None;
+        while let Some(msg) = rx.recv().await {
+            if msg.topic.ends_with("/boost") {
+                boost = Some(msg.payload);
+            }
+        }
+        assert_eq!(boost.as_deref(), Some("80"));
+    }
+
+    #[tokio::test]
+    async fn test_process_publishes_json_frames() {
+        let config = AppConfig {
+            publish_mode: "json".to_string(),
+            ..Default::default()
+        };
+        let config = Arc::new(config);
+        let mut packet = [0u8; 130];
+        packet[14] = 0xB8;
+        packet[15] = 0x0B; // RPM 3000
+        let mut state = PublishState::from_config(&config).unwrap();
+        let (tx, mut rx) = mpsc::channel(1000);
+        for _ in 0..2 {
+            process_speeduino_realtime_data(&packet, &config, &PRIMARY, Some(&tx), &mut state)
+                .await
+                .unwrap();
+        }
+        drop(tx);
+        let mut frames = Vec::new();
+        while let Some(msg) = rx.recv().await {
+            assert!(msg.topic.ends_with("/FRAME"), "{}", msg.topic);
+            frames.push(serde_json::from_str::<serde_json::Value>(&msg.payload).unwrap());
+        }
+        assert_eq!(frames.len(), 2, "one message per frame");
+        assert_eq!(frames[0]["RPM"], 3000);
+        assert_eq!(frames[0]["QUALITY"], "ok");
+        assert!(frames[0]["ts"].as_u64().unwrap() > 0);
+        assert_eq!(frames[0]["seq"], 0);
+        assert_eq!(frames[1]["seq"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_process_publishes_only_changes() {
+        let config = AppConfig {
+            publish_on_change: true,
+            ..Default::default()
+        };
+        let config = Arc::new(config);
+        let mut state = PublishState::from_config(&config).unwrap();
+        let mut packet = [0u8; 130];
+        packet[14] = 0xB8;
+        packet[15] = 0x0B; // RPM 3000
+        let (tx, mut rx) = mpsc::channel(1000);
+        let mut published = Vec::new();
+        for rpm in [0x0B, 0x0B, 0x0C] {
+            packet[15] = rpm;
+            process_speeduino_realtime_data(&packet, &config, &PRIMARY, Some(&tx), &mut state)
+                .await
+                .unwrap();
+            let mut topics = Vec::new();
+            while let Ok(msg) = rx.try_recv() {
+                topics.push(msg.topic);
+            }
+            published.push(topics);
+        }
+        assert!(published[0].len() > 10, "first frame publishes everything");
+        assert!(published[1].is_empty(), "{:?}", published[1]);
+        assert!(published[2].iter().all(|t| !t.ends_with("/CLT")));
+        assert!(published[2].iter().any(|t| t.ends_with("/RPM")));
+    }
+
+    #[tokio::test]
+    async fn test_process_rejects_frame_outside_limits() {
+        let mut config = AppConfig::default();
+        config.limits.insert(
+            "RPM".to_string(),
+            LimitConfig {
+                max: Some(8000.0),
+                policy: Some("drop_frame".to_string()),
+                ..LimitConfig::default()
+            },
+        );
+        let config = Arc::new(config);
+        let mut packet = [0u8; 130];
+        packet[14] = 0xFF;
+        packet[15] = 0xFF; // RPM 65535
+        let (tx, mut rx) = mpsc::channel(1000);
+        let err = process_speeduino_realtime_data(
+            &packet,
+            &config,
+            &PRIMARY,
+            Some(&tx),
+            &mut PublishState::from_config(&config).unwrap(),
+        )
+        .await
+        .unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::Parse(ParseError::ValidationFailed { .. })
+        ));
+        drop(tx);
+        let mut topics = Vec::new();
+        while let Some(msg) = rx.recv().await {
+            topics.push((msg.topic, msg.payload));
+        }
+        assert_eq!(topics.len(), 2, "only the quality is published");
+        assert!(topics[0].0.ends_with("/QUALITY"));
+        assert_eq!(topics[0].1, "rejected");
+        assert_eq!(topics[1].1, "RPM");
+    }
+
     #[test]
     fn test_get_parsed_data_too_short() {
         assert!(get_parsed_data(&[0u8; 10]).is_err());
@@ -1045,4 +1422,99 @@ mod tests {
     fn test_get_parsed_data_valid() {
         assert!(get_parsed_data(&[0u8; 130]).is_ok());
     }
+
+    // ── Firmware-specific layouts ─────────────────────────────────────────
+
+    #[test]
+    fn test_u8_dot_layout_scales_dots_and_shifts_fields() {
+        use crate::packet_layout::PRIMARY_U8_DOT;
+        let mut p = [0u8; 128];
+        p[14] = 0xB8;
+        p[15] = 0x0B; // RPM 3000, same offset as current firmware
+        p[22] = 20; // tpsDOT ÷ 10
+        p[23] = 30; // advance moved down one byte
+        p[92] = 7; // mapDOT ÷ 10
+        p[102] = 88; // VSS low byte (current layout: 104)
+        let d = parse_realtime_data(&p, &PRIMARY_U8_DOT).unwrap();
+        assert_eq!(d.rpm, 3000);
+        assert_eq!(d.tps_dot, 200);
+        assert_eq!(d.advance, 30);
+        assert_eq!(d.map_dot, 70);
+        assert_eq!(d.vss, 88);
+        assert!(d.pw5.is_none());
+        assert!(!d.has(Field::Pw5));
+        assert!(d.has(Field::KnockRetard));
+    }
+
+    #[test]
+    fn test_secondary_layout_decodes_75_bytes() {
+        use crate::packet_layout::SECONDARY;
+        let mut p = [0u8; 75];
+        p[4] = 0x64; // MAP 100 kPa
+        p[7] = 125; // CLT 85 °C
+        p[14] = 0xB8;
+        p[15] = 0x0B; // RPM 3000
+        p[17] = 105; // corrections (single byte)
+        p[21] = 5; // tpsDOT ÷ 10
+        p[23] = 42; // TPS
+        p[39] = 101; // baro
+        p[40] = 0x34;
+        p[41] = 0x12; // CAN input 0
+        let d = parse_realtime_data(&p, &SECONDARY).unwrap();
+        assert_eq!(d.map, 100);
+        assert_eq!(d.coolant_celsius(), 85);
+        assert_eq!(d.rpm, 3000);
+        assert_eq!(d.corrections, 105);
+        assert_eq!(d.tps_dot, 50);
+        assert_eq!(d.tps, 42);
+        assert_eq!(d.baro, 101);
+        assert_eq!(d.canin[0], 0x1234);
+
+        // Fields beyond the secondary packet are absent, not zero
+        assert!(d.emap.is_none());
+        assert!(d.knock_count.is_none());
+        assert!(!d.has(Field::Pw1));
+        assert!(!d.has(Field::Vss));
+        let params = get_params_to_publish(&d, &Units::default());
+        assert!(params.iter().any(|(c, _)| *c == "RPM"));
+        assert!(!params.iter().any(|(c, _)| *c == "PW1" || *c == "VSS"));
+    }
+
+    #[tokio::test]
+    async fn test_process_strips_secondary_command_echo() {
+        use crate::packet_layout::SECONDARY;
+        let config = Arc::new(AppConfig::default());
+        let mut data = vec![b'A', 0x30];
+        data.extend_from_slice(&[0u8; 75]);
+        data[2] = 9; // secl
+        let d = process_speeduino_realtime_data(
+            &data,
+            &config,
+            &SECONDARY,
+            None,
+            &mut PublishState::from_config(&config).unwrap(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(d.secl, 9);
+    }
+
+    #[test]
+    fn test_u8_dot_layout_too_short() {
+        use crate::packet_layout::PRIMARY_U8_DOT;
+        assert!(parse_realtime_data(&[0u8; 127], &PRIMARY_U8_DOT).is_err());
+    }
+
+    #[test]
+    fn test_short_packet_marks_trailing_fields_missing() {
+        let d = parse_realtime_data(&[0u8; 130], &PRIMARY).unwrap();
+        assert!(d.has(Field::KnockRetard));
+        assert!(!d.has(Field::Pw5));
+        // Absent optional fields are not published
+        assert!(
+            !get_params_to_publish(&d, &Units::default())
+                .iter()
+                .any(|(c, _)| *c == "PW5")
+        );
+    }
 }
@@ -0,0 +1,296 @@
+//! Speeduino serial protocol framing.
+//!
+//! Current Speeduino firmware understands two protocols on the primary serial port:
+//!
+//! | Protocol | Request | Response |
+//! |----------|---------|----------|
+//! | `legacy` | bare command byte (e.g. `'A'`) | raw bytes, no length or checksum |
+//! | `crc`    | msEnvelope frame | msEnvelope frame, first payload byte is a return code |
+//!
+//! **msEnvelope frame**: `[len_hi, len_lo] ‖ payload ‖ [crc32 b3, b2, b1, b0]`.
+//! Both the length and the CRC are **big-endian**; the CRC32 (IEEE 802.3, as used
+//! by zlib) covers the payload only.
+//!
+//! Realtime data is requested with the `'r'` command:
+//! `['r', can_id, 0x30, offset_lo, offset_hi, length_lo, length_hi]`.
+//!
+//! The firmware identifies itself with `'Q'` (signature, e.g. `"speeduino 202402"`)
+//! and `'S'` (product string, e.g. `"Speeduino 2024.02"`); both answer with plain
+//! ASCII in either protocol.
+
+use crate::errors::{ParseError, Result, SerialError};
+
+/// ECU command to request realtime data (legacy protocol)
+pub const CMD_REALTIME: u8 = b'A';
+/// ECU command returning the firmware signature
+pub const CMD_SIGNATURE: u8 = b'Q';
+/// ECU command returning the firmware product/version string
+pub const CMD_VERSION: u8 = b'S';
+/// ECU command to read a range of the output-channel block
+pub const CMD_READ: u8 = b'r';
+/// `'r'` sub-command selecting the realtime output channels
+pub const READ_OUTPUT_CHANNELS: u8 = 0x30;
+/// TunerStudio CAN ID of the ECU itself
+pub const TS_CAN_ID: u8 = 0x00;
+
+/// Size of the msEnvelope length header in bytes.
+pub const FRAME_HEADER_BYTES: usize = 2;
+/// Size of the msEnvelope CRC32 trailer in bytes.
+pub const FRAME_CRC_BYTES: usize = 4;
+/// Largest payload we accept — anything bigger means the link is out of sync.
+pub const MAX_FRAME_PAYLOAD: usize = 1024;
+
+// Response return codes (`SERIAL_RC_*` in Speeduino newserial.h)
+pub const SERIAL_RC_OK: u8 = 0x00;
+pub const SERIAL_RC_BURN_OK: u8 = 0x04;
+pub const SERIAL_RC_TIMEOUT: u8 = 0x80;
+pub const SERIAL_RC_CRC_ERR: u8 = 0x82;
+pub const SERIAL_RC_UKWN_ERR: u8 = 0x83;
+pub const SERIAL_RC_RANGE_ERR: u8 = 0x84;
+pub const SERIAL_RC_BUSY_ERR: u8 = 0x85;
+
+/// Wire protocol used to talk to the ECU (`ecu_protocol` config value).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    /// Bare single-byte commands, unframed responses.
+    Legacy,
+    /// msEnvelope framing with CRC32 trailer.
+    Crc,
+}
+
+impl Protocol {
+    /// Parse the `ecu_protocol` config string (case-insensitive).
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "legacy" => Some(Protocol::Legacy),
+            "crc" => Some(Protocol::Crc),
+            _ => None,
+        }
+    }
+}
+
+/// Firmware identification obtained from the `'Q'`/`'S'` handshake.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirmwareInfo {
+    /// Signature string, e.g. `"speeduino 202402"`
+    pub signature: String,
+    /// Product/version string, e.g. `"Speeduino 2024.02"`
+    pub version: String,
+}
+
+impl FirmwareInfo {
+    /// Build from the raw `'Q'` and `'S'` responses.
+    pub fn from_responses(signature: &[u8], version: &[u8]) -> Self {
+        Self {
+            signature: response_text(signature),
+            version: response_text(version),
+        }
+    }
+
+    /// True when the signature identifies a Speeduino ECU.
+    pub fn is_speeduino(&self) -> bool {
+        self.signature.to_lowercase().starts_with("speeduino")
+    }
+
+    /// Release number from the signature (`"speeduino 202402-dev"` → `202402`).
+    pub fn release(&self) -> Option<u32> {
+        let token = self.signature.split_whitespace().nth(1)?;
+        let digits: String = token.chars().take_while(char::is_ascii_digit).collect();
+        digits.parse().ok()
+    }
+}
+
+/// Decode an ASCII text response, dropping NUL padding and surrounding whitespace.
+fn response_text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_matches(|c: char| c == '\0' || c.is_whitespace())
+        .to_string()
+}
+
+/// Human-readable name for a Speeduino response return code.
+pub fn return_code_name(code: u8) -> &'static str {
+    match code {
+        SERIAL_RC_OK => "ok",
+        SERIAL_RC_BURN_OK => "burn ok",
+        SERIAL_RC_TIMEOUT => "timeout",
+        SERIAL_RC_CRC_ERR => "request CRC mismatch",
+        SERIAL_RC_UKWN_ERR => "unknown command",
+        SERIAL_RC_RANGE_ERR => "range error",
+        SERIAL_RC_BUSY_ERR => "busy",
+        _ => "unrecognised",
+    }
+}
+
+/// CRC32 (IEEE 802.3 polynomial, reflected) as used by the msEnvelope trailer.
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// Wrap `payload` in an msEnvelope frame (length header + CRC32 trailer).
+pub fn frame(payload: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(FRAME_HEADER_BYTES + payload.len() + FRAME_CRC_BYTES);
+    out.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+    out.extend_from_slice(payload);
+    out.extend_from_slice(&crc32(payload).to_be_bytes());
+    out
+}
+
+/// Build the `'r'` request payload for `length` bytes of output channels at `offset`.
+pub fn read_request(offset: u16, length: u16) -> [u8; 7] {
+    let [off_lo, off_hi] = offset.to_le_bytes();
+    let [len_lo, len_hi] = length.to_le_bytes();
+    [
+        CMD_READ,
+        TS_CAN_ID,
+        READ_OUTPUT_CHANNELS,
+        off_lo,
+        off_hi,
+        len_lo,
+        len_hi,
+    ]
+}
+
+/// Decode the big-endian payload length from an msEnvelope header.
+///
+/// Zero-length or oversized frames are rejected because they can only come from
+/// a desynchronised or noisy link.
+pub fn payload_length(header: [u8; FRAME_HEADER_BYTES]) -> Result<usize> {
+    let len = u16::from_be_bytes(header) as usize;
+    if len == 0 || len > MAX_FRAME_PAYLOAD {
+        return Err(ParseError::InvalidData {
+            offset: 0,
+            message: format!("implausible frame length {}", len),
+        }
+        .into());
+    }
+    Ok(len)
+}
+
+/// Validate a framed response body (`payload ‖ crc32`) and return the payload
+/// with its leading return-code byte stripped.
+pub fn decode_response(body: &[u8]) -> Result<&[u8]> {
+    if body.len() < 1 + FRAME_CRC_BYTES {
+        return Err(ParseError::InsufficientData {
+            expected: 1 + FRAME_CRC_BYTES,
+            actual: body.len(),
+        }
+        .into());
+    }
+
+    let (payload, trailer) = body.split_at(body.len() - FRAME_CRC_BYTES);
+    let expected = u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let actual = crc32(payload);
+    if expected != actual {
+        return Err(ParseError::ChecksumMismatch { expected, actual }.into());
+    }
+
+    match payload[0] {
+        SERIAL_RC_OK => Ok(&payload[1..]),
+        code => Err(SerialError::ErrorResponse {
+            code,
+            reason: return_code_name(code),
+        }
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::errors::AppError;
+
+    fn response_body(payload: &[u8]) -> Vec<u8> {
+        frame(payload)[FRAME_HEADER_BYTES..].to_vec()
+    }
+
+    #[test]
+    fn test_crc32_check_value() {
+        // Standard CRC-32 check value
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
+    }
+
+    #[test]
+    fn test_frame_layout() {
+        let f = frame(b"A");
+        assert_eq!(&f[..2], &[0x00, 0x01]);
+        assert_eq!(f[2], b'A');
+        assert_eq!(&f[3..], &crc32(b"A").to_be_bytes());
+    }
+
+    #[test]
+    fn test_read_request_encoding() {
+        let r = read_request(4, 0x0102);
+        assert_eq!(r, [b'r', 0x00, 0x30, 4, 0, 0x02, 0x01]);
+    }
+
+    #[test]
+    fn test_payload_length_rejects_zero_and_oversized() {
+        assert_eq!(payload_length([0x00, 0x8B]).unwrap(), 139);
+        assert!(payload_length([0x00, 0x00]).is_err());
+        assert!(payload_length([0xFF, 0xFF]).is_err());
+    }
+
+    #[test]
+    fn test_decode_response_ok_strips_return_code() {
+        let body = response_body(&[SERIAL_RC_OK, 1, 2, 3]);
+        assert_eq!(decode_response(&body).unwrap(), &[1, 2, 3]);
+    }
+
+    #[test]
+    fn test_decode_response_checksum_mismatch() {
+        let mut body = response_body(&[SERIAL_RC_OK, 1, 2, 3]);
+        body[2] ^= 0x40; // flip a bit in the payload
+        let err = decode_response(&body).unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::Parse(ParseError::ChecksumMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_response_error_code() {
+        let body = response_body(&[SERIAL_RC_RANGE_ERR]);
+        let err = decode_response(&body).unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::Serial(SerialError::ErrorResponse { code: 0x84, .. })
+        ));
+    }
+
+    #[test]
+    fn test_decode_response_too_short() {
+        assert!(decode_response(&[0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn test_firmware_info_from_responses() {
+        let fw = FirmwareInfo::from_responses(b"speeduino 202402\0", b"Speeduino 2024.02\r\n");
+        assert_eq!(fw.signature, "speeduino 202402");
+        assert_eq!(fw.version, "Speeduino 2024.02");
+        assert!(fw.is_speeduino());
+        assert_eq!(fw.release(), Some(202402));
+
+        let dev = FirmwareInfo::from_responses(b"speeduino 202501-dev", b"");
+        assert_eq!(dev.release(), Some(202501));
+
+        let gps = FirmwareInfo::from_responses(b"$GPGGA,123519,4807.038,N", b"");
+        assert!(!gps.is_speeduino());
+        assert_eq!(gps.release(), None);
+    }
+
+    #[test]
+    fn test_protocol_from_config() {
+        assert_eq!(Protocol::from_config("legacy"), Some(Protocol::Legacy));
+        assert_eq!(Protocol::from_config("CRC"), Some(Protocol::Crc));
+        assert_eq!(Protocol::from_config("msenvelope"), None);
+    }
+}
@@ -1,25 +1,44 @@
 //! ECU Communication Handler
 //!
 //! Handles async communication with the Speeduino ECU over either a hardware
-//! serial port or a raw TCP socket (e.g. an ESP32 / Moxa WiFi bridge).
+//! serial port or a raw TCP socket (e.g. an ESP32 / Moxa WiFi bridge), or
+//! with a capture file standing in for the ECU.
+//! Speaks either the legacy bare-command protocol or the CRC32-framed
+//! msEnvelope protocol (see [`crate::ecu_protocol`]).
 //! Implements exponential backoff for reconnection and robust error handling.
 
 use crate::config::AppConfig;
 use crate::connection::EcuConnection;
-use crate::errors::{Result, SerialError};
+use crate::ecu_data_parser::requested_fields;
+use crate::ecu_protocol::{self, FRAME_CRC_BYTES, FRAME_HEADER_BYTES, FirmwareInfo, Protocol};
+use crate::errors::{AppError, Result, SerialError};
+use crate::packet_layout::{Field, Layout, PRIMARY, byte_ranges, select_layout};
+use std::ops::Range;
 use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::time::{sleep, timeout};
+use tokio::time::{Instant, sleep, timeout};
 use tracing::{debug, error, info, warn};
 
 /// ECU command to request realtime data
-const ECU_COMMAND: u8 = b'A';
+const ECU_COMMAND: u8 = ecu_protocol::CMD_REALTIME;
 
-/// Time to wait after sending the command before draining the buffer.
-/// At 115200 baud, 138 bytes take ~12 ms to transmit.  150 ms gives
-/// comfortable headroom for any Speeduino firmware version so the entire
-/// packet is sitting in the OS buffer before we start reading.
-const COMMAND_PROCESSING_DELAY_MS: u64 = 150;
+/// With the firmware unknown, once the shortest acceptable packet is in, how
+/// long the line may stay quiet before the remaining optional bytes are given
+/// up on (e.g. a 130-byte simulator packet when 138 bytes are possible).  Kept
+/// above the 16 ms default latency timer of FTDI USB-serial adapters, whose
+/// pauses would otherwise cut a packet short.
+const INTER_BYTE_IDLE_MS: u64 = 25;
+
+/// Command echo some ports send in front of the packet (see [`Layout::payload`]).
+const ECHO_HEADER_BYTES: usize = 2;
+
+/// secl may advance by this much more than the wall-clock seconds between two
+/// frames (ECU and host clocks tick independently).
+const SECL_SLACK_SECS: u64 = 2;
+
+/// After this many consecutive rejections the frame checker assumes the ECU
+/// really changed (reset, firmware swap) and re-anchors on the new frames.
+const REANCHOR_AFTER_REJECTS: u32 = 3;
 
 /// Maximum response buffer size — larger than any known Speeduino packet.
 const MAX_PACKET_BYTES: usize = 256;
@@ -28,6 +47,16 @@ const MAX_PACKET_BYTES: usize = 256;
 pub struct EcuSerialHandler {
     connection: Option<EcuConnection>,
     config: AppConfig,
+    protocol: Protocol,
+    /// Packet layout the ECU is expected to send
+    layout: &'static Layout,
+    /// Byte ranges fetched with 'r' when `ecu_channels` is set (empty = full packet)
+    read_ranges: Vec<Range<usize>>,
+    /// Firmware reported by the last successful handshake
+    firmware: Option<FirmwareInfo>,
+    frame_checker: FrameChecker,
+    /// Frames discarded as corrupted or misaligned since start-up
+    bad_frames: u64,
     retry_count: u32,
     current_delay_ms: u64,
 }
@@ -35,8 +64,25 @@ pub struct EcuSerialHandler {
 impl EcuSerialHandler {
     /// Create a new ECU serial handler
     pub fn new(config: AppConfig) -> Self {
+        let layout = select_layout(&config.ecu_layout, None).unwrap_or(&PRIMARY);
+        // A capture holds whole unframed packets, so a replay is always read
+        // with plain 'A' requests; ecu_channels still filters what is published.
+        let (protocol, read_ranges) = if config.is_replay() {
+            (Protocol::Legacy, Vec::new())
+        } else {
+            (
+                Protocol::from_config(&config.ecu_protocol).unwrap_or(Protocol::Legacy),
+                byte_ranges(layout, requested_fields(&config.ecu_channels)),
+            )
+        };
         Self {
             connection: None,
+            protocol,
+            layout,
+            read_ranges,
+            firmware: None,
+            frame_checker: FrameChecker::default(),
+            bad_frames: 0,
             config: config.clone(),
             retry_count: 0,
             current_delay_ms: config.initial_retry_delay_ms,
@@ -49,6 +95,29 @@ impl EcuSerialHandler {
 
         let conn = EcuConnection::open(&self.config).await?;
         self.connection = Some(conn);
+        self.firmware = None;
+        self.frame_checker = FrameChecker::default();
+
+        if self.config.ecu_handshake && !self.config.is_replay() {
+            match self.handshake().await {
+                Ok(firmware) => {
+                    info!(
+                        "ECU firmware: {} ({})",
+                        firmware.signature, firmware.version
+                    );
+                    self.firmware = Some(firmware);
+                }
+                Err(e @ AppError::Serial(SerialError::UnexpectedDevice { .. })) => {
+                    self.connection = None;
+                    return Err(e);
+                }
+                // Some bridges and very old firmware never answer 'Q'/'S';
+                // realtime polling still works, so keep the connection.
+                Err(e) => warn!("Firmware handshake failed: {}", e),
+            }
+        }
+        self.select_layout();
+
         self.retry_count = 0;
         self.current_delay_ms = self.config.initial_retry_delay_ms;
 
@@ -62,6 +131,75 @@ impl EcuSerialHandler {
         self.connection.is_some()
     }
 
+    /// Packet layout matching the connected firmware (or the configured one).
+    pub fn layout(&self) -> &'static Layout {
+        self.layout
+    }
+
+    /// Pick the packet layout for the connected firmware and re-plan partial reads.
+    fn select_layout(&mut self) {
+        let release = self.firmware.as_ref().and_then(FirmwareInfo::release);
+        let layout = select_layout(&self.config.ecu_layout, release).unwrap_or(&PRIMARY);
+        if layout != self.layout {
+            info!("Using {} packet layout", layout.name);
+        }
+        self.layout = layout;
+        self.read_ranges = byte_ranges(layout, requested_fields(&self.config.ecu_channels));
+    }
+
+    /// True once the packet length is certain: the firmware answered the
+    /// handshake or `ecu_layout` pins the layout.  Replays may hold packets of
+    /// any firmware, so their length is never taken for granted.
+    fn layout_known(&self) -> bool {
+        !self.config.is_replay()
+            && (self.firmware.is_some() || !self.config.ecu_layout.eq_ignore_ascii_case("auto"))
+    }
+
+    /// Bytes requested per realtime read.  Once the layout is known its length
+    /// is authoritative; otherwise `expected_data_length` is used.
+    fn packet_length(&self) -> usize {
+        if self.layout_known() {
+            self.layout.length
+        } else {
+            self.config.expected_data_length
+        }
+    }
+
+    /// Firmware identification from the last handshake, if any.
+    pub fn firmware(&self) -> Option<&FirmwareInfo> {
+        self.firmware.as_ref()
+    }
+
+    /// Query signature ('Q') and version ('S') and verify it is a Speeduino.
+    async fn handshake(&mut self) -> Result<FirmwareInfo> {
+        let signature = self.text_request(ecu_protocol::CMD_SIGNATURE).await?;
+        let version = self.text_request(ecu_protocol::CMD_VERSION).await?;
+        let firmware = FirmwareInfo::from_responses(&signature, &version);
+        if !firmware.is_speeduino() {
+            return Err(SerialError::UnexpectedDevice {
+                signature: firmware.signature,
+            }
+            .into());
+        }
+        Ok(firmware)
+    }
+
+    /// Send a single-byte command whose answer is a text string of unknown length.
+    async fn text_request(&mut self, command: u8) -> Result<Vec<u8>> {
+        if self.protocol == Protocol::Crc {
+            return self.framed_request(&[command]).await;
+        }
+
+        let first_deadline = Duration::from_millis(self.config.read_timeout_ms);
+        let conn = self.connection.as_mut().ok_or(SerialError::Disconnected)?;
+        conn.clear_buffers().ok();
+        conn.write_all(&[command])
+            .await
+            .map_err(SerialError::WriteFailed)?;
+        conn.flush().await.map_err(SerialError::WriteFailed)?;
+        read_response(conn, MAX_PACKET_BYTES, 1, first_deadline).await
+    }
+
     /// Close the ECU connection.
     pub async fn disconnect(&mut self) {
         if self.connection.take().is_some() {
@@ -69,14 +207,149 @@ impl EcuSerialHandler {
         }
     }
 
-    /// Read engine data from the ECU
+    /// Read engine data from the ECU using the configured protocol.
+    ///
+    /// With `ecu_channels` configured only the needed byte ranges are requested;
+    /// the result is still a full-length packet with the unread bytes zeroed.
     ///
-    /// Flushes the hardware buffer, sends 'A', then sleeps long enough for the
-    /// ECU to finish transmitting before draining whatever arrived.  Because we
-    /// wait before reading, all bytes are already in the OS buffer — each
-    /// non-blocking drain read completes instantly.  No `read_exact` means no
-    /// hang regardless of firmware packet size (130, 138, or anything else).
+    /// Every frame is sanity-checked before it is handed out.  Corrupted
+    /// (CRC) and misaligned ([`SerialError::Desync`]) frames are counted in
+    /// [`Self::bad_frames`], the input is flushed so the next request starts on
+    /// a frame boundary, and the error is returned instead of the data.
     pub async fn read_engine_data(&mut self) -> Result<Vec<u8>> {
+        if self
+            .connection
+            .as_ref()
+            .is_some_and(EcuConnection::is_exhausted)
+        {
+            return Err(SerialError::ReplayFinished.into());
+        }
+        let result = match self.read_frame().await {
+            Ok(data) => self.verify_frame(&data).map(|_| data),
+            Err(e) => Err(e),
+        };
+        if let Err(AppError::Parse(_) | AppError::Serial(SerialError::Desync(_))) = result {
+            self.bad_frames += 1;
+            self.resync().await;
+        }
+        result
+    }
+
+    /// Number of frames discarded as corrupted or misaligned.
+    pub fn bad_frames(&self) -> u64 {
+        self.bad_frames
+    }
+
+    /// Fetch one raw frame without any sanity checks.
+    async fn read_frame(&mut self) -> Result<Vec<u8>> {
+        if !self.read_ranges.is_empty() {
+            return self.read_channel_ranges().await;
+        }
+        match self.protocol {
+            Protocol::Legacy => self.read_legacy_realtime().await,
+            Protocol::Crc => {
+                let length = self.packet_length() as u16;
+                self.framed_request(&ecu_protocol::read_request(0, length))
+                    .await
+            }
+        }
+    }
+
+    /// Framing sanity checks: plausible length and secl progression.  A
+    /// packet assembled from partial reads is checked the same way, its secl
+    /// only when that byte was fetched.
+    fn verify_frame(&mut self, data: &[u8]) -> Result<()> {
+        let partial = !self.read_ranges.is_empty();
+        let (min, max) = match self.protocol {
+            _ if partial => (self.packet_length(), self.packet_length()),
+            Protocol::Legacy if self.layout.echo_header => (
+                self.layout.min_length,
+                self.layout.length + ECHO_HEADER_BYTES,
+            ),
+            Protocol::Legacy => (self.layout.min_length, self.layout.length),
+            Protocol::Crc => (self.packet_length(), self.packet_length()),
+        };
+        if data.len() < min || data.len() > max {
+            return Err(SerialError::Desync(format!(
+                "implausible response length {} (expected {}–{})",
+                data.len(),
+                min,
+                max
+            ))
+            .into());
+        }
+
+        // Replayed frames are paced by the capture, not the wall clock.
+        let payload = if partial {
+            data
+        } else {
+            self.layout.payload(data)
+        };
+        let secl = self
+            .layout
+            .def(Field::Secl)
+            .filter(|_| !self.config.is_replay())
+            .filter(|d| !partial || self.read_ranges.iter().any(|r| r.contains(&d.offset)))
+            .and_then(|d| payload.get(d.offset).copied());
+        self.frame_checker
+            .check(data.len(), secl, Instant::now())
+            .map_err(|reason| SerialError::Desync(reason).into())
+    }
+
+    /// Discard whatever is buffered so the next request starts on a frame
+    /// boundary.  TCP has no driver buffer to clear, so read until the line
+    /// has been quiet for [`INTER_BYTE_IDLE_MS`].
+    async fn resync(&mut self) {
+        let Some(conn) = self.connection.as_mut() else {
+            return;
+        };
+        conn.clear_buffers().ok();
+        let idle = Duration::from_millis(INTER_BYTE_IDLE_MS);
+        let mut tmp = [0u8; MAX_PACKET_BYTES];
+        let mut discarded = 0;
+        while let Ok(Ok(n)) = timeout(idle, conn.read(&mut tmp)).await {
+            if n == 0 {
+                break;
+            }
+            discarded += n;
+        }
+        debug!(
+            "Resynchronised ECU link ({} stray bytes discarded)",
+            discarded
+        );
+    }
+
+    /// Legacy 'A' realtime read.
+    ///
+    /// Flushes the hardware buffer, sends 'A' and streams the response until the
+    /// active layout's full length has arrived.  With the layout known exactly
+    /// [`Self::packet_length`] bytes are read, however long the line pauses
+    /// in between.  Otherwise short packets (e.g. 130 bytes from the simulator)
+    /// are accepted once the layout minimum is in and the line goes idle; from
+    /// then on the read stops at the length of the last accepted frame, so
+    /// only the first poll waits for the line to go quiet.  No fixed
+    /// post-command sleep: the read returns as soon as the packet is complete,
+    /// so the poll rate is bounded by the link, not by us.
+    async fn read_legacy_realtime(&mut self) -> Result<Vec<u8>> {
+        let deadline = Duration::from_millis(self.config.read_timeout_ms);
+        let exact = self.layout_known();
+        let (min, max) = if exact {
+            let length = self.packet_length();
+            (length, length)
+        } else if self.layout.echo_header {
+            (
+                self.layout.min_length,
+                self.layout.length + ECHO_HEADER_BYTES,
+            )
+        } else {
+            (self.layout.min_length, self.layout.length)
+        };
+        let expected = self
+            .frame_checker
+            .length
+            .filter(|length| (min..=max).contains(length))
+            .unwrap_or(max);
+        let echo_header = self.layout.echo_header;
         let conn = self.connection.as_mut().ok_or(SerialError::Disconnected)?;
 
         // ── Flush hardware buffer before sending ──────────────────────────────
@@ -89,45 +362,107 @@ impl EcuSerialHandler {
             .map_err(SerialError::WriteFailed)?;
         conn.flush().await.map_err(SerialError::WriteFailed)?;
 
-        // ── Wait for ECU to finish transmitting ───────────────────────────────
-        sleep(Duration::from_millis(COMMAND_PROCESSING_DELAY_MS)).await;
+        // ── Stream the response ───────────────────────────────────────────────
+        let mut buffer = read_response(conn, expected, min, deadline).await?;
 
-        // ── Drain whatever is in the OS buffer ────────────────────────────────
-        // For serial: all bytes arrived during the sleep above, so every read()
-        // returns immediately.  For TCP: the first read() uses the full deadline
-        // in case the response is still in-flight (TCP has no UART buffer
-        // guarantee).  Subsequent reads use 5 ms to drain any remaining bytes
-        // without blocking once the bus goes idle.
-        let mut buffer: Vec<u8> = Vec::with_capacity(MAX_PACKET_BYTES);
-        let first_deadline = Duration::from_millis(self.config.read_timeout_ms);
-        let mut first_read = true;
-        loop {
-            let per_read_timeout = if first_read { first_deadline } else { Duration::from_millis(5) };
-            let mut tmp = [0u8; 64];
-            match timeout(per_read_timeout, conn.read(&mut tmp)).await {
-                Ok(Ok(0)) => break,
-                Ok(Ok(n)) => {
-                    first_read = false;
-                    buffer.extend_from_slice(&tmp[..n]);
-                    if buffer.len() >= MAX_PACKET_BYTES {
-                        break;
-                    }
-                }
-                Ok(Err(e)) => return Err(SerialError::ReadFailed(e).into()),
-                Err(_) => break, // idle — buffer fully drained
-            }
+        // An exact read counted the command echo as packet bytes; fetch the
+        // two it displaced.
+        let echoed = matches!(buffer[..], [b'A' | b'r', 0x30 | 0x31, ..]);
+        if echo_header && exact && echoed && buffer.len() == expected {
+            let rest = read_response(conn, ECHO_HEADER_BYTES, ECHO_HEADER_BYTES, deadline).await?;
+            buffer.extend_from_slice(&rest);
         }
+        debug!("Received {} bytes from ECU", buffer.len());
+        Ok(buffer)
+    }
 
-        if buffer.is_empty() {
-            warn!("No data received from ECU");
-            return Err(SerialError::ReadTimeout {
-                timeout_ms: self.config.read_timeout_ms,
+    /// Assemble a packet from one 'r' request per configured byte range.  A
+    /// reply of the wrong length is a misaligned frame, like a full packet
+    /// of implausible length.
+    async fn read_channel_ranges(&mut self) -> Result<Vec<u8>> {
+        let mut packet = vec![0u8; self.packet_length()];
+        for range in self.read_ranges.clone() {
+            let bytes = self.read_range(range.start, range.len()).await?;
+            if bytes.len() != range.len() {
+                return Err(SerialError::Desync(format!(
+                    "implausible response length {} for bytes {}–{}",
+                    bytes.len(),
+                    range.start,
+                    range.end - 1
+                ))
+                .into());
             }
-            .into());
+            packet[range].copy_from_slice(&bytes);
         }
+        debug!(
+            "Assembled packet from {} partial read(s)",
+            self.read_ranges.len()
+        );
+        Ok(packet)
+    }
 
-        debug!("Received {} bytes from ECU", buffer.len());
-        Ok(buffer)
+    /// Read `length` bytes of output channels starting at `offset`.
+    async fn read_range(&mut self, offset: usize, length: usize) -> Result<Vec<u8>> {
+        let request = ecu_protocol::read_request(offset as u16, length as u16);
+        if self.protocol == Protocol::Crc {
+            return self.framed_request(&request).await;
+        }
+
+        // Legacy 'r': the ECU answers with exactly `length` raw bytes; a
+        // short or overlong reply comes back as is, for the caller to reject.
+        let deadline = Duration::from_millis(self.config.read_timeout_ms);
+        let conn = self.connection.as_mut().ok_or(SerialError::Disconnected)?;
+        conn.clear_buffers().ok();
+        conn.write_all(&request)
+            .await
+            .map_err(SerialError::WriteFailed)?;
+        conn.flush().await.map_err(SerialError::WriteFailed)?;
+
+        read_response(conn, length, length, deadline).await
+    }
+
+    /// Send an msEnvelope-framed request and return the verified response payload
+    /// (return-code byte stripped).
+    ///
+    /// The length header tells us exactly how many bytes to expect, so the whole
+    /// exchange is bounded by a single `read_timeout_ms` deadline.  A corrupted
+    /// frame surfaces as [`ParseError::ChecksumMismatch`](crate::errors::ParseError)
+    /// and never reaches the parser.
+    async fn framed_request(&mut self, payload: &[u8]) -> Result<Vec<u8>> {
+        let timeout_ms = self.config.read_timeout_ms;
+        let conn = self.connection.as_mut().ok_or(SerialError::Disconnected)?;
+
+        conn.clear_buffers().ok();
+
+        debug!(
+            "Sending framed ECU command: 0x{:02X} ({} bytes)",
+            payload[0],
+            payload.len()
+        );
+        conn.write_all(&ecu_protocol::frame(payload))
+            .await
+            .map_err(SerialError::WriteFailed)?;
+        conn.flush().await.map_err(SerialError::WriteFailed)?;
+
+        let body = timeout(Duration::from_millis(timeout_ms), async {
+            let mut header = [0u8; FRAME_HEADER_BYTES];
+            conn.read_exact(&mut header)
+                .await
+                .map_err(SerialError::ReadFailed)?;
+            let len = ecu_protocol::payload_length(header)?;
+
+            let mut body = vec![0u8; len + FRAME_CRC_BYTES];
+            conn.read_exact(&mut body)
+                .await
+                .map_err(SerialError::ReadFailed)?;
+            Ok::<_, AppError>(body)
+        })
+        .await
+        .map_err(|_| SerialError::ReadTimeout { timeout_ms })??;
+
+        let data = ecu_protocol::decode_response(&body)?.to_vec();
+        debug!("Received {} byte framed response from ECU", data.len());
+        Ok(data)
     }
 
     /// Attempt to reconnect with exponential backoff
@@ -176,10 +511,16 @@ impl EcuSerialHandler {
 
     /// Check whether the connection target exists / is reachable.
     ///
-    /// For TCP mode, always returns `true` (TCP connectivity is determined at
-    /// connect time).  For serial, walks the available-ports list.
+    /// For TCP and replay mode, always returns `true` (reachability is
+    /// determined at connect time).  For serial, checks the device path (Unix)
+    /// and otherwise walks the available-ports list.
     pub fn check_device_exists(&self) -> bool {
-        if self.config.connection_type == "tcp" {
+        if self.config.connection_type == "tcp" || self.config.is_replay() {
+            return true;
+        }
+        // Pseudo-terminals (e.g. speeduino-sim) are never enumerated.
+        #[cfg(unix)]
+        if std::path::Path::new(&self.config.port_name).exists() {
             return true;
         }
         match tokio_serial::available_ports() {
@@ -215,6 +556,121 @@ impl EcuSerialHandler {
     }
 }
 
+// ---------------------------------------------------------------------------
+// Frame sanity checks
+// ---------------------------------------------------------------------------
+
+/// Tracks what a well-aligned frame looks like so that a shifted buffer (a
+/// dropped byte, a stray echo) is caught even though its length is plausible.
+#[derive(Debug, Default)]
+struct FrameChecker {
+    /// Length of the last accepted frame
+    length: Option<usize>,
+    /// secl of the last accepted frame and when it arrived
+    secl: Option<(u8, Instant)>,
+    /// Consecutive rejected frames
+    rejected: u32,
+}
+
+impl FrameChecker {
+    /// Accept the frame, or return why it looks misaligned.
+    ///
+    /// The frame length must not change between frames, and secl (the ECU's
+    /// seconds counter, wrapping at 255) may only advance by roughly the time
+    /// elapsed since the last accepted frame.
+    fn check(
+        &mut self,
+        length: usize,
+        secl: Option<u8>,
+        now: Instant,
+    ) -> std::result::Result<(), String> {
+        let problem = match (self.length, secl, self.secl) {
+            (Some(last), _, _) if last != length => Some(format!(
+                "frame length changed from {} to {} bytes",
+                last, length
+            )),
+            (_, Some(secl), Some((last, at))) => {
+                let advanced = secl.wrapping_sub(last) as u64;
+                let allowed = now.duration_since(at).as_secs() + SECL_SLACK_SECS;
+                (advanced > allowed).then(|| format!("secl jumped from {} to {}", last, secl))
+            }
+            _ => None,
+        };
+
+        if let Some(reason) = problem {
+            self.rejected += 1;
+            if self.rejected < REANCHOR_AFTER_REJECTS {
+                return Err(reason);
+            }
+            warn!(
+                "{} for {} frames in a row – assuming the ECU restarted",
+                reason, self.rejected
+            );
+        }
+
+        self.length = Some(length);
+        if let Some(secl) = secl {
+            self.secl = Some((secl, now));
+        }
+        self.rejected = 0;
+        Ok(())
+    }
+}
+
+/// Read a response of up to `expected` bytes.
+///
+/// Returns as soon as `expected` bytes have arrived, or once at least `min`
+/// bytes are in and the line has been idle for [`INTER_BYTE_IDLE_MS`]
+/// (`min == expected` waits for the whole response, gaps or not).  The
+/// whole exchange is bounded by `deadline`.  Bytes already sitting in the OS
+/// buffer beyond `expected` are drained without waiting, so a longer-than-
+/// expected packet never leaks into the next read.
+async fn read_response(
+    conn: &mut EcuConnection,
+    expected: usize,
+    min: usize,
+    deadline: Duration,
+) -> Result<Vec<u8>> {
+    let expires = Instant::now() + deadline;
+    let idle = Duration::from_millis(INTER_BYTE_IDLE_MS);
+    let mut buffer: Vec<u8> = Vec::with_capacity(MAX_PACKET_BYTES);
+    let mut tmp = [0u8; MAX_PACKET_BYTES];
+
+    while buffer.len() < expected {
+        let remaining = expires.saturating_duration_since(Instant::now());
+        let wait = if buffer.len() >= min {
+            idle.min(remaining)
+        } else {
+            remaining
+        };
+        let want = (expected - buffer.len()).min(tmp.len());
+        match timeout(wait, conn.read(&mut tmp[..want])).await {
+            Ok(Ok(0)) => break,
+            Ok(Ok(n)) => buffer.extend_from_slice(&tmp[..n]),
+            Ok(Err(e)) => return Err(SerialError::ReadFailed(e).into()),
+            Err(_) => break, // deadline, or idle after the minimum
+        }
+    }
+
+    // Pick up any surplus that is already buffered (zero-wait poll).
+    while buffer.len() < MAX_PACKET_BYTES {
+        match timeout(Duration::ZERO, conn.read(&mut tmp)).await {
+            Ok(Ok(n)) if n > 0 => buffer.extend_from_slice(&tmp[..n]),
+            Ok(Err(e)) => return Err(SerialError::ReadFailed(e).into()),
+            _ => break,
+        }
+    }
+
+    if buffer.is_empty() {
+        warn!("No data received from ECU");
+        return Err(SerialError::ReadTimeout {
+            timeout_ms: deadline.as_millis() as u64,
+        }
+        .into());
+    }
+    Ok(buffer)
+}
+
 impl Drop for EcuSerialHandler {
     fn drop(&mut self) {
         debug!("Dropping EcuSerialHandler (connection will close automatically)");
@@ -259,6 +715,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_exponential_backoff_calculation() {
         let mut config = AppConfig::default();
         config.initial_retry_delay_ms = 1000;
@@ -285,6 +742,348 @@ mod tests {
         assert_eq!(handler.current_delay_ms, 60000);
     }
 
+    /// Handler wired to one end of a pseudo-terminal; the other end plays the ECU.
+    #[cfg(unix)]
+    fn pty_handler(config: AppConfig) -> (EcuSerialHandler, tokio_serial::SerialStream) {
+        let (ours, ecu) = tokio_serial::SerialStream::pair().expect("pty pair");
+        let mut handler = EcuSerialHandler::new(config);
+        handler.connection = Some(EcuConnection::Serial(ours));
+        (handler, ecu)
+    }
+
+    /// Fake ECU answering 'Q' and 'S' with the given strings.
+    #[cfg(unix)]
+    fn spawn_identity(mut ecu: tokio_serial::SerialStream, signature: &'static [u8]) {
+        tokio::spawn(async move {
+            let mut cmd = [0u8; 1];
+            while ecu.read_exact(&mut cmd).await.is_ok() {
+                let reply: &[u8] = match cmd[0] {
+                    b'Q' => signature,
+                    b'S' => b"Speeduino 2024.02",
+                    _ => continue,
+                };
+                ecu.write_all(reply).await.unwrap();
+            }
+        });
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_handshake_identifies_speeduino() {
+        let (mut handler, ecu) = pty_handler(AppConfig::default());
+        spawn_identity(ecu, b"speeduino 202402");
+
+        let firmware = handler.handshake().await.unwrap();
+        assert_eq!(firmware.signature, "speeduino 202402");
+        assert_eq!(firmware.version, "Speeduino 2024.02");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_handshake_rejects_other_device() {
+        let (mut handler, ecu) = pty_handler(AppConfig::default());
+        spawn_identity(ecu, b"rusEFI master.2024");
+
+        let err = handler.handshake().await.unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::Serial(SerialError::UnexpectedDevice { .. })
+        ));
+    }
+
+    /// Fake ECU answering every 'A' with `packet`.
+    #[cfg(unix)]
+    fn spawn_realtime(mut ecu: tokio_serial::SerialStream, packet: Vec<u8>) {
+        tokio::spawn(async move {
+            let mut cmd = [0u8; 1];
+            while ecu.read_exact(&mut cmd).await.is_ok() {
+                if cmd[0] == b'A' {
+                    ecu.write_all(&packet).await.unwrap();
+                }
+            }
+        });
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_legacy_read_returns_full_packet() {
+        let (mut handler, ecu) = pty_handler(AppConfig::default());
+        let packet: Vec<u8> = (0..138).map(|i| i as u8).collect();
+        spawn_realtime(ecu, packet.clone());
+
+        assert_eq!(handler.read_engine_data().await.unwrap(), packet);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_legacy_read_accepts_short_packet_after_idle() {
+        let (mut handler, ecu) = pty_handler(AppConfig::default());
+        spawn_realtime(ecu, vec![0x55; 130]);
+
+        assert_eq!(handler.read_engine_data().await.unwrap().len(), 130);
+    }
+
+    /// Firmware identity as the handshake would record it.
+    fn firmware_202402() -> Option<FirmwareInfo> {
+        Some(FirmwareInfo::from_responses(
+            b"speeduino 202402",
+            b"Speeduino 2024.02",
+        ))
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_legacy_read_waits_out_gap_when_firmware_known() {
+        let (mut handler, mut ecu) = pty_handler(AppConfig::default());
+        handler.firmware = firmware_202402();
+        let packet: Vec<u8> = (0..138).map(|i| i as u8).collect();
+        let sent = packet.clone();
+        tokio::spawn(async move {
+            let mut cmd = [0u8; 1];
+            ecu.read_exact(&mut cmd).await.unwrap();
+            // A USB-serial latency timer holding back the tail of the packet
+            ecu.write_all(&sent[..130]).await.unwrap();
+            sleep(Duration::from_millis(4 * INTER_BYTE_IDLE_MS)).await;
+            ecu.write_all(&sent[130..]).await.unwrap();
+            while ecu.read_exact(&mut cmd).await.is_ok() {}
+        });
+
+        assert_eq!(handler.read_engine_data().await.unwrap(), packet);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_secondary_read_includes_command_echo() {
+        let config = AppConfig {
+            ecu_layout: "secondary".to_string(),
+            ..Default::default()
+        };
+        let (mut handler, ecu) = pty_handler(config);
+        let mut packet = vec![b'A', 0x30];
+        packet.extend(std::iter::repeat_n(7u8, 75));
+        spawn_realtime(ecu, packet.clone());
+
+        assert_eq!(handler.read_engine_data().await.unwrap(), packet);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_legacy_read_times_out_without_answer() {
+        let config = AppConfig {
+            read_timeout_ms: 50,
+            ..Default::default()
+        };
+        let (mut handler, _ecu) = pty_handler(config);
+
+        let err = handler.read_engine_data().await.unwrap_err();
+        assert!(matches!(
+            err,
+            AppError::Serial(SerialError::ReadTimeout { timeout_ms: 50 })
+        ));
+    }
+
+    /// Polling throughput against a PTY-backed fake ECU sending short packets
+    /// with the firmware unknown: only the first read waits for the line to
+    /// go idle, later ones stop at the learned length.
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_legacy_poll_rate_with_short_packets() {
+        const SAMPLES: u32 = 100;
+        let (mut handler, ecu) = pty_handler(AppConfig::default());
+        spawn_realtime(ecu, vec![0x11; 130]);
+        assert_eq!(handler.read_engine_data().await.unwrap().len(), 130);
+
+        let start = std::time::Instant::now();
+        for _ in 0..SAMPLES {
+            assert_eq!(handler.read_engine_data().await.unwrap().len(), 130);
+        }
+        let rate = SAMPLES as f64 / start.elapsed().as_secs_f64();
+        // Waiting out INTER_BYTE_IDLE_MS on every poll caps this below 40 Hz
+        assert!(rate > 60.0, "only {:.1} samples/s", rate);
+    }
+
+    /// Fake ECU answering successive 'r' requests with successive `responses`.
+    #[cfg(unix)]
+    fn spawn_ranges(mut ecu: tokio_serial::SerialStream, responses: Vec<Vec<u8>>) {
+        tokio::spawn(async move {
+            let mut request = [0u8; 7];
+            for response in responses {
+                if ecu.read_exact(&mut request).await.is_err() {
+                    return;
+                }
+                ecu.write_all(&response).await.unwrap();
+            }
+            while ecu.read_exact(&mut request).await.is_ok() {}
+        });
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_partial_read_is_sanity_checked() {
+        let config = AppConfig {
+            ecu_channels: vec!["SCL".to_string()],
+            read_timeout_ms: 200,
+            ..Default::default()
+        };
+        let (mut handler, ecu) = pty_handler(config);
+        assert_eq!(handler.read_ranges, vec![0..1]);
+        spawn_ranges(
+            ecu,
+            vec![vec![10], vec![11, 12], vec![11], vec![200], vec![12]],
+        );
+
+        assert_eq!(handler.read_engine_data().await.unwrap()[0], 10);
+        // Overlong reply
+        let err = handler.read_engine_data().await.unwrap_err();
+        assert!(matches!(err, AppError::Serial(SerialError::Desync(_))));
+        assert_eq!(handler.read_engine_data().await.unwrap()[0], 11);
+        // secl jumping ahead of the clock
+        let err = handler.read_engine_data().await.unwrap_err();
+        assert!(matches!(err, AppError::Serial(SerialError::Desync(_))));
+        assert_eq!(handler.bad_frames(), 2);
+        assert_eq!(handler.read_engine_data().await.unwrap()[0], 12);
+    }
+
+    /// Fake ECU answering successive 'A' commands with successive `responses`.
+    #[cfg(unix)]
+    fn spawn_scripted(mut ecu: tokio_serial::SerialStream, responses: Vec<Vec<u8>>) {
+        tokio::spawn(async move {
+            let mut cmd = [0u8; 1];
+            for response in responses {
+                while ecu.read_exact(&mut cmd).await.is_ok() && cmd[0] != b'A' {}
+                ecu.write_all(&response).await.unwrap();
+            }
+            // Keep the PTY open so the last reply is not lost to a hangup
+            while ecu.read_exact(&mut cmd).await.is_ok() {}
+        });
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stray_byte_is_counted_and_not_returned() {
+        let (mut handler, ecu) = pty_handler(AppConfig::default());
+        let good = vec![10u8; 138];
+        let mut shifted = vec![b'A'];
+        shifted.extend_from_slice(&good);
+        spawn_scripted(ecu, vec![good.clone(), shifted, good.clone()]);
+
+        assert_eq!(handler.read_engine_data().await.unwrap(), good);
+        let err = handler.read_engine_data().await.unwrap_err();
+        assert!(matches!(err, AppError::Serial(SerialError::Desync(_))));
+        assert_eq!(handler.bad_frames(), 1);
+        // Re-aligned: the next frame is accepted again
+        assert_eq!(handler.read_engine_data().await.unwrap(), good);
+    }
+
+    #[tokio::test]
+    async fn test_replay_serves_capture_then_finishes() {
+        let first = vec![1u8; 138];
+        let second = vec![2u8; 138];
+        let capture = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            capture.path(),
+            crate::capture::tests::capture_bytes(&[(0, &first), (20_000, &second)]),
+        )
+        .unwrap();
+
+        let config = AppConfig {
+            connection_type: "replay".to_string(),
+            replay_file: Some(capture.path().display().to_string()),
+            replay_speed: "fast".to_string(),
+            ecu_protocol: "crc".to_string(),
+            ..Default::default()
+        };
+        let mut handler = EcuSerialHandler::new(config);
+        handler.connect().await.unwrap();
+
+        assert!(handler.check_device_exists());
+        assert_eq!(handler.read_engine_data().await.unwrap(), first);
+        assert_eq!(handler.read_engine_data().await.unwrap(), second);
+        let err = handler.read_engine_data().await.unwrap_err();
+        assert!(matches!(err, AppError::Serial(SerialError::ReplayFinished)));
+        assert_eq!(handler.bad_frames(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_fast_replay_does_not_wait_for_idle() {
+        const RECORDS: u64 = 40;
+        let packet = vec![3u8; 130];
+        let records: Vec<(u64, &[u8])> = (0..RECORDS)
+            .map(|i| (i * 50_000, packet.as_slice()))
+            .collect();
+        let capture = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            capture.path(),
+            crate::capture::tests::capture_bytes(&records),
+        )
+        .unwrap();
+
+        let config = AppConfig {
+            connection_type: "replay".to_string(),
+            replay_file: Some(capture.path().display().to_string()),
+            replay_speed: "fast".to_string(),
+            ..Default::default()
+        };
+        let mut handler = EcuSerialHandler::new(config);
+        handler.connect().await.unwrap();
+
+        let start = std::time::Instant::now();
+        for _ in 0..RECORDS {
+            // No learned length: the stream itself has to end each response
+            handler.frame_checker = FrameChecker::default();
+            assert_eq!(handler.read_engine_data().await.unwrap(), packet);
+        }
+        // Waiting out the idle cutoff would take RECORDS × 25 ms
+        let idle_total = Duration::from_millis(RECORDS * INTER_BYTE_IDLE_MS);
+        assert!(start.elapsed() < idle_total / 4, "{:?}", start.elapsed());
+    }
+
+    #[test]
+    fn test_frame_checker_accepts_steady_secl() {
+        let mut checker = FrameChecker::default();
+        let t0 = Instant::now();
+        assert!(checker.check(138, Some(254), t0).is_ok());
+        assert!(checker.check(138, Some(254), t0).is_ok());
+        assert!(
+            checker
+                .check(138, Some(255), t0 + Duration::from_secs(1))
+                .is_ok()
+        );
+        // Wraps at 255
+        assert!(
+            checker
+                .check(138, Some(0), t0 + Duration::from_secs(2))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_frame_checker_rejects_jump_and_length_change() {
+        let mut checker = FrameChecker::default();
+        let t0 = Instant::now();
+        assert!(checker.check(138, Some(10), t0).is_ok());
+        assert!(checker.check(138, Some(200), t0).is_err());
+        assert!(checker.check(137, Some(10), t0).is_err());
+        assert!(checker.check(138, Some(11), t0).is_ok());
+    }
+
+    #[test]
+    fn test_frame_checker_reanchors_after_ecu_reset() {
+        let mut checker = FrameChecker::default();
+        let t0 = Instant::now();
+        assert!(checker.check(138, Some(120), t0).is_ok());
+        // ECU restarted: secl starts again from 0
+        for _ in 1..REANCHOR_AFTER_REJECTS {
+            assert!(checker.check(138, Some(0), t0).is_err());
+        }
+        assert!(checker.check(138, Some(0), t0).is_ok());
+        assert!(
+            checker
+                .check(138, Some(1), t0 + Duration::from_secs(1))
+                .is_ok()
+        );
+    }
+
     #[tokio::test]
     async fn test_device_check() {
         let config = AppConfig::default();
@@ -86,6 +86,27 @@ pub enum SerialError {
 
     #[error("Maximum reconnection attempts ({0}) exceeded")]
     MaxRetriesExceeded(u32),
+
+    #[error("ECU returned error code {code:#04x} ({reason})")]
+    ErrorResponse { code: u8, reason: &'static str },
+
+    #[error("Lost packet alignment: {0}")]
+    Desync(String),
+
+    #[error("Connected device is not a Speeduino ECU (signature: \"{signature}\")")]
+    UnexpectedDevice { signature: String },
+
+    #[error("Failed to open capture file '{path}': {source}")]
+    CaptureOpenFailed {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Invalid capture file: {0}")]
+    InvalidCapture(String),
+
+    #[error("Replay finished: end of capture reached")]
+    ReplayFinished,
 }
 
 /// MQTT client errors
@@ -134,12 +155,10 @@ pub enum ParseError {
     #[error("Invalid data at offset {offset}: {message}")]
     InvalidData { offset: usize, message: String },
 
-    #[error("Checksum mismatch: expected {expected:x}, got {actual:x}")]
-    #[allow(dead_code)]
-    ChecksumMismatch { expected: u8, actual: u8 },
+    #[error("Checksum mismatch: expected {expected:08x}, got {actual:08x}")]
+    ChecksumMismatch { expected: u32, actual: u32 },
 
     #[error("Data validation failed for '{field}': value {value} is out of range [{min}, {max}]")]
-    #[allow(dead_code)]
     ValidationFailed {
         field: String,
         value: f64,
@@ -148,6 +167,32 @@ pub enum ParseError {
     },
 }
 
+/// Derived-channel expression errors (reported through [`ConfigError`])
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ExpressionError {
+    #[error("expression is empty")]
+    Empty,
+
+    #[error("unexpected '{found}' at position {position}")]
+    Unexpected { found: String, position: usize },
+
+    #[error("unexpected end of expression, expected {expected}")]
+    UnexpectedEnd { expected: &'static str },
+
+    #[error("unknown channel '{0}'")]
+    UnknownChannel(String),
+
+    #[error("unknown function '{0}'")]
+    UnknownFunction(String),
+
+    #[error("{function}() takes {expected} argument(s), got {actual}")]
+    WrongArgumentCount {
+        function: String,
+        expected: &'static str,
+        actual: usize,
+    },
+}
+
 /// Result type alias for application operations
 pub type Result<T> = std::result::Result<T, AppError>;
 
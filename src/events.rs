@@ -0,0 +1,490 @@
+//! Engine events.
+//!
+//! [`EventDetector`] compares each decoded frame with the previous one and
+//! turns status-bit changes into discrete events: engine start/stall,
+//! cranking, launch control, flat shift, DFCO, A/C idle-up, fans, A/C requests
+//! and engine protection cuts.  Every event carries the RPM / MAP / coolant
+//! temperature of the frame it was detected in, is logged (and so shown in
+//! the TUI log panel) and is published as a JSON object to
+//! `<mqtt_base_topic>EVENTS`:
+//!
+//! ```json
+//! {"ts":1739800000123,"event":"protection_start","cause":"rpm","rpm":7250,"map":182,"clt":91}
+//! ```
+//!
+//! `map` and `clt` follow the configured unit system (see [`crate::units`]).
+//! A value the frame did not carry (partial polling) is left out.
+
+use crate::config::AppConfig;
+use crate::ecu_data_parser::SpeeduinoData;
+use crate::mqtt_handler::{MqttMessage, build_topic_path};
+use crate::packet_layout::Field;
+use crate::status_flags::flags_of;
+use crate::units::Units;
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+/// Topic code events are published under.
+pub const EVENTS_TOPIC: &str = "EVENTS";
+/// Prefix of event lines in the log (highlighted by the TUI).
+pub const EVENT_LOG_PREFIX: &str = "Engine event:";
+
+/// What happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum EventKind {
+    EngineStart,
+    EngineStall,
+    CrankingStart,
+    CrankingEnd,
+    /// Hard or soft launch limit engaged
+    LaunchStart,
+    LaunchEnd,
+    /// Hard or soft flat-shift cut engaged
+    FlatShiftStart,
+    FlatShiftEnd,
+    DfcoEnter,
+    DfcoExit,
+    /// Idle raised ahead of the A/C compressor engaging (the realtime packet
+    /// has no separate bit for the idle-up input)
+    IdleUpOn,
+    IdleUpOff,
+    FanOn,
+    FanOff,
+    AcRequestOn,
+    AcRequestOff,
+    /// A protection cut became active; `cause` is the `EPS` flag name
+    /// (`rpm`, `map`, `oil`, `afr`, `coolant`)
+    ProtectionStart {
+        cause: &'static str,
+    },
+    /// A protection cut was lifted
+    ProtectionEnd {
+        cause: &'static str,
+    },
+}
+
+impl EventKind {
+    /// Events worth a warning in the log.
+    fn is_warning(&self) -> bool {
+        matches!(
+            self,
+            EventKind::EngineStall | EventKind::ProtectionStart { .. }
+        )
+    }
+
+    fn describe(&self) -> String {
+        use EventKind::*;
+        match self {
+            EngineStart => "engine started".into(),
+            EngineStall => "engine stalled".into(),
+            CrankingStart => "cranking started".into(),
+            CrankingEnd => "cranking ended".into(),
+            LaunchStart => "launch control engaged".into(),
+            LaunchEnd => "launch control released".into(),
+            FlatShiftStart => "flat shift engaged".into(),
+            FlatShiftEnd => "flat shift released".into(),
+            DfcoEnter => "DFCO entered".into(),
+            DfcoExit => "DFCO exited".into(),
+            IdleUpOn => "idle-up on".into(),
+            IdleUpOff => "idle-up off".into(),
+            FanOn => "fan on".into(),
+            FanOff => "fan off".into(),
+            AcRequestOn => "A/C requested".into(),
+            AcRequestOff => "A/C request cleared".into(),
+            ProtectionStart { cause } => format!("{} protection cut started", cause),
+            ProtectionEnd { cause } => format!("{} protection cut ended", cause),
+        }
+    }
+}
+
+/// One detected event with the engine state at that moment (metric), `None`
+/// for values the frame did not carry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EngineEvent {
+    /// Detection time in milliseconds since the Unix epoch
+    pub ts: u64,
+    pub kind: EventKind,
+    pub rpm: Option<u16>,
+    /// kPa
+    pub map: Option<u16>,
+    /// °C
+    pub clt: Option<i16>,
+}
+
+impl EngineEvent {
+    fn new(ts: u64, kind: EventKind, d: &SpeeduinoData) -> Self {
+        Self {
+            ts,
+            kind,
+            rpm: d.has(Field::Rpm).then_some(d.rpm),
+            map: d.has(Field::Map).then_some(d.map),
+            clt: d.has(Field::CoolantRaw).then(|| d.coolant_celsius()),
+        }
+    }
+
+    /// JSON payload for the events topic, with `map` and `clt` in `units`.
+    pub fn to_json(&self, units: &Units) -> String {
+        let payload = EventPayload {
+            ts: self.ts,
+            kind: &self.kind,
+            rpm: self.rpm,
+            map: self.map.map(|map| units.json_value("MAP", map as f64)),
+            clt: self.clt.map(|clt| units.json_value("CLT", clt as f64)),
+        };
+        serde_json::to_string(&payload).unwrap_or_default()
+    }
+
+    /// One-line description for the log.
+    pub fn describe(&self, units: &Units) -> String {
+        let context: Vec<String> = [
+            self.rpm.map(|rpm| format!("RPM {}", rpm)),
+            self.map
+                .map(|map| format!("MAP {}", units.display("MAP", map as f64))),
+            self.clt
+                .map(|clt| format!("CLT {}", units.display("CLT", clt as f64))),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        if context.is_empty() {
+            self.kind.describe()
+        } else {
+            format!("{} ({})", self.kind.describe(), context.join(", "))
+        }
+    }
+}
+
+/// [`EngineEvent`] as published, with converted values.
+#[derive(Serialize)]
+struct EventPayload<'a> {
+    ts: u64,
+    #[serde(flatten)]
+    kind: &'a EventKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rpm: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    map: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clt: Option<serde_json::Value>,
+}
+
+// ---------------------------------------------------------------------------
+// Detection
+// ---------------------------------------------------------------------------
+
+/// A two-state condition and the events for entering / leaving it.
+struct Edge {
+    /// Current state, `None` when the frame lacks the byte it is read from
+    state: fn(&SpeeduinoData) -> Option<bool>,
+    on: EventKind,
+    off: EventKind,
+}
+
+/// `test` applied to `d`, if the status byte `field` was received.
+fn when(d: &SpeeduinoData, field: Field, test: fn(&SpeeduinoData) -> bool) -> Option<bool> {
+    d.status_byte(field).map(|_| test(d))
+}
+
+const EDGES: [Edge; 8] = [
+    Edge {
+        state: |d| when(d, Field::Engine, SpeeduinoData::running),
+        on: EventKind::EngineStart,
+        off: EventKind::EngineStall,
+    },
+    Edge {
+        state: |d| when(d, Field::Engine, SpeeduinoData::cranking),
+        on: EventKind::CrankingStart,
+        off: EventKind::CrankingEnd,
+    },
+    Edge {
+        state: |d| when(d, Field::Spark, |d| d.launch_hard() || d.launch_soft()),
+        on: EventKind::LaunchStart,
+        off: EventKind::LaunchEnd,
+    },
+    Edge {
+        state: |d| {
+            when(d, Field::Status5, |d| {
+                d.flat_shift_hard() || d.flat_shift_soft()
+            })
+        },
+        on: EventKind::FlatShiftStart,
+        off: EventKind::FlatShiftEnd,
+    },
+    Edge {
+        state: |d| when(d, Field::Status1, SpeeduinoData::dfco),
+        on: EventKind::DfcoEnter,
+        off: EventKind::DfcoExit,
+    },
+    Edge {
+        state: |d| when(d, Field::AirConStatus, SpeeduinoData::ac_turning_on),
+        on: EventKind::IdleUpOn,
+        off: EventKind::IdleUpOff,
+    },
+    Edge {
+        state: |d| when(d, Field::Status4, SpeeduinoData::fan_on),
+        on: EventKind::FanOn,
+        off: EventKind::FanOff,
+    },
+    Edge {
+        state: |d| when(d, Field::AirConStatus, SpeeduinoData::ac_request),
+        on: EventKind::AcRequestOn,
+        off: EventKind::AcRequestOff,
+    },
+];
+
+/// Edge detector over consecutive frames.
+#[derive(Debug, Default)]
+pub struct EventDetector {
+    /// Last known state of each entry in `EDGES`.  The first frame only sets
+    /// the baseline, so starting the bridge next to a running engine does not
+    /// report an engine start.
+    edges: [Option<bool>; EDGES.len()],
+    /// Last received engine protection byte.  Starts clear, so a cut that is
+    /// already active when the bridge starts is reported too.
+    protect_status: u8,
+}
+
+impl EventDetector {
+    /// Events between the previous frame and `d`.  Bytes missing from `d`
+    /// keep their previous state.
+    pub fn update(&mut self, d: &SpeeduinoData, ts: u64) -> Vec<EngineEvent> {
+        let mut events = Vec::new();
+
+        for (edge, last) in EDGES.iter().zip(self.edges.iter_mut()) {
+            let Some(now) = (edge.state)(d) else {
+                continue;
+            };
+            if last.is_some_and(|was| was != now) {
+                let kind = if now { edge.on } else { edge.off };
+                events.push(EngineEvent::new(ts, kind, d));
+            }
+            *last = Some(now);
+        }
+
+        if let Some(status) = d.status_byte(Field::EngineProtectStatus) {
+            let changed = status ^ self.protect_status;
+            for flag in flags_of(Field::EngineProtectStatus).filter(|f| changed & f.mask != 0) {
+                let kind = if status & flag.mask != 0 {
+                    EventKind::ProtectionStart { cause: flag.name() }
+                } else {
+                    EventKind::ProtectionEnd { cause: flag.name() }
+                };
+                events.push(EngineEvent::new(ts, kind, d));
+            }
+            self.protect_status = status;
+        }
+
+        events
+    }
+}
+
+/// Log `events` in `units` and, when MQTT is enabled, queue them on the
+/// events topic.
+pub async fn publish_events(
+    events: &[EngineEvent],
+    config: &AppConfig,
+    units: &Units,
+    mqtt_sender: Option<&mpsc::Sender<MqttMessage>>,
+) {
+    for event in events {
+        if event.kind.is_warning() {
+            warn!("{} {}", EVENT_LOG_PREFIX, event.describe(units));
+        } else {
+            info!("{} {}", EVENT_LOG_PREFIX, event.describe(units));
+        }
+        let Some(sender) = mqtt_sender else {
+            continue;
+        };
+        let topic = build_topic_path(&config.mqtt_base_topic, EVENTS_TOPIC);
+        let msg = MqttMessage::new(topic, event.to_json(units), config.mqtt_qos);
+        if sender.send(msg).await.is_err() {
+            warn!("Failed to queue event message (channel closed)");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet_layout::FieldSet;
+    use crate::status_flags::{
+        AIRCON_REQUEST, ENGINE_CRANK, ENGINE_RUN, PROTECT_MAP, PROTECT_RPM, SPARK_SOFT_LAUNCH,
+        STATUS1_DFCO, STATUS5_FLAT_SHIFT_HARD,
+    };
+
+    fn frame(protect: u8) -> SpeeduinoData {
+        SpeeduinoData {
+            engine_protect_status: protect,
+            rpm: 7200,
+            map: 180,
+            coolant_raw: 130,
+            ..SpeeduinoData::default()
+        }
+    }
+
+    fn kinds(events: &[EngineEvent]) -> Vec<EventKind> {
+        events.iter().map(|e| e.kind).collect()
+    }
+
+    #[test]
+    fn test_protection_start_and_end() {
+        let mut detector = EventDetector::default();
+        assert!(detector.update(&frame(0), 1).is_empty());
+
+        let started = detector.update(&frame(PROTECT_RPM), 2);
+        assert_eq!(
+            started,
+            vec![EngineEvent {
+                ts: 2,
+                kind: EventKind::ProtectionStart { cause: "rpm" },
+                rpm: Some(7200),
+                map: Some(180),
+                clt: Some(90),
+            }]
+        );
+        // Still cutting: no new event
+        assert!(detector.update(&frame(PROTECT_RPM), 3).is_empty());
+
+        let events = detector.update(&frame(PROTECT_MAP), 4);
+        assert_eq!(
+            kinds(&events),
+            vec![
+                EventKind::ProtectionEnd { cause: "rpm" },
+                EventKind::ProtectionStart { cause: "map" },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_start_sequence_and_stall() {
+        let mut detector = EventDetector::default();
+        let mut d = frame(0);
+        assert!(detector.update(&d, 1).is_empty());
+
+        d.engine = ENGINE_CRANK;
+        assert_eq!(
+            kinds(&detector.update(&d, 2)),
+            vec![EventKind::CrankingStart]
+        );
+        d.engine = ENGINE_RUN;
+        assert_eq!(
+            kinds(&detector.update(&d, 3)),
+            vec![EventKind::EngineStart, EventKind::CrankingEnd]
+        );
+        d.engine = 0;
+        assert_eq!(kinds(&detector.update(&d, 4)), vec![EventKind::EngineStall]);
+    }
+
+    #[test]
+    fn test_first_frame_is_baseline() {
+        let mut detector = EventDetector::default();
+        let mut d = frame(0);
+        d.engine = ENGINE_RUN;
+        d.status1 = STATUS1_DFCO;
+        assert!(detector.update(&d, 1).is_empty());
+        d.status1 = 0;
+        assert_eq!(kinds(&detector.update(&d, 2)), vec![EventKind::DfcoExit]);
+    }
+
+    #[test]
+    fn test_launch_flat_shift_and_ac() {
+        let mut detector = EventDetector::default();
+        let mut d = frame(0);
+        d.status5 = Some(0);
+        d.air_con_status = Some(0);
+        detector.update(&d, 1);
+
+        d.spark = SPARK_SOFT_LAUNCH;
+        d.status5 = Some(STATUS5_FLAT_SHIFT_HARD);
+        d.air_con_status = Some(AIRCON_REQUEST);
+        assert_eq!(
+            kinds(&detector.update(&d, 2)),
+            vec![
+                EventKind::LaunchStart,
+                EventKind::FlatShiftStart,
+                EventKind::AcRequestOn
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_byte_keeps_state() {
+        let mut detector = EventDetector::default();
+        detector.update(&frame(PROTECT_RPM), 1);
+        let mut partial = frame(0);
+        partial.missing = FieldSet::from_iter([Field::EngineProtectStatus, Field::Engine]);
+        assert!(detector.update(&partial, 2).is_empty());
+        assert_eq!(detector.update(&frame(0), 3).len(), 1);
+
+        // status5 is absent from short packets: no flat-shift edges
+        let mut d = frame(0);
+        d.status5 = Some(STATUS5_FLAT_SHIFT_HARD);
+        detector.update(&d, 4);
+        d.status5 = None;
+        assert!(detector.update(&d, 5).is_empty());
+    }
+
+    #[test]
+    fn test_event_json() {
+        let event = EngineEvent::new(
+            1_739_800_000_123,
+            EventKind::ProtectionStart { cause: "coolant" },
+            &frame(0),
+        );
+        let metric = Units::default();
+        assert_eq!(
+            event.to_json(&metric),
+            r#"{"ts":1739800000123,"event":"protection_start","cause":"coolant","rpm":7200,"map":180,"clt":90}"#
+        );
+        let event = EngineEvent::new(5, EventKind::AcRequestOn, &frame(0));
+        assert_eq!(
+            event.to_json(&metric),
+            r#"{"ts":5,"event":"ac_request_on","rpm":7200,"map":180,"clt":90}"#
+        );
+        let imperial = Units::new(crate::units::UnitSystem::Imperial);
+        assert_eq!(
+            event.to_json(&imperial),
+            r#"{"ts":5,"event":"ac_request_on","rpm":7200,"map":26.1,"clt":194}"#
+        );
+        assert_eq!(
+            event.describe(&imperial),
+            "A/C requested (RPM 7200, MAP 26.1 psi, CLT 194°F)"
+        );
+    }
+
+    #[test]
+    fn test_event_leaves_out_missing_values() {
+        let mut d = frame(0);
+        d.missing = FieldSet::from_iter([Field::Map, Field::CoolantRaw]);
+        let event = EngineEvent::new(5, EventKind::FanOn, &d);
+        assert_eq!((event.rpm, event.map, event.clt), (Some(7200), None, None));
+        let metric = Units::default();
+        assert_eq!(
+            event.to_json(&metric),
+            r#"{"ts":5,"event":"fan_on","rpm":7200}"#
+        );
+        assert_eq!(event.describe(&metric), "fan on (RPM 7200)");
+
+        d.missing = FieldSet::from_iter([Field::Rpm, Field::Map, Field::CoolantRaw]);
+        let event = EngineEvent::new(5, EventKind::FanOn, &d);
+        assert_eq!(event.to_json(&metric), r#"{"ts":5,"event":"fan_on"}"#);
+        assert_eq!(event.describe(&metric), "fan on");
+    }
+
+    #[tokio::test]
+    async fn test_publish_events_to_topic() {
+        let config = AppConfig::default();
+        let (tx, mut rx) = mpsc::channel(10);
+        let events = EventDetector::default().update(&frame(PROTECT_RPM), 5);
+        publish_events(&events, &config, &Units::default(), Some(&tx)).await;
+        let msg = rx.recv().await.unwrap();
+        assert_eq!(
+            msg.topic,
+            build_topic_path(&config.mqtt_base_topic, EVENTS_TOPIC)
+        );
+        assert!(msg.payload.contains(r#""event":"protection_start""#));
+        assert!(!msg.retained);
+    }
+}
@@ -0,0 +1,593 @@
+//! Injector duty cycle and fuel consumption.
+//!
+//! With `injector_flow_cc_min` set, [`FuelTracker`] turns every frame into
+//! fuel usage channels:
+//!
+//! | Topic | Value |
+//! |-------|-------|
+//! | `DUTY1`–`DUTY8` | Duty cycle of each injector channel, `PWn × RPM × squirts / 1200` (%) |
+//! | `FUEL_FLOW` | Fuel flow of all injectors (L/h) |
+//! | `FUEL_ECON` | Instant consumption, L/100km (mpg when imperial); only while moving |
+//! | `TRIP_FUEL` | Fuel used on the trip (L) |
+//! | `TRIP_DIST` | Distance of the trip, km (mi when imperial) |
+//! | `TRIP_ECON` | Average consumption of the trip |
+//!
+//! Pulse widths include the injector opening time, so `injector_dead_time_ms`
+//! is subtracted before computing the flow.  The trip is integrated over
+//! wall-clock time and saved to `fuel_trip_file`, so it survives restarts;
+//! delete the file to start a new trip.
+
+use crate::channels::channel;
+use crate::config::AppConfig;
+use crate::ecu_data_parser::SpeeduinoData;
+use crate::errors::{ConfigError, Result};
+use crate::units::{UnitSystem, with_symbol};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::{info, warn};
+
+pub const FUEL_FLOW_TOPIC: &str = "FUEL_FLOW";
+pub const FUEL_ECON_TOPIC: &str = "FUEL_ECON";
+pub const TRIP_FUEL_TOPIC: &str = "TRIP_FUEL";
+pub const TRIP_DIST_TOPIC: &str = "TRIP_DIST";
+pub const TRIP_ECON_TOPIC: &str = "TRIP_ECON";
+
+/// Duty cycle topics, one per injector channel.
+const DUTY_TOPICS: [&str; 8] = [
+    "DUTY1", "DUTY2", "DUTY3", "DUTY4", "DUTY5", "DUTY6", "DUTY7", "DUTY8",
+];
+/// Pulse width channels, in injector channel order.
+const PW_CODES: [&str; 8] = ["PW1", "PW2", "PW3", "PW4", "PW5", "PW6", "PW7", "PW8"];
+
+/// Every topic published by the fuel tracker, duty cycles first.
+pub const FUEL_TOPICS: [&str; 13] = [
+    "DUTY1",
+    "DUTY2",
+    "DUTY3",
+    "DUTY4",
+    "DUTY5",
+    "DUTY6",
+    "DUTY7",
+    "DUTY8",
+    FUEL_FLOW_TOPIC,
+    FUEL_ECON_TOPIC,
+    TRIP_FUEL_TOPIC,
+    TRIP_DIST_TOPIC,
+    TRIP_ECON_TOPIC,
+];
+
+/// km per mile
+const KM_PER_MILE: f64 = 1.609_344;
+/// L/100km × mpg (US gallons)
+const MPG_FACTOR: f64 = 235.214_583;
+/// Slower than this, instant consumption is not published (km/h).
+const MIN_ECON_SPEED: f64 = 5.0;
+/// Trip consumption is published from this distance on (km).
+const MIN_TRIP_DISTANCE: f64 = 0.1;
+/// Longer gaps between frames (reconnects, stalls) are not integrated (ms).
+const MAX_STEP_MS: u64 = 2_000;
+/// How often a changed trip is written to disk (ms).
+const SAVE_INTERVAL_MS: u64 = 10_000;
+
+/// Injector setup, from the settings.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuelModel {
+    /// Flow of one injector (cc/min)
+    pub flow_cc_min: f64,
+    pub injectors: u8,
+    /// Injections per engine cycle
+    pub squirts: u8,
+    pub dead_time_ms: f64,
+    pub system: UnitSystem,
+}
+
+impl FuelModel {
+    /// Injector setup, `None` when `injector_flow_cc_min` is not set.  Errors
+    /// on invalid injector settings, even while fuel usage is off.
+    pub fn from_config(config: &AppConfig) -> Result<Option<Self>> {
+        if let Some(flow) = config.injector_flow_cc_min
+            && !(flow.is_finite() && flow > 0.0)
+        {
+            return Err(ConfigError::InvalidValue {
+                field: "injector_flow_cc_min".to_string(),
+                message: format!("must be a positive flow rate, got {}", flow),
+            }
+            .into());
+        }
+
+        if config.injector_count == 0 || config.injector_count as usize > PW_CODES.len() {
+            return Err(ConfigError::InvalidValue {
+                field: "injector_count".to_string(),
+                message: format!("must be between 1 and 8, got {}", config.injector_count),
+            }
+            .into());
+        }
+
+        if config.injector_squirts == 0 || config.injector_squirts > 8 {
+            return Err(ConfigError::InvalidValue {
+                field: "injector_squirts".to_string(),
+                message: format!("must be between 1 and 8, got {}", config.injector_squirts),
+            }
+            .into());
+        }
+
+        if !(config.injector_dead_time_ms.is_finite() && config.injector_dead_time_ms >= 0.0) {
+            return Err(ConfigError::InvalidValue {
+                field: "injector_dead_time_ms".to_string(),
+                message: format!(
+                    "must be zero or more milliseconds, got {}",
+                    config.injector_dead_time_ms
+                ),
+            }
+            .into());
+        }
+
+        let Some(system) = UnitSystem::from_config(&config.unit_system) else {
+            return Err(ConfigError::InvalidValue {
+                field: "unit_system".to_string(),
+                message: format!(
+                    "must be \"metric\" or \"imperial\", got \"{}\"",
+                    config.unit_system
+                ),
+            }
+            .into());
+        };
+
+        Ok(config.injector_flow_cc_min.map(|flow_cc_min| Self {
+            flow_cc_min,
+            injectors: config.injector_count,
+            squirts: config.injector_squirts,
+            dead_time_ms: config.injector_dead_time_ms,
+            system,
+        }))
+    }
+
+    /// Pulse width (ms) of each injector channel in use that the ECU sent.
+    fn pulse_widths(&self, d: &SpeeduinoData) -> Vec<(usize, f64)> {
+        PW_CODES
+            .iter()
+            .take(self.injectors as usize)
+            .enumerate()
+            .filter_map(|(i, code)| Some((i, channel(code)?.value(d)?)))
+            .collect()
+    }
+
+    /// Share of an engine cycle (0–1) an injector firing `pw_ms` is open.
+    fn duty(&self, pw_ms: f64, rpm: f64) -> f64 {
+        pw_ms * rpm * self.squirts as f64 / 120_000.0
+    }
+
+    /// Duty cycle (%) of each injector channel.
+    pub fn duty_cycles(&self, d: &SpeeduinoData) -> Vec<(usize, f64)> {
+        let Some(rpm) = channel("RPM").and_then(|c| c.value(d)) else {
+            return Vec::new();
+        };
+        self.pulse_widths(d)
+            .into_iter()
+            .map(|(i, pw)| (i, self.duty(pw, rpm) * 100.0))
+            .collect()
+    }
+
+    /// Fuel flow of all injectors (L/h), averaged over the channels that fire.
+    pub fn flow_lph(&self, d: &SpeeduinoData) -> Option<f64> {
+        let rpm = channel("RPM")?.value(d)?;
+        let open: Vec<f64> = self
+            .pulse_widths(d)
+            .into_iter()
+            .filter(|(_, pw)| *pw > 0.0)
+            .map(|(_, pw)| self.duty((pw - self.dead_time_ms).max(0.0), rpm))
+            .collect();
+        if open.is_empty() {
+            return Some(0.0);
+        }
+        let duty = open.iter().sum::<f64>() / open.len() as f64;
+        Some(duty * self.injectors as f64 * self.flow_cc_min * 60.0 / 1000.0)
+    }
+
+    /// (topic, unit) of every fuel channel, for the `UNITS` metadata.
+    pub fn metadata(&self) -> Vec<(&'static str, &'static str)> {
+        DUTY_TOPICS
+            .iter()
+            .take(self.injectors as usize)
+            .chain(&FUEL_TOPICS[DUTY_TOPICS.len()..])
+            .map(|topic| (*topic, unit_of(topic, self.system)))
+            .collect()
+    }
+}
+
+/// Unit `topic` is published in.
+fn unit_of(topic: &str, system: UnitSystem) -> &'static str {
+    match (topic, system) {
+        (FUEL_FLOW_TOPIC, _) => "L/h",
+        (TRIP_FUEL_TOPIC, _) => "L",
+        (FUEL_ECON_TOPIC | TRIP_ECON_TOPIC, UnitSystem::Metric) => "L/100km",
+        (FUEL_ECON_TOPIC | TRIP_ECON_TOPIC, UnitSystem::Imperial) => "mpg",
+        (TRIP_DIST_TOPIC, UnitSystem::Metric) => "km",
+        (TRIP_DIST_TOPIC, UnitSystem::Imperial) => "mi",
+        _ => "%",
+    }
+}
+
+/// TUI label of `topic`.
+fn label_of(topic: &str) -> &'static str {
+    const INJ_LABELS: [&str; 8] = [
+        "INJ1", "INJ2", "INJ3", "INJ4", "INJ5", "INJ6", "INJ7", "INJ8",
+    ];
+    match topic {
+        FUEL_FLOW_TOPIC => "FLOW",
+        FUEL_ECON_TOPIC => "ECON",
+        TRIP_FUEL_TOPIC => "TRIP",
+        TRIP_DIST_TOPIC => "DIST",
+        TRIP_ECON_TOPIC => "AVG",
+        _ => DUTY_TOPICS
+            .iter()
+            .position(|t| *t == topic)
+            .map_or("", |i| INJ_LABELS[i]),
+    }
+}
+
+/// Descriptive name of `topic`, for Home Assistant.
+pub fn name_of(topic: &str) -> String {
+    match topic {
+        FUEL_FLOW_TOPIC => "Fuel flow".to_string(),
+        FUEL_ECON_TOPIC => "Fuel consumption".to_string(),
+        TRIP_FUEL_TOPIC => "Trip fuel".to_string(),
+        TRIP_DIST_TOPIC => "Trip distance".to_string(),
+        TRIP_ECON_TOPIC => "Trip consumption".to_string(),
+        _ => format!("Injector duty {}", topic.trim_start_matches("DUTY")),
+    }
+}
+
+/// Running trip totals, as saved in `fuel_trip_file`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Trip {
+    pub fuel_l: f64,
+    pub distance_km: f64,
+}
+
+impl Trip {
+    /// Average consumption (L/100km), once the trip is long enough.
+    pub fn l_per_100km(&self) -> Option<f64> {
+        (self.distance_km >= MIN_TRIP_DISTANCE).then(|| self.fuel_l / self.distance_km * 100.0)
+    }
+}
+
+/// Fuel usage of one frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuelUsage {
+    /// (injector channel index, duty %)
+    pub duty: Vec<(usize, f64)>,
+    pub flow_lph: Option<f64>,
+    /// Instant consumption (L/100km), while moving
+    pub l_per_100km: Option<f64>,
+    pub trip: Trip,
+    pub system: UnitSystem,
+}
+
+impl FuelUsage {
+    /// Consumption from L/100km in the configured system (`None` for an
+    /// infinite mpg).
+    fn economy(&self, l_per_100km: f64) -> Option<f64> {
+        match self.system {
+            UnitSystem::Metric => Some(l_per_100km),
+            UnitSystem::Imperial if l_per_100km > 0.0 => Some(MPG_FACTOR / l_per_100km),
+            UnitSystem::Imperial => None,
+        }
+    }
+
+    /// (topic, published value) of every fuel channel this frame allows.
+    pub fn params(&self) -> Vec<(&'static str, String)> {
+        let mut params: Vec<(&'static str, String)> = self
+            .duty
+            .iter()
+            .map(|&(i, duty)| (DUTY_TOPICS[i], format!("{:.1}", duty)))
+            .collect();
+        if let Some(flow) = self.flow_lph {
+            params.push((FUEL_FLOW_TOPIC, format!("{:.2}", flow)));
+        }
+        if let Some(econ) = self.l_per_100km.and_then(|v| self.economy(v)) {
+            params.push((FUEL_ECON_TOPIC, format!("{:.1}", econ)));
+        }
+        let distance = match self.system {
+            UnitSystem::Metric => self.trip.distance_km,
+            UnitSystem::Imperial => self.trip.distance_km / KM_PER_MILE,
+        };
+        params.push((TRIP_FUEL_TOPIC, format!("{:.2}", self.trip.fuel_l)));
+        params.push((TRIP_DIST_TOPIC, format!("{:.1}", distance)));
+        if let Some(econ) = self.trip.l_per_100km().and_then(|v| self.economy(v)) {
+            params.push((TRIP_ECON_TOPIC, format!("{:.1}", econ)));
+        }
+        params
+    }
+
+    /// (label, value with unit) of every fuel channel, for the TUI.
+    pub fn display(&self) -> Vec<(&'static str, String)> {
+        self.params()
+            .into_iter()
+            .map(|(topic, value)| {
+                (
+                    label_of(topic),
+                    with_symbol(value, unit_of(topic, self.system)),
+                )
+            })
+            .collect()
+    }
+}
+
+/// Integrates fuel flow and speed into a trip that is saved between runs.
+#[derive(Debug)]
+pub struct FuelTracker {
+    model: FuelModel,
+    trip: Trip,
+    path: Option<PathBuf>,
+    /// Time of the previous frame (ms)
+    last_ms: Option<u64>,
+    last_save_ms: u64,
+    /// The trip changed since it was last saved
+    dirty: bool,
+    /// Usage of the latest frame
+    usage: Option<FuelUsage>,
+}
+
+impl FuelTracker {
+    pub fn new(model: FuelModel, trip: Trip, path: Option<PathBuf>) -> Self {
+        Self {
+            model,
+            trip,
+            path,
+            last_ms: None,
+            last_save_ms: 0,
+            dirty: false,
+            usage: None,
+        }
+    }
+
+    /// Tracker from the settings, continuing the trip in `fuel_trip_file`.
+    /// `None` when `injector_flow_cc_min` is not set.
+    pub fn from_config(config: &AppConfig) -> Result<Option<Self>> {
+        let Some(model) = FuelModel::from_config(config)? else {
+            return Ok(None);
+        };
+        let path = config.fuel_trip_file.as_ref().map(PathBuf::from);
+        let trip = match &path {
+            Some(path) if path.exists() => match load_trip(path) {
+                Ok(trip) => {
+                    info!(
+                        "Continuing fuel trip from {}: {:.2} L over {:.1} km",
+                        path.display(),
+                        trip.fuel_l,
+                        trip.distance_km
+                    );
+                    trip
+                }
+                Err(e) => {
+                    warn!(
+                        "Cannot read fuel trip {}: {} – starting a new trip",
+                        path.display(),
+                        e
+                    );
+                    Trip::default()
+                }
+            },
+            _ => Trip::default(),
+        };
+        Ok(Some(Self::new(model, trip, path)))
+    }
+
+    /// Injector setup the tracker computes with.
+    pub fn model(&self) -> &FuelModel {
+        &self.model
+    }
+
+    pub fn trip(&self) -> Trip {
+        self.trip
+    }
+
+    /// Usage of the latest frame, for the TUI.
+    pub fn usage(&self) -> Option<&FuelUsage> {
+        self.usage.as_ref()
+    }
+
+    /// Usage in frame `d`, received at `now_ms`, adding the time since the
+    /// previous frame to the trip.
+    pub fn update(&mut self, d: &SpeeduinoData, now_ms: u64) -> FuelUsage {
+        let flow_lph = self.model.flow_lph(d);
+        let speed = channel("VSS").and_then(|c| c.value(d));
+
+        if let Some(last) = self.last_ms
+            && let Some(step) = now_ms.checked_sub(last).filter(|&s| s <= MAX_STEP_MS)
+        {
+            let hours = step as f64 / 3_600_000.0;
+            self.trip.fuel_l += flow_lph.unwrap_or(0.0) * hours;
+            self.trip.distance_km += speed.unwrap_or(0.0) * hours;
+            self.dirty |= step > 0;
+        }
+        self.last_ms = Some(now_ms);
+
+        if self.dirty && now_ms.saturating_sub(self.last_save_ms) >= SAVE_INTERVAL_MS {
+            self.save();
+            self.last_save_ms = now_ms;
+        }
+
+        let usage = FuelUsage {
+            duty: self.model.duty_cycles(d),
+            flow_lph,
+            l_per_100km: flow_lph
+                .zip(speed.filter(|&s| s >= MIN_ECON_SPEED))
+                .map(|(flow, speed)| flow / speed * 100.0),
+            trip: self.trip,
+            system: self.model.system,
+        };
+        self.usage = Some(usage.clone());
+        usage
+    }
+
+    /// Write the trip to `fuel_trip_file`, if configured.
+    pub fn save(&mut self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        match save_trip(path, &self.trip) {
+            Ok(()) => self.dirty = false,
+            Err(e) => warn!("Cannot save fuel trip to {}: {}", path.display(), e),
+        }
+    }
+}
+
+fn load_trip(path: &Path) -> std::io::Result<Trip> {
+    let text = fs::read_to_string(path)?;
+    serde_json::from_str(&text).map_err(std::io::Error::other)
+}
+
+/// Save through a temporary file, so a crash never leaves half a trip.
+fn save_trip(path: &Path, trip: &Trip) -> std::io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(
+        &tmp,
+        serde_json::to_string(trip).map_err(std::io::Error::other)?,
+    )?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> FuelModel {
+        FuelModel {
+            flow_cc_min: 500.0,
+            injectors: 4,
+            squirts: 1,
+            dead_time_ms: 1.0,
+            system: UnitSystem::Metric,
+        }
+    }
+
+    /// 6000 rpm, 10 ms on all four channels, 100 km/h
+    fn frame() -> SpeeduinoData {
+        SpeeduinoData {
+            rpm: 6000,
+            pw1: 100,
+            pw2: 100,
+            pw3: 100,
+            pw4: 100,
+            vss: 100,
+            ..SpeeduinoData::default()
+        }
+    }
+
+    #[test]
+    fn test_duty_and_flow() {
+        let m = model();
+        assert_eq!(
+            m.duty_cycles(&frame()),
+            vec![(0, 50.0), (1, 50.0), (2, 50.0), (3, 50.0)]
+        );
+        // 9 ms effective of a 20 ms cycle = 45 % × 4 × 500 cc/min = 54 L/h
+        assert!((m.flow_lph(&frame()).unwrap() - 54.0).abs() < 1e-9);
+
+        let dfco = SpeeduinoData {
+            pw1: 0,
+            pw2: 0,
+            pw3: 0,
+            pw4: 0,
+            ..frame()
+        };
+        assert_eq!(m.flow_lph(&dfco), Some(0.0));
+    }
+
+    #[test]
+    fn test_only_configured_injectors_are_used() {
+        let m = FuelModel {
+            injectors: 2,
+            ..model()
+        };
+        assert_eq!(m.duty_cycles(&frame()).len(), 2);
+        assert_eq!(m.metadata()[1], ("DUTY2", "%"));
+        assert_eq!(m.metadata()[2], (FUEL_FLOW_TOPIC, "L/h"));
+    }
+
+    #[test]
+    fn test_trip_integration() {
+        let mut tracker = FuelTracker::new(model(), Trip::default(), None);
+        tracker.update(&frame(), 0);
+        let usage = tracker.update(&frame(), 1_000);
+        assert!((usage.trip.fuel_l - 54.0 / 3600.0).abs() < 1e-9);
+        assert!((usage.trip.distance_km - 100.0 / 3600.0).abs() < 1e-9);
+        assert!((usage.l_per_100km.unwrap() - 54.0).abs() < 1e-9);
+
+        // A gap (reconnect) is not counted
+        let usage = tracker.update(&frame(), 60_000);
+        assert!((usage.trip.fuel_l - 54.0 / 3600.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_params_follow_unit_system() {
+        let usage = FuelUsage {
+            duty: vec![(0, 42.0)],
+            flow_lph: Some(6.0),
+            l_per_100km: Some(6.0),
+            trip: Trip {
+                fuel_l: 8.0,
+                distance_km: 160.9344,
+            },
+            system: UnitSystem::Imperial,
+        };
+        assert_eq!(
+            usage.params(),
+            vec![
+                ("DUTY1", "42.0".to_string()),
+                ("FUEL_FLOW", "6.00".to_string()),
+                ("FUEL_ECON", "39.2".to_string()),
+                ("TRIP_FUEL", "8.00".to_string()),
+                ("TRIP_DIST", "100.0".to_string()),
+                ("TRIP_ECON", "47.3".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_display_labels() {
+        let usage = FuelTracker::new(model(), Trip::default(), None).update(&frame(), 0);
+        let cells = usage.display();
+        assert_eq!(cells[0], ("INJ1", "50.0%".to_string()));
+        assert_eq!(cells[4], ("FLOW", "54.00 L/h".to_string()));
+        assert_eq!(cells[5], ("ECON", "54.0 L/100km".to_string()));
+    }
+
+    #[test]
+    fn test_trip_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trip.json");
+        let config = AppConfig {
+            injector_flow_cc_min: Some(500.0),
+            fuel_trip_file: Some(path.display().to_string()),
+            ..AppConfig::default()
+        };
+
+        let mut tracker = FuelTracker::from_config(&config).unwrap().unwrap();
+        tracker.update(&frame(), 0);
+        tracker.update(&frame(), 1_000);
+        tracker.save();
+        let trip = tracker.trip();
+
+        let resumed = FuelTracker::from_config(&config).unwrap().unwrap();
+        assert_eq!(resumed.trip(), trip);
+
+        fs::write(&path, "not json").unwrap();
+        assert_eq!(
+            FuelTracker::from_config(&config).unwrap().unwrap().trip(),
+            Trip::default()
+        );
+    }
+
+    #[test]
+    fn test_disabled_without_flow_rate() {
+        assert!(
+            FuelTracker::from_config(&AppConfig::default())
+                .unwrap()
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,448 @@
+//! Home Assistant MQTT discovery.
+//!
+//! With `ha_discovery = true` the bridge announces every topic it publishes
+//! each time the ECU connects, as retained discovery documents, so Home
+//! Assistant creates the entities without any YAML:
+//!
+//! | Document | For |
+//! |----------|-----|
+//! | `<prefix>/sensor/<node>/<topic>/config` | Registry channels, CAN inputs, AFR, fuel, derived and quality topics, `NER/error` |
+//! | `<prefix>/binary_sensor/<node>/<topic>/config` | Status flags (`ENG/running`, …) |
+//!
+//! `<prefix>` is `ha_discovery_prefix` and `<node>` is derived from
+//! `mqtt_base_topic`, so each ECU (base topic) becomes one device named
+//! `ha_device_name`.  Units follow `unit_system` and `[units]`; channels the
+//! active packet layout lacks, or that `ecu_channels` leaves out, are not
+//! announced; one a reconnect no longer announces is removed with an empty
+//! retained document.  Entities are available while the
+//! availability topic reads `online`, so they turn unavailable both when the
+//! bridge is gone and when the ECU link is down.
+
+use crate::afr::{AFR_ERROR_TOPIC, AFR_TOPIC, LAMBDA_TOPIC};
+use crate::can_inputs::can_input_index;
+use crate::channels::CHANNELS;
+use crate::config::AppConfig;
+use crate::ecu_data_parser::{ERROR_NAME_TOPIC, PublishState};
+use crate::fuel::{TRIP_DIST_TOPIC, TRIP_FUEL_TOPIC, name_of};
+use crate::limits::{QUALITY_TOPIC, VIOLATIONS_TOPIC};
+use crate::mqtt_handler::{Availability, MqttMessage, build_topic_path};
+use crate::packet_layout::{CAN_INPUTS, Field, Layout};
+use crate::status_flags::STATUS_FLAGS;
+use serde_json::{Value, json};
+
+/// Home Assistant device class of a value published in `unit`.
+fn device_class(unit: &str) -> Option<&'static str> {
+    match unit {
+        "°C" | "°F" => Some("temperature"),
+        "kPa" | "psi" | "bar" | "inHg" => Some("pressure"),
+        "km/h" | "mph" => Some("speed"),
+        "V" => Some("voltage"),
+        "ms" | "s" => Some("duration"),
+        "km" | "mi" => Some("distance"),
+        "L" => Some("volume"),
+        "B" => Some("data_size"),
+        _ => None,
+    }
+}
+
+/// Discovery id of a topic code: lower case, `/` and other symbols as `_`.
+fn object_id(topic: &str) -> String {
+    topic
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect::<String>()
+        .trim_matches('_')
+        .to_string()
+}
+
+/// Node id of the device publishing under `base_topic`
+/// (`/GOLF86/ECU/` → `golf86_ecu`).
+pub fn node_id(base_topic: &str) -> String {
+    let id = object_id(base_topic);
+    if id.is_empty() {
+        "speeduino".to_string()
+    } else {
+        id
+    }
+}
+
+/// One announced entity.
+#[derive(Debug, Clone, PartialEq)]
+struct Entity {
+    /// Published topic code
+    topic: String,
+    name: String,
+    unit: String,
+    /// `false` for text topics (error name, quality)
+    numeric: bool,
+    /// Running total (trip), rather than a measurement
+    total: bool,
+}
+
+impl Entity {
+    fn sensor(topic: &str, name: impl Into<String>, unit: &str) -> Self {
+        Self {
+            topic: topic.to_string(),
+            name: name.into(),
+            unit: unit.to_string(),
+            numeric: true,
+            total: false,
+        }
+    }
+
+    fn text(topic: &str, name: &str) -> Self {
+        Self {
+            numeric: false,
+            ..Self::sensor(topic, name, "")
+        }
+    }
+}
+
+/// Discovery documents of one ECU.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Discovery {
+    prefix: String,
+    node: String,
+    base_topic: String,
+    availability_topic: String,
+    device: Value,
+    sensors: Vec<Entity>,
+    /// (topic, name) of every status flag
+    flags: Vec<(&'static str, String)>,
+}
+
+impl Discovery {
+    /// Documents for the topics the settings publish from packets in
+    /// `layout`, in the units and with the channels of `state`, `None` unless
+    /// `ha_discovery` is set.  `firmware` is the ECU's signature, shown as the
+    /// device's software version.
+    pub fn from_config(
+        config: &AppConfig,
+        state: &PublishState,
+        layout: &Layout,
+        firmware: Option<&str>,
+    ) -> Option<Self> {
+        if !config.ha_discovery {
+            return None;
+        }
+        let sent = |field| layout.def(field).is_some() && !state.unrequested.contains(field);
+        let units = &state.units;
+
+        let mut sensors: Vec<Entity> = CHANNELS
+            .iter()
+            .filter(|c| sent(c.field) && can_input_index(c.code).is_none())
+            .map(|c| Entity::sensor(c.code, c.name, units.symbol(c.code)))
+            .collect();
+        if sent(Field::NextError) {
+            sensors.push(Entity::text(ERROR_NAME_TOPIC, "ECU error"));
+        }
+        sensors.extend(
+            state
+                .can_inputs
+                .inputs()
+                .iter()
+                .filter(|input| sent(CAN_INPUTS[input.index]))
+                .map(|input| Entity::sensor(&input.topic, input.name.as_str(), &input.unit)),
+        );
+        if sent(Field::O2Primary) {
+            sensors.extend([
+                Entity::sensor(AFR_TOPIC, "AFR", ""),
+                Entity::sensor(LAMBDA_TOPIC, "Lambda", ""),
+                Entity::sensor(AFR_ERROR_TOPIC, "AFR error", ""),
+            ]);
+        }
+        if let Some(tracker) = &state.fuel {
+            sensors.extend(
+                tracker
+                    .model()
+                    .metadata()
+                    .into_iter()
+                    .map(|(topic, unit)| Entity {
+                        total: topic == TRIP_FUEL_TOPIC || topic == TRIP_DIST_TOPIC,
+                        ..Entity::sensor(topic, name_of(topic), unit)
+                    }),
+            );
+        }
+        sensors.extend(
+            state
+                .derived
+                .names()
+                .map(|name| Entity::sensor(name, name, "")),
+        );
+        sensors.push(Entity::text(QUALITY_TOPIC, "Frame quality"));
+        sensors.push(Entity::text(VIOLATIONS_TOPIC, "Out-of-range channels"));
+
+        let flags = STATUS_FLAGS
+            .iter()
+            .filter(|flag| sent(flag.field))
+            .map(|flag| {
+                // Bytes share flag names (`fan`, `boost_cut`), so name the byte
+                let byte = flag.topic.split('/').next().unwrap_or_default();
+                let name = format!("{} ({})", flag.name().replace('_', " "), byte);
+                (flag.topic, name)
+            })
+            .collect();
+
+        let node = node_id(&config.mqtt_base_topic);
+        let mut device = json!({
+            "identifiers": [format!("speeduino_{}", node)],
+            "name": config.ha_device_name,
+            "manufacturer": "Speeduino",
+            "model": "Speeduino",
+        });
+        if let Some(signature) = firmware {
+            device["sw_version"] = json!(signature);
+        }
+        Some(Self {
+            prefix: config.ha_discovery_prefix.trim_end_matches('/').to_string(),
+            node,
+            base_topic: config.mqtt_base_topic.clone(),
+            availability_topic: Availability::topic(config),
+            device,
+            sensors,
+            flags,
+        })
+    }
+
+    /// Fields every document shares.
+    fn document(&self, topic: &str, name: &str) -> Value {
+        json!({
+            "name": name,
+            "unique_id": format!("{}_{}", self.node, object_id(topic)),
+            "state_topic": build_topic_path(&self.base_topic, topic),
+            "availability_topic": self.availability_topic,
+            // Anything but "online" (also "ecu_offline") means unavailable
+            "availability_template": "{{ 'online' if value == 'online' else 'offline' }}",
+            "device": self.device,
+        })
+    }
+
+    fn config_topic(&self, component: &str, topic: &str) -> String {
+        format!(
+            "{}/{}/{}/{}/config",
+            self.prefix,
+            component,
+            self.node,
+            object_id(topic)
+        )
+    }
+
+    /// (discovery topic, document) of every entity.
+    pub fn documents(&self) -> Vec<(String, Value)> {
+        let sensors = self.sensors.iter().map(|entity| {
+            let mut doc = self.document(&entity.topic, &entity.name);
+            if !entity.unit.is_empty() {
+                doc["unit_of_measurement"] = json!(entity.unit);
+            }
+            if let Some(class) = device_class(&entity.unit) {
+                doc["device_class"] = json!(class);
+            }
+            if entity.numeric {
+                let state_class = if entity.total {
+                    "total_increasing"
+                } else {
+                    "measurement"
+                };
+                doc["state_class"] = json!(state_class);
+            }
+            (self.config_topic("sensor", &entity.topic), doc)
+        });
+        let flags = self.flags.iter().map(|(topic, name)| {
+            let mut doc = self.document(topic, name);
+            doc["payload_on"] = json!("true");
+            doc["payload_off"] = json!("false");
+            (self.config_topic("binary_sensor", topic), doc)
+        });
+        sensors.chain(flags).collect()
+    }
+
+    /// Retained messages announcing every entity, after empty retained ones
+    /// that remove the entities `previous` announced and these documents no
+    /// longer do (e.g. channels a new packet layout lacks).
+    pub fn messages(&self, previous: Option<&Self>, qos: i32) -> Vec<MqttMessage> {
+        let documents = self.documents();
+        let mut payloads: Vec<(String, String)> = previous
+            .map(|previous| previous.documents())
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(topic, _)| !documents.iter().any(|(t, _)| t == topic))
+            .map(|(topic, _)| (topic, String::new()))
+            .collect();
+        payloads.extend(
+            documents
+                .into_iter()
+                .map(|(topic, doc)| (topic, doc.to_string())),
+        );
+        payloads
+            .into_iter()
+            .map(|(topic, payload)| MqttMessage {
+                retained: true,
+                ..MqttMessage::new(topic, payload, qos)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet_layout::{PRIMARY, PRIMARY_U8_DOT, SECONDARY};
+    use std::collections::HashMap;
+
+    fn config() -> AppConfig {
+        AppConfig {
+            ha_discovery: true,
+            mqtt_base_topic: "/GOLF86/ECU/".to_string(),
+            ..AppConfig::default()
+        }
+    }
+
+    fn discover(config: &AppConfig, firmware: Option<&str>) -> Option<Discovery> {
+        let state = PublishState::from_config(config).unwrap();
+        Discovery::from_config(config, &state, &PRIMARY, firmware)
+    }
+
+    fn document(discovery: &Discovery, topic: &str) -> Value {
+        discovery
+            .documents()
+            .into_iter()
+            .find(|(t, _)| t == topic)
+            .map(|(_, doc)| doc)
+            .unwrap_or_else(|| panic!("no document {}", topic))
+    }
+
+    #[test]
+    fn test_ids() {
+        assert_eq!(node_id("/GOLF86/ECU/"), "golf86_ecu");
+        assert_eq!(node_id("/"), "speeduino");
+        assert_eq!(object_id("ENG/running"), "eng_running");
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        assert!(discover(&AppConfig::default(), None).is_none());
+    }
+
+    #[test]
+    fn test_sensor_document() {
+        let discovery = discover(&config(), Some("speeduino 202402")).unwrap();
+        let clt = document(&discovery, "homeassistant/sensor/golf86_ecu/clt/config");
+        assert_eq!(clt["name"], "Coolant temperature");
+        assert_eq!(clt["unique_id"], "golf86_ecu_clt");
+        assert_eq!(clt["state_topic"], "/GOLF86/ECU/CLT");
+        assert_eq!(clt["availability_topic"], "/GOLF86/ECU/AVAILABILITY");
+        assert!(clt["availability_template"].is_string());
+        assert_eq!(clt["unit_of_measurement"], "°C");
+        assert_eq!(clt["device_class"], "temperature");
+        assert_eq!(clt["state_class"], "measurement");
+        assert_eq!(clt["device"]["identifiers"][0], "speeduino_golf86_ecu");
+        assert_eq!(clt["device"]["sw_version"], "speeduino 202402");
+
+        let error = document(
+            &discovery,
+            "homeassistant/sensor/golf86_ecu/ner_error/config",
+        );
+        assert!(error.get("state_class").is_none());
+    }
+
+    #[test]
+    fn test_status_flags_are_binary_sensors() {
+        let discovery = discover(&config(), None).unwrap();
+        let running = document(
+            &discovery,
+            "homeassistant/binary_sensor/golf86_ecu/eng_running/config",
+        );
+        assert_eq!(running["state_topic"], "/GOLF86/ECU/ENG/running");
+        assert_eq!(running["payload_on"], "true");
+        assert_eq!(running["name"], "running (ENG)");
+        assert!(running["device"].get("sw_version").is_none());
+    }
+
+    #[test]
+    fn test_follows_settings() {
+        let config = AppConfig {
+            unit_system: "imperial".to_string(),
+            ecu_channels: vec!["RPM".to_string(), "CLT".to_string()],
+            derived: HashMap::from([("boost".to_string(), "MAP - BAR".to_string())]),
+            injector_flow_cc_min: Some(440.0),
+            ha_discovery_prefix: "ha/".to_string(),
+            ..config()
+        };
+        let discovery = discover(&config, None).unwrap();
+        let topics: Vec<String> = discovery.documents().into_iter().map(|(t, _)| t).collect();
+        assert!(topics.contains(&"ha/sensor/golf86_ecu/rpm/config".to_string()));
+        assert!(!topics.iter().any(|t| t.contains("/map/")));
+        assert!(!topics.iter().any(|t| t.starts_with("ha/binary_sensor")));
+        assert!(topics.contains(&"ha/sensor/golf86_ecu/boost/config".to_string()));
+
+        let clt = document(&discovery, "ha/sensor/golf86_ecu/clt/config");
+        assert_eq!(clt["unit_of_measurement"], "°F");
+        let trip = document(&discovery, "ha/sensor/golf86_ecu/trip_dist/config");
+        assert_eq!(trip["unit_of_measurement"], "mi");
+        assert_eq!(trip["state_class"], "total_increasing");
+
+        let messages = discovery.messages(None, 1);
+        assert_eq!(messages.len(), topics.len());
+        assert!(messages.iter().all(|m| m.retained && m.qos == 1));
+    }
+
+    #[test]
+    fn test_follows_layout() {
+        let config = config();
+        let state = PublishState::from_config(&config).unwrap();
+        let topics = |layout| {
+            Discovery::from_config(&config, &state, layout, None)
+                .unwrap()
+                .documents()
+                .into_iter()
+                .map(|(t, _)| t)
+                .collect::<Vec<_>>()
+        };
+        let pw5 = "homeassistant/sensor/golf86_ecu/pw5/config".to_string();
+        assert!(topics(&PRIMARY).contains(&pw5));
+        assert!(!topics(&PRIMARY_U8_DOT).contains(&pw5));
+
+        let secondary = topics(&SECONDARY);
+        assert!(secondary.contains(&"homeassistant/sensor/golf86_ecu/rpm/config".to_string()));
+        assert!(!secondary.iter().any(|t| t.contains("/vss/")));
+    }
+
+    #[test]
+    fn test_removes_entities_that_drop_out() {
+        let config = config();
+        let state = PublishState::from_config(&config).unwrap();
+        let primary = Discovery::from_config(&config, &state, &PRIMARY, None).unwrap();
+        let secondary = Discovery::from_config(&config, &state, &SECONDARY, None).unwrap();
+
+        let messages = secondary.messages(Some(&primary), 0);
+        let payload = |topic: &str| {
+            messages
+                .iter()
+                .find(|m| m.topic == topic)
+                .map(|m| m.payload.clone())
+        };
+        let vss = payload("homeassistant/sensor/golf86_ecu/vss/config");
+        assert_eq!(vss.as_deref(), Some(""));
+        let rpm = payload("homeassistant/sensor/golf86_ecu/rpm/config").unwrap();
+        assert!(!rpm.is_empty());
+        assert!(messages.iter().all(|m| m.retained));
+        assert_eq!(
+            messages.iter().filter(|m| m.payload.is_empty()).count(),
+            primary.documents().len() - secondary.documents().len()
+        );
+
+        // Nothing to remove the first time or when nothing changed
+        assert!(
+            primary
+                .messages(Some(&primary), 0)
+                .iter()
+                .all(|m| !m.payload.is_empty())
+        );
+    }
+}
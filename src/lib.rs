@@ -0,0 +1,30 @@
+//! # Speeduino to MQTT
+//!
+//! ECU communication, packet decoding and MQTT publishing, shared by the
+//! `speeduino-to-mqtt` bridge, the `speeduino-sim` ECU simulator and the
+//! integration tests.
+
+pub mod afr;
+pub mod can_inputs;
+pub mod capture;
+pub mod channels;
+pub mod config;
+pub mod connection;
+pub mod decimation;
+pub mod derived;
+pub mod ecu_data_parser;
+pub mod ecu_protocol;
+pub mod ecu_serial_comms_handler;
+pub mod errors;
+pub mod events;
+pub mod fuel;
+pub mod ha_discovery;
+pub mod limits;
+pub mod mqtt_handler;
+pub mod on_change;
+pub mod packet_layout;
+pub mod replay;
+pub mod simulator;
+pub mod status_flags;
+pub mod tui;
+pub mod units;
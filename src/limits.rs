@@ -0,0 +1,396 @@
+//! Sanity limits for decoded frames.
+//!
+//! Every registry channel has a plausible range (see [`crate::channels`]);
+//! the `[limits]` tables narrow or widen it per channel and pick what happens
+//! to a value outside it:
+//!
+//! ```toml
+//! limit_policy = "log"        # channels without a table
+//!
+//! [limits.RPM]
+//! max    = 9000
+//! policy = "drop_frame"
+//! ```
+//!
+//! | Policy | Effect |
+//! |--------|--------|
+//! | `log` | Warn and publish as usual |
+//! | `flag` | Warn, publish, and mark the frame `flagged` |
+//! | `drop_field` | Warn and leave the channel out of this frame (`partial`) |
+//! | `drop_frame` | Warn and publish nothing but the quality (`rejected`) |
+//!
+//! The frame's quality is published to `QUALITY`, and the channels out of
+//! range to `QUALITY/violations` (comma-separated, empty for a clean frame).
+
+use crate::channels::{CHANNELS, Channel, channel};
+use crate::config::AppConfig;
+use crate::ecu_data_parser::SpeeduinoData;
+use crate::errors::{ConfigError, ParseError, Result};
+use tracing::warn;
+
+pub const QUALITY_TOPIC: &str = "QUALITY";
+pub const VIOLATIONS_TOPIC: &str = "QUALITY/violations";
+
+/// What happens to a value outside its limits, mildest first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LimitPolicy {
+    #[default]
+    Log,
+    Flag,
+    DropField,
+    DropFrame,
+}
+
+impl LimitPolicy {
+    /// Parse `"log"`, `"flag"`, `"drop_field"` or `"drop_frame"`
+    /// (case-insensitive).
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "log" => Some(LimitPolicy::Log),
+            "flag" => Some(LimitPolicy::Flag),
+            "drop_field" => Some(LimitPolicy::DropField),
+            "drop_frame" => Some(LimitPolicy::DropFrame),
+            _ => None,
+        }
+    }
+}
+
+/// Quality of a frame after the limit checks, best first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Quality {
+    #[default]
+    Ok,
+    /// Published with values outside their limits
+    Flagged,
+    /// Published without the channels outside their limits
+    Partial,
+    /// Not published
+    Rejected,
+}
+
+impl Quality {
+    pub fn name(self) -> &'static str {
+        match self {
+            Quality::Ok => "ok",
+            Quality::Flagged => "flagged",
+            Quality::Partial => "partial",
+            Quality::Rejected => "rejected",
+        }
+    }
+
+    /// The worse of `self` and the outcome of `policy`.
+    fn max_with(self, policy: LimitPolicy) -> Self {
+        let outcome = match policy {
+            LimitPolicy::Log => Quality::Ok,
+            LimitPolicy::Flag => Quality::Flagged,
+            LimitPolicy::DropField => Quality::Partial,
+            LimitPolicy::DropFrame => Quality::Rejected,
+        };
+        self.max(outcome)
+    }
+}
+
+/// Limits of one channel.
+#[derive(Debug, Clone, PartialEq)]
+struct Limit {
+    channel: &'static Channel,
+    min: f64,
+    max: f64,
+    policy: LimitPolicy,
+    /// From the registry: a zero below `min` means an unwired input and passes
+    registry: bool,
+}
+
+/// A value outside its limits.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub code: &'static str,
+    pub value: f64,
+    pub min: f64,
+    pub max: f64,
+    pub policy: LimitPolicy,
+}
+
+impl Violation {
+    pub fn to_error(&self) -> ParseError {
+        ParseError::ValidationFailed {
+            field: self.code.to_string(),
+            value: self.value,
+            min: self.min,
+            max: self.max,
+        }
+    }
+}
+
+/// Outcome of checking one frame.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Validation {
+    pub quality: Quality,
+    pub violations: Vec<Violation>,
+}
+
+impl Validation {
+    /// First violation that rejects the frame.
+    pub fn rejection(&self) -> Option<&Violation> {
+        self.violations
+            .iter()
+            .find(|v| v.policy == LimitPolicy::DropFrame)
+    }
+
+    /// (topic, value) of the quality topics.
+    pub fn params(&self) -> Vec<(&'static str, String)> {
+        let codes: Vec<&str> = self.violations.iter().map(|v| v.code).collect();
+        vec![
+            (QUALITY_TOPIC, self.quality.name().to_string()),
+            (VIOLATIONS_TOPIC, codes.join(",")),
+        ]
+    }
+}
+
+/// Limits of every registry channel.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    limits: Vec<Limit>,
+}
+
+impl Default for Limits {
+    /// Registry ranges, logged only.
+    fn default() -> Self {
+        Self::with_policy(LimitPolicy::Log)
+    }
+}
+
+impl Limits {
+    /// Registry ranges, all with `policy`.
+    pub fn with_policy(policy: LimitPolicy) -> Self {
+        Self {
+            limits: CHANNELS
+                .iter()
+                .map(|channel| Limit {
+                    channel,
+                    min: channel.min,
+                    max: channel.max,
+                    policy,
+                    registry: true,
+                })
+                .collect(),
+        }
+    }
+
+    /// Limits from `limit_policy` and the `[limits]` tables.
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        let Some(policy) = LimitPolicy::from_config(&config.limit_policy) else {
+            return Err(ConfigError::InvalidValue {
+                field: "limit_policy".to_string(),
+                message: format!(
+                    "must be log, flag, drop_field or drop_frame, got \"{}\"",
+                    config.limit_policy
+                ),
+            }
+            .into());
+        };
+        let mut limits = Self::with_policy(policy);
+        for (code, settings) in &config.limits {
+            let field = format!("limits.{}", code);
+            let Some(channel) = channel(code) else {
+                return Err(ConfigError::InvalidValue {
+                    field,
+                    message: format!("unknown channel \"{}\"", code),
+                }
+                .into());
+            };
+            if settings
+                .min
+                .into_iter()
+                .chain(settings.max)
+                .any(|v| !v.is_finite())
+                || settings
+                    .min
+                    .zip(settings.max)
+                    .is_some_and(|(min, max)| min > max)
+            {
+                return Err(ConfigError::InvalidValue {
+                    field,
+                    message: "min and max must be finite numbers with min ≤ max".to_string(),
+                }
+                .into());
+            }
+            let policy = match settings.policy.as_deref() {
+                None => None,
+                Some(name) => match LimitPolicy::from_config(name) {
+                    Some(policy) => Some(policy),
+                    None => {
+                        return Err(ConfigError::InvalidValue {
+                            field,
+                            message: format!(
+                                "policy must be log, flag, drop_field or drop_frame, got \"{}\"",
+                                name
+                            ),
+                        }
+                        .into());
+                    }
+                },
+            };
+            if let Some(limit) = limits
+                .limits
+                .iter_mut()
+                .find(|l| l.channel.code == channel.code)
+            {
+                limit.min = settings.min.unwrap_or(channel.min);
+                limit.max = settings.max.unwrap_or(channel.max);
+                if let Some(policy) = policy {
+                    limit.policy = policy;
+                }
+                limit.registry = false;
+            }
+        }
+        Ok(limits)
+    }
+
+    /// Check `d` against the limits, logging every violation and removing
+    /// the channels whose policy drops them.
+    pub fn check(&self, d: &mut SpeeduinoData) -> Validation {
+        let mut validation = Validation::default();
+        for limit in &self.limits {
+            let Some(value) = limit.channel.value(d) else {
+                continue;
+            };
+            if (limit.min..=limit.max).contains(&value)
+                || (limit.registry && value == 0.0 && limit.min > 0.0)
+            {
+                continue;
+            }
+            let channel = limit.channel;
+            warn!(
+                "{} out of range: {:.*}{} (expected {}–{})",
+                channel.name, channel.decimals, value, channel.unit, limit.min, limit.max
+            );
+            if limit.policy == LimitPolicy::DropField {
+                d.missing.insert(channel.field);
+            }
+            validation.quality = validation.quality.max_with(limit.policy);
+            validation.violations.push(Violation {
+                code: channel.code,
+                value,
+                min: limit.min,
+                max: limit.max,
+                policy: limit.policy,
+            });
+        }
+        validation
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::LimitConfig;
+    use crate::packet_layout::Field;
+    use std::collections::HashMap;
+
+    fn frame() -> SpeeduinoData {
+        SpeeduinoData {
+            rpm: 3000,
+            map: 100,
+            battery_10: 140,
+            ..SpeeduinoData::default()
+        }
+    }
+
+    fn limits(policy: &str) -> Limits {
+        let config = AppConfig {
+            limit_policy: "flag".to_string(),
+            limits: HashMap::from([(
+                "rpm".to_string(),
+                LimitConfig {
+                    min: None,
+                    max: Some(8000.0),
+                    policy: Some(policy.to_string()),
+                },
+            )]),
+            ..AppConfig::default()
+        };
+        Limits::from_config(&config).unwrap()
+    }
+
+    #[test]
+    fn test_clean_frame() {
+        let mut d = frame();
+        let validation = Limits::default().check(&mut d);
+        assert_eq!(validation, Validation::default());
+        assert_eq!(
+            validation.params(),
+            vec![
+                ("QUALITY", "ok".to_string()),
+                ("QUALITY/violations", String::new())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_log_keeps_quality() {
+        let mut d = SpeeduinoData {
+            rpm: 65535,
+            ..frame()
+        };
+        let validation = Limits::default().check(&mut d);
+        assert_eq!(validation.quality, Quality::Ok);
+        assert_eq!(validation.violations[0].code, "RPM");
+        assert!(!d.missing.contains(Field::Rpm));
+    }
+
+    #[test]
+    fn test_policies() {
+        let spike = SpeeduinoData {
+            rpm: 9000,
+            ..frame()
+        };
+
+        let mut d = spike.clone();
+        assert_eq!(limits("flag").check(&mut d).quality, Quality::Flagged);
+
+        let mut d = spike.clone();
+        let validation = limits("drop_field").check(&mut d);
+        assert_eq!(validation.quality, Quality::Partial);
+        assert!(d.missing.contains(Field::Rpm));
+        assert_eq!(channel("RPM").unwrap().value(&d), None);
+
+        let mut d = spike.clone();
+        let validation = limits("drop_frame").check(&mut d);
+        assert_eq!(validation.quality, Quality::Rejected);
+        assert!(
+            validation
+                .rejection()
+                .unwrap()
+                .to_error()
+                .to_string()
+                .contains("RPM")
+        );
+    }
+
+    #[test]
+    fn test_default_policy_and_unwired_inputs() {
+        // MAP above the registry range is flagged by limit_policy
+        let mut d = SpeeduinoData {
+            map: 600,
+            battery_10: 0,
+            ..frame()
+        };
+        let validation = limits("drop_frame").check(&mut d);
+        assert_eq!(validation.quality, Quality::Flagged);
+        assert_eq!(
+            validation.params()[1],
+            ("QUALITY/violations", "MAP".to_string())
+        );
+    }
+
+    #[test]
+    fn test_policy_names() {
+        assert_eq!(
+            LimitPolicy::from_config("Drop_Frame"),
+            Some(LimitPolicy::DropFrame)
+        );
+        assert_eq!(LimitPolicy::from_config("ignore"), None);
+        assert!(LimitPolicy::Flag < LimitPolicy::DropField);
+    }
+}
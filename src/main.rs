@@ -1,6 +1,7 @@
 //! # Speeduino to MQTT
 //!
-//! Bridges a Speeduino ECU (serial or TCP) to an MQTT broker.
+//! Bridges a Speeduino ECU (serial or TCP) to an MQTT broker, or plays back a
+//! recorded capture through the same pipeline.
 //!
 //! **Interactive mode** (TTY detected): renders a live TUI and optionally
 //! writes to MQTT when `mqtt_enabled = true`.
@@ -8,25 +9,24 @@
 //! **Service mode** (no TTY / running under systemd): structured logging to
 //! stdout, same ECU polling logic.
 
-mod config;
-mod connection;
-mod ecu_data_parser;
-mod ecu_serial_comms_handler;
-mod errors;
-mod mqtt_handler;
-mod tui;
-
-use crate::config::{AppConfig, load_configuration};
-use crate::ecu_data_parser::{SpeeduinoData, process_speeduino_realtime_data};
-use crate::ecu_serial_comms_handler::EcuSerialHandler;
-use crate::mqtt_handler::{MqttHandler, MqttMessage};
-use crate::tui::{TuiState, TuiWriter, run_tui};
 use gumdrop::Options;
-use std::collections::VecDeque;
+use speeduino_to_mqtt::capture::{CaptureWriter, timestamp_us};
+use speeduino_to_mqtt::config::{AppConfig, load_configuration};
+use speeduino_to_mqtt::ecu_data_parser::{
+    PublishState, SpeeduinoData, process_speeduino_realtime_data,
+};
+use speeduino_to_mqtt::ecu_serial_comms_handler::EcuSerialHandler;
+use speeduino_to_mqtt::errors::{AppError, ParseError, SerialError};
+use speeduino_to_mqtt::events::{EventDetector, publish_events};
+use speeduino_to_mqtt::fuel::FuelUsage;
+use speeduino_to_mqtt::ha_discovery::Discovery;
+use speeduino_to_mqtt::mqtt_handler::{Availability, MqttHandler, MqttMessage, build_topic_path};
+use speeduino_to_mqtt::tui::{TuiState, TuiWriter, run_tui};
+use std::collections::{BTreeMap, VecDeque};
 use std::sync::{Arc, Mutex};
 use tokio::select;
 use tokio::sync::{RwLock, mpsc};
-use tokio::time::{Duration, interval};
+use tokio::time::{Duration, interval, timeout};
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::EnvFilter;
@@ -52,14 +52,28 @@ fn print_help() {
     println!("  -c, --config FILE        Path to TOML config file");
     println!();
     println!("Environment variables (SPEEDUINO_ prefix overrides config file):");
-    println!("  SPEEDUINO_CONNECTION_TYPE  'serial' (default) or 'tcp'");
+    println!("  SPEEDUINO_CONNECTION_TYPE  'serial' (default), 'tcp' or 'replay'");
     println!("  SPEEDUINO_PORT_NAME        Serial device path");
     println!("  SPEEDUINO_BAUD_RATE        Serial baud rate");
+    println!("  SPEEDUINO_ECU_PROTOCOL     'legacy' (default) or 'crc' (framed, CRC32-checked)");
+    println!("  SPEEDUINO_ECU_CHANNELS     Comma-separated channel codes to poll (default: all)");
+    println!("  SPEEDUINO_ECU_HANDSHAKE    true (default) / false – check firmware signature");
+    println!("  SPEEDUINO_ECU_LAYOUT       'auto' (default), 'secondary' or a release (202207)");
     println!("  SPEEDUINO_TCP_HOST         TCP host (when connection_type=tcp)");
     println!("  SPEEDUINO_TCP_PORT         TCP port (when connection_type=tcp)");
+    println!("  SPEEDUINO_REPLAY_FILE      Capture file (when connection_type=replay)");
+    println!("  SPEEDUINO_REPLAY_SPEED     'realtime' (default), 'fast' or a multiplier (4x)");
+    println!("  SPEEDUINO_REPLAY_LOOP      true/false – restart the capture at its end");
+    println!("  SPEEDUINO_UNIT_SYSTEM      'metric' (default) or 'imperial'");
+    println!("  SPEEDUINO_FUEL_TYPE        'petrol' (default), 'e85', 'flex', … – stoich AFR");
+    println!("  SPEEDUINO_CAPTURE_DIR      Record raw ECU responses into this directory");
     println!("  SPEEDUINO_MQTT_ENABLED     true/false – set false for display-only");
     println!("  SPEEDUINO_MQTT_HOST        MQTT broker hostname");
     println!("  SPEEDUINO_MQTT_PORT        MQTT broker port");
+    println!("  SPEEDUINO_PUBLISH_MODE     'per_topic' (default), 'json' or 'both'");
+    println!("  SPEEDUINO_PUBLISH_ON_CHANGE true/false – publish topics only when they change");
+    println!("  SPEEDUINO_AVAILABILITY_TOPIC Availability topic code (default AVAILABILITY)");
+    println!("  SPEEDUINO_HA_DISCOVERY     true/false – Home Assistant MQTT discovery");
     println!("  SPEEDUINO_LOG_LEVEL        trace|debug|info|warn|error");
     println!("  .env file is loaded automatically from the working directory");
 }
@@ -144,9 +158,14 @@ async fn ecu_communication_loop(
     config: Arc<AppConfig>,
     mqtt_sender: Option<mpsc::Sender<MqttMessage>>,
     tui_state: Arc<RwLock<TuiState>>,
+    mut publish_state: PublishState,
     cancel: CancellationToken,
 ) -> anyhow::Result<()> {
     let mut handler = EcuSerialHandler::new((*config).clone());
+    let mut capture = open_capture(&config);
+    let mut events = EventDetector::default();
+    // Home Assistant entities announced on the last connect
+    let mut discovery: Option<Discovery> = None;
 
     // Initial connection with backoff – retries indefinitely, never exits.
     loop {
@@ -156,11 +175,16 @@ async fn ecu_communication_loop(
         match handler.connect().await {
             Ok(_) => {
                 info!("Connected to ECU: {}", config.connection_display());
-                {
-                    let mut s = tui_state.write().await;
-                    s.ecu_connected = true;
-                    s.connection_address = config.connection_display();
-                }
+                tui_state.write().await.connection_address = config.connection_display();
+                on_ecu_connected(
+                    &handler,
+                    &config,
+                    &publish_state,
+                    &mqtt_sender,
+                    &tui_state,
+                    &mut discovery,
+                )
+                .await;
                 break;
             }
             Err(e) => {
@@ -177,14 +201,17 @@ async fn ecu_communication_loop(
         }
     }
 
-    let mut poll = interval(Duration::from_millis(config.refresh_rate_ms));
+    // A replay is paced by the capture timestamps, so poll it back to back.
+    let poll_ms = if config.is_replay() {
+        1
+    } else {
+        config.refresh_rate_ms
+    };
+    let mut poll = interval(Duration::from_millis(poll_ms));
     let mut consecutive_errors: u32 = 0;
     const MAX_ERRORS: u32 = 10;
 
-    info!(
-        "ECU polling started at {}ms interval",
-        config.refresh_rate_ms
-    );
+    info!("ECU polling started at {}ms interval", poll_ms);
 
     loop {
         tokio::select! {
@@ -198,11 +225,19 @@ async fn ecu_communication_loop(
         if !handler.check_device_exists() {
             warn!("ECU device not found, attempting reconnect…");
             handler.disconnect().await;
-            tui_state.write().await.ecu_connected = false;
+            set_ecu_link(&config, &mqtt_sender, &tui_state, false).await;
 
             if handler.reconnect().await.is_ok() {
                 consecutive_errors = 0;
-                tui_state.write().await.ecu_connected = true;
+                on_ecu_connected(
+                    &handler,
+                    &config,
+                    &publish_state,
+                    &mqtt_sender,
+                    &tui_state,
+                    &mut discovery,
+                )
+                .await;
             } else {
                 consecutive_errors += 1;
                 if consecutive_errors >= MAX_ERRORS {
@@ -214,58 +249,222 @@ async fn ecu_communication_loop(
             continue;
         }
 
-        match handler.read_engine_data().await {
+        // Every failed poll counts towards the reconnect, whatever the cause
+        let failed = match handler.read_engine_data().await {
             Ok(data) => {
                 debug!("Read {} bytes from ECU", data.len());
+                if let Some(writer) = capture.as_mut()
+                    && let Err(e) = writer.write(timestamp_us(), &data)
+                {
+                    error!("Capture recording stopped: {}", e);
+                    capture = None;
+                }
                 let sender_ref = mqtt_sender.as_ref();
-                match process_speeduino_realtime_data(&data, &config, sender_ref).await {
+                let layout = handler.layout();
+                match process_speeduino_realtime_data(
+                    &data,
+                    &config,
+                    layout,
+                    sender_ref,
+                    &mut publish_state,
+                )
+                .await
+                {
                     Ok(ecu_data) => {
                         consecutive_errors = 0;
                         handler.reset_retry_count();
-                        update_tui_ecu_data(&tui_state, ecu_data, &mqtt_sender).await;
+                        let found = events.update(&ecu_data, timestamp_us() / 1000);
+                        publish_events(&found, &config, &publish_state.units, sender_ref).await;
+                        // Frames flow again after the link was reported down
+                        set_ecu_link(&config, &mqtt_sender, &tui_state, true).await;
+                        let usage = publish_state.fuel.as_ref().and_then(|f| f.usage()).cloned();
+                        update_tui_ecu_data(&tui_state, ecu_data, usage, &mqtt_sender).await;
+                        false
+                    }
+                    Err(e @ AppError::Parse(ParseError::ValidationFailed { .. })) => {
+                        // Rejected by a drop_frame limit; the link itself is fine
+                        warn!("Discarding implausible ECU frame: {}", e);
+                        false
                     }
                     Err(e) => {
                         error!("Failed to process ECU data: {}", e);
-                        consecutive_errors += 1;
+                        true
                     }
                 }
             }
+            Err(AppError::Serial(SerialError::ReplayFinished)) => {
+                info!("Replay finished: end of capture reached");
+                break;
+            }
+            Err(e @ (AppError::Parse(_) | AppError::Serial(SerialError::Desync(_)))) => {
+                // Corrupted (CRC mismatch) or misaligned frame — the handler has
+                // re-aligned; drop the frame rather than publishing garbage.
+                warn!("Discarding ECU frame: {}", e);
+                tui_state.write().await.bad_frames = handler.bad_frames();
+                true
+            }
             Err(e) => {
                 error!("Failed to read from ECU: {}", e);
-                consecutive_errors += 1;
-                tui_state.write().await.ecu_connected = false;
+                true
+            }
+        };
+        if !failed {
+            continue;
+        }
 
-                if consecutive_errors >= MAX_ERRORS {
-                    error!("Too many read errors, reconnecting…");
-                    handler.disconnect().await;
-                    match handler.reconnect().await {
-                        Ok(_) => {
-                            consecutive_errors = 0;
-                            tui_state.write().await.ecu_connected = true;
-                        }
-                        Err(e) => {
-                            warn!("Reconnect failed after read errors: {} – resetting and retrying indefinitely", e);
-                            handler.reset_retry_count();
-                            consecutive_errors = 0;
-                            tui_state.write().await.ecu_connected = false;
-                        }
-                    }
+        // A single bad poll is not an outage: the link is reported down only
+        // once the errors pile up, so the availability topic does not flap.
+        consecutive_errors += 1;
+        if consecutive_errors >= MAX_ERRORS {
+            error!("Too many ECU errors in a row, reconnecting…");
+            set_ecu_link(&config, &mqtt_sender, &tui_state, false).await;
+            handler.disconnect().await;
+            match handler.reconnect().await {
+                Ok(_) => {
+                    consecutive_errors = 0;
+                    on_ecu_connected(
+                        &handler,
+                        &config,
+                        &publish_state,
+                        &mqtt_sender,
+                        &tui_state,
+                        &mut discovery,
+                    )
+                    .await;
+                }
+                Err(e) => {
+                    warn!(
+                        "Reconnect failed after ECU errors: {} – resetting and retrying indefinitely",
+                        e
+                    );
+                    handler.reset_retry_count();
+                    consecutive_errors = 0;
                 }
             }
         }
     }
 
+    if let Some(tracker) = publish_state.fuel.as_mut() {
+        tracker.save();
+    }
     handler.disconnect().await;
     Ok(())
 }
 
+/// Start the raw packet recorder when `capture_dir` is configured.
+fn open_capture(config: &AppConfig) -> Option<CaptureWriter> {
+    let dir = config.capture_dir.as_deref()?;
+    let max_age = (config.capture_rotate_minutes > 0)
+        .then(|| Duration::from_secs(config.capture_rotate_minutes * 60));
+    match CaptureWriter::new(
+        std::path::Path::new(dir),
+        config.capture_max_size_mb * 1024 * 1024,
+        max_age,
+        (config.capture_max_files > 0).then_some(config.capture_max_files),
+    ) {
+        Ok(writer) => Some(writer),
+        Err(e) => {
+            error!("Cannot record captures to {}: {}", dir, e);
+            None
+        }
+    }
+}
+
+/// Mark the ECU online and announce its firmware, units and Home Assistant
+/// entities (retained, so late subscribers still learn what is on the other
+/// end of the bridge).  `announced` holds the entities of the previous
+/// connect; those the ECU no longer sends are removed.
+async fn on_ecu_connected(
+    handler: &EcuSerialHandler,
+    config: &AppConfig,
+    publish: &PublishState,
+    mqtt_sender: &Option<mpsc::Sender<MqttMessage>>,
+    state: &Arc<RwLock<TuiState>>,
+    announced: &mut Option<Discovery>,
+) {
+    let firmware = handler.firmware().map(|fw| fw.signature.clone());
+    state.write().await.ecu_firmware = firmware.clone();
+    set_ecu_link(config, mqtt_sender, state, true).await;
+
+    if let (Some(sender), Some(signature)) = (mqtt_sender, firmware.clone()) {
+        let topic = build_topic_path(&config.mqtt_base_topic, "FIRMWARE");
+        let msg = MqttMessage {
+            retained: true,
+            ..MqttMessage::new(topic, signature, config.mqtt_qos)
+        };
+        if sender.send(msg).await.is_err() {
+            warn!("Failed to queue firmware message (channel closed)");
+        }
+    }
+
+    if let Some(sender) = mqtt_sender {
+        let mut metadata: BTreeMap<&str, &str> = publish.units.metadata();
+        metadata.extend(publish.can_inputs.metadata());
+        if let Some(tracker) = &publish.fuel {
+            metadata.extend(tracker.model().metadata());
+        }
+        let topic = build_topic_path(&config.mqtt_base_topic, "UNITS");
+        let msg = MqttMessage {
+            retained: true,
+            ..MqttMessage::new(
+                topic,
+                serde_json::to_string(&metadata).unwrap_or_default(),
+                config.mqtt_qos,
+            )
+        };
+        if sender.send(msg).await.is_err() {
+            warn!("Failed to queue units message (channel closed)");
+        }
+    }
+
+    if let (Some(sender), Some(discovery)) = (
+        mqtt_sender,
+        Discovery::from_config(config, publish, handler.layout(), firmware.as_deref()),
+    ) {
+        for msg in discovery.messages(announced.as_ref(), config.mqtt_qos) {
+            if sender.send(msg).await.is_err() {
+                warn!("Failed to queue discovery message (channel closed)");
+                break;
+            }
+        }
+        *announced = Some(discovery);
+    }
+}
+
+/// Track the ECU link in the TUI state and announce every change on the
+/// availability topic (`online` / `ecu_offline`).
+async fn set_ecu_link(
+    config: &AppConfig,
+    mqtt_sender: &Option<mpsc::Sender<MqttMessage>>,
+    state: &Arc<RwLock<TuiState>>,
+    connected: bool,
+) {
+    // Called for every frame: only take the write lock on a change
+    if state.read().await.ecu_connected == connected {
+        return;
+    }
+    state.write().await.ecu_connected = connected;
+    if let Some(sender) = mqtt_sender {
+        let availability = if connected {
+            Availability::Online
+        } else {
+            Availability::EcuOffline
+        };
+        if sender.send(availability.message(config)).await.is_err() {
+            warn!("Failed to queue availability message (channel closed)");
+        }
+    }
+}
+
 async fn update_tui_ecu_data(
     state: &Arc<RwLock<TuiState>>,
     data: SpeeduinoData,
+    fuel: Option<FuelUsage>,
     mqtt_sender: &Option<mpsc::Sender<MqttMessage>>,
 ) {
     let mut s = state.write().await;
     s.ecu_data = Some(data);
+    s.fuel = fuel;
     if mqtt_sender.is_some() {
         s.messages_published = s.messages_published.saturating_add(1);
     }
@@ -296,6 +495,9 @@ fn display_welcome() {
 // Entry point
 // ---------------------------------------------------------------------------
 
+/// How long shutdown waits for the ECU loop to save its state.
+const ECU_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let opts = CliOptions::parse_args_default_or_exit();
@@ -321,6 +523,9 @@ async fn main() -> anyhow::Result<()> {
     let force_no_tui = std::env::var("SPEEDUINO_NO_TUI").map(|v| v == "1").unwrap_or(false);
     let is_tty = !force_no_tui && atty::is(atty::Stream::Stdout);
 
+    // Settings compiled for publishing, shared with the TUI
+    let publish_state = PublishState::from_config(&config)?;
+
     // Shared state for TUI
     let log_buffer: Arc<Mutex<VecDeque<String>>> = Arc::new(Mutex::new(VecDeque::new()));
     let tui_state: Arc<RwLock<TuiState>> = Arc::new(RwLock::new(TuiState {
@@ -331,6 +536,10 @@ async fn main() -> anyhow::Result<()> {
         } else {
             String::new()
         },
+        units: publish_state.units.clone(),
+        can_inputs: publish_state.can_inputs.clone(),
+        wideband: publish_state.wideband.clone(),
+        derived: publish_state.derived.clone(),
         ..TuiState::default()
     }));
 
@@ -352,7 +561,7 @@ async fn main() -> anyhow::Result<()> {
     let signals_task = spawn_signal_handler(cancel.clone());
 
     // Optional MQTT setup — handler must stay on the main task (paho futures are !Send)
-    let (mqtt_sender, mqtt_handler_opt): (Option<mpsc::Sender<MqttMessage>>, Option<MqttHandler>) =
+    let (mqtt_sender, mut mqtt_handler): (Option<mpsc::Sender<MqttMessage>>, Option<MqttHandler>) =
         if config.mqtt_enabled {
             info!(
                 "Setting up MQTT connection to {}:{}",
@@ -389,8 +598,15 @@ async fn main() -> anyhow::Result<()> {
     let ecu_config = Arc::clone(&config);
     let ecu_state = Arc::clone(&tui_state);
     let ecu_cancel = cancel.clone();
-    let ecu_task = tokio::spawn(async move {
-        if let Err(e) = ecu_communication_loop(ecu_config, mqtt_sender, ecu_state, ecu_cancel).await
+    let mut ecu_task = tokio::spawn(async move {
+        if let Err(e) = ecu_communication_loop(
+            ecu_config,
+            mqtt_sender,
+            ecu_state,
+            publish_state,
+            ecu_cancel,
+        )
+        .await
         {
             error!("ECU loop exited with error: {}", e);
         }
@@ -414,19 +630,21 @@ async fn main() -> anyhow::Result<()> {
 
     // Drive the MQTT publish task directly on the main task (paho futures are !Send).
     // ECU and TUI tasks are spawned because they only use Send types.
+    let mut ecu_finished = false;
     select! {
         _ = cancel.cancelled() => {
             info!("Shutdown signal received");
         }
-        _ = ecu_task => {
+        _ = &mut ecu_task => {
             warn!("ECU task terminated; shutting down");
+            ecu_finished = true;
             cancel.cancel();
         }
         result = async {
-            if let Some(handler) = mqtt_handler_opt {
+            if let Some(handler) = mqtt_handler.as_mut() {
                 handler.start_publishing_task().await
             } else {
-                std::future::pending::<crate::errors::Result<()>>().await
+                std::future::pending::<speeduino_to_mqtt::errors::Result<()>>().await
             }
         } => {
             match result {
@@ -438,6 +656,18 @@ async fn main() -> anyhow::Result<()> {
         }
     }
 
+    // Let the ECU loop save the fuel trip and close the port
+    if !ecu_finished && timeout(ECU_SHUTDOWN_TIMEOUT, &mut ecu_task).await.is_err() {
+        warn!("ECU task did not stop in time");
+    }
+
+    // A clean disconnect does not fire the last will, so announce `offline`
+    if let Some(handler) = mqtt_handler.as_mut()
+        && let Err(e) = handler.disconnect().await
+    {
+        warn!("MQTT disconnect failed: {}", e);
+    }
+
     // Wait for TUI to finish restoring the terminal
     if let Some(t) = tui_task {
         let _ = t.await;
@@ -2,11 +2,17 @@
 //!
 //! Handles asynchronous MQTT client operations including connection, authentication,
 //! TLS/SSL support, message buffering, and automatic reconnection with circuit breaker pattern.
+//!
+//! The retained availability topic (`availability_topic`) reads `online` while
+//! the ECU data is live, `ecu_offline` while the broker is connected but the
+//! ECU link is down, and `offline` – the last will – once the bridge is gone.
+//! The current state is re-published after every (re)connect, since the broker
+//! may have fired the last will in between.
 
 use crate::config::AppConfig;
 use crate::errors::{MqttError, Result};
 use paho_mqtt as mqtt;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::time::sleep;
@@ -32,6 +38,114 @@ impl MqttMessage {
     }
 }
 
+/// Topic code whole frames are published under in JSON mode.
+pub const FRAME_TOPIC: &str = "FRAME";
+
+/// Default topic code of the bridge's retained availability.
+pub const AVAILABILITY_TOPIC: &str = "AVAILABILITY";
+
+/// State published to the availability topic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Availability {
+    /// Broker and ECU connected, data is live
+    Online,
+    /// Broker connected, ECU link down
+    EcuOffline,
+    /// Bridge gone (last will)
+    Offline,
+}
+
+impl Availability {
+    pub fn payload(self) -> &'static str {
+        match self {
+            Availability::Online => "online",
+            Availability::EcuOffline => "ecu_offline",
+            Availability::Offline => "offline",
+        }
+    }
+
+    pub fn from_payload(payload: &str) -> Option<Self> {
+        match payload {
+            "online" => Some(Availability::Online),
+            "ecu_offline" => Some(Availability::EcuOffline),
+            "offline" => Some(Availability::Offline),
+            _ => None,
+        }
+    }
+
+    /// Full path of the availability topic.
+    pub fn topic(config: &AppConfig) -> String {
+        build_topic_path(&config.mqtt_base_topic, &config.availability_topic)
+    }
+
+    /// Retained message announcing this state on the availability topic.
+    pub fn message(self, config: &AppConfig) -> MqttMessage {
+        MqttMessage {
+            retained: true,
+            ..MqttMessage::new(
+                Self::topic(config),
+                self.payload().to_string(),
+                config.mqtt_qos,
+            )
+        }
+    }
+}
+
+/// `publish_mode` setting: how a decoded frame is sent.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PublishMode {
+    /// One message per channel (`<base>RPM`, `<base>MAP`, …)
+    #[default]
+    PerTopic,
+    /// One JSON object per frame on `<base>FRAME`
+    Json,
+    /// Both of the above
+    Both,
+}
+
+impl PublishMode {
+    /// Parse `"per_topic"`, `"json"` or `"both"` (case-insensitive).
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "per_topic" => Some(PublishMode::PerTopic),
+            "json" => Some(PublishMode::Json),
+            "both" => Some(PublishMode::Both),
+            _ => None,
+        }
+    }
+
+    pub fn per_topic(self) -> bool {
+        matches!(self, PublishMode::PerTopic | PublishMode::Both)
+    }
+
+    pub fn json(self) -> bool {
+        matches!(self, PublishMode::Json | PublishMode::Both)
+    }
+}
+
+/// JSON object of one frame: `ts` (ms since the epoch) and `seq` first, then
+/// every (code, value) in publishing order.  Numeric values become numbers,
+/// `true`/`false` booleans, anything else a string.
+pub fn frame_json(ts: u64, seq: u64, params: &[(&str, String)]) -> String {
+    let mut json = format!("{{\"ts\":{},\"seq\":{}", ts, seq);
+    for (code, value) in params {
+        let literal = value == "true"
+            || value == "false"
+            || serde_json::from_str::<serde_json::Number>(value).is_ok();
+        let value = if literal {
+            value.clone()
+        } else {
+            serde_json::Value::from(value.as_str()).to_string()
+        };
+        json.push(',');
+        json.push_str(&serde_json::Value::from(*code).to_string());
+        json.push(':');
+        json.push_str(&value);
+    }
+    json.push('}');
+    json
+}
+
 /// MQTT Client Handler with buffering and reconnection logic
 pub struct MqttHandler {
     client: mqtt::AsyncClient,
@@ -40,6 +154,8 @@ pub struct MqttHandler {
     buffer_receiver: Option<mpsc::Receiver<MqttMessage>>,
     is_connected: bool,
     reconnection_attempts: u32,
+    /// Latest availability queued, re-announced on every (re)connect
+    availability: Arc<Mutex<Availability>>,
 }
 
 impl MqttHandler {
@@ -67,6 +183,17 @@ impl MqttHandler {
         let client = mqtt::AsyncClient::new(create_opts)
             .map_err(|e| MqttError::ClientCreationFailed(e.to_string()))?;
 
+        // Until the ECU connects, the broker is up but there is no data.
+        // Called after the first connect and every automatic reconnect.
+        let availability = Arc::new(Mutex::new(Availability::EcuOffline));
+        let current = Arc::clone(&availability);
+        let birth_config = Arc::clone(&config);
+        client.set_connected_callback(move |cli| {
+            let state = *current.lock().unwrap_or_else(|e| e.into_inner());
+            let msg = state.message(&birth_config);
+            cli.publish(mqtt::Message::new_retained(msg.topic, msg.payload, msg.qos));
+        });
+
         // Create message buffer channel
         let (tx, rx) = mpsc::channel(config.message_buffer_size);
 
@@ -77,6 +204,7 @@ impl MqttHandler {
             buffer_receiver: Some(rx),
             is_connected: false,
             reconnection_attempts: 0,
+            availability,
         })
     }
 
@@ -93,6 +221,14 @@ impl MqttHandler {
             .clean_session(true)
             .automatic_reconnect(Duration::from_secs(1), Duration::from_secs(60));
 
+        // The broker announces "offline" if the connection drops unexpectedly
+        let will = Availability::Offline.message(&self.config);
+        conn_opts_builder.will_message(mqtt::Message::new_retained(
+            will.topic,
+            will.payload,
+            will.qos,
+        ));
+
         // Add authentication if configured
         if let Some(ref username) = self.config.mqtt_username {
             debug!("Configuring MQTT authentication for user: {}", username);
@@ -196,14 +332,20 @@ impl MqttHandler {
     }
 
     /// Start the message publishing task (consumes buffer receiver)
-    pub async fn start_publishing_task(mut self) -> Result<()> {
+    pub async fn start_publishing_task(&mut self) -> Result<()> {
         let mut receiver = self.buffer_receiver.take().ok_or_else(|| {
             MqttError::ClientCreationFailed("Buffer receiver already taken".to_string())
         })?;
 
         info!("Starting MQTT message publishing task");
 
+        let availability_topic = Availability::topic(&self.config);
         while let Some(message) = receiver.recv().await {
+            if message.topic == availability_topic
+                && let Some(state) = Availability::from_payload(&message.payload)
+            {
+                *self.availability.lock().unwrap_or_else(|e| e.into_inner()) = state;
+            }
             match self.publish(&message).await {
                 Ok(_) => {
                     // Success - reset reconnection attempts counter
@@ -232,10 +374,10 @@ impl MqttHandler {
                         }
 
                         // Retry publishing this message
-                        if self.is_connected {
-                            if let Err(e) = self.publish(&message).await {
-                                error!("Retry publish failed: {}", e);
-                            }
+                        if self.is_connected
+                            && let Err(e) = self.publish(&message).await
+                        {
+                            error!("Retry publish failed: {}", e);
                         }
                     } else {
                         error!("Max reconnection attempts exceeded, dropping message");
@@ -252,7 +394,7 @@ impl MqttHandler {
     fn calculate_backoff_delay(&self) -> u64 {
         let base_delay = self.config.initial_retry_delay_ms;
         let max_delay = self.config.max_retry_delay_ms;
-        let attempts = self.reconnection_attempts.saturating_sub(1) as u32;
+        let attempts = self.reconnection_attempts.saturating_sub(1);
 
         let delay = base_delay * 2_u64.pow(attempts);
         delay.min(max_delay)
@@ -264,12 +406,19 @@ impl MqttHandler {
         self.is_connected && self.client.is_connected()
     }
 
-    /// Disconnect from broker
-    #[allow(dead_code)]
+    /// Disconnect from broker, announcing `offline` first
     pub async fn disconnect(&mut self) -> Result<()> {
         if self.is_connected {
             info!("Disconnecting from MQTT broker");
 
+            // A clean disconnect does not trigger the last will
+            if let Err(e) = self
+                .publish(&Availability::Offline.message(&self.config))
+                .await
+            {
+                warn!("Failed to announce going offline: {}", e);
+            }
+
             self.client
                 .disconnect(None)
                 .await
@@ -310,6 +459,53 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_frame_json() {
+        let params = vec![
+            ("RPM", "3000".to_string()),
+            ("BAT", "14.2".to_string()),
+            ("ENG/running", "true".to_string()),
+            ("NER/error", "none".to_string()),
+            ("QUALITY/violations", String::new()),
+        ];
+        let json = frame_json(1_700_000_000_123, 7, &params);
+        assert!(json.starts_with("{\"ts\":1700000000123,\"seq\":7,\"RPM\":3000,"));
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["BAT"], 14.2);
+        assert_eq!(value["ENG/running"], true);
+        assert_eq!(value["NER/error"], "none");
+        assert_eq!(value["QUALITY/violations"], "");
+    }
+
+    #[test]
+    fn test_publish_modes() {
+        assert_eq!(PublishMode::from_config("JSON"), Some(PublishMode::Json));
+        assert!(PublishMode::Both.per_topic() && PublishMode::Both.json());
+        assert!(!PublishMode::default().json());
+        assert_eq!(PublishMode::from_config("per-topic"), None);
+    }
+
+    #[test]
+    fn test_availability() {
+        let config = AppConfig {
+            mqtt_base_topic: "/GOLF86/ECU/".to_string(),
+            availability_topic: "status".to_string(),
+            ..Default::default()
+        };
+        let msg = Availability::EcuOffline.message(&config);
+        assert_eq!(msg.topic, "/GOLF86/ECU/status");
+        assert_eq!(msg.payload, "ecu_offline");
+        assert!(msg.retained);
+        for state in [
+            Availability::Online,
+            Availability::EcuOffline,
+            Availability::Offline,
+        ] {
+            assert_eq!(Availability::from_payload(state.payload()), Some(state));
+        }
+        assert_eq!(Availability::from_payload("ONLINE"), None);
+    }
+
     #[test]
     fn test_mqtt_message_creation() {
         let msg = MqttMessage::new("/test/topic".to_string(), "test payload".to_string(), 1);
@@ -321,6 +517,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::field_reassign_with_default)]
     fn test_backoff_calculation() {
         let mut config = AppConfig::default();
         config.initial_retry_delay_ms = 1000;
@@ -0,0 +1,225 @@
+//! Publish-on-change.
+//!
+//! With `publish_on_change = true`, [`ChangeFilter`] drops per-topic messages
+//! whose value has not moved since it was last published:
+//!
+//! * a numeric value is sent when it differs from the last published one by
+//!   more than its deadband (`[deadbands]` entry, else `publish_deadband`);
+//! * any other value (flags, error names) is sent when it changes;
+//! * every topic is sent at least every `publish_heartbeat_ms`, so late
+//!   subscribers see data.
+//!
+//! The deadband is measured from the last *published* value, so a slow drift
+//! is still published once it adds up.  JSON frames are not filtered.
+
+use crate::can_inputs::CanInputs;
+use crate::config::AppConfig;
+use crate::ecu_data_parser::is_frame_topic;
+use crate::errors::{ConfigError, Result};
+use std::collections::HashMap;
+
+/// Value and time a topic was last published.
+#[derive(Debug, Clone, PartialEq)]
+struct Sent {
+    value: String,
+    at_ms: u64,
+}
+
+/// Drops unchanged values between heartbeats.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangeFilter {
+    deadband: f64,
+    /// Per-topic deadbands (upper-case topic → deadband)
+    deadbands: HashMap<String, f64>,
+    /// 0 = never force a refresh
+    heartbeat_ms: u64,
+    last: HashMap<String, Sent>,
+}
+
+impl ChangeFilter {
+    pub fn new(deadband: f64, heartbeat_ms: u64) -> Self {
+        Self {
+            deadband,
+            heartbeat_ms,
+            ..Self::default()
+        }
+    }
+
+    /// Use `deadband` for `topic` instead of the default.
+    pub fn with_deadband(mut self, topic: &str, deadband: f64) -> Self {
+        self.deadbands.insert(topic.to_uppercase(), deadband);
+        self
+    }
+
+    /// Filter from the settings, `None` unless `publish_on_change` is set.
+    /// The deadbands are checked either way; `can_inputs` names the CAN
+    /// topics a `[deadbands]` entry may refer to.
+    pub fn from_config(config: &AppConfig, can_inputs: &CanInputs) -> Result<Option<Self>> {
+        if let Some(topic) = config
+            .deadbands
+            .keys()
+            .find(|topic| !is_frame_topic(topic, config, can_inputs))
+        {
+            return Err(ConfigError::InvalidValue {
+                field: format!("deadbands.{}", topic),
+                message: format!("unknown topic \"{}\"", topic),
+            }
+            .into());
+        }
+        let deadbands = std::iter::once(("publish_deadband".to_string(), config.publish_deadband))
+            .chain(
+                config
+                    .deadbands
+                    .iter()
+                    .map(|(t, d)| (format!("deadbands.{}", t), *d)),
+            );
+        for (field, deadband) in deadbands {
+            if !(deadband.is_finite() && deadband >= 0.0) {
+                return Err(ConfigError::InvalidValue {
+                    field,
+                    message: format!("must be zero or more, got {}", deadband),
+                }
+                .into());
+            }
+        }
+
+        if !config.publish_on_change {
+            return Ok(None);
+        }
+        let filter = Self::new(config.publish_deadband, config.publish_heartbeat_ms);
+        Ok(Some(
+            config
+                .deadbands
+                .iter()
+                .fold(filter, |f, (topic, deadband)| {
+                    f.with_deadband(topic, *deadband)
+                }),
+        ))
+    }
+
+    fn deadband(&self, topic: &str) -> f64 {
+        self.deadbands
+            .get(&topic.to_uppercase())
+            .copied()
+            .unwrap_or(self.deadband)
+    }
+
+    fn changed(&self, topic: &str, value: &str, last: &str) -> bool {
+        match (value.parse::<f64>(), last.parse::<f64>()) {
+            (Ok(now), Ok(was)) => (now - was).abs() > self.deadband(topic),
+            _ => value != last,
+        }
+    }
+
+    /// The (topic, value) pairs of `params` that are due at `now_ms`,
+    /// remembering them as published.
+    pub fn retain<'a>(
+        &mut self,
+        params: Vec<(&'a str, String)>,
+        now_ms: u64,
+    ) -> Vec<(&'a str, String)> {
+        let mut due = Vec::with_capacity(params.len());
+        for (topic, value) in params {
+            let send = match self.last.get(topic) {
+                None => true,
+                Some(sent) => {
+                    (self.heartbeat_ms > 0
+                        && now_ms.saturating_sub(sent.at_ms) >= self.heartbeat_ms)
+                        || self.changed(topic, &value, &sent.value)
+                }
+            };
+            if send {
+                self.last.insert(
+                    topic.to_string(),
+                    Sent {
+                        value: value.clone(),
+                        at_ms: now_ms,
+                    },
+                );
+                due.push((topic, value));
+            }
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(rpm: &str, running: &str) -> Vec<(&'static str, String)> {
+        vec![
+            ("RPM", rpm.to_string()),
+            ("BAR", "101".to_string()),
+            ("ENG/running", running.to_string()),
+        ]
+    }
+
+    fn topics(params: &[(&str, String)]) -> Vec<String> {
+        params.iter().map(|(t, _)| t.to_string()).collect()
+    }
+
+    #[test]
+    fn test_first_frame_publishes_everything() {
+        let mut filter = ChangeFilter::new(0.0, 10_000);
+        assert_eq!(filter.retain(params("3000", "true"), 0).len(), 3);
+        assert!(filter.retain(params("3000", "true"), 50).is_empty());
+    }
+
+    #[test]
+    fn test_changes_beyond_deadband() {
+        let mut filter = ChangeFilter::new(0.0, 10_000).with_deadband("rpm", 50.0);
+        filter.retain(params("3000", "true"), 0);
+        // Within the deadband of the last published value
+        assert!(filter.retain(params("3040", "true"), 50).is_empty());
+        assert!(filter.retain(params("2960", "true"), 100).is_empty());
+        assert_eq!(
+            topics(&filter.retain(params("3060", "false"), 150)),
+            ["RPM", "ENG/running"]
+        );
+    }
+
+    #[test]
+    fn test_heartbeat_refreshes_unchanged_topics() {
+        let mut filter = ChangeFilter::new(0.0, 1_000);
+        filter.retain(params("3000", "true"), 0);
+        assert!(filter.retain(params("3000", "true"), 999).is_empty());
+        assert_eq!(filter.retain(params("3000", "true"), 1_000).len(), 3);
+
+        let mut never = ChangeFilter::new(0.0, 0);
+        never.retain(params("3000", "true"), 0);
+        assert!(never.retain(params("3000", "true"), 1_000_000).is_empty());
+    }
+
+    #[test]
+    fn test_from_config() {
+        let can_inputs = CanInputs::default();
+        let mut config = AppConfig::default();
+        assert!(
+            ChangeFilter::from_config(&config, &can_inputs)
+                .unwrap()
+                .is_none()
+        );
+        config.publish_on_change = true;
+        config.deadbands.insert("CLT".to_string(), 1.0);
+        let filter = ChangeFilter::from_config(&config, &can_inputs)
+            .unwrap()
+            .unwrap();
+        assert_eq!(filter.deadband("clt"), 1.0);
+        assert_eq!(filter.deadband("RPM"), config.publish_deadband);
+
+        config.publish_on_change = false;
+        config.deadbands.insert("MAP".to_string(), -1.0);
+        assert!(ChangeFilter::from_config(&config, &can_inputs).is_err());
+
+        config.deadbands.remove("MAP");
+        config.deadbands.insert("rmp".to_string(), 25.0);
+        let err = ChangeFilter::from_config(&config, &can_inputs).unwrap_err();
+        assert!(err.to_string().contains("deadbands.rmp"), "{}", err);
+        config.deadbands.remove("rmp");
+        for topic in ["eng/running", "NER/error", "LAMBDA", "QUALITY", "CN03"] {
+            config.deadbands.insert(topic.to_string(), 1.0);
+        }
+        assert!(ChangeFilter::from_config(&config, &can_inputs).is_ok());
+    }
+}
@@ -0,0 +1,799 @@
+//! Realtime packet layouts.
+//!
+//! Describes where each raw [`SpeeduinoData`](crate::ecu_data_parser::SpeeduinoData)
+//! field lives in the primary-serial output-channel block (`getTSLogEntry()` order),
+//! so that a subset of channels can be fetched with the `'r'` command instead of the
+//! whole 130/138-byte packet.
+//!
+//! Byte meanings have shifted between firmware releases, so there is one [`Layout`]
+//! per incompatible revision, keyed by the first release that uses it:
+//!
+//! | Layout | Releases | Differences |
+//! |--------|----------|-------------|
+//! | [`PRIMARY`] | 202305 → | `tpsDOT` / `mapDOT` are u16, PW5–PW8 at bytes 130–137 |
+//! | [`PRIMARY_U8_DOT`] | older | `tpsDOT` / `mapDOT` are single bytes stored ÷ 10, no PW5–PW8 |
+//! | [`SECONDARY`] | any (secondary serial) | 75-byte CAN/dash packet, roughly the first half of the primary one |
+//!
+//! Each field lists its offset, width/signedness ([`Kind`]) and the multiplier that
+//! brings the raw value into the units used by `SpeeduinoData` (those of [`PRIMARY`]).
+
+use std::ops::Range;
+
+/// Gap (in bytes) below which two requested ranges are merged into one `'r'` read.
+/// A round trip costs far more than a few extra bytes on the wire.
+const MERGE_GAP_BYTES: usize = 8;
+
+/// Every raw field of the realtime packet.
+///
+/// The discriminant doubles as the bit index in [`FieldSet`] and as the index
+/// into [`PRIMARY_FIELDS`], which is the only table guaranteed to hold them all.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[repr(u8)]
+pub enum Field {
+    Secl,
+    Status1,
+    Engine,
+    SyncLossCounter,
+    Map,
+    IatRaw,
+    CoolantRaw,
+    BatCorrection,
+    Battery10,
+    O2Primary,
+    EgoCorrection,
+    IatCorrection,
+    WueCorrection,
+    Rpm,
+    TaeAmount,
+    Corrections,
+    Ve1,
+    Ve2,
+    AfrTarget,
+    TpsDot,
+    Advance,
+    Tps,
+    LoopsPerSecond,
+    FreeRam,
+    BoostTarget,
+    BoostDuty,
+    Spark,
+    RpmDot,
+    EthanolPct,
+    FlexCorrection,
+    FlexIgnCorrection,
+    IdleLoad,
+    TestOutputs,
+    O2Secondary,
+    Baro,
+    CanIn0,
+    CanIn1,
+    CanIn2,
+    CanIn3,
+    CanIn4,
+    CanIn5,
+    CanIn6,
+    CanIn7,
+    CanIn8,
+    CanIn9,
+    CanIn10,
+    CanIn11,
+    CanIn12,
+    CanIn13,
+    CanIn14,
+    CanIn15,
+    TpsAdc,
+    NextError,
+    Pw1,
+    Pw2,
+    Pw3,
+    Pw4,
+    Status3,
+    EngineProtectStatus,
+    FuelLoad,
+    IgnLoad,
+    Dwell,
+    ClIdleTarget,
+    MapDot,
+    Vvt1Angle,
+    Vvt1TargetAngle,
+    Vvt1Duty,
+    FlexBoostCorrection,
+    BaroCorrection,
+    VeCurrent,
+    AseValue,
+    Vss,
+    Gear,
+    FuelPressure,
+    OilPressure,
+    WmiPw,
+    Status4,
+    Vvt2Angle,
+    Vvt2TargetAngle,
+    Vvt2Duty,
+    OutputsStatus,
+    FuelTempRaw,
+    FuelTempCorrection,
+    Advance1,
+    Advance2,
+    TsSdStatus,
+    Emap,
+    FanDuty,
+    AirConStatus,
+    ActualDwell,
+    Status5,
+    KnockCount,
+    KnockRetard,
+    Pw5,
+    Pw6,
+    Pw7,
+    Pw8,
+}
+
+/// CAN input fields in channel order (`canin[0]`–`canin[15]`).
+pub const CAN_INPUTS: [Field; 16] = [
+    Field::CanIn0,
+    Field::CanIn1,
+    Field::CanIn2,
+    Field::CanIn3,
+    Field::CanIn4,
+    Field::CanIn5,
+    Field::CanIn6,
+    Field::CanIn7,
+    Field::CanIn8,
+    Field::CanIn9,
+    Field::CanIn10,
+    Field::CanIn11,
+    Field::CanIn12,
+    Field::CanIn13,
+    Field::CanIn14,
+    Field::CanIn15,
+];
+
+/// Wire encoding of a field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    U8,
+    /// Little-endian u16
+    U16,
+    /// Little-endian i16
+    I16,
+}
+
+impl Kind {
+    pub fn width(self) -> usize {
+        match self {
+            Kind::U8 => 1,
+            Kind::U16 | Kind::I16 => 2,
+        }
+    }
+}
+
+/// Position and encoding of a single field inside the packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldDef {
+    pub field: Field,
+    pub offset: usize,
+    pub kind: Kind,
+    /// Multiplier from the raw value to `SpeeduinoData` units
+    pub scale: u16,
+}
+
+impl FieldDef {
+    /// Byte range occupied by this field.
+    pub fn bytes(&self) -> Range<usize> {
+        self.offset..self.offset + self.kind.width()
+    }
+
+    /// Read and scale this field from `data` (which must cover [`Self::bytes`]).
+    pub fn decode(&self, data: &[u8]) -> i32 {
+        let o = self.offset;
+        let raw = match self.kind {
+            Kind::U8 => data[o] as i32,
+            Kind::U16 => u16::from_le_bytes([data[o], data[o + 1]]) as i32,
+            Kind::I16 => i16::from_le_bytes([data[o], data[o + 1]]) as i32,
+        };
+        raw * self.scale as i32
+    }
+
+    /// Inverse of [`Self::decode`]: unscale `value` and write it into `data`,
+    /// saturating at the limits of the wire type.
+    pub fn encode(&self, data: &mut [u8], value: i32) {
+        let o = self.offset;
+        let raw = value / self.scale as i32;
+        match self.kind {
+            Kind::U8 => data[o] = raw.clamp(0, u8::MAX as i32) as u8,
+            Kind::U16 => {
+                let v = raw.clamp(0, u16::MAX as i32) as u16;
+                data[o..o + 2].copy_from_slice(&v.to_le_bytes());
+            }
+            Kind::I16 => {
+                let v = raw.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+                data[o..o + 2].copy_from_slice(&v.to_le_bytes());
+            }
+        }
+    }
+}
+
+const fn def(field: Field, offset: usize, kind: Kind) -> FieldDef {
+    scaled(field, offset, kind, 1)
+}
+
+const fn scaled(field: Field, offset: usize, kind: Kind, scale: u16) -> FieldDef {
+    FieldDef {
+        field,
+        offset,
+        kind,
+        scale,
+    }
+}
+
+/// One revision of the realtime packet.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Layout {
+    /// Name used in logs
+    pub name: &'static str,
+    /// First firmware release (YYYYMM) sending this layout; 0 for the oldest
+    pub since_release: u32,
+    /// Full packet length in bytes
+    pub length: usize,
+    /// Shortest packet accepted; trailing fields beyond it are optional
+    pub min_length: usize,
+    /// Responses may start with a two-byte command echo (`'A'`/`'r'` + sub-command)
+    pub echo_header: bool,
+    pub fields: &'static [FieldDef],
+}
+
+impl Layout {
+    /// Definition of `field` in this layout, `None` if the layout lacks it.
+    pub fn def(&self, field: Field) -> Option<&'static FieldDef> {
+        self.fields.iter().find(|d| d.field == field)
+    }
+
+    /// Strip the command echo some ports put in front of the packet.
+    pub fn payload<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        match data {
+            [b'A' | b'r', 0x30 | 0x31, rest @ ..]
+                if self.echo_header && rest.len() >= self.min_length =>
+            {
+                rest
+            }
+            _ => data,
+        }
+    }
+}
+
+/// Current primary-serial layout (`LOG_ENTRY_SIZE = 138`).  130-byte packets
+/// (speeduino-serial-sim) are accepted without PW5–PW8.
+pub const PRIMARY: Layout = Layout {
+    name: "primary",
+    since_release: 202305,
+    length: 138,
+    min_length: 130,
+    echo_header: false,
+    fields: PRIMARY_FIELDS,
+};
+
+/// Primary-serial layout of releases before `tpsDOT`/`mapDOT` were widened to u16.
+pub const PRIMARY_U8_DOT: Layout = Layout {
+    name: "primary-u8dot",
+    since_release: 0,
+    length: 128,
+    min_length: 128,
+    echo_header: false,
+    fields: PRIMARY_U8_DOT_FIELDS,
+};
+
+/// Secondary-serial `'A'` response (`CAN_PACKET_SIZE = 75`), for bridges that can
+/// only reach the secondary port because a dash or logger occupies USB.  Lacks
+/// everything after the next-error byte (pulse widths, VVT, loads, EMAP, …).
+pub const SECONDARY: Layout = Layout {
+    name: "secondary",
+    since_release: 0,
+    length: 75,
+    min_length: 75,
+    echo_header: true,
+    fields: SECONDARY_FIELDS,
+};
+
+/// Every firmware-keyed layout, newest first.
+pub const LAYOUTS: &[&Layout] = &[&PRIMARY, &PRIMARY_U8_DOT];
+
+/// Layout sent by firmware `release` (e.g. `202402`).
+pub fn layout_for_release(release: u32) -> &'static Layout {
+    LAYOUTS
+        .iter()
+        .find(|l| release >= l.since_release)
+        .copied()
+        .unwrap_or(&PRIMARY_U8_DOT)
+}
+
+/// Resolve the `ecu_layout` setting: `"auto"` follows the firmware `release`
+/// reported by the handshake (current layout when unknown), a release number
+/// such as `"202207"` pins that release's layout and `"secondary"` selects the
+/// secondary-serial packet.  `None` for invalid settings.
+pub fn select_layout(setting: &str, release: Option<u32>) -> Option<&'static Layout> {
+    match setting.to_lowercase().as_str() {
+        "auto" => Some(release.map_or(&PRIMARY, layout_for_release)),
+        "secondary" => Some(&SECONDARY),
+        other => other.parse().ok().map(layout_for_release),
+    }
+}
+
+/// Primary-serial layout, in [`Field`] discriminant order.
+pub const PRIMARY_FIELDS: &[FieldDef] = &[
+    def(Field::Secl, 0, Kind::U8),
+    def(Field::Status1, 1, Kind::U8),
+    def(Field::Engine, 2, Kind::U8),
+    def(Field::SyncLossCounter, 3, Kind::U8),
+    def(Field::Map, 4, Kind::U16),
+    def(Field::IatRaw, 6, Kind::U8),
+    def(Field::CoolantRaw, 7, Kind::U8),
+    def(Field::BatCorrection, 8, Kind::U8),
+    def(Field::Battery10, 9, Kind::U8),
+    def(Field::O2Primary, 10, Kind::U8),
+    def(Field::EgoCorrection, 11, Kind::U8),
+    def(Field::IatCorrection, 12, Kind::U8),
+    def(Field::WueCorrection, 13, Kind::U8),
+    def(Field::Rpm, 14, Kind::U16),
+    def(Field::TaeAmount, 16, Kind::U8),
+    def(Field::Corrections, 17, Kind::U16),
+    def(Field::Ve1, 19, Kind::U8),
+    def(Field::Ve2, 20, Kind::U8),
+    def(Field::AfrTarget, 21, Kind::U8),
+    def(Field::TpsDot, 22, Kind::U16),
+    def(Field::Advance, 24, Kind::U8),
+    def(Field::Tps, 25, Kind::U8),
+    def(Field::LoopsPerSecond, 26, Kind::U16),
+    def(Field::FreeRam, 28, Kind::U16),
+    def(Field::BoostTarget, 30, Kind::U8),
+    def(Field::BoostDuty, 31, Kind::U8),
+    def(Field::Spark, 32, Kind::U8),
+    def(Field::RpmDot, 33, Kind::I16),
+    def(Field::EthanolPct, 35, Kind::U8),
+    def(Field::FlexCorrection, 36, Kind::U8),
+    def(Field::FlexIgnCorrection, 37, Kind::U8),
+    def(Field::IdleLoad, 38, Kind::U8),
+    def(Field::TestOutputs, 39, Kind::U8),
+    def(Field::O2Secondary, 40, Kind::U8),
+    def(Field::Baro, 41, Kind::U8),
+    def(Field::CanIn0, 42, Kind::U16),
+    def(Field::CanIn1, 44, Kind::U16),
+    def(Field::CanIn2, 46, Kind::U16),
+    def(Field::CanIn3, 48, Kind::U16),
+    def(Field::CanIn4, 50, Kind::U16),
+    def(Field::CanIn5, 52, Kind::U16),
+    def(Field::CanIn6, 54, Kind::U16),
+    def(Field::CanIn7, 56, Kind::U16),
+    def(Field::CanIn8, 58, Kind::U16),
+    def(Field::CanIn9, 60, Kind::U16),
+    def(Field::CanIn10, 62, Kind::U16),
+    def(Field::CanIn11, 64, Kind::U16),
+    def(Field::CanIn12, 66, Kind::U16),
+    def(Field::CanIn13, 68, Kind::U16),
+    def(Field::CanIn14, 70, Kind::U16),
+    def(Field::CanIn15, 72, Kind::U16),
+    def(Field::TpsAdc, 74, Kind::U8),
+    def(Field::NextError, 75, Kind::U8),
+    def(Field::Pw1, 76, Kind::U16),
+    def(Field::Pw2, 78, Kind::U16),
+    def(Field::Pw3, 80, Kind::U16),
+    def(Field::Pw4, 82, Kind::U16),
+    def(Field::Status3, 84, Kind::U8),
+    def(Field::EngineProtectStatus, 85, Kind::U8),
+    def(Field::FuelLoad, 86, Kind::U16),
+    def(Field::IgnLoad, 88, Kind::U16),
+    def(Field::Dwell, 90, Kind::U16),
+    def(Field::ClIdleTarget, 92, Kind::U8),
+    def(Field::MapDot, 93, Kind::U16),
+    def(Field::Vvt1Angle, 95, Kind::I16),
+    def(Field::Vvt1TargetAngle, 97, Kind::U8),
+    def(Field::Vvt1Duty, 98, Kind::U8),
+    def(Field::FlexBoostCorrection, 99, Kind::U16),
+    def(Field::BaroCorrection, 101, Kind::U8),
+    def(Field::VeCurrent, 102, Kind::U8),
+    def(Field::AseValue, 103, Kind::U8),
+    def(Field::Vss, 104, Kind::U16),
+    def(Field::Gear, 106, Kind::U8),
+    def(Field::FuelPressure, 107, Kind::U8),
+    def(Field::OilPressure, 108, Kind::U8),
+    def(Field::WmiPw, 109, Kind::U8),
+    def(Field::Status4, 110, Kind::U8),
+    def(Field::Vvt2Angle, 111, Kind::I16),
+    def(Field::Vvt2TargetAngle, 113, Kind::U8),
+    def(Field::Vvt2Duty, 114, Kind::U8),
+    def(Field::OutputsStatus, 115, Kind::U8),
+    def(Field::FuelTempRaw, 116, Kind::U8),
+    def(Field::FuelTempCorrection, 117, Kind::U8),
+    def(Field::Advance1, 118, Kind::U8),
+    def(Field::Advance2, 119, Kind::U8),
+    def(Field::TsSdStatus, 120, Kind::U8),
+    def(Field::Emap, 121, Kind::U16),
+    def(Field::FanDuty, 123, Kind::U8),
+    def(Field::AirConStatus, 124, Kind::U8),
+    def(Field::ActualDwell, 125, Kind::U16),
+    def(Field::Status5, 127, Kind::U8),
+    def(Field::KnockCount, 128, Kind::U8),
+    def(Field::KnockRetard, 129, Kind::U8),
+    def(Field::Pw5, 130, Kind::U16),
+    def(Field::Pw6, 132, Kind::U16),
+    def(Field::Pw7, 134, Kind::U16),
+    def(Field::Pw8, 136, Kind::U16),
+];
+
+/// Pre-202305 primary-serial layout: `tpsDOT` and `mapDOT` are one byte each
+/// (stored ÷ 10), shifting every later field down; PW5–PW8 do not exist yet.
+pub const PRIMARY_U8_DOT_FIELDS: &[FieldDef] = &[
+    def(Field::Secl, 0, Kind::U8),
+    def(Field::Status1, 1, Kind::U8),
+    def(Field::Engine, 2, Kind::U8),
+    def(Field::SyncLossCounter, 3, Kind::U8),
+    def(Field::Map, 4, Kind::U16),
+    def(Field::IatRaw, 6, Kind::U8),
+    def(Field::CoolantRaw, 7, Kind::U8),
+    def(Field::BatCorrection, 8, Kind::U8),
+    def(Field::Battery10, 9, Kind::U8),
+    def(Field::O2Primary, 10, Kind::U8),
+    def(Field::EgoCorrection, 11, Kind::U8),
+    def(Field::IatCorrection, 12, Kind::U8),
+    def(Field::WueCorrection, 13, Kind::U8),
+    def(Field::Rpm, 14, Kind::U16),
+    def(Field::TaeAmount, 16, Kind::U8),
+    def(Field::Corrections, 17, Kind::U16),
+    def(Field::Ve1, 19, Kind::U8),
+    def(Field::Ve2, 20, Kind::U8),
+    def(Field::AfrTarget, 21, Kind::U8),
+    scaled(Field::TpsDot, 22, Kind::U8, 10),
+    def(Field::Advance, 23, Kind::U8),
+    def(Field::Tps, 24, Kind::U8),
+    def(Field::LoopsPerSecond, 25, Kind::U16),
+    def(Field::FreeRam, 27, Kind::U16),
+    def(Field::BoostTarget, 29, Kind::U8),
+    def(Field::BoostDuty, 30, Kind::U8),
+    def(Field::Spark, 31, Kind::U8),
+    def(Field::RpmDot, 32, Kind::I16),
+    def(Field::EthanolPct, 34, Kind::U8),
+    def(Field::FlexCorrection, 35, Kind::U8),
+    def(Field::FlexIgnCorrection, 36, Kind::U8),
+    def(Field::IdleLoad, 37, Kind::U8),
+    def(Field::TestOutputs, 38, Kind::U8),
+    def(Field::O2Secondary, 39, Kind::U8),
+    def(Field::Baro, 40, Kind::U8),
+    def(Field::CanIn0, 41, Kind::U16),
+    def(Field::CanIn1, 43, Kind::U16),
+    def(Field::CanIn2, 45, Kind::U16),
+    def(Field::CanIn3, 47, Kind::U16),
+    def(Field::CanIn4, 49, Kind::U16),
+    def(Field::CanIn5, 51, Kind::U16),
+    def(Field::CanIn6, 53, Kind::U16),
+    def(Field::CanIn7, 55, Kind::U16),
+    def(Field::CanIn8, 57, Kind::U16),
+    def(Field::CanIn9, 59, Kind::U16),
+    def(Field::CanIn10, 61, Kind::U16),
+    def(Field::CanIn11, 63, Kind::U16),
+    def(Field::CanIn12, 65, Kind::U16),
+    def(Field::CanIn13, 67, Kind::U16),
+    def(Field::CanIn14, 69, Kind::U16),
+    def(Field::CanIn15, 71, Kind::U16),
+    def(Field::TpsAdc, 73, Kind::U8),
+    def(Field::NextError, 74, Kind::U8),
+    def(Field::Pw1, 75, Kind::U16),
+    def(Field::Pw2, 77, Kind::U16),
+    def(Field::Pw3, 79, Kind::U16),
+    def(Field::Pw4, 81, Kind::U16),
+    def(Field::Status3, 83, Kind::U8),
+    def(Field::EngineProtectStatus, 84, Kind::U8),
+    def(Field::FuelLoad, 85, Kind::U16),
+    def(Field::IgnLoad, 87, Kind::U16),
+    def(Field::Dwell, 89, Kind::U16),
+    def(Field::ClIdleTarget, 91, Kind::U8),
+    scaled(Field::MapDot, 92, Kind::U8, 10),
+    def(Field::Vvt1Angle, 93, Kind::I16),
+    def(Field::Vvt1TargetAngle, 95, Kind::U8),
+    def(Field::Vvt1Duty, 96, Kind::U8),
+    def(Field::FlexBoostCorrection, 97, Kind::U16),
+    def(Field::BaroCorrection, 99, Kind::U8),
+    def(Field::VeCurrent, 100, Kind::U8),
+    def(Field::AseValue, 101, Kind::U8),
+    def(Field::Vss, 102, Kind::U16),
+    def(Field::Gear, 104, Kind::U8),
+    def(Field::FuelPressure, 105, Kind::U8),
+    def(Field::OilPressure, 106, Kind::U8),
+    def(Field::WmiPw, 107, Kind::U8),
+    def(Field::Status4, 108, Kind::U8),
+    def(Field::Vvt2Angle, 109, Kind::I16),
+    def(Field::Vvt2TargetAngle, 111, Kind::U8),
+    def(Field::Vvt2Duty, 112, Kind::U8),
+    def(Field::OutputsStatus, 113, Kind::U8),
+    def(Field::FuelTempRaw, 114, Kind::U8),
+    def(Field::FuelTempCorrection, 115, Kind::U8),
+    def(Field::Advance1, 116, Kind::U8),
+    def(Field::Advance2, 117, Kind::U8),
+    def(Field::TsSdStatus, 118, Kind::U8),
+    def(Field::Emap, 119, Kind::U16),
+    def(Field::FanDuty, 121, Kind::U8),
+    def(Field::AirConStatus, 122, Kind::U8),
+    def(Field::ActualDwell, 123, Kind::U16),
+    def(Field::Status5, 125, Kind::U8),
+    def(Field::KnockCount, 126, Kind::U8),
+    def(Field::KnockRetard, 127, Kind::U8),
+];
+
+/// Secondary-serial layout.  `corrections` is a single byte here and `tpsDOT` is
+/// stored ÷ 10; byte 74 is reserved.
+pub const SECONDARY_FIELDS: &[FieldDef] = &[
+    def(Field::Secl, 0, Kind::U8),
+    def(Field::Status1, 1, Kind::U8),
+    def(Field::Engine, 2, Kind::U8),
+    def(Field::SyncLossCounter, 3, Kind::U8),
+    def(Field::Map, 4, Kind::U16),
+    def(Field::IatRaw, 6, Kind::U8),
+    def(Field::CoolantRaw, 7, Kind::U8),
+    def(Field::BatCorrection, 8, Kind::U8),
+    def(Field::Battery10, 9, Kind::U8),
+    def(Field::O2Primary, 10, Kind::U8),
+    def(Field::EgoCorrection, 11, Kind::U8),
+    def(Field::IatCorrection, 12, Kind::U8),
+    def(Field::WueCorrection, 13, Kind::U8),
+    def(Field::Rpm, 14, Kind::U16),
+    def(Field::TaeAmount, 16, Kind::U8),
+    def(Field::Corrections, 17, Kind::U8),
+    def(Field::Ve1, 18, Kind::U8),
+    def(Field::Ve2, 19, Kind::U8),
+    def(Field::AfrTarget, 20, Kind::U8),
+    scaled(Field::TpsDot, 21, Kind::U8, 10),
+    def(Field::Advance, 22, Kind::U8),
+    def(Field::Tps, 23, Kind::U8),
+    def(Field::LoopsPerSecond, 24, Kind::U16),
+    def(Field::FreeRam, 26, Kind::U16),
+    def(Field::BoostTarget, 28, Kind::U8),
+    def(Field::BoostDuty, 29, Kind::U8),
+    def(Field::Spark, 30, Kind::U8),
+    def(Field::RpmDot, 31, Kind::I16),
+    def(Field::EthanolPct, 33, Kind::U8),
+    def(Field::FlexCorrection, 34, Kind::U8),
+    def(Field::FlexIgnCorrection, 35, Kind::U8),
+    def(Field::IdleLoad, 36, Kind::U8),
+    def(Field::TestOutputs, 37, Kind::U8),
+    def(Field::O2Secondary, 38, Kind::U8),
+    def(Field::Baro, 39, Kind::U8),
+    def(Field::CanIn0, 40, Kind::U16),
+    def(Field::CanIn1, 42, Kind::U16),
+    def(Field::CanIn2, 44, Kind::U16),
+    def(Field::CanIn3, 46, Kind::U16),
+    def(Field::CanIn4, 48, Kind::U16),
+    def(Field::CanIn5, 50, Kind::U16),
+    def(Field::CanIn6, 52, Kind::U16),
+    def(Field::CanIn7, 54, Kind::U16),
+    def(Field::CanIn8, 56, Kind::U16),
+    def(Field::CanIn9, 58, Kind::U16),
+    def(Field::CanIn10, 60, Kind::U16),
+    def(Field::CanIn11, 62, Kind::U16),
+    def(Field::CanIn12, 64, Kind::U16),
+    def(Field::CanIn13, 66, Kind::U16),
+    def(Field::CanIn14, 68, Kind::U16),
+    def(Field::CanIn15, 70, Kind::U16),
+    def(Field::TpsAdc, 72, Kind::U8),
+    def(Field::NextError, 73, Kind::U8),
+];
+
+/// Compact set of [`Field`]s (one bit per field).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FieldSet(u128);
+
+impl FieldSet {
+    pub const EMPTY: FieldSet = FieldSet(0);
+
+    /// Every field of the layout.
+    pub fn all() -> Self {
+        FieldSet((1u128 << PRIMARY_FIELDS.len()) - 1)
+    }
+
+    pub fn insert(&mut self, field: Field) {
+        self.0 |= 1 << field as u8;
+    }
+
+    pub fn contains(&self, field: Field) -> bool {
+        self.0 & (1 << field as u8) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn union(&self, other: FieldSet) -> Self {
+        FieldSet(self.0 | other.0)
+    }
+
+    /// Fields of the layout that are *not* in this set.
+    pub fn complement(&self) -> Self {
+        FieldSet(Self::all().0 & !self.0)
+    }
+
+    /// Iterate the contained fields in layout order.
+    pub fn iter(&self) -> impl Iterator<Item = Field> + '_ {
+        PRIMARY_FIELDS
+            .iter()
+            .map(|d| d.field)
+            .filter(|f| self.contains(*f))
+    }
+}
+
+impl FromIterator<Field> for FieldSet {
+    fn from_iter<I: IntoIterator<Item = Field>>(iter: I) -> Self {
+        let mut set = FieldSet::EMPTY;
+        for field in iter {
+            set.insert(field);
+        }
+        set
+    }
+}
+
+/// Minimal list of byte ranges covering `fields` in `layout`, sorted by offset.
+/// Fields the layout lacks are skipped.
+///
+/// Ranges closer than [`MERGE_GAP_BYTES`] are merged so the ECU is asked for a
+/// few unused bytes rather than an extra round trip.
+pub fn byte_ranges(layout: &Layout, fields: FieldSet) -> Vec<Range<usize>> {
+    let mut spans: Vec<Range<usize>> = fields
+        .iter()
+        .filter_map(|f| layout.def(f))
+        .map(|d| d.bytes())
+        .collect();
+    spans.sort_by_key(|r| r.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::new();
+    for span in spans {
+        match merged.last_mut() {
+            Some(last) if span.start <= last.end + MERGE_GAP_BYTES => {
+                last.end = last.end.max(span.end);
+            }
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_primary_fields_indexed_by_discriminant() {
+        for (i, d) in PRIMARY_FIELDS.iter().enumerate() {
+            assert_eq!(d.field as usize, i, "{:?} out of order", d.field);
+        }
+    }
+
+    #[test]
+    fn test_primary_fields_contiguous_138_bytes() {
+        let mut next = 0;
+        for d in PRIMARY_FIELDS {
+            assert_eq!(d.offset, next, "gap or overlap before {:?}", d.field);
+            next = d.bytes().end;
+        }
+        assert_eq!(next, 138);
+    }
+
+    #[test]
+    fn test_u8_dot_layout_contiguous_128_bytes() {
+        let mut next = 0;
+        for d in PRIMARY_U8_DOT_FIELDS {
+            assert_eq!(d.offset, next, "gap or overlap before {:?}", d.field);
+            next = d.bytes().end;
+        }
+        assert_eq!(next, PRIMARY_U8_DOT.length);
+        assert!(PRIMARY_U8_DOT.def(Field::Pw5).is_none());
+    }
+
+    #[test]
+    fn test_secondary_layout_fits_75_bytes() {
+        let mut next = 0;
+        for d in SECONDARY_FIELDS {
+            assert_eq!(d.offset, next, "gap or overlap before {:?}", d.field);
+            next = d.bytes().end;
+        }
+        assert_eq!(next, 74); // byte 74 reserved
+        assert!(SECONDARY.def(Field::Pw1).is_none());
+    }
+
+    #[test]
+    fn test_payload_strips_command_echo() {
+        let mut echoed = vec![b'A', 0x30];
+        echoed.extend_from_slice(&[7u8; 75]);
+        assert_eq!(SECONDARY.payload(&echoed), &[7u8; 75][..]);
+        assert_eq!(SECONDARY.payload(&[7u8; 75]), &[7u8; 75][..]);
+        // Primary packets never carry an echo, even if byte 0 happens to be 'A'
+        assert_eq!(PRIMARY.payload(&echoed).len(), 77);
+    }
+
+    #[test]
+    fn test_layout_for_release() {
+        assert_eq!(layout_for_release(202402), &PRIMARY);
+        assert_eq!(layout_for_release(202305), &PRIMARY);
+        assert_eq!(layout_for_release(202207), &PRIMARY_U8_DOT);
+        assert_eq!(layout_for_release(0), &PRIMARY_U8_DOT);
+    }
+
+    #[test]
+    fn test_select_layout() {
+        assert_eq!(select_layout("auto", None), Some(&PRIMARY));
+        assert_eq!(select_layout("AUTO", Some(202202)), Some(&PRIMARY_U8_DOT));
+        assert_eq!(select_layout("202207", Some(202402)), Some(&PRIMARY_U8_DOT));
+        assert_eq!(select_layout("Secondary", Some(202402)), Some(&SECONDARY));
+        assert_eq!(select_layout("latest", None), None);
+    }
+
+    #[test]
+    fn test_decode_scales_and_signs() {
+        let data = [0x9C, 0xFF, 12];
+        assert_eq!(def(Field::RpmDot, 0, Kind::I16).decode(&data), -100);
+        assert_eq!(def(Field::Rpm, 0, Kind::U16).decode(&data), 0xFF9C);
+        assert_eq!(scaled(Field::TpsDot, 2, Kind::U8, 10).decode(&data), 120);
+    }
+
+    #[test]
+    fn test_encode_round_trips_and_saturates() {
+        let mut data = [0u8; 3];
+        def(Field::RpmDot, 0, Kind::I16).encode(&mut data, -100);
+        assert_eq!(&data[..2], &[0x9C, 0xFF]);
+        scaled(Field::TpsDot, 2, Kind::U8, 10).encode(&mut data, 120);
+        assert_eq!(data[2], 12);
+
+        for d in PRIMARY_FIELDS {
+            let mut packet = [0u8; 138];
+            d.encode(&mut packet, 100);
+            assert_eq!(d.decode(&packet), 100, "{:?}", d.field);
+        }
+
+        let mut data = [0u8; 2];
+        def(Field::Rpm, 0, Kind::U16).encode(&mut data, 70_000);
+        assert_eq!(data, [0xFF, 0xFF]);
+        def(Field::Tps, 0, Kind::U8).encode(&mut data, -5);
+        assert_eq!(data[0], 0);
+    }
+
+    #[test]
+    fn test_byte_ranges_follow_layout() {
+        let set: FieldSet = [Field::Vss].into_iter().collect();
+        assert_eq!(byte_ranges(&PRIMARY_U8_DOT, set), vec![102..104]);
+        let pw8: FieldSet = [Field::Pw8].into_iter().collect();
+        assert!(byte_ranges(&PRIMARY_U8_DOT, pw8).is_empty());
+    }
+
+    #[test]
+    fn test_field_set_basics() {
+        let mut set = FieldSet::EMPTY;
+        assert!(set.is_empty());
+        set.insert(Field::Rpm);
+        set.insert(Field::Pw8);
+        assert!(set.contains(Field::Rpm));
+        assert!(set.contains(Field::Pw8));
+        assert!(!set.contains(Field::Map));
+        assert_eq!(set.iter().collect::<Vec<_>>(), vec![Field::Rpm, Field::Pw8]);
+
+        let rest = set.complement();
+        assert!(!rest.contains(Field::Rpm));
+        assert!(rest.contains(Field::Map));
+        assert_eq!(FieldSet::all().complement(), FieldSet::EMPTY);
+    }
+
+    #[test]
+    fn test_byte_ranges_merges_nearby_fields() {
+        // MAP (4–5), CLT (7), RPM (14–15) sit close together → one read
+        let set: FieldSet = [Field::Map, Field::CoolantRaw, Field::Rpm]
+            .into_iter()
+            .collect();
+        assert_eq!(byte_ranges(&PRIMARY, set), vec![4..16]);
+    }
+
+    #[test]
+    fn test_byte_ranges_keeps_distant_fields_apart() {
+        let set: FieldSet = [Field::Rpm, Field::Vss].into_iter().collect();
+        assert_eq!(byte_ranges(&PRIMARY, set), vec![14..16, 104..106]);
+    }
+
+    #[test]
+    fn test_byte_ranges_empty() {
+        assert!(byte_ranges(&PRIMARY, FieldSet::EMPTY).is_empty());
+    }
+}
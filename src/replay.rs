@@ -0,0 +1,277 @@
+//! Capture playback posing as an ECU.
+//!
+//! [`ReplayStream`] answers each realtime command (`'A'`) with the next record
+//! of a capture file (see [`crate::capture`]), so replayed packets travel the
+//! same handler → parser → MQTT → TUI path as live ones.  Responses are held
+//! back until their recorded time, scaled by the configured speed.  Once a
+//! response has been read the stream reports end of data until the next
+//! request, so the handler never waits for the line to go idle.
+
+use crate::capture::{CaptureReader, CaptureRecord};
+use crate::ecu_protocol::CMD_REALTIME;
+use crate::errors::Result;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, BufReader};
+use std::pin::Pin;
+use std::task::{Context, Poll, ready};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep, sleep_until};
+use tracing::{info, warn};
+
+/// Playback pace (`replay_speed` config value).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// Recorded timing divided by this factor (1.0 = real time).
+    Scaled(f64),
+    /// No pacing — each request is answered immediately.
+    Fast,
+}
+
+impl ReplaySpeed {
+    /// Parse `"realtime"`, `"fast"` or a multiplier such as `"4"` / `"0.5x"`.
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "realtime" => Some(ReplaySpeed::Scaled(1.0)),
+            "fast" => Some(ReplaySpeed::Fast),
+            other => other
+                .trim_end_matches('x')
+                .parse::<f64>()
+                .ok()
+                .filter(|f| f.is_finite() && *f > 0.0)
+                .map(ReplaySpeed::Scaled),
+        }
+    }
+}
+
+/// A capture file played back as if it were an ECU.
+pub struct ReplayStream {
+    path: String,
+    speed: ReplaySpeed,
+    looping: bool,
+    reader: CaptureReader<BufReader<File>>,
+    /// Record that answers the next request (read ahead so the end is known early)
+    next: Option<CaptureRecord>,
+    /// Capture timestamp and local instant the pacing is anchored to
+    origin: Option<(u64, Instant)>,
+    /// Response being handed to the reader and how much of it has gone
+    response: Vec<u8>,
+    sent: usize,
+    /// Holds the response back until its recorded time
+    delay: Option<Pin<Box<Sleep>>>,
+}
+
+impl ReplayStream {
+    /// Open `path` for playback.
+    pub fn open(path: &str, speed: ReplaySpeed, looping: bool) -> Result<Self> {
+        let mut reader = CaptureReader::open(path)?;
+        let next = reader.next_record()?;
+        info!("Replaying capture {} ({:?})", path, speed);
+        Ok(Self {
+            path: path.to_string(),
+            speed,
+            looping,
+            reader,
+            next,
+            origin: None,
+            response: Vec::new(),
+            sent: 0,
+            delay: None,
+        })
+    }
+
+    /// True once every record has been served and read.
+    pub fn is_finished(&self) -> bool {
+        self.next.is_none() && self.sent >= self.response.len()
+    }
+
+    /// Queue the next record as the response to a realtime request.
+    fn serve_next(&mut self) {
+        let Some(record) = self.next.take() else {
+            return;
+        };
+
+        self.delay = match self.speed {
+            ReplaySpeed::Fast => None,
+            ReplaySpeed::Scaled(factor) => {
+                let (start_ts, start) = *self
+                    .origin
+                    .get_or_insert((record.timestamp_us, Instant::now()));
+                let offset_us = record.timestamp_us.saturating_sub(start_ts) as f64 / factor;
+                Some(Box::pin(sleep_until(
+                    start + Duration::from_micros(offset_us as u64),
+                )))
+            }
+        };
+        self.response = record.data;
+        self.sent = 0;
+
+        self.next = self.read_ahead();
+    }
+
+    /// Following record, starting over at the end when looping.  A damaged
+    /// record ends the playback instead of feeding garbage to the parser.
+    fn read_ahead(&mut self) -> Option<CaptureRecord> {
+        let result = match self.reader.next_record() {
+            Ok(None) if self.looping => {
+                info!("End of capture reached, restarting {}", self.path);
+                self.origin = None;
+                CaptureReader::open(&self.path).and_then(|mut r| {
+                    let first = r.next_record();
+                    self.reader = r;
+                    first
+                })
+            }
+            other => other,
+        };
+        result.unwrap_or_else(|e| {
+            warn!("Stopping replay of {}: {}", self.path, e);
+            None
+        })
+    }
+}
+
+impl AsyncRead for ReplayStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if let Some(delay) = this.delay.as_mut() {
+            ready!(delay.as_mut().poll(cx));
+            this.delay = None;
+        }
+        if this.sent >= this.response.len() {
+            // End of the response: a zero-length read, not a wait
+            return Poll::Ready(Ok(()));
+        }
+        let n = buf.remaining().min(this.response.len() - this.sent);
+        buf.put_slice(&this.response[this.sent..this.sent + n]);
+        this.sent += n;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for ReplayStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        for _ in buf.iter().filter(|&&b| b == CMD_REALTIME) {
+            this.serve_next();
+        }
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::capture::tests::capture_bytes;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn write_capture(records: &[(u64, &[u8])]) -> tempfile::NamedTempFile {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), capture_bytes(records)).unwrap();
+        file
+    }
+
+    async fn request(stream: &mut ReplayStream) -> Vec<u8> {
+        stream.write_all(&[CMD_REALTIME]).await.unwrap();
+        let mut buf = vec![0u8; 256];
+        let n = stream.read(&mut buf).await.unwrap();
+        buf.truncate(n);
+        buf
+    }
+
+    #[test]
+    fn test_replay_speed_from_config() {
+        assert_eq!(
+            ReplaySpeed::from_config("realtime"),
+            Some(ReplaySpeed::Scaled(1.0))
+        );
+        assert_eq!(ReplaySpeed::from_config("FAST"), Some(ReplaySpeed::Fast));
+        assert_eq!(
+            ReplaySpeed::from_config("4x"),
+            Some(ReplaySpeed::Scaled(4.0))
+        );
+        assert_eq!(
+            ReplaySpeed::from_config("0.5"),
+            Some(ReplaySpeed::Scaled(0.5))
+        );
+        assert_eq!(ReplaySpeed::from_config("0"), None);
+        assert_eq!(ReplaySpeed::from_config("-2"), None);
+        assert_eq!(ReplaySpeed::from_config("warp"), None);
+    }
+
+    #[tokio::test]
+    async fn test_answers_requests_in_order_then_finishes() {
+        let file = write_capture(&[(0, &[1, 2, 3]), (20_000, &[4, 5, 6])]);
+        let path = file.path().to_str().unwrap();
+        let mut stream = ReplayStream::open(path, ReplaySpeed::Fast, false).unwrap();
+
+        assert_eq!(request(&mut stream).await, vec![1, 2, 3]);
+        assert!(!stream.is_finished());
+        assert_eq!(request(&mut stream).await, vec![4, 5, 6]);
+        assert!(stream.is_finished());
+    }
+
+    #[tokio::test]
+    async fn test_looping_restarts_capture() {
+        let file = write_capture(&[(0, &[1]), (20_000, &[2])]);
+        let path = file.path().to_str().unwrap();
+        let mut stream = ReplayStream::open(path, ReplaySpeed::Fast, true).unwrap();
+
+        for expected in [1, 2, 1, 2, 1] {
+            assert_eq!(request(&mut stream).await, vec![expected]);
+        }
+        assert!(!stream.is_finished());
+    }
+
+    #[tokio::test]
+    async fn test_paces_responses_by_recorded_time() {
+        let file = write_capture(&[(0, &[1]), (100_000, &[2]), (300_000, &[3])]);
+        let path = file.path().to_str().unwrap();
+        let mut stream = ReplayStream::open(path, ReplaySpeed::Scaled(2.0), false).unwrap();
+
+        let start = Instant::now();
+        assert_eq!(request(&mut stream).await, vec![1]);
+        assert!(start.elapsed() < Duration::from_millis(40));
+        assert_eq!(request(&mut stream).await, vec![2]);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(request(&mut stream).await, vec![3]);
+        assert!(start.elapsed() >= Duration::from_millis(150));
+    }
+
+    #[tokio::test]
+    async fn test_response_ends_without_waiting() {
+        let file = write_capture(&[(0, &[1, 2, 3])]);
+        let path = file.path().to_str().unwrap();
+        let mut stream = ReplayStream::open(path, ReplaySpeed::Fast, false).unwrap();
+
+        assert_eq!(request(&mut stream).await, vec![1, 2, 3]);
+        let mut buf = [0u8; 8];
+        let n = tokio::time::timeout(Duration::from_millis(10), stream.read(&mut buf))
+            .await
+            .expect("end of response is reported at once")
+            .unwrap();
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    fn test_open_missing_file_fails() {
+        assert!(ReplayStream::open("/nonexistent/capture.bin", ReplaySpeed::Fast, false).is_err());
+    }
+}
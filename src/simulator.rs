@@ -0,0 +1,574 @@
+//! Speeduino ECU simulator.
+//!
+//! Generates realtime packets in the [`PRIMARY`] layout and answers the
+//! commands the bridge sends, in either protocol:
+//!
+//! | Command | Legacy | CRC (msEnvelope) |
+//! |---------|--------|------------------|
+//! | `'A'` | 138-byte packet | framed packet |
+//! | `'Q'` / `'S'` | signature / version text | framed text |
+//! | `'r'` | `'r'` + 6 argument bytes → raw byte range, zero past the packet | framed byte range |
+//!
+//! As in the firmware, a request that does not start with a known command byte
+//! is taken as the length header of an msEnvelope frame.
+//!
+//! The engine follows a [`Scenario`] as a pure function of the time since
+//! start, so tests can ask for the packet at any instant.
+
+use crate::ecu_protocol::{
+    self, CMD_READ, CMD_REALTIME, CMD_SIGNATURE, CMD_VERSION, FRAME_CRC_BYTES, MAX_FRAME_PAYLOAD,
+    READ_OUTPUT_CHANNELS, SERIAL_RC_CRC_ERR, SERIAL_RC_OK, SERIAL_RC_RANGE_ERR, SERIAL_RC_UKWN_ERR,
+};
+use crate::packet_layout::{Field, PRIMARY};
+use crate::status_flags::{
+    ENGINE_ACC, ENGINE_CRANK, ENGINE_RUN, SPARK_SYNC, STATUS1_DFCO, STATUS4_FAN,
+};
+use std::io;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::time::Instant;
+
+/// Signature answered to `'Q'`.
+pub const SIM_SIGNATURE: &str = "speeduino 202402";
+/// Product string answered to `'S'`.
+pub const SIM_VERSION: &str = "Speeduino 2024.02 (simulated)";
+
+/// Cranking phase at the start of every scenario.
+const CRANK_SECS: f64 = 1.0;
+/// Length of one idle → pull → overrun cycle of the WOT scenario.
+const PULL_PERIOD_SECS: f64 = 12.0;
+/// Interval between sync losses, and how long each lasts.
+const SYNC_LOSS_PERIOD_SECS: f64 = 4.0;
+const SYNC_LOSS_SECS: f64 = 0.5;
+
+/// Engine behaviour to simulate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scenario {
+    /// Warm engine idling
+    Idle,
+    /// Repeating wide-open-throttle pulls followed by overrun fuel cut
+    WotPull,
+    /// Idle with a coolant temperature that keeps climbing
+    Overheat,
+    /// Idle with periodic loss of crank/cam sync
+    SyncLoss,
+    /// Idle, but the link is dropped after a while on every connection
+    Disconnect,
+}
+
+impl Scenario {
+    pub const ALL: [Scenario; 5] = [
+        Scenario::Idle,
+        Scenario::WotPull,
+        Scenario::Overheat,
+        Scenario::SyncLoss,
+        Scenario::Disconnect,
+    ];
+
+    /// Parse a scenario name (`idle`, `wot`, `overheat`, `sync-loss`, `disconnect`).
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|s| s.name().eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scenario::Idle => "idle",
+            Scenario::WotPull => "wot",
+            Scenario::Overheat => "overheat",
+            Scenario::SyncLoss => "sync-loss",
+            Scenario::Disconnect => "disconnect",
+        }
+    }
+}
+
+/// Instantaneous engine state in `SpeeduinoData` units.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineState {
+    pub secl: u8,
+    pub status1: u8,
+    pub engine: u8,
+    pub spark: u8,
+    pub status4: u8,
+    pub sync_loss_counter: u8,
+    pub rpm: u16,
+    pub map: u16,
+    pub tps: u8,
+    pub coolant_c: i16,
+    pub iat_c: i16,
+    /// AFR × 10
+    pub afr: u8,
+    /// Target AFR × 10
+    pub afr_target: u8,
+    pub advance: u8,
+    pub vss: u16,
+    pub gear: u8,
+}
+
+impl EngineState {
+    /// Warm idle (cranking during the first second).
+    fn idle(secs: f64) -> Self {
+        let wobble = (secs * 3.0).sin();
+        let mut s = Self {
+            secl: secs as u64 as u8,
+            status1: 0,
+            engine: ENGINE_RUN,
+            spark: SPARK_SYNC,
+            status4: 0,
+            sync_loss_counter: 0,
+            rpm: (850.0 + 15.0 * wobble) as u16,
+            map: (35.0 + wobble) as u16,
+            tps: 0,
+            coolant_c: 85,
+            iat_c: 30,
+            afr: (147.0 + 2.0 * wobble) as u8,
+            afr_target: 147,
+            advance: 14,
+            vss: 0,
+            gear: 0,
+        };
+        if secs < CRANK_SECS {
+            s.engine = ENGINE_CRANK;
+            s.rpm = 220;
+            s.map = 95;
+            s.afr = 120;
+            s.advance = 5;
+        }
+        s
+    }
+}
+
+/// A simulated Speeduino ECU.
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    scenario: Scenario,
+    started: Instant,
+    /// Connection lifetime in the [`Scenario::Disconnect`] scenario
+    disconnect_after: Duration,
+}
+
+/// Why [`Simulator::serve`] stopped answering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionEnd {
+    /// The client closed the connection
+    ClientClosed,
+    /// The scenario dropped the link
+    Dropped,
+}
+
+impl Simulator {
+    pub fn new(scenario: Scenario) -> Self {
+        Self {
+            scenario,
+            started: Instant::now(),
+            disconnect_after: Duration::from_secs(10),
+        }
+    }
+
+    /// How long each connection lasts in the [`Scenario::Disconnect`] scenario.
+    pub fn with_disconnect_after(mut self, after: Duration) -> Self {
+        self.disconnect_after = after;
+        self
+    }
+
+    pub fn scenario(&self) -> Scenario {
+        self.scenario
+    }
+
+    /// Engine state `t` after start.
+    pub fn state_at(&self, t: Duration) -> EngineState {
+        let secs = t.as_secs_f64();
+        let mut s = EngineState::idle(secs);
+        if secs < CRANK_SECS {
+            return s;
+        }
+
+        match self.scenario {
+            Scenario::Idle | Scenario::Disconnect => {}
+            Scenario::WotPull => {
+                let phase = secs % PULL_PERIOD_SECS;
+                if (3.0..9.0).contains(&phase) {
+                    let p = (phase - 3.0) / 6.0;
+                    s.rpm = (2500.0 + 4500.0 * p) as u16;
+                    s.map = 98;
+                    s.tps = 100;
+                    s.afr = 125;
+                    s.afr_target = 125;
+                    s.advance = 26;
+                    s.gear = 3;
+                    s.vss = s.rpm / 45;
+                    if phase < 3.5 {
+                        s.engine |= ENGINE_ACC;
+                    }
+                } else if (9.0..11.0).contains(&phase) {
+                    let p = (phase - 9.0) / 2.0;
+                    s.rpm = (7000.0 - 6000.0 * p) as u16;
+                    s.map = 22;
+                    s.afr = 190;
+                    s.advance = 30;
+                    s.gear = 3;
+                    s.vss = s.rpm / 45;
+                    s.status1 |= STATUS1_DFCO;
+                }
+            }
+            Scenario::Overheat => {
+                s.coolant_c = (85.0 + 0.5 * (secs - CRANK_SECS)).min(130.0) as i16;
+                if s.coolant_c >= 95 {
+                    s.status4 |= STATUS4_FAN;
+                }
+            }
+            Scenario::SyncLoss => {
+                let since = secs - CRANK_SECS;
+                let phase = since % SYNC_LOSS_PERIOD_SECS;
+                let lost = phase >= SYNC_LOSS_PERIOD_SECS - SYNC_LOSS_SECS;
+                let losses = (since / SYNC_LOSS_PERIOD_SECS) as u64 + lost as u64;
+                s.sync_loss_counter = losses as u8;
+                if lost {
+                    s.spark &= !SPARK_SYNC;
+                    s.engine = 0;
+                    s.rpm = 0;
+                }
+            }
+        }
+        s
+    }
+
+    /// Full realtime packet `t` after start.
+    pub fn packet_at(&self, t: Duration) -> Vec<u8> {
+        let s = self.state_at(t);
+        let ve = 40 + s.map as i32 / 3;
+        let pw = 5 + s.map as i32 * 8 / 10; // 0.1 ms
+        let fields = [
+            (Field::Secl, s.secl as i32),
+            (Field::Status1, s.status1 as i32),
+            (Field::Engine, s.engine as i32),
+            (Field::SyncLossCounter, s.sync_loss_counter as i32),
+            (Field::Map, s.map as i32),
+            (Field::IatRaw, s.iat_c as i32 + 40),
+            (Field::CoolantRaw, s.coolant_c as i32 + 40),
+            (Field::BatCorrection, 100),
+            (Field::Battery10, 141),
+            (Field::O2Primary, s.afr as i32),
+            (Field::EgoCorrection, 100),
+            (Field::IatCorrection, 100),
+            (Field::WueCorrection, 100),
+            (Field::Rpm, s.rpm as i32),
+            (Field::TaeAmount, 100),
+            (Field::Corrections, 100),
+            (Field::Ve1, ve),
+            (Field::Ve2, ve),
+            (Field::AfrTarget, s.afr_target as i32),
+            (Field::Advance, s.advance as i32),
+            (Field::Tps, s.tps as i32),
+            (Field::LoopsPerSecond, 1000),
+            (Field::FreeRam, 1800),
+            (Field::Spark, s.spark as i32),
+            (Field::Baro, 101),
+            (Field::TpsAdc, s.tps as i32 * 2),
+            (Field::Pw1, pw),
+            (Field::Pw2, pw),
+            (Field::Pw3, pw),
+            (Field::Pw4, pw),
+            (Field::FuelLoad, s.map as i32),
+            (Field::IgnLoad, s.map as i32),
+            (Field::Dwell, 35),
+            (Field::BaroCorrection, 100),
+            (Field::VeCurrent, ve),
+            (Field::Vss, s.vss as i32),
+            (Field::Gear, s.gear as i32),
+            (Field::Status4, s.status4 as i32),
+            (Field::FuelTempRaw, 70),
+            (Field::FuelTempCorrection, 100),
+            (Field::Advance1, s.advance as i32),
+            (Field::ActualDwell, 35),
+        ];
+
+        let mut packet = vec![0u8; PRIMARY.length];
+        for (field, value) in fields {
+            PRIMARY
+                .def(field)
+                .expect("primary layout holds every field")
+                .encode(&mut packet, value);
+        }
+        packet
+    }
+
+    /// Current realtime packet.
+    pub fn packet(&self) -> Vec<u8> {
+        self.packet_at(self.started.elapsed())
+    }
+
+    /// Answer requests on `stream` until the client goes away or, in the
+    /// [`Scenario::Disconnect`] scenario, the connection has lasted long enough.
+    pub async fn serve<S>(&self, stream: &mut S) -> io::Result<SessionEnd>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let session = Instant::now();
+        let mut cmd = [0u8; 1];
+        loop {
+            match stream.read_exact(&mut cmd).await {
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    return Ok(SessionEnd::ClientClosed);
+                }
+                Err(e) => return Err(e),
+            }
+            if self.scenario == Scenario::Disconnect && session.elapsed() >= self.disconnect_after {
+                return Ok(SessionEnd::Dropped);
+            }
+
+            let reply = match cmd[0] {
+                CMD_REALTIME => self.packet(),
+                CMD_SIGNATURE => SIM_SIGNATURE.as_bytes().to_vec(),
+                CMD_VERSION => SIM_VERSION.as_bytes().to_vec(),
+                CMD_READ => {
+                    let mut args = [0u8; 6];
+                    stream.read_exact(&mut args).await?;
+                    self.legacy_read_range(&args[1..])
+                }
+                len_hi => match self.framed_request(stream, len_hi).await? {
+                    Some(reply) => reply,
+                    None => continue,
+                },
+            };
+            stream.write_all(&reply).await?;
+            stream.flush().await?;
+        }
+    }
+
+    /// Read the rest of an msEnvelope request and build the framed reply;
+    /// `None` for a length header that cannot be valid (line noise).
+    async fn framed_request<S>(&self, stream: &mut S, len_hi: u8) -> io::Result<Option<Vec<u8>>>
+    where
+        S: AsyncRead + Unpin,
+    {
+        let mut len_lo = [0u8; 1];
+        stream.read_exact(&mut len_lo).await?;
+        let len = u16::from_be_bytes([len_hi, len_lo[0]]) as usize;
+        if len == 0 || len > MAX_FRAME_PAYLOAD {
+            return Ok(None);
+        }
+
+        let mut body = vec![0u8; len + FRAME_CRC_BYTES];
+        stream.read_exact(&mut body).await?;
+        let (payload, trailer) = body.split_at(len);
+        if u32::from_be_bytes([trailer[0], trailer[1], trailer[2], trailer[3]])
+            != ecu_protocol::crc32(payload)
+        {
+            return Ok(Some(ecu_protocol::frame(&[SERIAL_RC_CRC_ERR])));
+        }
+
+        let data = match payload {
+            [CMD_REALTIME, ..] => Ok(self.packet()),
+            [CMD_SIGNATURE, ..] => Ok(SIM_SIGNATURE.as_bytes().to_vec()),
+            [CMD_VERSION, ..] => Ok(SIM_VERSION.as_bytes().to_vec()),
+            [CMD_READ, _can_id, args @ ..] => self.read_range(args).ok_or(SERIAL_RC_RANGE_ERR),
+            _ => Err(SERIAL_RC_UKWN_ERR),
+        };
+        let body = match data {
+            Ok(data) => [&[SERIAL_RC_OK][..], &data].concat(),
+            Err(code) => vec![code],
+        };
+        Ok(Some(ecu_protocol::frame(&body)))
+    }
+
+    /// Bytes for a legacy `'r'` request.  Like the firmware, which reads each
+    /// byte through `getTSLogEntry`, every requested byte is sent and those
+    /// beyond the packet are zero, so the client is never left waiting.
+    fn legacy_read_range(&self, args: &[u8]) -> Vec<u8> {
+        let [sub, off_lo, off_hi, len_lo, len_hi, ..] = *args else {
+            return Vec::new();
+        };
+        if sub != READ_OUTPUT_CHANNELS {
+            return Vec::new();
+        }
+        let offset = u16::from_le_bytes([off_lo, off_hi]) as usize;
+        let length = u16::from_le_bytes([len_lo, len_hi]) as usize;
+        let packet = self.packet();
+        (offset..offset + length)
+            .map(|i| packet.get(i).copied().unwrap_or(0))
+            .collect()
+    }
+
+    /// Bytes for an `'r'` request (`[sub, offset_lo, offset_hi, length_lo, length_hi]`).
+    fn read_range(&self, args: &[u8]) -> Option<Vec<u8>> {
+        let [sub, off_lo, off_hi, len_lo, len_hi, ..] = *args else {
+            return None;
+        };
+        if sub != READ_OUTPUT_CHANNELS {
+            return None;
+        }
+        let offset = u16::from_le_bytes([off_lo, off_hi]) as usize;
+        let length = u16::from_le_bytes([len_lo, len_hi]) as usize;
+        self.packet()
+            .get(offset..offset + length)
+            .map(<[u8]>::to_vec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecu_data_parser::get_parsed_data;
+    use crate::ecu_protocol::{decode_response, frame, read_request};
+
+    fn secs(s: f64) -> Duration {
+        Duration::from_secs_f64(s)
+    }
+
+    #[test]
+    fn test_scenario_names_round_trip() {
+        for scenario in Scenario::ALL {
+            assert_eq!(Scenario::from_name(scenario.name()), Some(scenario));
+        }
+        assert_eq!(Scenario::from_name("WOT"), Some(Scenario::WotPull));
+        assert_eq!(Scenario::from_name("drift"), None);
+    }
+
+    #[test]
+    fn test_packet_decodes_as_idle_engine() {
+        let sim = Simulator::new(Scenario::Idle);
+        let packet = sim.packet_at(secs(5.0));
+        assert_eq!(packet.len(), 138);
+
+        let d = get_parsed_data(&packet).unwrap();
+        assert!((830..=870).contains(&d.rpm), "rpm {}", d.rpm);
+        assert_eq!(d.coolant_celsius(), 85);
+        assert_eq!(d.iat_celsius(), 30);
+        assert_eq!(d.secl, 5);
+        assert_eq!(d.baro, 101);
+        assert!((d.battery_voltage() - 14.1).abs() < 0.01);
+        assert_eq!(d.engine & ENGINE_RUN, ENGINE_RUN);
+        assert_eq!(d.spark & SPARK_SYNC, SPARK_SYNC);
+    }
+
+    #[test]
+    fn test_engine_cranks_first() {
+        let state = Simulator::new(Scenario::Idle).state_at(secs(0.5));
+        assert_eq!(state.engine, ENGINE_CRANK);
+        assert!(state.rpm < 400);
+    }
+
+    #[test]
+    fn test_wot_pull_and_overrun() {
+        let sim = Simulator::new(Scenario::WotPull);
+        let idle = sim.state_at(secs(2.0));
+        let pull = sim.state_at(secs(8.0));
+        let overrun = sim.state_at(secs(10.0));
+        assert_eq!(idle.tps, 0);
+        assert_eq!(pull.tps, 100);
+        assert!(pull.rpm > 6000);
+        assert!(pull.map > 90);
+        assert_eq!(overrun.status1 & STATUS1_DFCO, STATUS1_DFCO);
+        assert_eq!(sim.state_at(secs(14.0)).tps, 0, "next cycle idles again");
+    }
+
+    #[test]
+    fn test_overheat_climbs_and_runs_fan() {
+        let sim = Simulator::new(Scenario::Overheat);
+        assert_eq!(sim.state_at(secs(1.0)).coolant_c, 85);
+        let hot = sim.state_at(secs(61.0));
+        assert_eq!(hot.coolant_c, 115);
+        assert_eq!(hot.status4 & STATUS4_FAN, STATUS4_FAN);
+        assert_eq!(sim.state_at(secs(600.0)).coolant_c, 130);
+    }
+
+    #[test]
+    fn test_sync_loss_counts_and_drops_sync() {
+        let sim = Simulator::new(Scenario::SyncLoss);
+        let before = sim.state_at(secs(3.0));
+        assert_eq!(before.sync_loss_counter, 0);
+        assert_eq!(before.spark & SPARK_SYNC, SPARK_SYNC);
+
+        let lost = sim.state_at(secs(4.7));
+        assert_eq!(lost.sync_loss_counter, 1);
+        assert_eq!(lost.spark & SPARK_SYNC, 0);
+        assert_eq!(lost.rpm, 0);
+
+        let after = sim.state_at(secs(6.0));
+        assert_eq!(after.sync_loss_counter, 1);
+        assert_eq!(after.spark & SPARK_SYNC, SPARK_SYNC);
+        assert_eq!(sim.state_at(secs(9.0)).sync_loss_counter, 2);
+    }
+
+    #[tokio::test]
+    async fn test_serves_legacy_commands() {
+        let sim = Simulator::new(Scenario::Idle);
+        let (mut client, mut ecu) = tokio::io::duplex(1024);
+        tokio::spawn(async move { sim.serve(&mut ecu).await });
+
+        client.write_all(b"Q").await.unwrap();
+        let mut sig = vec![0u8; SIM_SIGNATURE.len()];
+        client.read_exact(&mut sig).await.unwrap();
+        assert_eq!(sig, SIM_SIGNATURE.as_bytes());
+
+        client.write_all(b"A").await.unwrap();
+        let mut packet = [0u8; 138];
+        client.read_exact(&mut packet).await.unwrap();
+        assert_eq!(packet[41], 101); // baro
+
+        client
+            .write_all(&[b'r', 0x00, READ_OUTPUT_CHANNELS, 41, 0, 1, 0])
+            .await
+            .unwrap();
+        let mut baro = [0u8; 1];
+        client.read_exact(&mut baro).await.unwrap();
+        assert_eq!(baro, [101]);
+    }
+
+    async fn framed_exchange(client: &mut tokio::io::DuplexStream, payload: &[u8]) -> Vec<u8> {
+        client.write_all(&frame(payload)).await.unwrap();
+        let mut header = [0u8; 2];
+        client.read_exact(&mut header).await.unwrap();
+        let mut body = vec![0u8; u16::from_be_bytes(header) as usize + FRAME_CRC_BYTES];
+        client.read_exact(&mut body).await.unwrap();
+        body
+    }
+
+    #[tokio::test]
+    async fn test_serves_framed_requests() {
+        let sim = Simulator::new(Scenario::Idle);
+        let (mut client, mut ecu) = tokio::io::duplex(1024);
+        tokio::spawn(async move { sim.serve(&mut ecu).await });
+
+        let body = framed_exchange(&mut client, &read_request(41, 1)).await;
+        assert_eq!(decode_response(&body).unwrap(), &[101]);
+
+        let body = framed_exchange(&mut client, &read_request(130, 16)).await;
+        assert!(decode_response(&body).is_err(), "beyond the packet");
+
+        let body = framed_exchange(&mut client, b"S").await;
+        assert_eq!(decode_response(&body).unwrap(), SIM_VERSION.as_bytes());
+
+        let body = framed_exchange(&mut client, b"Z").await;
+        assert_eq!(body[0], SERIAL_RC_UKWN_ERR);
+
+        // Corrupt the CRC trailer
+        let mut request = frame(b"A");
+        *request.last_mut().unwrap() ^= 0xFF;
+        client.write_all(&request).await.unwrap();
+        let mut reply = [0u8; 7];
+        client.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[2], SERIAL_RC_CRC_ERR);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_scenario_drops_link() {
+        let sim = Simulator::new(Scenario::Disconnect).with_disconnect_after(Duration::ZERO);
+        let (mut client, mut ecu) = tokio::io::duplex(1024);
+        let server = tokio::spawn(async move { sim.serve(&mut ecu).await });
+
+        client.write_all(b"A").await.unwrap();
+        assert_eq!(server.await.unwrap().unwrap(), SessionEnd::Dropped);
+    }
+
+    #[tokio::test]
+    async fn test_client_close_ends_session() {
+        let sim = Simulator::new(Scenario::Idle);
+        let (client, mut ecu) = tokio::io::duplex(1024);
+        drop(client);
+        assert_eq!(sim.serve(&mut ecu).await.unwrap(), SessionEnd::ClientClosed);
+    }
+}
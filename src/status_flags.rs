@@ -0,0 +1,763 @@
+//! Named bits of the realtime status bytes.
+//!
+//! Bit meanings follow `globals.h` of the Speeduino firmware:
+//!
+//! | Topic code | Byte | Bits |
+//! |------------|------|------|
+//! | `STA` | status1 | injector channels 1–4, DFCO, boost cut (fuel), tooth logs ready |
+//! | `ENG` | engine | running, cranking, ASE, warm-up, TPS/MAP accel and decel |
+//! | `SPK` | spark | launch hard/soft, rev limiter hard/soft, boost cut (spark), error, idle, sync |
+//! | `ST3` | status3 | reset prevention, nitrous, second fuel table, VSS refresh, half sync |
+//! | `ST4` | status4 | WMI tank empty, VVT1/VVT2 error, fan, burn pending, staging, comms flags |
+//! | `ST5` | status5 | flat shift hard/soft, second spark table, knock active/pulse |
+//! | `EPS` | engine_protect_status | protection cut active: RPM, MAP, oil pressure, AFR, coolant |
+//! | `ACS` | air_con_status | A/C request, compressor, RPM/TPS/coolant lockouts, turning on, A/C fan |
+//! | `OUT` | outputs_status | programmable outputs 1–8 |
+//!
+//! [`STATUS_FLAGS`] drives the per-flag boolean topics (`ENG/cranking`, …) and
+//! the TUI indicators; [`SpeeduinoData`] has a typed accessor for every flag.
+//! The squirts-per-cycle count in the top three bits of status3 is a number,
+//! not a flag, and is not listed.
+//!
+//! The `next_error` byte is not a bitfield but a packed error slot and code
+//! (`errors.h`); [`EcuErrorCode`] names the codes.
+
+use crate::ecu_data_parser::SpeeduinoData;
+use crate::packet_layout::Field;
+
+// ---------------------------------------------------------------------------
+// Bit masks
+// ---------------------------------------------------------------------------
+
+pub const STATUS1_INJ1: u8 = 1 << 0;
+pub const STATUS1_INJ2: u8 = 1 << 1;
+pub const STATUS1_INJ3: u8 = 1 << 2;
+pub const STATUS1_INJ4: u8 = 1 << 3;
+pub const STATUS1_DFCO: u8 = 1 << 4;
+pub const STATUS1_BOOST_CUT: u8 = 1 << 5;
+pub const STATUS1_TOOTHLOG1_READY: u8 = 1 << 6;
+pub const STATUS1_TOOTHLOG2_READY: u8 = 1 << 7;
+
+pub const ENGINE_RUN: u8 = 1 << 0;
+pub const ENGINE_CRANK: u8 = 1 << 1;
+pub const ENGINE_ASE: u8 = 1 << 2;
+pub const ENGINE_WARMUP: u8 = 1 << 3;
+pub const ENGINE_ACC: u8 = 1 << 4;
+pub const ENGINE_DCC: u8 = 1 << 5;
+pub const ENGINE_MAP_ACC: u8 = 1 << 6;
+pub const ENGINE_MAP_DCC: u8 = 1 << 7;
+
+pub const SPARK_HARD_LAUNCH: u8 = 1 << 0;
+pub const SPARK_SOFT_LAUNCH: u8 = 1 << 1;
+pub const SPARK_HARD_LIMIT: u8 = 1 << 2;
+pub const SPARK_SOFT_LIMIT: u8 = 1 << 3;
+pub const SPARK_BOOST_CUT: u8 = 1 << 4;
+pub const SPARK_ERROR: u8 = 1 << 5;
+pub const SPARK_IDLE: u8 = 1 << 6;
+pub const SPARK_SYNC: u8 = 1 << 7;
+
+pub const STATUS3_RESET_PREVENT: u8 = 1 << 0;
+pub const STATUS3_NITROUS: u8 = 1 << 1;
+pub const STATUS3_FUEL2_ACTIVE: u8 = 1 << 2;
+pub const STATUS3_VSS_REFRESH: u8 = 1 << 3;
+pub const STATUS3_HALF_SYNC: u8 = 1 << 4;
+
+pub const STATUS4_WMI_EMPTY: u8 = 1 << 0;
+pub const STATUS4_VVT1_ERROR: u8 = 1 << 1;
+pub const STATUS4_VVT2_ERROR: u8 = 1 << 2;
+pub const STATUS4_FAN: u8 = 1 << 3;
+pub const STATUS4_BURN_PENDING: u8 = 1 << 4;
+pub const STATUS4_STAGING_ACTIVE: u8 = 1 << 5;
+pub const STATUS4_COMMS_COMPAT: u8 = 1 << 6;
+pub const STATUS4_ALLOW_LEGACY_COMMS: u8 = 1 << 7;
+
+pub const STATUS5_FLAT_SHIFT_HARD: u8 = 1 << 0;
+pub const STATUS5_FLAT_SHIFT_SOFT: u8 = 1 << 1;
+pub const STATUS5_SPARK2_ACTIVE: u8 = 1 << 2;
+pub const STATUS5_KNOCK_ACTIVE: u8 = 1 << 3;
+pub const STATUS5_KNOCK_PULSE: u8 = 1 << 4;
+
+pub const PROTECT_RPM: u8 = 1 << 0;
+pub const PROTECT_MAP: u8 = 1 << 1;
+pub const PROTECT_OIL: u8 = 1 << 2;
+pub const PROTECT_AFR: u8 = 1 << 3;
+pub const PROTECT_COOLANT: u8 = 1 << 4;
+
+pub const AIRCON_REQUEST: u8 = 1 << 0;
+pub const AIRCON_COMPRESSOR: u8 = 1 << 1;
+pub const AIRCON_RPM_LOCKOUT: u8 = 1 << 2;
+pub const AIRCON_TPS_LOCKOUT: u8 = 1 << 3;
+pub const AIRCON_TURNING_ON: u8 = 1 << 4;
+pub const AIRCON_CLT_LOCKOUT: u8 = 1 << 5;
+pub const AIRCON_FAN: u8 = 1 << 6;
+
+// ---------------------------------------------------------------------------
+// Flag table
+// ---------------------------------------------------------------------------
+
+/// One named bit of a status byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusFlag {
+    /// Status byte holding the bit
+    pub field: Field,
+    pub mask: u8,
+    /// MQTT topic code, `<byte code>/<flag name>`
+    pub topic: &'static str,
+    /// Short label for the TUI
+    pub label: &'static str,
+}
+
+impl StatusFlag {
+    /// State of the flag in `d`, or `None` when its byte was not received.
+    pub fn get(&self, d: &SpeeduinoData) -> Option<bool> {
+        d.status_byte(self.field).map(|b| b & self.mask != 0)
+    }
+
+    /// Flag name without the byte code (`"cranking"` for `ENG/cranking`).
+    pub fn name(&self) -> &'static str {
+        self.topic
+            .split_once('/')
+            .map_or(self.topic, |(_, name)| name)
+    }
+}
+
+const fn flag(field: Field, mask: u8, topic: &'static str, label: &'static str) -> StatusFlag {
+    StatusFlag {
+        field,
+        mask,
+        topic,
+        label,
+    }
+}
+
+/// Every named flag, grouped by status byte in packet order.
+pub const STATUS_FLAGS: &[StatusFlag] = &[
+    flag(Field::Status1, STATUS1_INJ1, "STA/inj1", "INJ1"),
+    flag(Field::Status1, STATUS1_INJ2, "STA/inj2", "INJ2"),
+    flag(Field::Status1, STATUS1_INJ3, "STA/inj3", "INJ3"),
+    flag(Field::Status1, STATUS1_INJ4, "STA/inj4", "INJ4"),
+    flag(Field::Status1, STATUS1_DFCO, "STA/dfco", "DFCO"),
+    flag(Field::Status1, STATUS1_BOOST_CUT, "STA/boost_cut", "BCUT"),
+    flag(
+        Field::Status1,
+        STATUS1_TOOTHLOG1_READY,
+        "STA/toothlog1_ready",
+        "TLG1",
+    ),
+    flag(
+        Field::Status1,
+        STATUS1_TOOTHLOG2_READY,
+        "STA/toothlog2_ready",
+        "TLG2",
+    ),
+    flag(Field::Engine, ENGINE_RUN, "ENG/running", "RUN"),
+    flag(Field::Engine, ENGINE_CRANK, "ENG/cranking", "CRNK"),
+    flag(Field::Engine, ENGINE_ASE, "ENG/ase", "ASE"),
+    flag(Field::Engine, ENGINE_WARMUP, "ENG/warmup", "WUE"),
+    flag(Field::Engine, ENGINE_ACC, "ENG/tps_accel", "TACC"),
+    flag(Field::Engine, ENGINE_DCC, "ENG/tps_decel", "TDCC"),
+    flag(Field::Engine, ENGINE_MAP_ACC, "ENG/map_accel", "MACC"),
+    flag(Field::Engine, ENGINE_MAP_DCC, "ENG/map_decel", "MDCC"),
+    flag(Field::Spark, SPARK_HARD_LAUNCH, "SPK/launch_hard", "HLCH"),
+    flag(Field::Spark, SPARK_SOFT_LAUNCH, "SPK/launch_soft", "SLCH"),
+    flag(Field::Spark, SPARK_HARD_LIMIT, "SPK/limiter_hard", "HLIM"),
+    flag(Field::Spark, SPARK_SOFT_LIMIT, "SPK/limiter_soft", "SLIM"),
+    flag(Field::Spark, SPARK_BOOST_CUT, "SPK/boost_cut", "BCUT"),
+    flag(Field::Spark, SPARK_ERROR, "SPK/error", "ERR"),
+    flag(Field::Spark, SPARK_IDLE, "SPK/idle", "IDLE"),
+    flag(Field::Spark, SPARK_SYNC, "SPK/sync", "SYNC"),
+    flag(
+        Field::Status3,
+        STATUS3_RESET_PREVENT,
+        "ST3/reset_prevent",
+        "RSTP",
+    ),
+    flag(Field::Status3, STATUS3_NITROUS, "ST3/nitrous", "NOS"),
+    flag(
+        Field::Status3,
+        STATUS3_FUEL2_ACTIVE,
+        "ST3/fuel2_active",
+        "FUL2",
+    ),
+    flag(
+        Field::Status3,
+        STATUS3_VSS_REFRESH,
+        "ST3/vss_refresh",
+        "VSSR",
+    ),
+    flag(Field::Status3, STATUS3_HALF_SYNC, "ST3/half_sync", "HSYN"),
+    flag(Field::Status4, STATUS4_WMI_EMPTY, "ST4/wmi_empty", "WMIE"),
+    flag(Field::Status4, STATUS4_VVT1_ERROR, "ST4/vvt1_error", "VVE1"),
+    flag(Field::Status4, STATUS4_VVT2_ERROR, "ST4/vvt2_error", "VVE2"),
+    flag(Field::Status4, STATUS4_FAN, "ST4/fan", "FAN"),
+    flag(
+        Field::Status4,
+        STATUS4_BURN_PENDING,
+        "ST4/burn_pending",
+        "BURN",
+    ),
+    flag(
+        Field::Status4,
+        STATUS4_STAGING_ACTIVE,
+        "ST4/staging_active",
+        "STGE",
+    ),
+    flag(
+        Field::Status4,
+        STATUS4_COMMS_COMPAT,
+        "ST4/comms_compat",
+        "COMP",
+    ),
+    flag(
+        Field::Status4,
+        STATUS4_ALLOW_LEGACY_COMMS,
+        "ST4/allow_legacy_comms",
+        "LEGC",
+    ),
+    flag(
+        Field::Status5,
+        STATUS5_FLAT_SHIFT_HARD,
+        "ST5/flat_shift_hard",
+        "FSH",
+    ),
+    flag(
+        Field::Status5,
+        STATUS5_FLAT_SHIFT_SOFT,
+        "ST5/flat_shift_soft",
+        "FSS",
+    ),
+    flag(
+        Field::Status5,
+        STATUS5_SPARK2_ACTIVE,
+        "ST5/spark2_active",
+        "SPK2",
+    ),
+    flag(
+        Field::Status5,
+        STATUS5_KNOCK_ACTIVE,
+        "ST5/knock_active",
+        "KNCK",
+    ),
+    flag(
+        Field::Status5,
+        STATUS5_KNOCK_PULSE,
+        "ST5/knock_pulse",
+        "KPLS",
+    ),
+    flag(Field::EngineProtectStatus, PROTECT_RPM, "EPS/rpm", "RPM"),
+    flag(Field::EngineProtectStatus, PROTECT_MAP, "EPS/map", "MAP"),
+    flag(Field::EngineProtectStatus, PROTECT_OIL, "EPS/oil", "OIL"),
+    flag(Field::EngineProtectStatus, PROTECT_AFR, "EPS/afr", "AFR"),
+    flag(
+        Field::EngineProtectStatus,
+        PROTECT_COOLANT,
+        "EPS/coolant",
+        "CLT",
+    ),
+    flag(Field::AirConStatus, AIRCON_REQUEST, "ACS/request", "REQ"),
+    flag(
+        Field::AirConStatus,
+        AIRCON_COMPRESSOR,
+        "ACS/compressor",
+        "COMP",
+    ),
+    flag(
+        Field::AirConStatus,
+        AIRCON_RPM_LOCKOUT,
+        "ACS/rpm_lockout",
+        "RPML",
+    ),
+    flag(
+        Field::AirConStatus,
+        AIRCON_TPS_LOCKOUT,
+        "ACS/tps_lockout",
+        "TPSL",
+    ),
+    flag(
+        Field::AirConStatus,
+        AIRCON_TURNING_ON,
+        "ACS/turning_on",
+        "TURN",
+    ),
+    flag(
+        Field::AirConStatus,
+        AIRCON_CLT_LOCKOUT,
+        "ACS/clt_lockout",
+        "CLTL",
+    ),
+    flag(Field::AirConStatus, AIRCON_FAN, "ACS/fan", "FAN"),
+    flag(Field::OutputsStatus, 1 << 0, "OUT/output1", "O1"),
+    flag(Field::OutputsStatus, 1 << 1, "OUT/output2", "O2"),
+    flag(Field::OutputsStatus, 1 << 2, "OUT/output3", "O3"),
+    flag(Field::OutputsStatus, 1 << 3, "OUT/output4", "O4"),
+    flag(Field::OutputsStatus, 1 << 4, "OUT/output5", "O5"),
+    flag(Field::OutputsStatus, 1 << 5, "OUT/output6", "O6"),
+    flag(Field::OutputsStatus, 1 << 6, "OUT/output7", "O7"),
+    flag(Field::OutputsStatus, 1 << 7, "OUT/output8", "O8"),
+];
+
+/// Flag published under `topic` (case-insensitive), e.g. `"ENG/cranking"`.
+pub fn status_flag(topic: &str) -> Option<&'static StatusFlag> {
+    STATUS_FLAGS
+        .iter()
+        .find(|f| f.topic.eq_ignore_ascii_case(topic))
+}
+
+/// Flags of the status byte `field`, in bit order.
+pub fn flags_of(field: Field) -> impl Iterator<Item = &'static StatusFlag> {
+    STATUS_FLAGS.iter().filter(move |f| f.field == field)
+}
+
+// ---------------------------------------------------------------------------
+// Error codes
+// ---------------------------------------------------------------------------
+
+/// Error code reported in the `next_error` byte (Speeduino `errors.h`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EcuErrorCode {
+    Unknown,
+    IatShort,
+    IatGround,
+    CltShort,
+    CltGround,
+    O2Short,
+    O2Ground,
+    TpsShort,
+    TpsGround,
+    BatteryHigh,
+    BatteryLow,
+    MapHigh,
+    MapLow,
+    /// Code this bridge does not know (newer firmware)
+    Other(u8),
+}
+
+impl EcuErrorCode {
+    /// Code for an error ID; `None` for 0 (no error).
+    pub fn from_id(id: u8) -> Option<Self> {
+        use EcuErrorCode::*;
+        Some(match id {
+            0 => return None,
+            1 => Unknown,
+            2 => IatShort,
+            3 => IatGround,
+            4 => CltShort,
+            5 => CltGround,
+            6 => O2Short,
+            7 => O2Ground,
+            8 => TpsShort,
+            9 => TpsGround,
+            10 => BatteryHigh,
+            11 => BatteryLow,
+            12 => MapHigh,
+            13 => MapLow,
+            other => Other(other),
+        })
+    }
+
+    /// Name used in MQTT payloads and the TUI.
+    pub fn name(&self) -> &'static str {
+        use EcuErrorCode::*;
+        match self {
+            Unknown => "unknown",
+            IatShort => "iat_short",
+            IatGround => "iat_ground",
+            CltShort => "clt_short",
+            CltGround => "clt_ground",
+            O2Short => "o2_short",
+            O2Ground => "o2_ground",
+            TpsShort => "tps_short",
+            TpsGround => "tps_ground",
+            BatteryHigh => "battery_high",
+            BatteryLow => "battery_low",
+            MapHigh => "map_high",
+            MapLow => "map_low",
+            Other(_) => "unrecognised",
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Typed accessors
+// ---------------------------------------------------------------------------
+
+/// Flags of the status bytes.  status5 and the A/C status are absent from
+/// short packets; their flags read as `false` then.
+impl SpeeduinoData {
+    /// Raw status byte behind `field`, or `None` when it was not received
+    /// (or `field` is not a status byte).
+    pub fn status_byte(&self, field: Field) -> Option<u8> {
+        let byte = match field {
+            Field::Status1 => Some(self.status1),
+            Field::Engine => Some(self.engine),
+            Field::Spark => Some(self.spark),
+            Field::Status3 => Some(self.status3),
+            Field::Status4 => Some(self.status4),
+            Field::Status5 => self.status5,
+            Field::EngineProtectStatus => Some(self.engine_protect_status),
+            Field::AirConStatus => self.air_con_status,
+            Field::OutputsStatus => Some(self.outputs_status),
+            _ => None,
+        };
+        byte.filter(|_| self.has(field))
+    }
+
+    fn status5_bit(&self, mask: u8) -> bool {
+        self.status5.is_some_and(|s| s & mask != 0)
+    }
+
+    // ---- status1 ----
+    /// Injector channel `n` (1–4) is firing.
+    pub fn injector_active(&self, n: u8) -> bool {
+        (1..=4).contains(&n) && self.status1 & (1 << (n - 1)) != 0
+    }
+    /// Deceleration fuel cut-off
+    pub fn dfco(&self) -> bool {
+        self.status1 & STATUS1_DFCO != 0
+    }
+    /// Fuel cut by the MAP-based boost cut
+    pub fn boost_cut_fuel(&self) -> bool {
+        self.status1 & STATUS1_BOOST_CUT != 0
+    }
+    pub fn toothlog1_ready(&self) -> bool {
+        self.status1 & STATUS1_TOOTHLOG1_READY != 0
+    }
+    pub fn toothlog2_ready(&self) -> bool {
+        self.status1 & STATUS1_TOOTHLOG2_READY != 0
+    }
+
+    // ---- engine ----
+    pub fn running(&self) -> bool {
+        self.engine & ENGINE_RUN != 0
+    }
+    pub fn cranking(&self) -> bool {
+        self.engine & ENGINE_CRANK != 0
+    }
+    /// After-start enrichment active
+    pub fn ase_active(&self) -> bool {
+        self.engine & ENGINE_ASE != 0
+    }
+    /// Warm-up enrichment active
+    pub fn warmup(&self) -> bool {
+        self.engine & ENGINE_WARMUP != 0
+    }
+    /// TPS-based acceleration enrichment
+    pub fn tps_accel(&self) -> bool {
+        self.engine & ENGINE_ACC != 0
+    }
+    /// TPS-based deceleration enleanment
+    pub fn tps_decel(&self) -> bool {
+        self.engine & ENGINE_DCC != 0
+    }
+    /// MAP-based acceleration enrichment
+    pub fn map_accel(&self) -> bool {
+        self.engine & ENGINE_MAP_ACC != 0
+    }
+    /// MAP-based deceleration enleanment
+    pub fn map_decel(&self) -> bool {
+        self.engine & ENGINE_MAP_DCC != 0
+    }
+
+    // ---- spark ----
+    /// Launch control hard cut
+    pub fn launch_hard(&self) -> bool {
+        self.spark & SPARK_HARD_LAUNCH != 0
+    }
+    /// Launch control soft (retard) limit
+    pub fn launch_soft(&self) -> bool {
+        self.spark & SPARK_SOFT_LAUNCH != 0
+    }
+    /// Rev limiter hard cut
+    pub fn limiter_hard(&self) -> bool {
+        self.spark & SPARK_HARD_LIMIT != 0
+    }
+    /// Rev limiter soft (retard) limit
+    pub fn limiter_soft(&self) -> bool {
+        self.spark & SPARK_SOFT_LIMIT != 0
+    }
+    /// Spark cut by the MAP-based boost cut
+    pub fn boost_cut_spark(&self) -> bool {
+        self.spark & SPARK_BOOST_CUT != 0
+    }
+    /// The ECU has an error pending (see `next_error`)
+    pub fn error_pending(&self) -> bool {
+        self.spark & SPARK_ERROR != 0
+    }
+    /// Idle control active
+    pub fn idle_active(&self) -> bool {
+        self.spark & SPARK_IDLE != 0
+    }
+    /// Crank/cam position sync
+    pub fn has_sync(&self) -> bool {
+        self.spark & SPARK_SYNC != 0
+    }
+
+    // ---- status3 ----
+    pub fn reset_prevent(&self) -> bool {
+        self.status3 & STATUS3_RESET_PREVENT != 0
+    }
+    pub fn nitrous_active(&self) -> bool {
+        self.status3 & STATUS3_NITROUS != 0
+    }
+    /// Second fuel table in use
+    pub fn fuel2_active(&self) -> bool {
+        self.status3 & STATUS3_FUEL2_ACTIVE != 0
+    }
+    pub fn vss_refresh(&self) -> bool {
+        self.status3 & STATUS3_VSS_REFRESH != 0
+    }
+    /// Sync from the primary trigger only (no cam signal)
+    pub fn half_sync(&self) -> bool {
+        self.status3 & STATUS3_HALF_SYNC != 0
+    }
+
+    // ---- status4 ----
+    /// Water-methanol tank empty
+    pub fn wmi_empty(&self) -> bool {
+        self.status4 & STATUS4_WMI_EMPTY != 0
+    }
+    /// VVT1 cam angle outside its limits
+    pub fn vvt1_error(&self) -> bool {
+        self.status4 & STATUS4_VVT1_ERROR != 0
+    }
+    /// VVT2 cam angle outside its limits
+    pub fn vvt2_error(&self) -> bool {
+        self.status4 & STATUS4_VVT2_ERROR != 0
+    }
+    /// Radiator fan on
+    pub fn fan_on(&self) -> bool {
+        self.status4 & STATUS4_FAN != 0
+    }
+    /// Tune changes not yet burned to EEPROM
+    pub fn burn_pending(&self) -> bool {
+        self.status4 & STATUS4_BURN_PENDING != 0
+    }
+    /// Staged injection active
+    pub fn staging_active(&self) -> bool {
+        self.status4 & STATUS4_STAGING_ACTIVE != 0
+    }
+    pub fn comms_compat(&self) -> bool {
+        self.status4 & STATUS4_COMMS_COMPAT != 0
+    }
+    pub fn allow_legacy_comms(&self) -> bool {
+        self.status4 & STATUS4_ALLOW_LEGACY_COMMS != 0
+    }
+
+    // ---- status5 ----
+    /// Flat shift hard cut
+    pub fn flat_shift_hard(&self) -> bool {
+        self.status5_bit(STATUS5_FLAT_SHIFT_HARD)
+    }
+    /// Flat shift soft cut
+    pub fn flat_shift_soft(&self) -> bool {
+        self.status5_bit(STATUS5_FLAT_SHIFT_SOFT)
+    }
+    /// Second spark table in use
+    pub fn spark2_active(&self) -> bool {
+        self.status5_bit(STATUS5_SPARK2_ACTIVE)
+    }
+    /// Knock control active
+    pub fn knock_active(&self) -> bool {
+        self.status5_bit(STATUS5_KNOCK_ACTIVE)
+    }
+    /// Knock detected on this cycle
+    pub fn knock_pulse(&self) -> bool {
+        self.status5_bit(STATUS5_KNOCK_PULSE)
+    }
+
+    // ---- engine_protect_status ----
+    /// Any protection cut is active.
+    pub fn protection_active(&self) -> bool {
+        self.engine_protect_status
+            & (PROTECT_RPM | PROTECT_MAP | PROTECT_OIL | PROTECT_AFR | PROTECT_COOLANT)
+            != 0
+    }
+    /// Over-rev protection cut
+    pub fn protect_rpm(&self) -> bool {
+        self.engine_protect_status & PROTECT_RPM != 0
+    }
+    /// Over-boost protection cut
+    pub fn protect_map(&self) -> bool {
+        self.engine_protect_status & PROTECT_MAP != 0
+    }
+    /// Low oil pressure protection cut
+    pub fn protect_oil(&self) -> bool {
+        self.engine_protect_status & PROTECT_OIL != 0
+    }
+    /// Lean AFR protection cut
+    pub fn protect_afr(&self) -> bool {
+        self.engine_protect_status & PROTECT_AFR != 0
+    }
+    /// Coolant over-temperature protection cut
+    pub fn protect_coolant(&self) -> bool {
+        self.engine_protect_status & PROTECT_COOLANT != 0
+    }
+
+    // ---- air_con_status ----
+    fn aircon_bit(&self, mask: u8) -> bool {
+        self.air_con_status.is_some_and(|s| s & mask != 0)
+    }
+    /// A/C button pressed
+    pub fn ac_request(&self) -> bool {
+        self.aircon_bit(AIRCON_REQUEST)
+    }
+    /// A/C compressor running
+    pub fn ac_compressor(&self) -> bool {
+        self.aircon_bit(AIRCON_COMPRESSOR)
+    }
+    /// A/C locked out by RPM (or its stand-down period)
+    pub fn ac_rpm_lockout(&self) -> bool {
+        self.aircon_bit(AIRCON_RPM_LOCKOUT)
+    }
+    /// A/C locked out by high TPS (or its stand-down period)
+    pub fn ac_tps_lockout(&self) -> bool {
+        self.aircon_bit(AIRCON_TPS_LOCKOUT)
+    }
+    /// A/C requested and allowed, waiting for the start delay; the idle is
+    /// raised before the compressor engages
+    pub fn ac_turning_on(&self) -> bool {
+        self.aircon_bit(AIRCON_TURNING_ON)
+    }
+    /// A/C locked out by high coolant temperature
+    pub fn ac_clt_lockout(&self) -> bool {
+        self.aircon_bit(AIRCON_CLT_LOCKOUT)
+    }
+    /// A/C condenser fan running
+    pub fn ac_fan(&self) -> bool {
+        self.aircon_bit(AIRCON_FAN)
+    }
+
+    // ---- next_error ----
+    /// Error currently reported by the ECU, `None` when there is none.
+    /// The ECU cycles through its active errors, one per frame.
+    pub fn ecu_error(&self) -> Option<EcuErrorCode> {
+        EcuErrorCode::from_id(self.next_error >> 2)
+    }
+    /// Slot (0–3) of the error in [`ecu_error`](Self::ecu_error)
+    pub fn ecu_error_slot(&self) -> u8 {
+        self.next_error & 0b11
+    }
+
+    // ---- outputs_status ----
+    /// Programmable output `n` (1–8) is on.
+    pub fn output_active(&self, n: u8) -> bool {
+        (1..=8).contains(&n) && self.outputs_status & (1 << (n - 1)) != 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet_layout::FieldSet;
+
+    type Accessor = fn(&SpeeduinoData) -> bool;
+
+    #[test]
+    fn test_accessors_match_table() {
+        let accessors: &[(&str, Accessor)] = &[
+            ("STA/dfco", SpeeduinoData::dfco),
+            ("ENG/running", SpeeduinoData::running),
+            ("ENG/cranking", SpeeduinoData::cranking),
+            ("ENG/warmup", SpeeduinoData::warmup),
+            ("SPK/launch_hard", SpeeduinoData::launch_hard),
+            ("SPK/launch_soft", SpeeduinoData::launch_soft),
+            ("SPK/sync", SpeeduinoData::has_sync),
+            ("ST3/nitrous", SpeeduinoData::nitrous_active),
+            ("ST4/fan", SpeeduinoData::fan_on),
+            ("ST5/flat_shift_hard", SpeeduinoData::flat_shift_hard),
+        ];
+        for (topic, accessor) in accessors {
+            let flag = status_flag(topic).unwrap();
+            let mut d = SpeeduinoData {
+                status5: Some(0),
+                ..Default::default()
+            };
+            assert!(!accessor(&d), "{} set in an empty frame", topic);
+            match flag.field {
+                Field::Status1 => d.status1 = flag.mask,
+                Field::Engine => d.engine = flag.mask,
+                Field::Spark => d.spark = flag.mask,
+                Field::Status3 => d.status3 = flag.mask,
+                Field::Status4 => d.status4 = flag.mask,
+                Field::Status5 => d.status5 = Some(flag.mask),
+                _ => unreachable!(),
+            }
+            assert!(accessor(&d), "{} not set", topic);
+            assert_eq!(flag.get(&d), Some(true));
+        }
+    }
+
+    #[test]
+    fn test_numbered_injectors_and_outputs() {
+        let d = SpeeduinoData {
+            status1: STATUS1_INJ3,
+            outputs_status: 0b1000_0001,
+            ..Default::default()
+        };
+        assert!(d.injector_active(3));
+        assert!(!d.injector_active(1));
+        assert!(!d.injector_active(9));
+        assert!(d.output_active(1));
+        assert!(d.output_active(8));
+        assert!(!d.output_active(2));
+        assert!(!d.output_active(0));
+    }
+
+    #[test]
+    fn test_flag_unknown_without_its_byte() {
+        let mut d = SpeeduinoData::default();
+        let knock = status_flag("ST5/knock_active").unwrap();
+        assert_eq!(knock.get(&d), None);
+        assert!(!d.knock_active());
+
+        d.missing = FieldSet::from_iter([Field::Engine]);
+        assert_eq!(status_flag("eng/CRANKING").unwrap().get(&d), None);
+        assert_eq!(status_flag("SPK/sync").unwrap().get(&d), Some(false));
+    }
+
+    #[test]
+    fn test_protection_causes() {
+        let mut d = SpeeduinoData::default();
+        assert!(!d.protection_active());
+        d.engine_protect_status = PROTECT_OIL | PROTECT_COOLANT;
+        assert!(d.protection_active());
+        assert!(d.protect_oil() && d.protect_coolant());
+        assert!(!d.protect_rpm());
+        let active: Vec<&str> = flags_of(Field::EngineProtectStatus)
+            .filter(|f| f.get(&d) == Some(true))
+            .map(StatusFlag::name)
+            .collect();
+        assert_eq!(active, vec!["oil", "coolant"]);
+    }
+
+    #[test]
+    fn test_next_error_unpacks_slot_and_code() {
+        let mut d = SpeeduinoData::default();
+        assert_eq!(d.ecu_error(), None);
+        // Slot 1, code 4 (coolant sensor shorted)
+        d.next_error = (4 << 2) | 1;
+        assert_eq!(d.ecu_error(), Some(EcuErrorCode::CltShort));
+        assert_eq!(d.ecu_error_slot(), 1);
+        assert_eq!(d.ecu_error().unwrap().name(), "clt_short");
+        d.next_error = 50 << 2;
+        assert_eq!(d.ecu_error(), Some(EcuErrorCode::Other(50)));
+    }
+
+    #[test]
+    fn test_topics_are_unique_and_grouped() {
+        for (i, flag) in STATUS_FLAGS.iter().enumerate() {
+            assert!(
+                STATUS_FLAGS[i + 1..].iter().all(|f| f.topic != flag.topic),
+                "duplicate topic {}",
+                flag.topic
+            );
+            assert_eq!(
+                flag.mask.count_ones(),
+                1,
+                "{} is not a single bit",
+                flag.topic
+            );
+        }
+        assert!(status_flag("ENG/flying").is_none());
+    }
+}
@@ -16,7 +16,16 @@
 //! └───────────────────────────────────────────────────────────┘
 //! ```
 
+use crate::afr::Wideband;
+use crate::can_inputs::CanInputs;
+use crate::channels::{CATEGORIES, Category, channels_in};
+use crate::derived::DerivedChannels;
 use crate::ecu_data_parser::SpeeduinoData;
+use crate::events::EVENT_LOG_PREFIX;
+use crate::fuel::FuelUsage;
+use crate::packet_layout::Field;
+use crate::status_flags::flags_of;
+use crate::units::Units;
 use crossterm::{
     event::{Event, EventStream, KeyCode, KeyModifiers},
     execute,
@@ -52,9 +61,23 @@ pub struct TuiState {
     pub mqtt_connected: bool,
     pub mqtt_enabled: bool,
     pub connection_address: String,
+    /// Firmware signature reported by the ECU handshake
+    pub ecu_firmware: Option<String>,
+    /// Frames discarded as corrupted or misaligned
+    pub bad_frames: u64,
     pub mqtt_address: String,
     pub ecu_data: Option<SpeeduinoData>,
     pub messages_published: u64,
+    /// Units the ECU data panel is shown in
+    pub units: Units,
+    /// Names and scaling of the CAN inputs
+    pub can_inputs: CanInputs,
+    /// O2 interpretation for the AFR, lambda and AFR error cells
+    pub wideband: Wideband,
+    /// User-defined channels shown after the native ones
+    pub derived: DerivedChannels,
+    /// Injector duty, fuel flow and trip of the latest frame
+    pub fuel: Option<FuelUsage>,
 }
 
 // ---------------------------------------------------------------------------
@@ -153,9 +176,16 @@ async fn tui_loop(
                     mqtt_connected: s.mqtt_connected,
                     mqtt_enabled: s.mqtt_enabled,
                     connection_address: s.connection_address.clone(),
+                    ecu_firmware: s.ecu_firmware.clone(),
+                    bad_frames: s.bad_frames,
                     mqtt_address: s.mqtt_address.clone(),
                     ecu_data: s.ecu_data.clone(),
                     messages_published: s.messages_published,
+                    units: s.units.clone(),
+                    can_inputs: s.can_inputs.clone(),
+                    wideband: s.wideband.clone(),
+                    derived: s.derived.clone(),
+                    fuel: s.fuel.clone(),
                     logs,
                 };
                 drop(s);
@@ -191,9 +221,16 @@ struct StateSnapshot {
     mqtt_connected: bool,
     mqtt_enabled: bool,
     connection_address: String,
+    ecu_firmware: Option<String>,
+    bad_frames: u64,
     mqtt_address: String,
     ecu_data: Option<SpeeduinoData>,
     messages_published: u64,
+    units: Units,
+    can_inputs: CanInputs,
+    wideband: Wideband,
+    derived: DerivedChannels,
+    fuel: Option<FuelUsage>,
     logs: Vec<String>,
 }
 
@@ -272,6 +309,15 @@ fn render_connections(f: &mut Frame, area: Rect, snap: &StateSnapshot) {
             snap.connection_address
         ))));
     }
+    if let Some(ref firmware) = snap.ecu_firmware {
+        lines.push(Line::from(Span::raw(format!("  FW: {}", firmware))));
+    }
+    if snap.bad_frames > 0 {
+        lines.push(Line::from(Span::styled(
+            format!("  Bad frames: {}", snap.bad_frames),
+            Style::default().fg(Color::Yellow),
+        )));
+    }
     lines.push(Line::default());
 
     // MQTT connection
@@ -318,16 +364,7 @@ fn render_ecu_data(f: &mut Frame, area: Rect, snap: &StateSnapshot) {
         return;
     };
 
-    // ── Derived values ────────────────────────────────────────────────────
-    // Gauge boost pressure: positive = boost, negative = vacuum
-    let boost_rel = d.map as i32 - d.baro as i32;
-    // Lambda from AFR target (afr_target stored ×10, stoich 14.7 → 147)
-    let afr_lambda = d.afr_target as f32 / 147.0;
-    // Dwell efficiency: actual measured vs requested dwell (%)
-    let dwell_eff = d
-        .actual_dwell
-        .filter(|_| d.dwell > 0)
-        .map(|ad| ad as f32 / d.dwell as f32 * 100.0);
+    let units = &snap.units;
 
     // ── Layout helpers ────────────────────────────────────────────────────
     // inner_w: usable width inside borders (2) + padding (2)
@@ -341,31 +378,14 @@ fn render_ecu_data(f: &mut Frame, area: Rect, snap: &StateSnapshot) {
         .add_modifier(Modifier::BOLD);
     let sec = Style::default().fg(Color::DarkGray);
 
-    // Nested helpers (no closures needed — pure conversions)
-    fn c(label: &str, value: String) -> (String, String) {
-        (label.to_string(), value)
-    }
-    fn ms10(raw: u16) -> String {
-        format!("{:.1}ms", raw as f32 / 10.0)
-    }
-    fn opt_ms10(o: Option<u16>) -> String {
-        o.map_or_else(|| "—".into(), |v| ms10(v))
-    }
-    fn opt_str<T: std::fmt::Display>(o: Option<T>) -> String {
-        o.map_or_else(|| "—".into(), |v| v.to_string())
-    }
-    fn opt_unit<T: std::fmt::Display>(o: Option<T>, unit: &str) -> String {
-        o.map_or_else(|| "—".into(), |v| format!("{}{}", v, unit))
-    }
-
     let section_line = |title: &str| -> Line<'static> {
-        Line::from(Span::styled(format!("── {} ──", title), sec))
+        Line::from(Span::styled(format!("── {} ──", title.to_uppercase()), sec))
     };
 
-    let row = |cells: Vec<(String, String)>| -> Line<'static> {
+    let row = |cells: &[(&str, String)]| -> Line<'static> {
         let n = cells.len();
         let mut spans: Vec<Span<'static>> = Vec::new();
-        for (i, (label, value)) in cells.into_iter().enumerate() {
+        for (i, (label, value)) in cells.iter().enumerate() {
             spans.push(Span::styled(format!("{:<4}", label), lbl));
             let part = if i + 1 < n {
                 format!(": {:<width$}  ", value, width = val_w)
@@ -379,201 +399,152 @@ fn render_ecu_data(f: &mut Frame, area: Rect, snap: &StateSnapshot) {
 
     let mut lines: Vec<Line> = Vec::new();
 
-    // ── ENGINE ───────────────────────────────────────────────────────────
-    lines.push(section_line("ENGINE"));
-    lines.push(row(vec![
-        c("RPM", d.rpm.to_string()),
-        c("MAP", format!("{} kPa", d.map)),
-        c("TPS", format!("{}%", d.tps)),
-    ]));
-    lines.push(row(vec![
-        c("DRPM", format!("{:+}/s", d.rpm_dot)),
-        c("DMAP", format!("{:.1} kPa/s", d.map_dot as f32 / 10.0)),
-        c("DTPS", format!("{:.1}%/s", d.tps_dot as f32 / 10.0)),
-    ]));
-    lines.push(row(vec![
-        c("ADV", format!("{}°", d.advance)),
-        c("VE", format!("{}%", d.ve_current)),
-        c(
-            "AFR>",
-            format!("{:.1} λ{:.2}", d.afr_target_real(), afr_lambda),
-        ),
-    ]));
-    lines.push(row(vec![
-        c("BARO", format!("{} kPa", d.baro)),
-        c(
-            "EMAP",
-            d.emap.map_or_else(|| "—".into(), |v| format!("{} kPa", v)),
-        ),
-        c("GBST", format!("{:+} kPa", boost_rel)),
-    ]));
-
-    // ── SENSORS ──────────────────────────────────────────────────────────
-    lines.push(section_line("SENSORS"));
-    lines.push(row(vec![
-        c("IAT", format!("{}°C", d.iat_celsius())),
-        c("CLT", format!("{}°C", d.coolant_celsius())),
-        c("FTMP", format!("{}°C", d.fuel_temp_celsius())),
-    ]));
-    lines.push(row(vec![
-        c("BAT", format!("{:.1}V", d.battery_voltage())),
-        c("O2P", d.o2_primary.to_string()),
-        c("O2S", d.o2_secondary.to_string()),
-    ]));
-
-    // ── FUELING ──────────────────────────────────────────────────────────
-    lines.push(section_line("FUELING"));
-    lines.push(row(vec![
-        c("PW1", ms10(d.pw1)),
-        c("PW2", ms10(d.pw2)),
-        c("PW3", ms10(d.pw3)),
-    ]));
-    lines.push(row(vec![
-        c("PW4", ms10(d.pw4)),
-        c("EGO", format!("{}%", d.ego_correction)),
-        c("TAE", format!("{}%", d.tae_amount_pct())),
-    ]));
-    if d.pw5.is_some() || d.pw6.is_some() {
-        lines.push(row(vec![
-            c("PW5", opt_ms10(d.pw5)),
-            c("PW6", opt_ms10(d.pw6)),
-            c("PW7", opt_ms10(d.pw7)),
-        ]));
-        lines.push(row(vec![c("PW8", opt_ms10(d.pw8))]));
+    // ── One section per registry category, three channels per row ────────
+    // Channels the ECU did not send are left out.
+    for category in CATEGORIES {
+        if HIDDEN_CATEGORIES.contains(&category) {
+            continue;
+        }
+        let mut cells: Vec<(&str, String)> = if category == Category::Can {
+            // Named and scaled per the [can_inputs] settings
+            snap.can_inputs.display(d)
+        } else {
+            channels_in(category)
+                .filter_map(|c| Some((c.code, units.display(c.code, c.value(d)?))))
+                .collect()
+        };
+        cells.extend(derived_cells(category, d, units, &snap.wideband));
+        // Raw CAN inputs are only interesting once something writes to them
+        let idle_can = category == Category::Can
+            && !snap.can_inputs.is_configured()
+            && d.canin.iter().all(|&v| v == 0);
+        if cells.is_empty() || idle_can {
+            continue;
+        }
+        lines.push(section_line(category.title()));
+        for chunk in cells.chunks(3) {
+            lines.push(row(chunk));
+        }
     }
-    lines.push(row(vec![
-        c("IATC", format!("{}%", d.iat_correction)),
-        c("WUEC", format!("{}%", d.wue_correction)),
-        c("BARC", format!("{}%", d.baro_correction)),
-    ]));
-    lines.push(row(vec![
-        c("BATC", format!("{}%", d.bat_correction)),
-        c("FTEC", format!("{}%", d.fuel_temp_correction)),
-        c("CORR", format!("{}%", d.corrections)),
-    ]));
-    lines.push(row(vec![
-        c("ASE", format!("{}%", d.ase_value)),
-        c("FLXC", format!("{}%", d.flex_correction)),
-    ]));
 
-    // ── IGNITION ─────────────────────────────────────────────────────────
-    lines.push(section_line("IGNITION"));
-    lines.push(row(vec![
-        c("DWL", ms10(d.dwell)),
-        c("ADW", opt_ms10(d.actual_dwell)),
-        c(
-            "DEFF",
-            dwell_eff.map_or_else(|| "—".into(), |e| format!("{:.0}%", e)),
-        ),
-    ]));
-    lines.push(row(vec![
-        c("ADV1", format!("{}°", d.advance1)),
-        c("ADV2", format!("{}°", d.advance2)),
-        c("KNK", opt_str(d.knock_count)),
-    ]));
-    lines.push(row(vec![c("KRET", opt_unit(d.knock_retard, "°"))]));
-
-    // ── BOOST / VVT / FLEX ───────────────────────────────────────────────
-    lines.push(section_line("BOOST / VVT / FLEX"));
-    lines.push(row(vec![
-        // boost_duty_raw stores duty as 0-100 (= actual %)
-        c("BTGT", format!("{} kPa", d.boost_target_kpa())),
-        c("BDUT", format!("{}%", d.boost_duty_raw)),
-        c("ETH", format!("{}%", d.ethanol_pct)),
-    ]));
-    lines.push(row(vec![
-        c("VVT1", format!("{}°", d.vvt1_angle)),
-        c("VT1T", format!("{}°", d.vvt1_target_angle)),
-        c("VT1D", format!("{}%", d.vvt1_duty)),
-    ]));
-    lines.push(row(vec![
-        c("VVT2", format!("{}°", d.vvt2_angle)),
-        c("VT2T", format!("{}°", d.vvt2_target_angle)),
-        c("VT2D", format!("{}%", d.vvt2_duty)),
-    ]));
-    lines.push(row(vec![
-        c("FLXI", format!("{}°", d.flex_ign_correction)),
-        c("FLXB", d.flex_boost_correction.to_string()),
-    ]));
-
-    // ── VEHICLE ──────────────────────────────────────────────────────────
-    lines.push(section_line("VEHICLE"));
-    lines.push(row(vec![
-        c("VSS", format!("{} km/h", d.vss)),
-        c(
-            "GEAR",
-            if d.gear == 0 {
-                "N".into()
-            } else {
-                d.gear.to_string()
-            },
-        ),
-        c("WMI", format!("{} µs", d.wmi_pw)),
-    ]));
-    lines.push(row(vec![
-        c("OIL", format!("{} kPa", d.oil_pressure)),
-        c("FPRS", format!("{} kPa", d.fuel_pressure)),
-    ]));
-    lines.push(row(vec![
-        c("FAN", opt_unit(d.fan_duty, "%")),
-        c(
-            "ACS",
-            d.air_con_status
-                .map_or_else(|| "—".into(), |v| format!("{:#04x}", v)),
-        ),
-    ]));
+    // ── DERIVED ([derived] table of the settings) ───────────────────────
+    let derived: Vec<(&str, String)> = snap
+        .derived
+        .values(d)
+        .into_iter()
+        .map(|(name, value)| (name, DerivedChannels::format(value)))
+        .collect();
+    if !derived.is_empty() {
+        lines.push(section_line("Derived"));
+        for chunk in derived.chunks(3) {
+            lines.push(row(chunk));
+        }
+    }
 
-    // ── SYSTEM ───────────────────────────────────────────────────────────
-    lines.push(section_line("SYSTEM"));
-    lines.push(row(vec![
-        c("LPS", d.loops_per_second.to_string()),
-        c("RAM", format!("{} B", d.free_ram)),
-        c("SECL", d.secl.to_string()),
-    ]));
-    lines.push(row(vec![
-        c("SYNC", d.sync_loss_counter.to_string()),
-        c("ERR", format!("{:#04x}", d.next_error)),
-        c("SDCS", format!("{:#04x}", d.ts_sd_status)),
-    ]));
-    lines.push(row(vec![
-        c("LOAD", d.fuel_load.to_string()),
-        c("IGLD", d.ign_load.to_string()),
-        c("CILT", d.cl_idle_target.to_string()),
-    ]));
-    lines.push(row(vec![
-        c("STS1", format!("{:#04x}", d.status1)),
-        c("ENG", format!("{:#04x}", d.engine)),
-        c("SPRK", format!("{:#04x}", d.spark)),
-    ]));
-    lines.push(row(vec![
-        c("STS3", format!("{:#04x}", d.status3)),
-        c("STS4", format!("{:#04x}", d.status4)),
-        c("STS5", opt_str(d.status5.map(|v| format!("{:#04x}", v)))),
-    ]));
+    // ── FUEL USAGE (when injector_flow_cc_min is set) ───────────────────
+    if let Some(fuel) = &snap.fuel {
+        lines.push(section_line("Fuel usage"));
+        for chunk in fuel.display().chunks(3) {
+            lines.push(row(chunk));
+        }
+    }
 
-    // ── CAN INPUTS (only shown when any channel is non-zero) ─────────────
-    if d.canin.iter().any(|&v| v != 0) {
-        lines.push(section_line("CAN INPUTS"));
-        for i in (0..16usize).step_by(3) {
-            let end = (i + 3).min(16);
-            let cells: Vec<(String, String)> = (i..end)
-                .map(|j| (format!("C{:<2}", j), d.canin[j].to_string()))
-                .collect();
-            lines.push(row(cells));
+    // ── STATUS (lit indicator = bit set) ─────────────────────────────────
+    lines.push(section_line("STATUS"));
+    for (label, field) in STATUS_ROWS {
+        let mut spans = vec![Span::styled(format!("{:<6}", label), lbl)];
+        for flag in flags_of(*field) {
+            let style = match flag.get(d) {
+                Some(true) => Style::default()
+                    .fg(Color::Green)
+                    .add_modifier(Modifier::BOLD),
+                Some(false) => Style::default().fg(Color::DarkGray),
+                None => Style::default()
+                    .fg(Color::DarkGray)
+                    .add_modifier(Modifier::DIM),
+            };
+            spans.push(Span::styled(format!("{} ", flag.label), style));
         }
+        lines.push(Line::from(spans));
     }
 
     let para = Paragraph::new(lines).block(block);
     f.render_widget(para, area);
 }
 
+/// Status bytes shown as indicator rows, in display order.
+const STATUS_ROWS: &[(&str, Field)] = &[
+    ("STS1", Field::Status1),
+    ("ENG", Field::Engine),
+    ("SPRK", Field::Spark),
+    ("STS3", Field::Status3),
+    ("STS4", Field::Status4),
+    ("STS5", Field::Status5),
+    ("PROT", Field::EngineProtectStatus),
+    ("A/C", Field::AirConStatus),
+    ("OUT", Field::OutputsStatus),
+];
+
+/// Categories not shown as value rows (status bytes have their own
+/// indicator rows; raw bytes duplicate the converted temperatures).
+const HIDDEN_CATEGORIES: [Category; 2] = [Category::Status, Category::Raw];
+
+/// Values computed from several channels, shown after a category's channels.
+fn derived_cells(
+    category: Category,
+    d: &SpeeduinoData,
+    units: &Units,
+    wideband: &Wideband,
+) -> Vec<(&'static str, String)> {
+    use Field::*;
+    let mut cells = Vec::new();
+    match category {
+        // Gauge boost pressure: positive = boost, negative = vacuum
+        Category::Engine if d.has(Map) && d.has(Baro) => {
+            let boost_rel = d.map as f64 - d.baro as f64;
+            cells.push(("GBST", units.display_gauge_pressure(boost_rel)));
+        }
+        // Wideband AFR, lambda and error against target, for the fuel in use
+        Category::Afr => {
+            let values = [
+                ("AFR", wideband.afr(d).map(|v| format!("{:.1}", v))),
+                ("LAM", wideband.lambda(d).map(|v| format!("{:.2}", v))),
+                ("AERR", wideband.afr_error(d).map(|v| format!("{:+.1}", v))),
+                (
+                    "LAM>",
+                    wideband.target_lambda(d).map(|v| format!("{:.2}", v)),
+                ),
+            ];
+            cells.extend(values.into_iter().filter_map(|(l, v)| Some((l, v?))));
+        }
+        // Dwell efficiency: actual measured vs requested dwell (%)
+        Category::Ignition if d.has(Dwell) => {
+            if let Some(eff) = d
+                .actual_dwell
+                .filter(|_| d.dwell > 0)
+                .map(|ad| ad as f32 / d.dwell as f32 * 100.0)
+            {
+                cells.push(("DEFF", format!("{:.0}%", eff)));
+            }
+        }
+        Category::System if d.has(NextError) => {
+            cells.push(("ERR", d.ecu_error().map_or("none", |e| e.name()).into()));
+        }
+        _ => {}
+    }
+    cells
+}
+
 fn render_log(f: &mut Frame, area: Rect, snap: &StateSnapshot) {
     let max_lines = (area.height.saturating_sub(2)) as usize;
     let start = snap.logs.len().saturating_sub(max_lines);
     let items: Vec<ListItem> = snap.logs[start..]
         .iter()
         .map(|line| {
-            let style = if line.contains("ERROR") {
+            // Events are logged at WARN, so match them before the level
+            let style = if line.contains(EVENT_LOG_PREFIX) {
+                Style::default().fg(Color::Magenta)
+            } else if line.contains("ERROR") {
                 Style::default().fg(Color::Red)
             } else if line.contains("WARN") {
                 Style::default().fg(Color::Yellow)
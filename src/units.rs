@@ -0,0 +1,391 @@
+//! Unit systems for published and displayed values.
+//!
+//! [`SpeeduinoData`](crate::ecu_data_parser::SpeeduinoData) and its helpers
+//! are metric (°C, kPa, km/h).  [`Units`] converts the channels that carry a
+//! physical quantity into the configured `unit_system`, with per-channel
+//! overrides from the `[units]` table:
+//!
+//! | Quantity | Channels | Metric | Imperial | Also accepted |
+//! |----------|----------|--------|----------|---------------|
+//! | temperature | `IAT`, `CLT`, `FTP` | °C | °F | |
+//! | pressure | `MAP`, `BAR`, `EMP`, `BST`, `FPR`, `OPR` | kPa | psi | bar, inHg |
+//! | speed | `VSS` | km/h | mph | |
+//!
+//! A channel's quantity follows from its metric unit in the
+//! [channel registry](crate::channels); raw bytes (`MAT`, `CAD`) and the other
+//! channels are published in their registry unit and never converted.
+
+use crate::channels::{CHANNELS, channel};
+use crate::config::AppConfig;
+use crate::errors::{ConfigError, Result};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// kPa per psi
+const KPA_PER_PSI: f64 = 6.894_757;
+/// kPa per inch of mercury
+const KPA_PER_INHG: f64 = 3.386_389;
+/// km per mile
+const KM_PER_MILE: f64 = 1.609_344;
+
+/// `unit_system` setting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum UnitSystem {
+    #[default]
+    Metric,
+    Imperial,
+}
+
+impl UnitSystem {
+    /// Parse `"metric"` or `"imperial"` (case-insensitive).
+    pub fn from_config(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "metric" => Some(UnitSystem::Metric),
+            "imperial" => Some(UnitSystem::Imperial),
+            _ => None,
+        }
+    }
+}
+
+/// Physical quantity a channel measures.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantity {
+    Temperature,
+    Pressure,
+    Speed,
+}
+
+impl Quantity {
+    fn unit_in(self, system: UnitSystem) -> Unit {
+        match (self, system) {
+            (Quantity::Temperature, UnitSystem::Metric) => Unit::Celsius,
+            (Quantity::Temperature, UnitSystem::Imperial) => Unit::Fahrenheit,
+            (Quantity::Pressure, UnitSystem::Metric) => Unit::Kpa,
+            (Quantity::Pressure, UnitSystem::Imperial) => Unit::Psi,
+            (Quantity::Speed, UnitSystem::Metric) => Unit::Kmh,
+            (Quantity::Speed, UnitSystem::Imperial) => Unit::Mph,
+        }
+    }
+}
+
+/// A unit a value can be published in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    Celsius,
+    Fahrenheit,
+    Kpa,
+    Psi,
+    Bar,
+    InHg,
+    Kmh,
+    Mph,
+}
+
+impl Unit {
+    /// Parse a unit name as written in the `[units]` table (case-insensitive).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "c" | "°c" | "celsius" => Some(Unit::Celsius),
+            "f" | "°f" | "fahrenheit" => Some(Unit::Fahrenheit),
+            "kpa" => Some(Unit::Kpa),
+            "psi" => Some(Unit::Psi),
+            "bar" => Some(Unit::Bar),
+            "inhg" => Some(Unit::InHg),
+            "km/h" | "kmh" | "kph" => Some(Unit::Kmh),
+            "mph" => Some(Unit::Mph),
+            _ => None,
+        }
+    }
+
+    /// Symbol used in metadata and the TUI.
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Celsius => "°C",
+            Unit::Fahrenheit => "°F",
+            Unit::Kpa => "kPa",
+            Unit::Psi => "psi",
+            Unit::Bar => "bar",
+            Unit::InHg => "inHg",
+            Unit::Kmh => "km/h",
+            Unit::Mph => "mph",
+        }
+    }
+
+    pub fn quantity(self) -> Quantity {
+        match self {
+            Unit::Celsius | Unit::Fahrenheit => Quantity::Temperature,
+            Unit::Kpa | Unit::Psi | Unit::Bar | Unit::InHg => Quantity::Pressure,
+            Unit::Kmh | Unit::Mph => Quantity::Speed,
+        }
+    }
+
+    /// Decimal places published for this unit.
+    pub fn decimals(self) -> usize {
+        match self {
+            Unit::Psi | Unit::InHg => 1,
+            Unit::Bar => 2,
+            _ => 0,
+        }
+    }
+
+    /// Convert from the metric unit of the same quantity.
+    pub fn from_metric(self, value: f64) -> f64 {
+        match self {
+            Unit::Celsius | Unit::Kpa | Unit::Kmh => value,
+            Unit::Fahrenheit => value * 9.0 / 5.0 + 32.0,
+            Unit::Psi => value / KPA_PER_PSI,
+            Unit::Bar => value / 100.0,
+            Unit::InHg => value / KPA_PER_INHG,
+            Unit::Mph => value / KM_PER_MILE,
+        }
+    }
+}
+
+/// Quantity a topic code measures, `None` for channels that are not converted.
+pub fn channel_quantity(code: &str) -> Option<Quantity> {
+    channel(code)
+        .and_then(|c| Unit::from_name(c.unit))
+        .map(Unit::quantity)
+}
+
+/// `value` followed by its unit symbol; percentages and degrees are attached,
+/// other units are separated by a space.
+pub fn with_symbol(value: String, symbol: &str) -> String {
+    match symbol {
+        "" => value,
+        "%" | "°" | "°C" | "°F" => format!("{}{}", value, symbol),
+        _ => format!("{} {}", value, symbol),
+    }
+}
+
+/// Units every channel is published and displayed in.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Units {
+    system: UnitSystem,
+    /// Per-channel choices (upper-case topic code → unit)
+    overrides: Vec<(String, Unit)>,
+}
+
+impl Units {
+    pub fn new(system: UnitSystem) -> Self {
+        Self {
+            system,
+            overrides: Vec::new(),
+        }
+    }
+
+    /// Publish `code` in `unit` regardless of the unit system.
+    pub fn with_override(mut self, code: &str, unit: Unit) -> Self {
+        self.overrides.push((code.to_uppercase(), unit));
+        self
+    }
+
+    /// Units from `unit_system` and the `[units]` table.
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        let Some(system) = UnitSystem::from_config(&config.unit_system) else {
+            return Err(ConfigError::InvalidValue {
+                field: "unit_system".to_string(),
+                message: format!(
+                    "must be \"metric\" or \"imperial\", got \"{}\"",
+                    config.unit_system
+                ),
+            }
+            .into());
+        };
+        let mut units = Self::new(system);
+        for (code, name) in &config.units {
+            let Some(quantity) = channel_quantity(code) else {
+                return Err(ConfigError::InvalidValue {
+                    field: "units".to_string(),
+                    message: format!("channel \"{}\" has no unit to choose", code),
+                }
+                .into());
+            };
+            match Unit::from_name(name).filter(|u| u.quantity() == quantity) {
+                Some(unit) => units = units.with_override(code, unit),
+                None => {
+                    return Err(ConfigError::InvalidValue {
+                        field: "units".to_string(),
+                        message: format!(
+                            "\"{}\" is not a {:?} unit (channel \"{}\")",
+                            name, quantity, code
+                        ),
+                    }
+                    .into());
+                }
+            }
+        }
+        Ok(units)
+    }
+
+    /// Unit `code` is published in, `None` for channels that are not converted.
+    pub fn unit(&self, code: &str) -> Option<Unit> {
+        let quantity = channel_quantity(code)?;
+        let chosen = self
+            .overrides
+            .iter()
+            .find(|(c, _)| c.eq_ignore_ascii_case(code))
+            .map(|(_, u)| *u);
+        Some(chosen.unwrap_or_else(|| quantity.unit_in(self.system)))
+    }
+
+    /// `metric` converted for `code` (unchanged for channels without a unit).
+    pub fn convert(&self, code: &str, metric: f64) -> f64 {
+        self.unit(code).map_or(metric, |u| u.from_metric(metric))
+    }
+
+    fn decimals(&self, code: &str) -> usize {
+        match self.unit(code) {
+            Some(unit) => unit.decimals(),
+            None => channel(code).map_or(0, |c| c.decimals),
+        }
+    }
+
+    /// Converted value as published to MQTT (no unit symbol).
+    pub fn format(&self, code: &str, metric: f64) -> String {
+        format!("{:.*}", self.decimals(code), self.convert(code, metric))
+    }
+
+    /// Converted value with its unit symbol, for the TUI.
+    pub fn display(&self, code: &str, metric: f64) -> String {
+        with_symbol(self.format(code, metric), self.symbol(code))
+    }
+
+    /// Converted value for JSON payloads: an integer when the unit has no
+    /// decimals, otherwise rounded to the unit's precision.
+    pub fn json_value(&self, code: &str, metric: f64) -> Value {
+        let value = self.convert(code, metric);
+        match self.decimals(code) {
+            0 => Value::from(value.round() as i64),
+            places => {
+                let scale = 10f64.powi(places as i32);
+                Value::from((value * scale).round() / scale)
+            }
+        }
+    }
+
+    /// Unit symbol for `code`: the converted unit, else the registry unit
+    /// (empty for dimensionless and unknown channels).
+    pub fn symbol(&self, code: &str) -> &'static str {
+        match self.unit(code) {
+            Some(unit) => unit.symbol(),
+            None => channel(code).map_or("", |c| c.unit),
+        }
+    }
+
+    /// Boost (positive) or vacuum (negative) relative to baro, in the unit of
+    /// `MAP`.  Imperial readings show vacuum in inHg, as gauges do.
+    pub fn display_gauge_pressure(&self, kpa: f64) -> String {
+        let unit = match self.unit("MAP") {
+            Some(Unit::Psi) if kpa < 0.0 => Unit::InHg,
+            Some(unit) => unit,
+            None => Unit::Kpa,
+        };
+        format!(
+            "{:+.*} {}",
+            unit.decimals(),
+            unit.from_metric(kpa),
+            unit.symbol()
+        )
+    }
+
+    /// Unit of every channel that has one, published as metadata.
+    pub fn metadata(&self) -> BTreeMap<&'static str, &'static str> {
+        CHANNELS
+            .iter()
+            .map(|c| (c.code, self.symbol(c.code)))
+            .filter(|(_, symbol)| !symbol.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_metric_is_unchanged() {
+        let units = Units::default();
+        assert_eq!(units.format("CLT", 90.0), "90");
+        assert_eq!(units.format("MAP", 100.0), "100");
+        assert_eq!(units.format("RPM", 3000.0), "3000");
+        assert_eq!(units.symbol("VSS"), "km/h");
+        assert_eq!(units.symbol("RPM"), "rpm");
+        assert_eq!(units.symbol("O2P"), "");
+        assert_eq!(units.format("BAT", 14.2), "14.2");
+        assert_eq!(units.display("TPS", 42.0), "42%");
+        assert_eq!(units.display("PW1", 3.3), "3.3 ms");
+    }
+
+    #[test]
+    fn test_imperial_conversions() {
+        let units = Units::new(UnitSystem::Imperial);
+        assert_eq!(units.format("CLT", 90.0), "194");
+        assert_eq!(units.format("IAT", -40.0), "-40");
+        assert_eq!(units.format("MAP", 100.0), "14.5");
+        assert_eq!(units.format("VSS", 100.0), "62");
+        assert_eq!(units.display("CLT", 100.0), "212°F");
+        assert_eq!(units.display("OPR", 300.0), "43.5 psi");
+    }
+
+    #[test]
+    fn test_overrides_beat_the_system() {
+        let units = Units::new(UnitSystem::Imperial)
+            .with_override("map", Unit::Bar)
+            .with_override("CLT", Unit::Celsius);
+        assert_eq!(units.format("MAP", 150.0), "1.50");
+        assert_eq!(units.format("CLT", 90.0), "90");
+        assert_eq!(units.format("IAT", 0.0), "32");
+    }
+
+    #[test]
+    fn test_gauge_pressure_uses_inhg_for_imperial_vacuum() {
+        let imperial = Units::new(UnitSystem::Imperial);
+        assert_eq!(imperial.display_gauge_pressure(-60.0), "-17.7 inHg");
+        assert_eq!(imperial.display_gauge_pressure(100.0), "+14.5 psi");
+        assert_eq!(Units::default().display_gauge_pressure(-60.0), "-60 kPa");
+    }
+
+    #[test]
+    fn test_json_values() {
+        let units = Units::new(UnitSystem::Imperial);
+        assert_eq!(units.json_value("CLT", 90.0), Value::from(194));
+        assert_eq!(units.json_value("MAP", 180.0), Value::from(26.1));
+        assert_eq!(Units::default().json_value("MAP", 180.0), Value::from(180));
+    }
+
+    #[test]
+    fn test_from_config() {
+        let config = AppConfig {
+            unit_system: "Imperial".to_string(),
+            units: HashMap::from([
+                ("MAP".to_string(), "kPa".to_string()),
+                ("IAT".to_string(), "°C".to_string()),
+            ]),
+            ..AppConfig::default()
+        };
+        let units = Units::from_config(&config).unwrap();
+        assert_eq!(units.unit("MAP"), Some(Unit::Kpa));
+        assert_eq!(units.unit("IAT"), Some(Unit::Celsius));
+        assert_eq!(units.unit("CLT"), Some(Unit::Fahrenheit));
+        assert_eq!(units.metadata()["VSS"], "mph");
+        assert_eq!(units.metadata()["BAT"], "V");
+        assert!(!units.metadata().contains_key("O2P"));
+
+        // A unit of the wrong quantity is rejected
+        let config = AppConfig {
+            units: HashMap::from([("CLT".to_string(), "mph".to_string())]),
+            ..AppConfig::default()
+        };
+        assert!(Units::from_config(&config).is_err());
+    }
+
+    #[test]
+    fn test_unit_names() {
+        assert_eq!(Unit::from_name("°F"), Some(Unit::Fahrenheit));
+        assert_eq!(Unit::from_name("inHg"), Some(Unit::InHg));
+        assert_eq!(Unit::from_name("KPH"), Some(Unit::Kmh));
+        assert_eq!(Unit::from_name("furlongs"), None);
+    }
+}
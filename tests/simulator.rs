@@ -0,0 +1,166 @@
+//! End-to-end tests: the ECU handler, parser and MQTT publishing path talking
+//! to the built-in simulator over TCP.
+
+use speeduino_to_mqtt::config::AppConfig;
+use speeduino_to_mqtt::ecu_data_parser::{PublishState, process_speeduino_realtime_data};
+use speeduino_to_mqtt::ecu_protocol::READ_OUTPUT_CHANNELS;
+use speeduino_to_mqtt::ecu_serial_comms_handler::EcuSerialHandler;
+use speeduino_to_mqtt::packet_layout::{Field, PRIMARY};
+use speeduino_to_mqtt::simulator::{SIM_SIGNATURE, Scenario, Simulator};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+
+/// Serve `sim` on an ephemeral local port, one client at a time.
+async fn start_simulator(sim: Simulator) -> u16 {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let port = listener.local_addr().unwrap().port();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let _ = sim.serve(&mut stream).await;
+        }
+    });
+    port
+}
+
+fn tcp_config(port: u16) -> AppConfig {
+    AppConfig {
+        connection_type: "tcp".to_string(),
+        tcp_host: Some("127.0.0.1".to_string()),
+        tcp_port: Some(port),
+        read_timeout_ms: 500,
+        initial_retry_delay_ms: 10,
+        max_retry_delay_ms: 100,
+        ..AppConfig::default()
+    }
+}
+
+#[tokio::test]
+async fn legacy_poll_is_decoded_and_published() {
+    let port = start_simulator(Simulator::new(Scenario::Idle)).await;
+    let config = Arc::new(tcp_config(port));
+    let mut handler = EcuSerialHandler::new((*config).clone());
+    handler.connect().await.unwrap();
+
+    assert_eq!(handler.firmware().unwrap().signature, SIM_SIGNATURE);
+    assert_eq!(handler.layout(), &PRIMARY);
+
+    let mut state = PublishState::from_config(&config).unwrap();
+    let (tx, mut rx) = mpsc::channel(1000);
+    let data = handler.read_engine_data().await.unwrap();
+    assert_eq!(data.len(), PRIMARY.length);
+    let decoded =
+        process_speeduino_realtime_data(&data, &config, handler.layout(), Some(&tx), &mut state)
+            .await
+            .unwrap();
+    assert!(decoded.rpm > 0);
+    assert_eq!(decoded.coolant_celsius(), 85);
+
+    drop(tx);
+    let mut topics = Vec::new();
+    while let Some(msg) = rx.recv().await {
+        topics.push(msg.topic);
+    }
+    assert!(topics.iter().any(|t| t.ends_with("/RPM")));
+    assert!(topics.iter().any(|t| t.ends_with("/CLT")));
+}
+
+#[tokio::test]
+async fn legacy_poll_rate_without_handshake() {
+    const SAMPLES: u32 = 100;
+    let port = start_simulator(Simulator::new(Scenario::Idle)).await;
+    let mut handler = EcuSerialHandler::new(AppConfig {
+        ecu_handshake: false,
+        ..tcp_config(port)
+    });
+    handler.connect().await.unwrap();
+
+    let start = std::time::Instant::now();
+    for _ in 0..SAMPLES {
+        assert_eq!(
+            handler.read_engine_data().await.unwrap().len(),
+            PRIMARY.length
+        );
+    }
+    let rate = SAMPLES as f64 / start.elapsed().as_secs_f64();
+    assert!(rate > 60.0, "only {:.1} polls/s", rate);
+}
+
+#[tokio::test]
+async fn crc_protocol_reads_channel_subset() {
+    let port = start_simulator(Simulator::new(Scenario::Idle)).await;
+    let config = Arc::new(AppConfig {
+        ecu_protocol: "crc".to_string(),
+        ecu_channels: vec!["RPM".to_string(), "CLT".to_string()],
+        ..tcp_config(port)
+    });
+    let mut handler = EcuSerialHandler::new((*config).clone());
+    handler.connect().await.unwrap();
+
+    let mut state = PublishState::from_config(&config).unwrap();
+    let data = handler.read_engine_data().await.unwrap();
+    let decoded =
+        process_speeduino_realtime_data(&data, &config, handler.layout(), None, &mut state)
+            .await
+            .unwrap();
+    assert!(decoded.has(Field::Rpm));
+    assert!(decoded.has(Field::CoolantRaw));
+    assert!(!decoded.has(Field::Map));
+    assert_eq!(decoded.coolant_celsius(), 85);
+}
+
+#[tokio::test]
+async fn legacy_read_past_packet_end_is_zero_filled() {
+    let port = start_simulator(Simulator::new(Scenario::Idle)).await;
+    let mut client = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+
+    // 8 bytes from offset 134: PW7/PW8, then 4 bytes beyond the packet
+    client
+        .write_all(&[b'r', 0x00, READ_OUTPUT_CHANNELS, 134, 0, 8, 0])
+        .await
+        .unwrap();
+    let mut reply = [0xFFu8; 8];
+    tokio::time::timeout(Duration::from_millis(500), client.read_exact(&mut reply))
+        .await
+        .expect("simulator answered")
+        .unwrap();
+    assert_eq!(&reply[4..], &[0, 0, 0, 0]);
+
+    // Entirely out of range
+    client
+        .write_all(&[b'r', 0x00, READ_OUTPUT_CHANNELS, 0x00, 0x01, 2, 0])
+        .await
+        .unwrap();
+    let mut reply = [0xFFu8; 2];
+    tokio::time::timeout(Duration::from_millis(500), client.read_exact(&mut reply))
+        .await
+        .expect("simulator answered")
+        .unwrap();
+    assert_eq!(reply, [0, 0]);
+}
+
+#[tokio::test]
+async fn reconnects_after_dropped_link() {
+    let sim =
+        Simulator::new(Scenario::Disconnect).with_disconnect_after(Duration::from_millis(200));
+    let port = start_simulator(sim).await;
+    let mut handler = EcuSerialHandler::new(tcp_config(port));
+    handler.connect().await.unwrap();
+
+    // Poll until the simulator drops the link
+    let mut polls = 0;
+    while handler.read_engine_data().await.is_ok() {
+        polls += 1;
+        assert!(polls < 1000, "link was never dropped");
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+
+    handler.reconnect().await.unwrap();
+    assert_eq!(handler.get_retry_count(), 0);
+    assert_eq!(
+        handler.read_engine_data().await.unwrap().len(),
+        PRIMARY.length
+    );
+}